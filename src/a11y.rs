@@ -0,0 +1,213 @@
+// Lint rendered HTML for common accessibility problems
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::html::{strip_html, unescape_xml};
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+static COMMENT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").expect("Comment regex should be valid"));
+
+static START_TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<([a-zA-Z][a-zA-Z0-9]*)\b([^>]*)>").expect("Start tag regex should be valid")
+});
+
+static ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+        .expect("Attr regex should be valid")
+});
+
+static LINK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").expect("Link regex should be valid")
+});
+
+/// An accessibility problem found in a doc's HTML
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum A11yIssue {
+    /// An `<img>` without an `alt` attribute.
+    /// Decorative images should have `alt=""`.
+    MissingAlt { src: String },
+    /// A heading more than one level below the heading before it
+    SkippedHeading { from: u8, to: u8 },
+    /// A link with no text, and no `aria-label` or image alt text
+    EmptyLink { href: String },
+    /// An id used by more than one element
+    DuplicateId { id: String },
+}
+
+impl std::fmt::Display for A11yIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            A11yIssue::MissingAlt { src } => write!(f, "Image has no alt text: {}", src),
+            A11yIssue::SkippedHeading { from, to } => {
+                write!(f, "Heading level skipped: <h{}> follows <h{}>", to, from)
+            }
+            A11yIssue::EmptyLink { href } => write!(f, "Link has no text: {}", href),
+            A11yIssue::DuplicateId { id } => write!(f, "Duplicate id: {}", id),
+        }
+    }
+}
+
+/// Parse the attributes of a start tag into (lowercase name, value) pairs.
+/// Attributes without a value get an empty value.
+fn parse_attrs(attrs: &str) -> Vec<(String, String)> {
+    ATTR_REGEX
+        .captures_iter(attrs)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or(caps.get(3))
+                .or(caps.get(4))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), unescape_xml(value))
+        })
+        .collect()
+}
+
+fn get_attr<'a>(attrs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Does a link's inner HTML give it an accessible name?
+/// Counts text, and the alt text of images.
+fn has_link_text(inner: &str) -> bool {
+    if !unescape_xml(&strip_html(inner)).trim().is_empty() {
+        return true;
+    }
+    START_TAG_REGEX.captures_iter(inner).any(|caps| {
+        caps[1].eq_ignore_ascii_case("img")
+            && get_attr(&parse_attrs(&caps[2]), "alt").is_some_and(|alt| !alt.trim().is_empty())
+    })
+}
+
+/// Scan HTML for accessibility problems: images without alt text, skipped
+/// heading levels, links without text, and duplicate ids.
+/// Issues are returned in the order they are found.
+pub fn lint_a11y(html: &str) -> Vec<A11yIssue> {
+    let html = COMMENT_REGEX.replace_all(html, "");
+    let mut issues = Vec::new();
+    let mut ids = HashSet::new();
+    let mut reported_ids = HashSet::new();
+    let mut last_heading: Option<u8> = None;
+    for caps in START_TAG_REGEX.captures_iter(&html) {
+        let tag = caps[1].to_ascii_lowercase();
+        let attrs = parse_attrs(&caps[2]);
+        if let Some(id) = get_attr(&attrs, "id") {
+            if !ids.insert(id.to_string()) && reported_ids.insert(id.to_string()) {
+                issues.push(A11yIssue::DuplicateId { id: id.to_string() });
+            }
+        }
+        if tag == "img" && get_attr(&attrs, "alt").is_none() {
+            issues.push(A11yIssue::MissingAlt {
+                src: get_attr(&attrs, "src").unwrap_or_default().to_string(),
+            });
+        }
+        if let Some(level) = heading_level(&tag) {
+            if let Some(from) = last_heading {
+                if level > from + 1 {
+                    issues.push(A11yIssue::SkippedHeading { from, to: level });
+                }
+            }
+            last_heading = Some(level);
+        }
+    }
+    for caps in LINK_REGEX.captures_iter(&html) {
+        let attrs = parse_attrs(&caps[1]);
+        let labelled = ["aria-label", "aria-labelledby", "title"]
+            .iter()
+            .any(|name| get_attr(&attrs, name).is_some_and(|value| !value.trim().is_empty()));
+        if !labelled && !has_link_text(&caps[2]) {
+            issues.push(A11yIssue::EmptyLink {
+                href: get_attr(&attrs, "href").unwrap_or_default().to_string(),
+            });
+        }
+    }
+    issues
+}
+
+/// Get the level of a heading tag name, e.g. 2 for `h2`
+fn heading_level(tag: &str) -> Option<u8> {
+    match tag.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some(level - b'0'),
+        _ => None,
+    }
+}
+
+impl Doc {
+    /// Scan this doc's HTML content for accessibility problems.
+    /// See `lint_a11y`.
+    pub fn get_a11y_issues(&self) -> Vec<A11yIssue> {
+        lint_a11y(&self.content)
+    }
+
+    /// Lint this doc's HTML content for accessibility problems.
+    /// Returns an error listing every issue if any are found.
+    pub fn lint_a11y(self) -> Result<Self, Error> {
+        let issues = self.get_a11y_issues();
+        if issues.is_empty() {
+            return Ok(self);
+        }
+        let details: Vec<String> = issues.iter().map(|issue| format!("  {}", issue)).collect();
+        Err(
+            Error::value(format!("Accessibility issues:\n{}", details.join("\n")))
+                .with_doc(&self.id_path),
+        )
+    }
+}
+
+pub trait A11yDocs: Docs {
+    /// Lint each doc's HTML content for accessibility problems.
+    /// Docs with issues become errors describing each issue.
+    fn lint_a11y(self) -> impl DocResults {
+        self.map(|doc| doc.lint_a11y())
+    }
+}
+
+impl<I> A11yDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_a11y() {
+        let html = r##"<h1 id="top">Title</h1>
+<img src="a.png"><img src="b.png" alt="">
+<h3>Skipped</h3><h2 id="top">Back up</h2>
+<a href="/x"></a><a href="/y"><img src="y.png" alt="Y"></a>
+<a href="/z" aria-label="Z"></a><a href="#top"> <em></em> </a>
+<!-- <img src="commented.png"> -->"##;
+        assert_eq!(
+            lint_a11y(html),
+            vec![
+                A11yIssue::MissingAlt {
+                    src: "a.png".to_string()
+                },
+                A11yIssue::SkippedHeading { from: 1, to: 3 },
+                A11yIssue::DuplicateId {
+                    id: "top".to_string()
+                },
+                A11yIssue::EmptyLink {
+                    href: "/x".to_string()
+                },
+                A11yIssue::EmptyLink {
+                    href: "#top".to_string()
+                },
+            ]
+        );
+        let err = Doc::draft("a.md")
+            .set_content(html)
+            .lint_a11y()
+            .unwrap_err();
+        assert!(err.msg.contains("  Image has no alt text: a.png\n"));
+        assert!(Doc::draft("b.md")
+            .set_content("<h2>Fine</h2><img src=\"b.png\" alt=\"B\">")
+            .lint_a11y()
+            .is_ok());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{doc::Doc, docs::Docs};
 use regex::Regex;
+use std::path::Path;
 use std::sync::LazyLock;
 
 static HREF_SRC_REGEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -7,31 +8,219 @@ static HREF_SRC_REGEX: LazyLock<Regex> = LazyLock::new(|| {
         .expect("Failed to compile regex for absolutizing URLs")
 });
 
-/// Qualify a URL with a base URL if it's relative.
+static SRCSET_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"srcset=(?:"([^"]*)"|'([^']*)')"#)
+        .expect("Failed to compile regex for absolutizing URLs")
+});
+
+static STYLE_ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"style=(?:"([^"]*)"|'([^']*)')"#)
+        .expect("Failed to compile regex for absolutizing URLs")
+});
+
+static STYLE_BLOCK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)(<style[^>]*>)(.*?)(</style>)"#)
+        .expect("Failed to compile regex for absolutizing URLs")
+});
+
+static CSS_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"url\(\s*(?:"([^"]*)"|'([^']*)'|([^)"'\s]*))\s*\)"#)
+        .expect("Failed to compile regex for absolutizing URLs")
+});
+
+/// Does this URL have a scheme, such as `https:`, `mailto:` or `data:`?
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolve a relative URL path (such as `../img/a.png`) against the
+/// directory of `output_path`, returning a site-root URL.
+/// Queries and fragments are kept. `..` never climbs above the site root.
+pub fn resolve_relative_url(url: &str, output_path: &Path) -> String {
+    let split = url.find(['#', '?']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(split);
+    let mut segments: Vec<String> = output_path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+    let trailing_slash = path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..");
+    let mut resolved = format!("/{}", segments.join("/"));
+    if trailing_slash && !resolved.ends_with('/') {
+        resolved.push('/');
+    }
+    format!("{}{}", resolved, suffix)
+}
+
+/// Qualify a URL with a base URL if it's relative to the site root.
+/// Protocol-relative URLs (`//cdn.com/a.js`) are left alone.
 pub fn qualify_url(url: &str, base_url: &str) -> String {
-    if url.starts_with('/') {
+    if url.starts_with('/') && !url.starts_with("//") {
         format!("{}{}", base_url.trim_end_matches('/'), url)
     } else {
         url.to_string()
     }
 }
 
-/// Replace relative URLs in content with absolute URLs.
-pub fn absolutize_urls_in_html(html: &str, base_url: &str) -> String {
-    HREF_SRC_REGEX
-        .replace_all(html, |caps: &regex::Captures| {
-            let attr = &caps[1];
-            let value = &caps[2];
-            let url = qualify_url(value, base_url);
-            format!(r#"{}="{}""#, attr, url)
+/// Convert a site-relative path (such as a doc's output path) into a URL
+/// under base URL.
+pub fn to_url(path: &Path, base_url: &str) -> String {
+    let path = path.to_string_lossy();
+    format!(
+        "{}/{}",
+        base_url.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
+/// Qualify a URL with a base URL, resolving paths relative to the
+/// directory of `output_path` first. URLs with a scheme, and fragment or
+/// query-only URLs, are left alone.
+pub fn absolutize_url(url: &str, base_url: &str, output_path: &Path) -> String {
+    if url.is_empty() || url.starts_with(['#', '?']) || url.starts_with("//") || has_scheme(url) {
+        return url.to_string();
+    }
+    if url.starts_with('/') {
+        return qualify_url(url, base_url);
+    }
+    qualify_url(&resolve_relative_url(url, output_path), base_url)
+}
+
+/// Rewrite each candidate URL in a `srcset` value, keeping descriptors
+/// such as `2x` or `640w`.
+fn rewrite_srcset(srcset: &str, rewrite: &impl Fn(&str) -> String) -> String {
+    srcset
+        .split(',')
+        .map(|candidate| candidate.trim())
+        .filter(|candidate| !candidate.is_empty())
+        .map(
+            |candidate| match candidate.split_once(char::is_whitespace) {
+                Some((url, descriptor)) => format!("{} {}", rewrite(url), descriptor.trim()),
+                None => rewrite(candidate),
+            },
+        )
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Rewrite the URLs in CSS `url(...)` references, keeping their quotes.
+fn rewrite_css_urls(css: &str, rewrite: &impl Fn(&str) -> String) -> String {
+    CSS_URL_REGEX
+        .replace_all(css, |caps: &regex::Captures| {
+            if let Some(url) = caps.get(1) {
+                format!(r#"url("{}")"#, rewrite(url.as_str()))
+            } else if let Some(url) = caps.get(2) {
+                format!("url('{}')", rewrite(url.as_str()))
+            } else {
+                format!("url({})", rewrite(&caps[3]))
+            }
+        })
+        .to_string()
+}
+
+/// Rewrite the URLs in HTML with `rewrite`. Covers `src` and `href`
+/// attributes, `srcset` candidates, and CSS `url(...)` references in
+/// `style` attributes and `<style>` blocks.
+/// This is the engine shared by `absolutize_urls_in_html` and
+/// `relativize_urls_in_html`.
+pub fn rewrite_urls_in_html(html: &str, rewrite: impl Fn(&str) -> String) -> String {
+    let html = HREF_SRC_REGEX.replace_all(html, |caps: &regex::Captures| {
+        let attr = &caps[1];
+        let value = &caps[2];
+        format!(r#"{}="{}""#, attr, rewrite(value))
+    });
+    let html = SRCSET_REGEX.replace_all(&html, |caps: &regex::Captures| match caps.get(1) {
+        Some(value) => format!(r#"srcset="{}""#, rewrite_srcset(value.as_str(), &rewrite)),
+        None => format!("srcset='{}'", rewrite_srcset(&caps[2], &rewrite)),
+    });
+    let html = STYLE_ATTR_REGEX.replace_all(&html, |caps: &regex::Captures| match caps.get(1) {
+        Some(value) => format!(r#"style="{}""#, rewrite_css_urls(value.as_str(), &rewrite)),
+        None => format!("style='{}'", rewrite_css_urls(&caps[2], &rewrite)),
+    });
+    STYLE_BLOCK_REGEX
+        .replace_all(&html, |caps: &regex::Captures| {
+            format!(
+                "{}{}{}",
+                &caps[1],
+                rewrite_css_urls(&caps[2], &rewrite),
+                &caps[3]
+            )
         })
         .to_string()
 }
 
+/// Replace relative URLs in content with absolute URLs.
+/// Paths relative to the page (`./a.png`, `../a.png`, `a.png`) are resolved
+/// against the directory of `output_path`.
+pub fn absolutize_urls_in_html(html: &str, base_url: &str, output_path: &Path) -> String {
+    rewrite_urls_in_html(html, |url| absolutize_url(url, base_url, output_path))
+}
+
+/// Make a site-root URL (starting with `/`) relative to the directory of
+/// `output_path`. URLs ending in `/` get `index.html` appended, so links
+/// work when the site is opened via `file://`. Other URLs are returned
+/// unchanged.
+///
+/// url (output_path = posts/a/index.html):
+///     /css/site.css
+///
+/// relative url:
+///     ../../css/site.css
+pub fn relativize_url(url: &str, output_path: &Path) -> String {
+    if !url.starts_with('/') || url.starts_with("//") {
+        return url.to_string();
+    }
+    let split = url.find(['#', '?']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(split);
+    let mut path = path.trim_start_matches('/').to_string();
+    if path.is_empty() || path.ends_with('/') {
+        path.push_str("index.html");
+    }
+    let depth = output_path
+        .parent()
+        .map(|parent| parent.components().count())
+        .unwrap_or(0);
+    format!("{}{}{}", "../".repeat(depth), path, suffix)
+}
+
+/// Replace site-root URLs in content with URLs relative to `output_path`.
+/// The inverse of `absolutize_urls_in_html`.
+pub fn relativize_urls_in_html(html: &str, output_path: &Path) -> String {
+    rewrite_urls_in_html(html, |url| relativize_url(url, output_path))
+}
+
 impl Doc {
     /// Absolutize URLs in the content of this document.
     pub fn absolutize_urls(self, base_url: &str) -> Self {
-        let content = absolutize_urls_in_html(&self.content, base_url);
+        let content = absolutize_urls_in_html(&self.content, base_url, &self.output_path);
+        self.set_content(&content)
+    }
+
+    /// Make site-root URLs in the content of this document relative to its
+    /// output path.
+    pub fn relativize_urls(self) -> Self {
+        let content = relativize_urls_in_html(&self.content, &self.output_path);
         self.set_content(&content)
     }
 }
@@ -41,6 +230,13 @@ pub trait AbsolutizableDocs: Docs {
     fn absolutize_urls(self, base_url: &str) -> impl Docs {
         self.map(move |doc| doc.absolutize_urls(base_url))
     }
+
+    /// Make site-root URLs in the content of a sequence of documents relative
+    /// to each document's output path, so the site can be served from a
+    /// subdirectory or opened via `file://`.
+    fn relativize_urls(self) -> impl Docs {
+        self.map(|doc| doc.relativize_urls())
+    }
 }
 
 impl<I> AbsolutizableDocs for I where I: Docs {}
@@ -68,12 +264,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_url() {
+        assert_eq!(
+            to_url(Path::new("posts/a/index.html"), "https://example.com/"),
+            "https://example.com/posts/a/index.html"
+        );
+        assert_eq!(to_url(Path::new("/feed.xml"), "/"), "/feed.xml");
+    }
+
     #[test]
     fn test_absolutize_urls_in_html() {
         let html = r#"<a href="/relative">Link</a><img src="https://absolute.com/image.jpg">"#;
         let base_url = "https://example.com";
         let expected = r#"<a href="https://example.com/relative">Link</a><img src="https://absolute.com/image.jpg">"#;
-        assert_eq!(absolutize_urls_in_html(html, base_url), expected);
+        assert_eq!(
+            absolutize_urls_in_html(html, base_url, Path::new("index.html")),
+            expected
+        );
     }
 
     #[test]
@@ -95,4 +303,80 @@ mod tests {
         let absolutized = doc.absolutize_urls(base_url);
         assert_eq!(absolutized.content, expected_content);
     }
+
+    #[test]
+    fn test_relativize_url() {
+        let output_path = Path::new("posts/a/index.html");
+        assert_eq!(
+            relativize_url("/css/site.css", output_path),
+            "../../css/site.css"
+        );
+        assert_eq!(
+            relativize_url("/posts/b/#top", output_path),
+            "../../posts/b/index.html#top"
+        );
+        assert_eq!(relativize_url("/", Path::new("index.html")), "index.html");
+        assert_eq!(
+            relativize_url("//cdn.com/a.js", output_path),
+            "//cdn.com/a.js"
+        );
+        assert_eq!(relativize_url("b.html", output_path), "b.html");
+    }
+
+    #[test]
+    fn test_doc_relativize_urls() {
+        let doc = Doc::draft("a.md")
+            .set_output_path("a/index.html")
+            .set_content(r#"<a href="/">Home</a><img src="https://x.com/i.png">"#)
+            .relativize_urls();
+        assert_eq!(
+            doc.content,
+            r#"<a href="../index.html">Home</a><img src="https://x.com/i.png">"#
+        );
+    }
+
+    #[test]
+    fn test_absolutize_url() {
+        let output_path = Path::new("posts/a/index.html");
+        let base_url = "https://example.com";
+        assert_eq!(
+            absolutize_url("./img.png", base_url, output_path),
+            "https://example.com/posts/a/img.png"
+        );
+        assert_eq!(
+            absolutize_url("../b/#top", base_url, output_path),
+            "https://example.com/posts/b/#top"
+        );
+        assert_eq!(
+            absolutize_url("../../../c.html", base_url, output_path),
+            "https://example.com/c.html"
+        );
+        assert_eq!(
+            absolutize_url("mailto:a@b.com", base_url, output_path),
+            "mailto:a@b.com"
+        );
+        assert_eq!(absolutize_url("#top", base_url, output_path), "#top");
+        assert_eq!(
+            absolutize_url("//cdn.com/a.js", base_url, output_path),
+            "//cdn.com/a.js"
+        );
+    }
+
+    #[test]
+    fn test_absolutize_srcset_and_css() {
+        let html = concat!(
+            r#"<img srcset="a.png 1x, /b.png 2x" src="a.png">"#,
+            r#"<div style="background: url('../bg.png')"></div>"#,
+            "<style>body { background: url(/c.png) }</style>"
+        );
+        let expected = concat!(
+            r#"<img srcset="https://example.com/posts/a.png 1x, https://example.com/b.png 2x" src="https://example.com/posts/a.png">"#,
+            r#"<div style="background: url('https://example.com/bg.png')"></div>"#,
+            "<style>body { background: url(https://example.com/c.png) }</style>"
+        );
+        assert_eq!(
+            absolutize_urls_in_html(html, "https://example.com", Path::new("posts/index.html")),
+            expected
+        );
+    }
 }
@@ -1,9 +1,37 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use docs::SortKey;
+use lettersmith::comments::Comments;
+use lettersmith::doctor::{self, CheckStatus};
+use lettersmith::embed;
+use lettersmith::error::{ErrorFormat, ErrorHandler, OnError};
+use lettersmith::frontmatter::FrontmatterFormat;
+use lettersmith::graph::GraphFormat;
+use lettersmith::hash::Manifest;
+use lettersmith::html_rewrite::HtmlRewriter;
+use lettersmith::import::{self, ImportSource};
+use lettersmith::ingest;
+use lettersmith::io::write_file_deep;
+use lettersmith::linkify::HashtagAction;
+use lettersmith::markdown::MarkdownOptions;
+use lettersmith::paginate;
+use lettersmith::permalink::{PermalinkCollisions, PermalinkStyle};
+use lettersmith::pipeline::Step;
 use lettersmith::prelude::*;
-use lettersmith::wikilink::WikilinkDocs;
+use lettersmith::query::{self, Predicate};
+use lettersmith::redirects::RedirectsFormat;
+use lettersmith::report::SiteReportFormat;
+use lettersmith::scaffold;
+use lettersmith::schema::MetaSchemas;
+use lettersmith::stats::{self, BuildReport, ReportFormat};
+use lettersmith::tags;
+use lettersmith::wikilink::{SlugResolution, WikilinkDocs, WikilinkTemplates};
+use regex::Regex;
+use std::collections::HashMap;
 use std::env;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Instant;
 
 #[derive(Parser)]
 #[command(version = "0.1.0")]
@@ -12,6 +40,39 @@ use std::path::{Path, PathBuf};
     about = "Lettersmith is a static site generator built around a simple idea: piping JSON documents through stdio. Features are implemented as simple, single-purpose tools. To customize your own static site generator, you string together the features you want using Unix pipes and save those pipelines to a bash file."
 )]
 struct Cli {
+    #[arg(long = "on-error", global = true)]
+    #[arg(
+        help = "What to do when a doc fails: abort the build, or skip the doc and keep going. Defaults to on_error in config."
+    )]
+    on_error: Option<OnError>,
+
+    #[arg(long = "error-format", global = true)]
+    #[arg(
+        help = "How to report errors to stderr. Json prints one {doc, stage, kind, message} object per line. Defaults to error_format in config."
+    )]
+    error_format: Option<ErrorFormat>,
+
+    #[arg(long = "stats", global = true)]
+    #[arg(num_args = 0..=1, default_missing_value = "text")]
+    #[arg(
+        help = "Print a build report to stderr when the command finishes: docs read, rendered and written, bytes written, errors, and elapsed time. Format is text (default) or json."
+    )]
+    #[arg(value_name = "FORMAT")]
+    stats: Option<ReportFormat>,
+
+    #[arg(long = "strict", global = true)]
+    #[arg(
+        help = "Treat every line of stdin as a doc, reporting blank and non-JSON lines as errors rather than skipping them with a warning. Defaults to strict_stdin in config."
+    )]
+    strict: bool,
+
+    #[arg(long = "profile", global = true)]
+    #[arg(
+        help = "Config profile to merge over the base config. Defaults to the PROFILE env var. Example: smith --profile dev template"
+    )]
+    #[arg(value_name = "NAME")]
+    profile: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,51 +84,262 @@ enum Commands {
     )]
     Read {
         #[arg(
-            help = "File paths to read. Tip: you can use glob patterns to match specific lists of files. Example: smith read posts/*.md"
+            help = "File paths to read. Tip: you can use glob patterns to match specific lists of files. Patterns are expanded by smith, so quoting them works in shells that don't expand globs. Example: smith read \"posts/**/*.md\""
         )]
         #[arg(value_name = "FILE")]
         files: Vec<PathBuf>,
+
+        #[arg(long = "stubs")]
+        #[arg(
+            help = "Read stubs: only frontmatter and file stats, without loading content. Fast for index and feed pipelines over large content trees."
+        )]
+        stubs: bool,
+
+        #[arg(long = "max-size")]
+        #[arg(
+            help = "Maximum file size to read, in bytes. Larger files are skipped with a warning, as are binary files. Defaults to max_file_size in config (10 MB)."
+        )]
+        #[arg(value_name = "BYTES")]
+        max_size: Option<u64>,
+    },
+
+    #[command(
+        about = "Treat index files as page bundles: record the assets next to them (images, etc) in meta.bundle, so smith write --bundles can copy them. Hidden files, the output directory, and a root-level index are skipped. Run after smith frontmatter, which replaces meta."
+    )]
+    Bundles {
+        #[arg(
+            help = "Output directory, left out when collecting assets. Defaults to output_dir in config."
+        )]
+        #[arg(value_name = "DIRECTORY")]
+        output_dir: Option<PathBuf>,
     },
 
     #[command(
         about = "Write docs to a directory. Typically used at the end of a chain of piped smith commands to take the stream of JSON docs and write it to disk."
     )]
     Write {
-        #[arg(help = "Directory to write docs to")]
+        #[arg(help = "Directory to write docs to. Defaults to output_dir in config.")]
         #[arg(value_name = "DIRECTORY")]
-        #[arg(default_value = "public")]
-        output_dir: PathBuf,
+        output_dir: Option<PathBuf>,
+
+        #[arg(long = "frontmatter")]
+        #[arg(
+            help = "Serialize meta back to YAML frontmatter above the content. Useful for using lettersmith to transform content files."
+        )]
+        frontmatter: bool,
+
+        #[arg(long = "bundles")]
+        #[arg(
+            help = "Copy page bundle assets recorded by smith bundles next to each doc's output path, and rewrite relative references to them."
+        )]
+        #[arg(conflicts_with = "frontmatter")]
+        bundles: bool,
+
+        #[arg(long = "manifest")]
+        #[arg(
+            help = "Write a JSON manifest of the content hash of each doc, keyed by output path. Used to tell which files changed between builds."
+        )]
+        #[arg(value_name = "FILE")]
+        #[arg(conflicts_with_all = ["frontmatter", "bundles"])]
+        manifest: Option<PathBuf>,
+
+        #[arg(long = "headers")]
+        #[arg(
+            help = "Also write a _headers file with an ETag for each doc, from its content hash, for hosts that support it (Netlify, Cloudflare Pages)."
+        )]
+        #[arg(conflicts_with_all = ["frontmatter", "bundles"])]
+        headers: bool,
+
+        #[arg(long = "remove-drafts")]
+        #[arg(
+            help = "Leave out drafts, like smith remove-drafts. Drafts published as previews are kept."
+        )]
+        remove_drafts: bool,
+
+        #[arg(long = "remove-index")]
+        #[arg(help = "Leave out index docs, like smith remove-index")]
+        remove_index: bool,
     },
 
+    #[command(
+        about = "Assign a stable id to docs that don't have one, in meta.id, from a hash of the file they were read from. Write docs back with smith write --frontmatter to keep ids across renames. Ids are used to identify docs when deduping and in link graphs."
+    )]
+    AssignIds {},
+
+    #[command(
+        about = "Record a hash of each doc's content in meta.hash. The hash is stable between builds, so it can be used for change detection and ETags."
+    )]
+    Hash {},
+
     #[command(
         about = "Write docs to a JSON file. Useful when wanting to stash a set of documents for use in multiple pipelines, or to save a selection of documents for use in templating."
     )]
     Stash {
         #[arg(
-            help = "Write docs to a JSON file. You can use unstash to read docs back out from a stash. Example: smith stash build/posts.json"
+            help = "Write docs to a JSON file. You can use unstash to read docs back out from a stash. Use - to write to stdout. Example: smith stash build/posts.json"
+        )]
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        #[arg(long = "remove-drafts")]
+        #[arg(
+            help = "Leave out drafts, like smith remove-drafts. Drafts published as previews are kept."
         )]
+        remove_drafts: bool,
+
+        #[arg(long = "remove-index")]
+        #[arg(help = "Leave out index docs, like smith remove-index")]
+        remove_index: bool,
+    },
+
+    #[command(
+        about = "Write docs to a JSON stash while also passing them through to stdout, so a pipeline can save an intermediate stage and keep going. Example: smith read posts/*.md | smith markdown | smith tee build/posts.json | smith template"
+    )]
+    Tee {
+        #[arg(help = "File to write the stash to")]
         #[arg(value_name = "FILE")]
         file: PathBuf,
     },
 
+    #[command(
+        about = "Merge docs from JSON stashes into the stdin stream, de-duplicating by id_path. Docs from stdin come first, and the first doc with an id_path wins. Useful for joining generated docs, like tag archives, back into the main stream. Example: smith read posts/*.md | smith merge --stash build/archives.json | smith write public"
+    )]
+    Merge {
+        #[arg(long = "stash")]
+        #[arg(help = "JSON stash to merge in. Can be given more than once.")]
+        #[arg(value_name = "FILE")]
+        #[arg(required = true)]
+        stashes: Vec<PathBuf>,
+    },
+
     #[command(
         about = "Read docs from JSON stash. Deserializes the contents of the JSON and outputs docs to stdout."
     )]
     Unstash {
-        #[arg(help = "File path read stashed docs. Example: smith unstash build/posts.json")]
+        #[arg(
+            help = "File path read stashed docs. Use - to read from stdin. Example: smith unstash build/posts.json"
+        )]
         #[arg(value_name = "FILE")]
         file: PathBuf,
     },
 
+    #[command(
+        about = "Turn Markdown source docs into blog posts without templates: parse frontmatter, set permalinks, render Markdown, and absolutize URLs against site_url. Use smith blog to render templates too."
+    )]
+    MarkdownPost {
+        #[arg(long = "permalink-template")]
+        #[arg(
+            help = "Template for rendering permalinks. Defaults to permalink_template in config, or the template for the permalink style."
+        )]
+        permalink_template: Option<String>,
+
+        #[arg(long = "permalink-style")]
+        #[arg(
+            help = "Permalink style. Nice permalinks look like slug/index.html. Flat permalinks look like slug.html. Defaults to permalink_style in config."
+        )]
+        permalink_style: Option<PermalinkStyle>,
+    },
+
     #[command(about = "Render templates for blog posts or pages")]
     Blog {
         #[arg(long = "permalink-template")]
-        #[arg(default_value = "{parents}/{slug}/index.html")]
-        #[arg(help = "Template for rendering permalinks")]
-        permalink_template: String,
+        #[arg(
+            help = "Template for rendering permalinks. Defaults to permalink_template in config, or the template for the permalink style."
+        )]
+        permalink_template: Option<String>,
+
+        #[arg(long = "permalink-style")]
+        #[arg(
+            help = "Permalink style. Nice permalinks look like slug/index.html. Flat permalinks look like slug.html. Defaults to permalink_style in config."
+        )]
+        permalink_style: Option<PermalinkStyle>,
+
+        #[arg(
+            help = "JSON files or http(s) URLs to include in template context, keyed by file stem. URLs are cached for data_cache_ttl seconds. Example: smith template --data data/*.json"
+        )]
+        #[arg(long = "data")]
+        #[arg(value_name = "FILE")]
+        data: Vec<PathBuf>,
+    },
+
+    #[command(
+        about = "Convert docs to stubs. Stubs are lightweight docs without content, useful for index and feed pipelines where content isn't needed. Stubs can be piped into any other smith command."
+    )]
+    Stubs {},
+
+    #[command(
+        about = "Substitute site-wide tokens in content, such as {{site_url}} or :site_title:. Run before markdown to use base-URL-dependent links in content files."
+    )]
+    Tokens {
+        #[arg(long = "from-config")]
+        #[arg(
+            help = "Use tokens from config: site_url, site_title, site_description, site_author, and data.<key> for top-level data values"
+        )]
+        from_config: bool,
+
+        #[arg(long = "token", num_args = 1..)]
+        #[arg(help = "Extra tokens to substitute. Example: --token version=1.0")]
+        #[arg(value_name = "KEY=VALUE")]
+        tokens: Vec<String>,
+    },
+
+    #[command(
+        about = "Validate doc meta against the JSON Schemas in config. Schemas are keyed by a glob pattern matching doc id paths. Reports the path and violation for each invalid doc."
+    )]
+    Validate {
+        #[arg(long = "keep-going")]
+        #[arg(
+            help = "Report invalid docs to stderr and keep going, rather than stopping at the first invalid doc. Invalid docs are left out of the output."
+        )]
+        keep_going: bool,
+    },
+
+    #[command(
+        about = "Lint rendered HTML for accessibility problems: images without alt text, skipped heading levels, links without text, and duplicate ids. Reports each issue for each doc."
+    )]
+    LintA11y {
+        #[arg(long = "keep-going")]
+        #[arg(
+            help = "Report docs with issues to stderr and keep going, rather than stopping at the first. Docs with issues are left out of the output."
+        )]
+        keep_going: bool,
+    },
+
+    #[command(
+        about = "Publish drafts as previews, under preview/{token}/, where the token is derived from a secret key and the doc id. Previews get a noindex robots meta tag, and are left out of sitemaps and feeds. Drafts have a file name starting with an underscore, or draft: true in meta. Run after permalink."
+    )]
+    PreviewDrafts {
+        #[arg(long = "key")]
+        #[arg(help = "Secret key for preview paths. Defaults to preview_key in config.")]
+        key: Option<String>,
+    },
+
+    #[command(
+        about = "Preview a single post. Runs the blog pipeline (frontmatter, markdown, permalink, template) on one file and prints the rendered HTML, without building the whole site. Templates see only this doc in site.pages."
+    )]
+    Preview {
+        #[arg(help = "Text file to preview")]
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        #[arg(long = "open")]
+        #[arg(
+            help = "Write the HTML to a temporary file and open it in the default browser, instead of printing it"
+        )]
+        open: bool,
+
+        #[arg(long = "permalink-template")]
+        #[arg(
+            help = "Template for rendering the permalink. Defaults to permalink_template in config, or the template for the permalink style."
+        )]
+        permalink_template: Option<String>,
+
+        #[arg(long = "permalink-style")]
+        #[arg(help = "Permalink style. Defaults to permalink_style in config.")]
+        permalink_style: Option<PermalinkStyle>,
 
         #[arg(
-            help = "JSON files to include in template context. Example: smith template --data data/*.json"
+            help = "JSON files or http(s) URLs to include in template context, keyed by file stem"
         )]
         #[arg(long = "data")]
         #[arg(value_name = "FILE")]
@@ -85,6 +357,20 @@ enum Commands {
         #[arg(long = "asc")]
         #[arg(help = "Sort ascending?")]
         asc: bool,
+
+        #[arg(long = "spill-threshold")]
+        #[arg(
+            help = "Hold content larger than this many bytes in temp files while sorting, to reduce memory use on large sites"
+        )]
+        #[arg(value_name = "BYTES")]
+        spill_threshold: Option<usize>,
+
+        #[arg(long = "external")]
+        #[arg(
+            help = "Sort in chunks written to temp files, then merge them, so docs are never all held in memory at once"
+        )]
+        #[arg(conflicts_with = "spill_threshold")]
+        external: bool,
     },
 
     #[command(about = "Take up to n most recent docs")]
@@ -95,30 +381,226 @@ enum Commands {
         limit: usize,
     },
 
+    #[command(
+        about = "Give each index doc the most recent docs under its directory as stubs in meta.recent, newest first. Useful for \"latest posts\" lists on home and section pages."
+    )]
+    InjectRecent {
+        #[arg(help = "Number of recent docs to give each index doc")]
+        #[arg(value_name = "LIMIT")]
+        #[arg(default_value = "5")]
+        limit: usize,
+    },
+
     #[command(about = "Set permalink via a template")]
     Permalink {
         #[arg(long = "template")]
-        #[arg(default_value = "{parents}/{slug}/index.html")]
-        #[arg(help = "Template for rendering permalinks")]
-        permalink_template: String,
+        #[arg(
+            help = "Template for rendering permalinks. Defaults to the permalinks rules in config, then permalink_template, or the template for the permalink style."
+        )]
+        permalink_template: Option<String>,
+
+        #[arg(long = "style")]
+        #[arg(
+            help = "Permalink style. Nice permalinks look like slug/index.html. Flat permalinks look like slug.html. Defaults to permalink_style in config."
+        )]
+        permalink_style: Option<PermalinkStyle>,
+
+        #[arg(long = "collisions")]
+        #[arg(
+            help = "What to do when two docs produce the same permalink. Suffix appends -2, -3, etc. to later docs. Defaults to permalink_collisions in config."
+        )]
+        collisions: Option<PermalinkCollisions>,
+    },
+
+    #[command(
+        about = "Inline lines from source files into fenced code blocks, so docs stay in sync with real code. Example fence: ```rust file=src/main.rs lines=10-30. Run before smith markdown."
+    )]
+    Snippets {
+        #[arg(long = "root")]
+        #[arg(help = "Project root. Snippet paths are relative to it, and may not escape it.")]
+        #[arg(value_name = "DIR")]
+        #[arg(default_value = ".")]
+        root: PathBuf,
+    },
+
+    #[command(
+        about = "Convert bare URLs in content into links, skipping code. With --hashtags, also link #hashtags to their tag archive and add them to the taxonomy in meta. Run before smith markdown."
+    )]
+    Linkify {
+        #[arg(long = "hashtags")]
+        #[arg(help = "Also link #hashtags, and add them to the taxonomy in meta")]
+        hashtags: bool,
+
+        #[arg(long = "taxonomy")]
+        #[arg(help = "Meta key of the taxonomy to add hashtags to")]
+        #[arg(default_value = "tags")]
+        taxonomy: String,
+
+        #[arg(long = "tag-url")]
+        #[arg(help = "Template for hashtag links. Tokens: {taxonomy}, {term}")]
+        #[arg(value_name = "TEMPLATE")]
+        #[arg(default_value = "{taxonomy}/{term}/index.html")]
+        tag_url: String,
+    },
+
+    #[command(
+        about = "Add #hashtags in content to the taxonomy in meta, skipping code. Hashtags are normalized like tags. With --action, also strip hashtags from content, or link them to their tag archive. Run before smith markdown."
+    )]
+    Hashtags {
+        #[arg(long = "taxonomy")]
+        #[arg(help = "Meta key of the taxonomy to add hashtags to")]
+        #[arg(default_value = "tags")]
+        taxonomy: String,
+
+        #[arg(long = "action")]
+        #[arg(default_value = "keep")]
+        #[arg(help = "What to do with hashtags in content: keep, strip, or link")]
+        action: HashtagAction,
+
+        #[arg(long = "tag-url")]
+        #[arg(help = "Template for hashtag links. Tokens: {taxonomy}, {term}")]
+        #[arg(value_name = "TEMPLATE")]
+        #[arg(default_value = "{taxonomy}/{term}/index.html")]
+        tag_url: String,
+    },
+
+    #[command(
+        about = "Wrap rendered content in h-entry microformats markup for IndieWeb compatibility, with name, url, published date, author h-card, and categories from doc fields. Author is read from meta.author, falling back to site_author in config. Run after smith markdown and before smith template."
+    )]
+    HEntry {},
+
+    #[command(
+        about = "Split long docs into several pages at a marker in content. Each page gets its own output path, and meta.page with number, total, prev, next, and canonical output paths."
+    )]
+    SplitPages {
+        #[arg(long = "marker")]
+        #[arg(help = "Marker to split content at")]
+        #[arg(default_value = paginate::PAGE_MARKER)]
+        marker: String,
     },
 
     #[command(about = "Render markdown")]
-    Markdown {},
+    Markdown {
+        #[arg(long = "figures")]
+        #[arg(
+            help = "Render images that have a title, and that sit alone in their own paragraph, as figures with the title as a caption. Can also be enabled with markdown.figures in config."
+        )]
+        figures: bool,
+
+        #[arg(long = "footnotes")]
+        #[arg(help = "Render footnotes. Can also be enabled with markdown.footnotes in config.")]
+        footnotes: bool,
+
+        #[arg(long = "footnote-backrefs")]
+        #[arg(
+            help = "Link each footnote back to where it is referenced. Can also be enabled with markdown.footnote_backrefs in config."
+        )]
+        footnote_backrefs: bool,
+
+        #[arg(long = "footnote-popovers")]
+        #[arg(
+            help = "Repeat each footnote in an <aside> after the block that references it, for sidenote and popover themes. Can also be enabled with markdown.footnote_popovers in config."
+        )]
+        footnote_popovers: bool,
+
+        #[arg(long = "summary-paragraph")]
+        #[arg(
+            help = "Use the first paragraph as the summary, rather than the first 280 characters. Can also be enabled with markdown.summary_paragraph in config."
+        )]
+        summary_paragraph: bool,
+    },
+
+    #[command(
+        about = "Render Obsidian-style embeds. Image embeds like ![[cat.png]] become <img> tags, and the images are copied from the vault into the output directory. Note embeds like ![[Some Note]] are replaced with the content of the doc who's sluggified title matches. Run before smith wikilinks."
+    )]
+    Embeds {
+        #[arg(long = "vault")]
+        #[arg(help = "Vault directory to find embedded images in")]
+        #[arg(value_name = "DIR")]
+        #[arg(default_value = ".")]
+        vault: PathBuf,
+
+        #[arg(long = "output-dir")]
+        #[arg(
+            help = "Output directory to copy embedded images to. Defaults to output_dir in config."
+        )]
+        #[arg(value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+
+        #[arg(long = "assets-path")]
+        #[arg(help = "Path within the output directory for embedded images")]
+        #[arg(value_name = "PATH")]
+        #[arg(default_value = "assets")]
+        assets_path: String,
+    },
 
     #[command(
-        about = "Render wikilink markup for posts in this selection. Wikilinks will be linked to posts where the sluggified title matches the wikilink's slug."
+        about = "Render wikilink markup for posts in this selection. Wikilinks will be linked to posts where the sluggified title matches the wikilink's slug. Links point at each doc's output path as it is when wikilinks are rendered, so run after smith permalink, or pass --permalink to set permalinks first."
     )]
-    Wikilinks {},
+    Wikilinks {
+        #[arg(long = "permalink")]
+        #[arg(
+            help = "Set permalinks before rendering wikilinks, as smith permalink does. Uses the permalinks rules in config, unless --permalink-template or --permalink-style is given."
+        )]
+        permalink: bool,
+
+        #[arg(long = "permalink-template")]
+        #[arg(
+            help = "Template for rendering permalinks before rendering wikilinks. Implies --permalink."
+        )]
+        permalink_template: Option<String>,
+
+        #[arg(long = "permalink-style")]
+        #[arg(
+            help = "Permalink style to set before rendering wikilinks. Nice permalinks look like slug/index.html. Flat permalinks look like slug.html. Implies --permalink."
+        )]
+        permalink_style: Option<PermalinkStyle>,
+
+        #[arg(long = "collisions")]
+        #[arg(
+            help = "What to do when two docs produce the same permalink. Suffix appends -2, -3, etc. to later docs. Defaults to permalink_collisions in config."
+        )]
+        collisions: Option<PermalinkCollisions>,
 
-    #[command(about = "Render doc with the Tera template set on doc's template_path")]
+        #[arg(long = "resolve")]
+        #[arg(
+            help = "How to pick a doc when several docs share a title slug. A warning is printed for each shared slug."
+        )]
+        #[arg(value_name = "RESOLUTION")]
+        #[arg(default_value = "last")]
+        resolve: SlugResolution,
+
+        #[arg(long = "link-template")]
+        #[arg(
+            help = "Token template for wikilinks that resolve to a doc. Tokens: {text}, {slug}, {title}, {summary}, {output_path}, {permalink}, {canonical_url}. Defaults to wikilink_template in config."
+        )]
+        #[arg(value_name = "TEMPLATE")]
+        link_template: Option<String>,
+
+        #[arg(long = "nolink-template")]
+        #[arg(
+            help = "Token template for wikilinks that don't resolve to a doc. Tokens: {text}, {slug}. Defaults to nolink_template in config."
+        )]
+        #[arg(value_name = "TEMPLATE")]
+        nolink_template: Option<String>,
+    },
+
+    #[command(
+        about = "Render doc with the Tera template set on doc's template_path. Templates have access to doc, site (config), site.pages (stubs of every doc piped in), pages(glob, sort, asc, limit) to query those stubs, data, and nav (menus built from docs with menu meta)."
+    )]
     Template {
         #[arg(
-            help = "JSON files to include in template context. Example: smith template --data data/*.json"
+            help = "JSON files or http(s) URLs to include in template context, keyed by file stem. URLs are cached for data_cache_ttl seconds. Example: smith template --data data/*.json"
         )]
         #[arg(long = "data", num_args = 1..)]
         #[arg(value_name = "FILE")]
         data: Vec<PathBuf>,
+
+        #[arg(long = "cache")]
+        #[arg(
+            help = "Cache rendered output under data_cache_dir, so unchanged docs skip rendering on rebuilds. Can also be enabled with template_cache in config."
+        )]
+        cache: bool,
     },
 
     #[command(
@@ -132,56 +614,886 @@ enum Commands {
         #[arg(long = "taxonomy")]
         #[arg(default_value = "tags")]
         taxonomy: String,
+
+        #[arg(long = "limit")]
+        #[arg(help = "Maximum number of stubs to keep per term, most recent first")]
+        limit: Option<usize>,
     },
 
     #[command(
-        about = "Parse and uplift frontmatter. Frontmatter is parsed as YAML and assigned to doc meta. Blessed fields, such as title are assigned to the corresponding field on the doc."
+        about = "Fetch the RSS and Atom feeds listed in an OPML file, and output their entries as docs, with the entry link in meta.link and the feed in meta.feed. Feeds are fetched over HTTP, and local feed files are read as-is. Example: smith ingest-feeds feeds.opml"
     )]
-    Frontmatter {},
-}
+    IngestFeeds {
+        #[arg(help = "OPML file listing feeds")]
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
 
-/// Read all file paths to docs and stream JSON to stdout.
-fn main() {
-    let config_path = env::var("CONFIG").unwrap_or("lettersmith.json".to_string());
-    let config = Config::read(config_path).unwrap_or(Config::default());
-    let cli = Cli::parse();
+    #[command(
+        about = "Generate an RSS feed doc from the most recent docs, with the site, feed, and trailing_slash settings in config"
+    )]
+    Rss {
+        #[arg(long = "output-path")]
+        #[arg(help = "Output path for the feed. Defaults to feed_path in config.")]
+        #[arg(value_name = "FILE")]
+        output_path: Option<PathBuf>,
 
-    match cli.command {
-        Commands::Read { files } => read_cmd(files),
-        Commands::Write { output_dir } => write_cmd(output_dir.as_path()),
-        Commands::Stash { file } => stash_cmd(file.as_path()),
-        Commands::Unstash { file } => unstash_cmd(file),
-        Commands::Sort { key, asc } => sort_cmd(key, asc),
-        Commands::Recent { limit } => recent_cmd(limit),
-        Commands::Permalink { permalink_template } => permalink_cmd(&permalink_template),
-        Commands::Markdown {} => markdown_cmd(),
-        Commands::Wikilinks {} => wikilinks_cmd(),
-        Commands::Blog {
-            permalink_template,
-            data,
-        } => blog_cmd(&permalink_template, &data, &config),
-        Commands::Template { data } => template(&data, &config),
-        Commands::Tagindex {
-            output_path,
-            taxonomy,
-        } => tagindex_cmd(taxonomy, output_path),
-        Commands::Frontmatter {} => frontmatter_cmd(),
-    }
-}
+        #[arg(long = "limit")]
+        #[arg(help = "Number of most recent docs to include. Defaults to feed_items in config.")]
+        limit: Option<usize>,
 
-/// Read docs from paths
-fn read_cmd(files: Vec<PathBuf>) {
-    docs::read(files.into_iter())
-        .panic_at_first_error()
-        .write_stdio();
-}
+        #[arg(long = "taxonomy")]
+        #[arg(
+            help = "Generate a feed for each term in this meta taxonomy key, e.g. tags, rather than one feed for all docs"
+        )]
+        #[arg(conflicts_with = "output_path")]
+        taxonomy: Option<String>,
 
-/// Write docs as text files
-fn write_cmd(output_dir: &Path) {
-    docs::read_stdin().panic_at_first_error().write(output_dir);
-}
+        #[arg(long = "output-template")]
+        #[arg(help = "Output path template for per-term feeds. Tokens: {taxonomy}, {term}")]
+        #[arg(default_value = "{taxonomy}/{term}/feed.xml")]
+        output_template: String,
+    },
 
-/// Read docs from JSON file paths
+    #[command(
+        about = "Group docs by the value at a meta dot-path and generate an index doc for each group. Grouped docs are placed in the index doc's meta.items. Array values file a doc under each item. Example: smith groupby --meta category --output-template \"{term}/index.html\""
+    )]
+    Groupby {
+        #[arg(long = "meta")]
+        #[arg(help = "Dot-path of meta value to group by")]
+        #[arg(value_name = "PATH")]
+        meta: String,
+
+        #[arg(long = "output-template")]
+        #[arg(help = "Output path template for index docs. Tokens: {taxonomy}, {term}")]
+        #[arg(default_value = "{term}/index.html")]
+        output_template: String,
+
+        #[arg(long = "template")]
+        #[arg(help = "Template path to assign to index docs")]
+        #[arg(value_name = "FILE")]
+        template: Option<PathBuf>,
+
+        #[arg(long = "terms")]
+        #[arg(
+            help = "Glob of term pages to merge into index docs, e.g. \"tags/*.md\". A term page's content, frontmatter, and template are merged into the index doc who's term matches its file name."
+        )]
+        #[arg(value_name = "GLOB")]
+        terms: Option<PathBuf>,
+    },
+
+    #[cfg(feature = "script")]
+    #[command(
+        about = "Transform docs with a Rhai script. The script runs once per doc, with the doc exposed as a map in the doc variable. The value the script evaluates to replaces the doc, or if it evaluates to nothing, the modified doc variable is used. Example: smith script transform.rhai"
+    )]
+    Script {
+        #[arg(help = "Rhai script file")]
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    #[cfg(feature = "prose")]
+    #[command(
+        about = "Spellcheck doc content against a dictionary plus project word lists. Code blocks and inline code are skipped. Reports each unknown word in context for each doc."
+    )]
+    LintProse {
+        #[arg(long = "dictionary")]
+        #[arg(
+            help = "Dictionary word list, with one word per line. Hunspell .dic files also work, but affix flags are not expanded, so only the listed stems are known. Example: --dictionary /usr/share/dict/words"
+        )]
+        #[arg(value_name = "FILE")]
+        dictionary: PathBuf,
+
+        #[arg(long = "words")]
+        #[arg(help = "Project word list of extra known words, such as names. Can be repeated.")]
+        #[arg(value_name = "FILE")]
+        words: Vec<PathBuf>,
+
+        #[arg(long = "keep-going")]
+        #[arg(
+            help = "Report docs with unknown words to stderr and keep going, rather than stopping at the first. Docs with unknown words are left out of the output."
+        )]
+        keep_going: bool,
+    },
+
+    #[command(
+        about = "Run pipelines declared in the pipelines section of config, in-process. Faster than piping smith commands, since docs aren't re-serialized between steps. Runs every pipeline, in name order, unless a name is given."
+    )]
+    Build {
+        #[arg(help = "Name of the pipeline to run")]
+        #[arg(value_name = "NAME")]
+        name: Option<String>,
+    },
+
+    #[command(
+        about = "Show or check the effective config, after merging the selected profile and LETTERSMITH_* environment variable overrides (e.g. LETTERSMITH_SITE_URL) over the config file. Prints the config as JSON by default."
+    )]
+    Config {
+        #[arg(long = "check")]
+        #[arg(
+            help = "Validate config: site_url is an absolute URL or /, the templates glob matches files, and output_dir is not inside a content directory read by pipelines. Exits with an error if any check fails."
+        )]
+        check: bool,
+
+        #[arg(long = "print")]
+        #[arg(help = "Print the effective config as JSON")]
+        print: bool,
+    },
+
+    #[command(
+        about = "Check the project setup: config parses, the templates glob matches files, templates compile, data files exist, and the output directory is writable. Exits with an error if any check fails."
+    )]
+    Doctor {
+        #[arg(long = "data", num_args = 1..)]
+        #[arg(help = "JSON data files your build passes to --data")]
+        #[arg(value_name = "FILE")]
+        data: Vec<PathBuf>,
+
+        #[arg(long = "output-dir")]
+        #[arg(help = "Directory your build writes docs to. Defaults to output_dir in config.")]
+        #[arg(value_name = "DIRECTORY")]
+        output_dir: Option<PathBuf>,
+    },
+
+    #[command(
+        about = "Generate a starter project with a config file, templates, an example post, and a build script. Existing files are left untouched."
+    )]
+    Init {
+        #[arg(help = "Directory to create the project in")]
+        #[arg(value_name = "DIRECTORY")]
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+
+    #[command(
+        about = "Import a Jekyll or Hugo project. Writes lettersmith.json from the project's site and permalink settings, and content files with frontmatter converted to Lettersmith conventions. Reports anything that needs manual attention, such as template tags in content and layouts. Example: smith import jekyll ../old-blog --output-dir ."
+    )]
+    Import {
+        #[arg(help = "Static site generator the project was built with")]
+        #[arg(value_name = "SOURCE")]
+        source: ImportSource,
+
+        #[arg(help = "Project directory to import")]
+        #[arg(value_name = "DIRECTORY")]
+        dir: PathBuf,
+
+        #[arg(long = "output-dir")]
+        #[arg(help = "Directory to write the imported project to")]
+        #[arg(value_name = "DIRECTORY")]
+        #[arg(default_value = ".")]
+        output_dir: PathBuf,
+    },
+
+    #[command(
+        about = "Create a new post file with pre-filled frontmatter. The file is named after the sluggified title. Example: smith new \"My Post\" --dir posts"
+    )]
+    New {
+        #[arg(help = "Title of the new post")]
+        #[arg(value_name = "TITLE")]
+        title: String,
+
+        #[arg(long = "dir")]
+        #[arg(default_value = "posts")]
+        #[arg(help = "Directory to create the post in")]
+        #[arg(value_name = "DIRECTORY")]
+        dir: PathBuf,
+
+        #[arg(long = "template")]
+        #[arg(
+            help = "Archetype template to create the post from. Variables like {title}, {slug} and {created} are filled in. Example: smith new \"My Post\" --template archetypes/post.md"
+        )]
+        #[arg(value_name = "FILE")]
+        template: Option<PathBuf>,
+    },
+
+    #[command(
+        about = "Filter docs by the value at a dot-path. By default, keeps docs where the value exists and is not null or false. Example: smith query meta.tags --contains rust"
+    )]
+    Query {
+        #[arg(help = "Dot-path to a doc field. Example: meta.author.name")]
+        #[arg(value_name = "PATH")]
+        path: String,
+
+        #[arg(long = "eq")]
+        #[arg(conflicts_with = "contains")]
+        #[arg(
+            help = "Keep docs where value equals this value. Parsed as JSON if possible, otherwise as a string."
+        )]
+        #[arg(value_name = "VALUE")]
+        eq: Option<String>,
+
+        #[arg(long = "contains")]
+        #[arg(
+            help = "Keep docs where value is an array containing this value, a string containing this substring, or an object containing this key"
+        )]
+        #[arg(value_name = "VALUE")]
+        contains: Option<String>,
+
+        #[arg(long = "not")]
+        #[arg(help = "Invert the query, removing matching docs instead")]
+        not: bool,
+    },
+
+    #[command(
+        about = "Project docs into reduced JSON objects containing only the given fields. Useful at the end of a pipeline when only metadata is needed. Note that projected objects are not full docs, and can't be piped into other smith commands. Example: smith project --fields id_path,title,meta.tags"
+    )]
+    Project {
+        #[arg(long = "fields", value_delimiter = ',', required = true)]
+        #[arg(help = "Comma-separated dot-paths of fields to keep")]
+        #[arg(value_name = "FIELDS")]
+        fields: Vec<String>,
+    },
+
+    #[command(
+        about = "Rewrite doc output paths with a regular expression. The first match in each output path is replaced. Replacements may refer to capture groups, e.g. $1. Example: smith rename --from \"^notes/\" --to \"garden/\""
+    )]
+    Rename {
+        #[arg(long = "from")]
+        #[arg(help = "Regular expression to match in output paths")]
+        #[arg(value_name = "REGEX")]
+        from: String,
+
+        #[arg(long = "to")]
+        #[arg(help = "Replacement for the matched text")]
+        #[arg(value_name = "REPLACEMENT")]
+        to: String,
+    },
+
+    #[command(
+        about = "Replace every match of a regular expression in doc content, or in another text field with --in. Replacements may refer to capture groups, e.g. $1. Example: smith replace --regex 'http://old\\.example' --with 'https://new.example'"
+    )]
+    Replace {
+        #[arg(long = "regex")]
+        #[arg(help = "Regular expression to match")]
+        #[arg(value_name = "REGEX")]
+        regex: String,
+
+        #[arg(long = "with")]
+        #[arg(help = "Replacement for the matched text")]
+        #[arg(value_name = "REPLACEMENT")]
+        with: String,
+
+        #[arg(long = "in")]
+        #[arg(
+            help = "Field to replace in: content, title, summary, or a meta dot-path like meta.description"
+        )]
+        #[arg(value_name = "FIELD")]
+        #[arg(default_value = "content")]
+        field: String,
+    },
+
+    #[command(
+        about = "Apply an RFC 7396 JSON merge patch to the meta of docs. Keys in the patch are added or replaced, and keys set to null are removed. Example: smith merge-meta patch.json --matching \"posts/**\""
+    )]
+    MergeMeta {
+        #[arg(help = "JSON file containing the merge patch")]
+        #[arg(value_name = "FILE")]
+        patch: PathBuf,
+
+        #[arg(long = "matching")]
+        #[arg(help = "Only patch docs who's id_path matches this glob pattern")]
+        #[arg(value_name = "GLOB")]
+        matching: Option<String>,
+    },
+
+    #[command(
+        about = "Merge static comment data into meta.comments, so templates can render comments. Comment data is a JSON object mapping doc paths to arrays of comments. Keys may be id paths, output paths, public paths, or full URLs. Example: smith comments comments.json"
+    )]
+    Comments {
+        #[arg(help = "JSON file of comments, keyed by doc path")]
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+
+    #[command(
+        about = "Add feed autodiscovery, canonical link, and meta description tags to the <head> of rendered pages, from config and doc meta. Tags a page already has are left alone. Run after template."
+    )]
+    Head {},
+
+    #[command(
+        about = "Rewrite HTML in doc content using the html_rewrite rules in config. Rules match elements with a CSS selector, then set, default, or remove attributes and add classes. Example config: \"html_rewrite\": [{\"selector\": \"a[href^=http]\", \"set\": {\"rel\": \"noopener\", \"target\": \"_blank\"}}]"
+    )]
+    RewriteHtml {},
+
+    #[command(
+        about = "Compute meta fields from token templates in the computed section of config. Example config: \"computed\": {\"canonical\": \"{site_url}/{output_path}\"}"
+    )]
+    Compute {},
+
+    #[command(
+        about = "Generate an iCalendar file from event docs. Docs are included if they have an event.start date in meta. event.end and event.location are also supported."
+    )]
+    Ics {
+        #[arg(help = "Output path for the calendar file")]
+        #[arg(value_name = "FILE")]
+        #[arg(default_value = "events.ics")]
+        output_path: PathBuf,
+    },
+
+    #[command(
+        about = "Generate a link graph of wikilinks and internal hrefs between docs, for graph views. Outputs a single doc containing the graph as JSON (nodes and edges) or Graphviz DOT."
+    )]
+    Graph {
+        #[arg(long = "format")]
+        #[arg(default_value = "json")]
+        #[arg(help = "Graph file format")]
+        format: GraphFormat,
+
+        #[arg(long = "output-path")]
+        #[arg(help = "Output path for the graph file. Defaults to graph.json or graph.dot.")]
+        #[arg(value_name = "FILE")]
+        output_path: Option<PathBuf>,
+    },
+
+    #[command(
+        about = "Generate an internal site report: posts per year, docs per taxonomy term, word counts, and broken internal links, plus build stats. Outputs a single doc containing the report as an HTML page or JSON."
+    )]
+    Report {
+        #[arg(long = "format")]
+        #[arg(default_value = "html")]
+        #[arg(help = "Report file format")]
+        format: SiteReportFormat,
+
+        #[arg(long = "taxonomy")]
+        #[arg(default_value = "tags")]
+        #[arg(help = "Meta key of the taxonomy to count docs by term")]
+        taxonomy: String,
+
+        #[arg(long = "output-path")]
+        #[arg(
+            help = "Output path for the report file. Defaults to _report/index.html or _report/report.json."
+        )]
+        #[arg(value_name = "FILE")]
+        output_path: Option<PathBuf>,
+    },
+
+    #[command(
+        about = "Generate a hosting redirects file (Netlify _redirects or vercel.json) from doc aliases and the redirects table in config. Docs list old paths in an aliases meta field."
+    )]
+    Redirects {
+        #[arg(long = "format")]
+        #[arg(default_value = "netlify")]
+        #[arg(help = "Redirects file format")]
+        format: RedirectsFormat,
+    },
+
+    #[command(
+        about = "Parse and uplift frontmatter. Frontmatter is parsed as YAML and assigned to doc meta. Blessed fields, such as title are assigned to the corresponding field on the doc."
+    )]
+    Frontmatter {
+        #[arg(long = "format")]
+        #[arg(
+            help = "Frontmatter formats to recognize: yaml (---), toml (+++) or json (;;;). Can be given multiple times. Defaults to all formats."
+        )]
+        #[arg(value_name = "FORMAT")]
+        formats: Vec<FrontmatterFormat>,
+    },
+}
+
+/// Error handler for this run, set once config and args are loaded
+static ERROR_HANDLER: OnceLock<ErrorHandler> = OnceLock::new();
+
+fn error_handler() -> &'static ErrorHandler {
+    ERROR_HANDLER.get_or_init(ErrorHandler::default)
+}
+
+/// Report an error with this run's error handler. If the policy is to
+/// abort, the handler remembers, and `main` exits with an error status once
+/// output has been flushed.
+fn report_error(err: Error) {
+    let _ = error_handler().handle(err);
+}
+
+/// Report an error loading config, in the error format given on the command
+/// line, and exit with an error status. Config errors are reported before
+/// this run's error handler is set up, since it depends on config.
+fn exit_with_config_error(err: Error, format: Option<ErrorFormat>, stage: &str) -> ! {
+    let handler = ErrorHandler::new(OnError::Abort, format.unwrap_or_default(), stage);
+    let _ = handler.handle(err);
+    std::process::exit(1);
+}
+
+/// Whether to read stdin in strict mode, set once config and args are loaded
+static STRICT_STDIN: OnceLock<bool> = OnceLock::new();
+
+/// Read docs from stdin, in strict mode if set for this run
+fn read_stdin() -> impl DocResults {
+    docs::read_stdin_with(*STRICT_STDIN.get().unwrap_or(&false))
+}
+
+/// Create an in-process pipeline that reports errors with this run's
+/// error handler
+fn pipeline(config: &Config) -> Pipeline {
+    Pipeline::new(config.clone()).error_handler(error_handler().clone())
+}
+
+/// Read all file paths to docs and stream JSON to stdout.
+fn main() {
+    // Library warnings are emitted as `tracing` events. Print them to stderr.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_max_level(tracing::Level::WARN)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let matches = Cli::command().get_matches();
+    let stage = matches.subcommand_name().unwrap_or_default().to_string();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let config_path = env::var("CONFIG").unwrap_or("lettersmith.json".to_string());
+    let profile = cli.profile.clone().or(env::var("PROFILE").ok());
+
+    // Doctor loads config itself, so it can report config errors rather than
+    // panicking on them.
+    if let Commands::Doctor { data, output_dir } = &cli.command {
+        doctor_cmd(
+            Path::new(&config_path),
+            profile.as_deref(),
+            data,
+            output_dir.as_deref(),
+        );
+        return;
+    }
+
+    if let Commands::Config { check, print } = &cli.command {
+        config_cmd(&config_path, profile.as_deref(), *check, *print);
+        return;
+    }
+
+    let config = match (
+        Config::read_with_profile(&config_path, profile.as_deref()),
+        &profile,
+    ) {
+        (Ok(config), _) => config,
+        // A missing profile is a mistake we should surface, rather than
+        // silently building with the wrong settings.
+        (Err(mut err), Some(profile)) => {
+            err.msg = format!(
+                "Could not load config from {} with profile \"{}\": {}",
+                config_path, profile, err.msg
+            );
+            exit_with_config_error(err, cli.error_format, &stage)
+        }
+        (Err(_), None) => Config::default(),
+    };
+    let mut config = config
+        .merge_env(env::vars())
+        .unwrap_or_else(|err| exit_with_config_error(err, cli.error_format, &stage));
+    config.strict_stdin |= cli.strict;
+    STRICT_STDIN
+        .set(config.strict_stdin)
+        .expect("Strict stdin should only be set once");
+    ERROR_HANDLER
+        .set(ErrorHandler::new(
+            cli.on_error.unwrap_or(config.on_error),
+            cli.error_format.unwrap_or(config.error_format),
+            stage,
+        ))
+        .expect("Error handler should only be set once");
+
+    let stats_format = cli.stats;
+    let start = Instant::now();
+
+    match cli.command {
+        Commands::Read {
+            files, stubs: true, ..
+        } => read_stubs_cmd(files),
+        Commands::Read {
+            files, max_size, ..
+        } => read_cmd(files, max_size.unwrap_or(config.max_file_size)),
+        Commands::Bundles { output_dir } => {
+            bundles_cmd(output_dir.as_deref().unwrap_or(&config.output_dir))
+        }
+        Commands::Write {
+            output_dir,
+            frontmatter,
+            bundles,
+            manifest,
+            headers,
+            remove_drafts,
+            remove_index,
+        } => write_cmd(
+            output_dir.as_deref().unwrap_or(&config.output_dir),
+            frontmatter,
+            bundles,
+            manifest.as_deref(),
+            headers,
+            remove_drafts,
+            remove_index,
+            &config,
+        ),
+        Commands::Hash {} => hash_cmd(),
+        Commands::AssignIds {} => assign_ids_cmd(),
+        Commands::Stash {
+            file,
+            remove_drafts,
+            remove_index,
+        } => stash_cmd(file.as_path(), remove_drafts, remove_index),
+        Commands::Tee { file } => tee_cmd(&file),
+        Commands::Merge { stashes } => merge_cmd(&stashes),
+        Commands::Unstash { file } => unstash_cmd(file),
+        Commands::Stubs {} => stubs_cmd(),
+        Commands::Tokens {
+            from_config,
+            tokens,
+        } => tokens_cmd(from_config, &tokens, &config),
+        Commands::Validate { keep_going } => validate_cmd(keep_going, &config),
+        Commands::LintA11y { keep_going } => lint_a11y_cmd(keep_going),
+        Commands::PreviewDrafts { key } => preview_drafts_cmd(key, &config),
+        Commands::Preview {
+            file,
+            open,
+            permalink_template,
+            permalink_style,
+            data,
+        } => preview_cmd(
+            file,
+            open,
+            &resolve_permalink_template(permalink_template, permalink_style, &config),
+            &data,
+            &config,
+        ),
+        Commands::Sort {
+            key,
+            asc,
+            spill_threshold,
+            external,
+        } => sort_cmd(key, asc, spill_threshold, external),
+        Commands::Recent { limit } => recent_cmd(limit),
+        Commands::InjectRecent { limit } => inject_recent_cmd(limit),
+        Commands::Permalink {
+            permalink_template,
+            permalink_style,
+            collisions,
+        } => permalink_cmd(
+            permalink_template,
+            permalink_style,
+            collisions.unwrap_or(config.permalink_collisions),
+            &config,
+        ),
+        Commands::Embeds {
+            vault,
+            output_dir,
+            assets_path,
+        } => embeds_cmd(
+            &vault,
+            output_dir.as_deref().unwrap_or(&config.output_dir),
+            &assets_path,
+        ),
+        Commands::Linkify {
+            hashtags,
+            taxonomy,
+            tag_url,
+        } => linkify_cmd(hashtags, &taxonomy, &tag_url),
+        Commands::Hashtags {
+            taxonomy,
+            action,
+            tag_url,
+        } => hashtags_cmd(&taxonomy, action, &tag_url),
+        Commands::HEntry {} => h_entry_cmd(&config),
+        Commands::SplitPages { marker } => split_pages_cmd(&marker),
+        Commands::Snippets { root } => snippets_cmd(&root),
+        Commands::Markdown {
+            figures,
+            footnotes,
+            footnote_backrefs,
+            footnote_popovers,
+            summary_paragraph,
+        } => markdown_cmd(
+            &MarkdownOptions {
+                figures,
+                footnotes,
+                footnote_backrefs,
+                footnote_popovers,
+                summary_paragraph,
+            },
+            &config,
+        ),
+        Commands::Wikilinks {
+            permalink,
+            permalink_template,
+            permalink_style,
+            collisions,
+            resolve,
+            link_template,
+            nolink_template,
+        } => {
+            let permalinks = (permalink
+                || permalink_template.is_some()
+                || permalink_style.is_some())
+            .then_some(PermalinkOptions {
+                template: permalink_template,
+                style: permalink_style,
+                collisions: collisions.unwrap_or(config.permalink_collisions),
+            });
+            wikilinks_cmd(permalinks, resolve, link_template, nolink_template, &config)
+        }
+        Commands::MarkdownPost {
+            permalink_template,
+            permalink_style,
+        } => markdown_post_cmd(
+            &resolve_permalink_template(permalink_template, permalink_style, &config),
+            &config,
+        ),
+        Commands::Blog {
+            permalink_template,
+            permalink_style,
+            data,
+        } => blog_cmd(
+            &resolve_permalink_template(permalink_template, permalink_style, &config),
+            &data,
+            &config,
+        ),
+        Commands::Template { data, cache } => template(&data, cache, &config),
+        Commands::Tagindex {
+            output_path,
+            taxonomy,
+            limit,
+        } => tagindex_cmd(taxonomy, output_path, limit),
+        Commands::IngestFeeds { file } => ingest_feeds_cmd(&file),
+        Commands::Rss {
+            output_path,
+            limit,
+            taxonomy,
+            output_template,
+        } => rss_cmd(output_path, limit, taxonomy, &output_template, &config),
+        Commands::Groupby {
+            meta,
+            output_template,
+            template,
+            terms,
+        } => groupby_cmd(&meta, &output_template, template, terms),
+        Commands::Frontmatter { formats } => frontmatter_cmd(&formats),
+        Commands::Query {
+            path,
+            eq,
+            contains,
+            not,
+        } => query_cmd(&path, eq, contains, not),
+        Commands::Project { fields } => project_cmd(&fields),
+        Commands::Rename { from, to } => rename_cmd(&from, &to),
+        Commands::Replace { regex, with, field } => replace_cmd(&regex, &with, &field),
+        Commands::MergeMeta { patch, matching } => merge_meta_cmd(&patch, matching.as_deref()),
+        Commands::Comments { file } => comments_cmd(&file, &config),
+        Commands::Head {} => head_cmd(&config),
+        Commands::RewriteHtml {} => rewrite_html_cmd(&config),
+        Commands::Compute {} => compute_cmd(&config),
+        Commands::Ics { output_path } => ics_cmd(&output_path, &config),
+        Commands::Graph {
+            format,
+            output_path,
+        } => graph_cmd(format, output_path, &config),
+        Commands::Report {
+            format,
+            taxonomy,
+            output_path,
+        } => report_cmd(format, &taxonomy, output_path, &config),
+        Commands::Redirects { format } => redirects_cmd(format, &config),
+        Commands::Build { name } => build_cmd(name, &config),
+        #[cfg(feature = "script")]
+        Commands::Script { file } => script_cmd(&file),
+        #[cfg(feature = "prose")]
+        Commands::LintProse {
+            dictionary,
+            words,
+            keep_going,
+        } => lint_prose_cmd(&dictionary, &words, keep_going),
+        Commands::Doctor { .. } => unreachable!("Doctor is handled before config is loaded"),
+        Commands::Config { .. } => unreachable!("Config is handled before config is loaded"),
+        Commands::Init { dir } => init_cmd(&dir),
+        Commands::Import {
+            source,
+            dir,
+            output_dir,
+        } => import_cmd(source, &dir, &output_dir),
+        Commands::New {
+            title,
+            dir,
+            template,
+        } => new_cmd(&title, &dir, template.as_deref()),
+    }
+
+    if let Some(format) = stats_format {
+        stats::record_stage(&error_handler().stage, start.elapsed());
+        eprintln!("{}", BuildReport::snapshot().render(format));
+    }
+    if error_handler().aborted() {
+        std::process::exit(1);
+    }
+}
+
+/// Use explicit permalink template if given, otherwise fall back to the
+/// template for the permalink style given on the CLI, or else to the default
+/// permalink template in config.
+fn resolve_permalink_template(
+    template: Option<String>,
+    style: Option<PermalinkStyle>,
+    config: &Config,
+) -> String {
+    template
+        .or_else(|| style.map(|style| style.template().to_string()))
+        .unwrap_or_else(|| config.default_permalink_template())
+}
+
+/// Read docs from paths
+fn read_cmd(files: Vec<PathBuf>, max_size: u64) {
+    let files = docs::expand_globs(files).unwrap();
+    docs::read_with_max_size(files.into_iter(), max_size)
+        .skip_unreadable_files()
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Record page bundle assets for docs read from index files
+fn bundles_cmd(output_dir: &Path) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .read_bundle_assets(output_dir)
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Read stubs from files, without loading content
+fn read_stubs_cmd(files: Vec<PathBuf>) {
+    let files = docs::expand_globs(files).unwrap();
+    docs::read_stubs(files.into_iter())
+        .map_while(|result| match result {
+            Ok(stub) => Some(Some(stub)),
+            Err(err) => error_handler().handle(err).ok().map(|_| None),
+        })
+        .flatten()
+        .write_stdio();
+}
+
+/// Leave out drafts and index docs, when asked to.
+/// See `Docs::remove_drafts` and `Docs::remove_index`.
+fn remove_docs<'a>(
+    docs: impl Docs + 'a,
+    remove_drafts: bool,
+    remove_index: bool,
+) -> Box<dyn Iterator<Item = Doc> + 'a> {
+    let mut docs: Box<dyn Iterator<Item = Doc> + 'a> = Box::new(docs);
+    if remove_drafts {
+        docs = Box::new(docs.remove_drafts());
+    }
+    if remove_index {
+        docs = Box::new(docs.remove_index());
+    }
+    docs
+}
+
+/// Write docs as text files
+#[allow(clippy::too_many_arguments)]
+fn write_cmd(
+    output_dir: &Path,
+    frontmatter: bool,
+    bundles: bool,
+    manifest_path: Option<&Path>,
+    headers: bool,
+    remove_drafts: bool,
+    remove_index: bool,
+    config: &Config,
+) {
+    let docs = remove_docs(
+        read_stdin().handle_errors(error_handler()),
+        remove_drafts,
+        remove_index,
+    );
+    if bundles {
+        print_write_report(docs.write_bundles(output_dir));
+    } else if frontmatter {
+        print_write_report(docs.write_with_frontmatter(output_dir));
+    } else if manifest_path.is_some() || headers {
+        let mut manifest = Manifest::default();
+        print_write_report(docs.inspect(|doc| manifest.insert(doc)).write(output_dir));
+        if let Some(manifest_path) = manifest_path {
+            manifest.write(manifest_path).unwrap();
+        }
+        if headers {
+            write_file_deep(
+                output_dir.join("_headers"),
+                &manifest.render_headers(config.trailing_slash),
+            )
+            .unwrap();
+        }
+    } else {
+        print_write_report(docs.write(output_dir));
+    }
+}
+
+/// Print write errors with this run's error handler, followed by a summary
+fn print_write_report(report: WriteReport) {
+    let summary = report.summary();
+    for err in report.failed {
+        report_error(err);
+    }
+    println!("{}", summary);
+}
+
+/// Assign stable ids in meta
+fn assign_ids_cmd() {
+    read_stdin()
+        .handle_errors(error_handler())
+        .assign_ids()
+        .write_stdio();
+}
+
+/// Record content hashes in meta
+fn hash_cmd() {
+    read_stdin()
+        .handle_errors(error_handler())
+        .set_meta_hash()
+        .write_stdio();
+}
+
+/// Write docs to a stash, passing them through to stdout.
+/// Docs are written to the stash as they pass through.
+fn tee_cmd(file: &Path) {
+    if stash::is_stdio_path(file) {
+        report_error(Error::value(
+            "Can't tee to stdout, since docs are already written there. Give a file path.",
+        ));
+        return;
+    }
+    let mut stash = match stash::StashWriter::create(file) {
+        Ok(stash) => stash,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
+    let mut stash_error = None;
+    {
+        let mut stream = read_stdin().handle_errors(error_handler()).inspect(|doc| {
+            if stash_error.is_none() {
+                stash_error = stash.write(doc).err();
+            }
+        });
+        (&mut stream).write_stdio();
+        // Writing to stdout ends early if the reader closes the pipe.
+        // Keep reading, so the stash still gets every doc.
+        stream.for_each(drop);
+    }
+    let result = match stash_error {
+        Some(err) => Err(err),
+        None => stash.finish(),
+    };
+    if let Err(err) = result {
+        report_error(err);
+    }
+}
+
+/// Merge stashed docs into the stdin stream
+fn merge_cmd(stashes: &[PathBuf]) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .merge_stashes(stashes)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Read docs from JSON file paths
 fn unstash_cmd(file: PathBuf) {
     stash::read(file.as_path())
         .unwrap()
@@ -190,95 +1502,762 @@ fn unstash_cmd(file: PathBuf) {
 }
 
 /// Write docs as JSON file
-fn stash_cmd(output_dir: &Path) {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .write_stash(output_dir)
-        .unwrap();
+fn stash_cmd(output_dir: &Path, remove_drafts: bool, remove_index: bool) {
+    remove_docs(
+        read_stdin().handle_errors(error_handler()),
+        remove_drafts,
+        remove_index,
+    )
+    .write_stash(output_dir)
+    .unwrap();
 }
 
-fn sort_cmd(key: SortKey, asc: bool) {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .sorted_by(key, asc)
+/// Convert docs to stubs
+fn stubs_cmd() {
+    read_stdin()
+        .handle_errors(error_handler())
+        .to_stubs()
         .write_stdio();
 }
 
+/// Substitute site-wide tokens in content
+fn tokens_cmd(from_config: bool, extra_tokens: &[String], config: &Config) {
+    let mut tokens = if from_config {
+        config.get_tokens()
+    } else {
+        HashMap::new()
+    };
+    for token in extra_tokens {
+        let (key, value) = token
+            .split_once('=')
+            .expect("Tokens must be formatted as KEY=VALUE");
+        tokens.insert(key.to_string(), value.to_string());
+    }
+    read_stdin()
+        .handle_errors(error_handler())
+        .substitute_tokens(&tokens)
+        .write_stdio();
+}
+
+/// Validate doc meta against schemas in config
+fn validate_cmd(keep_going: bool, config: &Config) {
+    let schemas = MetaSchemas::compile(&config.schemas).unwrap();
+    let results = read_stdin()
+        .handle_errors(error_handler())
+        .validate_meta(&schemas);
+    if keep_going {
+        let handler = error_handler().with_on_error(OnError::Skip);
+        results.handle_errors(&handler).write_stdio();
+    } else {
+        results.handle_errors(error_handler()).write_stdio();
+    }
+}
+
+/// Lint rendered HTML for accessibility problems
+fn lint_a11y_cmd(keep_going: bool) {
+    let results = read_stdin().handle_errors(error_handler()).lint_a11y();
+    if keep_going {
+        let handler = error_handler().with_on_error(OnError::Skip);
+        results.handle_errors(&handler).write_stdio();
+    } else {
+        results.handle_errors(error_handler()).write_stdio();
+    }
+}
+
+/// Publish drafts under preview paths
+fn preview_drafts_cmd(key: Option<String>, config: &Config) {
+    let key = key.unwrap_or_else(|| config.preview_key.clone());
+    if key.is_empty() {
+        report_error(Error::value(
+            "A preview key is required. Set preview_key in config, or pass --key.",
+        ));
+        return;
+    }
+    read_stdin()
+        .handle_errors(error_handler())
+        .preview_drafts(&key)
+        .write_stdio();
+}
+
+/// Render a single file through the blog pipeline, and print or open it
+fn preview_cmd(
+    file: PathBuf,
+    open: bool,
+    permalink_template: &str,
+    data_files: &[PathBuf],
+    config: &Config,
+) {
+    let data = match json::read_data_map(data_files, &config.fetch_cache()) {
+        Ok(data) => data,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
+    let site_url = config.site_url.clone();
+    let docs: Vec<Doc> = pipeline(config)
+        .doc_results(docs::read_with_max_size(
+            std::iter::once(file),
+            config.max_file_size,
+        ))
+        .frontmatter()
+        .markdown()
+        .permalink(permalink_template)
+        .then(move |docs| docs.map(move |doc| doc.absolutize_urls(&site_url)))
+        .data(data)
+        .template()
+        .into_docs()
+        .collect();
+    let Some(doc) = docs.into_iter().next() else {
+        return;
+    };
+    if !open {
+        println!("{}", doc.content);
+        return;
+    }
+    let path = env::temp_dir()
+        .join("lettersmith-preview")
+        .join(format!("{}.html", doc.get_id().replace(['/', '\\'], "_")));
+    let result = write_file_deep(&path, &doc.content).and_then(|_| open_in_browser(&path));
+    match result {
+        Ok(_) => eprintln!("Opened {}", path.display()),
+        Err(err) => report_error(err),
+    }
+}
+
+/// Open a file with the system's default handler, usually a browser for
+/// HTML files
+fn open_in_browser(path: &Path) -> Result<(), Error> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener).arg(path).spawn()?;
+    Ok(())
+}
+
+fn sort_cmd(key: SortKey, asc: bool, spill_threshold: Option<usize>, external: bool) {
+    let docs = read_stdin().handle_errors(error_handler());
+    if external {
+        docs.sorted_by_external(key, asc, &env::temp_dir())
+            .handle_errors(error_handler())
+            .write_stdio();
+        return;
+    }
+    match spill_threshold {
+        Some(threshold) => docs
+            .sorted_by_spilling(key, asc, threshold, &env::temp_dir())
+            .handle_errors(error_handler())
+            .write_stdio(),
+        None => docs.sorted_by(key, asc).write_stdio(),
+    }
+}
+
 fn recent_cmd(limit: usize) {
-    docs::read_stdin()
-        .panic_at_first_error()
+    read_stdin()
+        .handle_errors(error_handler())
         .most_recent(limit)
         .write_stdio();
 }
 
-fn markdown_cmd() {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .render_markdown()
+/// Inject recent docs into index docs
+fn inject_recent_cmd(limit: usize) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .inject_recent_into_index(limit)
+        .write_stdio();
+}
+
+/// Inline file snippets into code blocks
+fn snippets_cmd(root: &Path) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .include_snippets(root)
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Link bare URLs, and optionally hashtags
+fn linkify_cmd(hashtags: bool, taxonomy: &str, tag_url: &str) {
+    let docs = read_stdin().handle_errors(error_handler()).linkify_urls();
+    if hashtags {
+        docs.linkify_hashtags(taxonomy, tag_url).write_stdio();
+    } else {
+        docs.write_stdio();
+    }
+}
+
+/// Extract hashtags into the taxonomy in meta
+fn hashtags_cmd(taxonomy: &str, action: HashtagAction, tag_url: &str) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .extract_hashtags(taxonomy, action, tag_url)
+        .write_stdio();
+}
+
+/// Wrap docs in h-entry markup
+fn h_entry_cmd(config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .wrap_h_entry(&config.site_url, config.trailing_slash, &config.site_author)
+        .write_stdio();
+}
+
+/// Split docs into pages
+fn split_pages_cmd(marker: &str) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .split_pages(marker)
+        .write_stdio();
+}
+
+/// Render markdown, with options enabled by flags or config
+fn markdown_cmd(flags: &MarkdownOptions, config: &Config) {
+    let mut config = config.clone();
+    let options = &mut config.markdown;
+    options.figures = options.figures || flags.figures;
+    options.footnotes = options.footnotes || flags.footnotes;
+    options.footnote_backrefs = options.footnote_backrefs || flags.footnote_backrefs;
+    options.footnote_popovers = options.footnote_popovers || flags.footnote_popovers;
+    options.summary_paragraph = options.summary_paragraph || flags.summary_paragraph;
+    pipeline(&config).read_stdin().markdown().write_stdio();
+}
+
+/// Render embeds, copying embedded images into the output directory
+fn embeds_cmd(vault: &Path, output_dir: &Path, assets_path: &str) {
+    let docs: Vec<Doc> = read_stdin().handle_errors(error_handler()).collect();
+    let copied = embed::copy_embedded_images(&docs, vault, output_dir, assets_path).unwrap();
+    for result in copied {
+        if let Err(err) = result {
+            report_error(err);
+        }
+    }
+    docs.into_iter()
+        .render_embeds_between(assets_path)
+        .write_stdio();
+}
+
+/// How to set permalinks before rendering wikilinks
+struct PermalinkOptions {
+    template: Option<String>,
+    style: Option<PermalinkStyle>,
+    collisions: PermalinkCollisions,
+}
+
+/// Set permalinks as `smith permalink` does. Without a template or style,
+/// the `permalinks` rules in config are used.
+fn set_permalinks(
+    pipeline: Pipeline,
+    template: Option<String>,
+    style: Option<PermalinkStyle>,
+    config: &Config,
+) -> Pipeline {
+    if template.is_none() && style.is_none() {
+        pipeline.permalink_rules()
+    } else {
+        pipeline.permalink(&resolve_permalink_template(template, style, config))
+    }
+}
+
+/// Render wikilinks between docs, with templates from the command line or
+/// config. If `permalinks` is given, permalinks are set first, so links
+/// point at final permalinks.
+fn wikilinks_cmd(
+    permalinks: Option<PermalinkOptions>,
+    resolution: SlugResolution,
+    link_template: Option<String>,
+    nolink_template: Option<String>,
+    config: &Config,
+) {
+    let mut templates = WikilinkTemplates::from_config(config);
+    if let Some(link_template) = link_template {
+        templates.link = link_template;
+    }
+    if let Some(nolink_template) = nolink_template {
+        templates.nolink = nolink_template;
+    }
+    let mut config = config.clone();
+    let steps = match permalinks {
+        Some(permalinks) => {
+            config.permalink_collisions = permalinks.collisions;
+            set_permalinks(
+                pipeline(&config).read_stdin(),
+                permalinks.template,
+                permalinks.style,
+                &config,
+            )
+        }
+        None => pipeline(&config).read_stdin(),
+    };
+    steps
+        .then(move |docs| {
+            let docs: Vec<Doc> = docs
+                .render_wikilinks_between_with_templates(&templates, resolution)
+                .collect();
+            docs.into_iter()
+        })
         .write_stdio();
 }
 
-fn wikilinks_cmd() {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .render_wikilinks_between()
+/// Turn Markdown source docs into blog posts, without templates
+fn markdown_post_cmd(permalink_template: &str, config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .markdown_post(permalink_template, &config.site_url, &config.markdown)
         .write_stdio();
 }
 
-fn blog_cmd(permalink_template: &str, data_files: &Vec<PathBuf>, config: &Config) {
-    let data = json::read_json_files_as_data_map(data_files).unwrap();
+fn blog_cmd(permalink_template: &str, data_files: &[PathBuf], config: &Config) {
+    let data = match json::read_data_map(data_files, &config.fetch_cache()) {
+        Ok(data) => data,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
 
     // Set up Tera instance
-    let renderer = tera::renderer(&config.templates).unwrap();
+    let renderer = match tera::renderer_for_config(config) {
+        Ok(renderer) => renderer,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
     let mut context = tera::context();
     context.insert("data", &data);
     context.insert("site", config);
 
-    docs::read_stdin()
-        .panic_at_first_error()
+    read_stdin()
+        .handle_errors(error_handler())
+        .apply_template_rules(config)
+        .unwrap()
         .blog_doc(permalink_template, &config.site_url, &renderer, &context)
-        .panic_at_first_error()
+        .handle_errors(error_handler())
         .write_stdio();
 }
 
-fn permalink_cmd(template: &str) {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .set_permalink(template)
+/// Set permalinks from an explicit template or style, or else from the
+/// `permalinks` rules in config
+fn permalink_cmd(
+    template: Option<String>,
+    style: Option<PermalinkStyle>,
+    collisions: PermalinkCollisions,
+    config: &Config,
+) {
+    let mut config = config.clone();
+    config.permalink_collisions = collisions;
+    set_permalinks(pipeline(&config).read_stdin(), template, style, &config).write_stdio();
+}
+
+/// Transform docs with a Rhai script
+#[cfg(feature = "script")]
+fn script_cmd(file: &Path) {
+    let script = lettersmith::script::Script::read(file).unwrap();
+    read_stdin()
+        .handle_errors(error_handler())
+        .run_script(&script)
+        .handle_errors(error_handler())
         .write_stdio();
 }
 
-/// Render Tera templates
-fn template(data_files: &Vec<PathBuf>, config: &Config) {
-    let data = json::read_json_files_as_data_map(data_files).unwrap();
+/// Spellcheck doc content against word lists
+#[cfg(feature = "prose")]
+fn lint_prose_cmd(dictionary: &Path, words: &[PathBuf], keep_going: bool) {
+    use lettersmith::prose::{Dictionary, ProseDocs};
+    let dictionary =
+        Dictionary::read(std::iter::once(dictionary).chain(words.iter().map(PathBuf::as_path)))
+            .unwrap();
+    let results = read_stdin()
+        .handle_errors(error_handler())
+        .lint_prose(&dictionary);
+    if keep_going {
+        let handler = error_handler().with_on_error(OnError::Skip);
+        results.handle_errors(&handler).write_stdio();
+    } else {
+        results.handle_errors(error_handler()).write_stdio();
+    }
+}
 
-    // Set up Tera instance
-    let renderer = tera::renderer(&config.templates).unwrap();
-    let mut context = tera::context();
-    context.insert("data", &data);
-    context.insert("site", config);
+/// Run pipelines declared in config
+fn build_cmd(name: Option<String>, config: &Config) {
+    let pipelines: Vec<(&String, &Vec<Step>)> = match &name {
+        Some(name) => match config.pipelines.get_key_value(name) {
+            Some(pipeline) => vec![pipeline],
+            None => {
+                report_error(Error::value(format!(
+                    "No pipeline named \"{}\" in config",
+                    name
+                )));
+                return;
+            }
+        },
+        None => config.pipelines.iter().collect(),
+    };
+    for (_, steps) in pipelines {
+        for report in pipeline(config).run(steps) {
+            print_write_report(report);
+        }
+    }
+}
 
-    docs::read_stdin()
-        .panic_at_first_error()
-        .auto_template()
-        .render_tera_template(&renderer, &context)
-        .panic_at_first_error()
+/// Render Tera templates
+fn template(data_files: &[PathBuf], cache: bool, config: &Config) {
+    let mut config = config.clone();
+    config.template_cache = config.template_cache || cache;
+    let data = match json::read_data_map(data_files, &config.fetch_cache()) {
+        Ok(data) => data,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
+    pipeline(&config)
+        .read_stdin()
+        .data(data)
+        .template()
         .write_stdio();
 }
 
 /// Index all docs by tag and create JSON doc
-fn tagindex_cmd(taxonomy: String, output_path: PathBuf) {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .generate_tag_index_doc(&taxonomy, &output_path)
+fn tagindex_cmd(taxonomy: String, output_path: PathBuf, limit: Option<usize>) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .generate_tag_index_doc(&taxonomy, &output_path, limit)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Fetch feeds listed in an OPML file and output their entries as docs
+fn ingest_feeds_cmd(file: &Path) {
+    let sources = std::fs::read_to_string(file)
+        .map_err(Error::from)
+        .and_then(|opml| ingest::parse_opml(&opml));
+    let sources = match sources {
+        Ok(sources) => sources,
+        Err(mut err) => {
+            err.msg = format!("Could not read feeds from {}: {}", file.display(), err.msg);
+            report_error(err);
+            return;
+        }
+    };
+    ingest::ingest_feeds(sources)
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Generate an RSS feed doc from the most recent docs, or a feed doc for
+/// each term in a taxonomy
+fn rss_cmd(
+    output_path: Option<PathBuf>,
+    limit: Option<usize>,
+    taxonomy: Option<String>,
+    output_template: &str,
+    config: &Config,
+) {
+    let mut config = config.clone();
+    config.feed_path = output_path.unwrap_or(config.feed_path);
+    config.feed_items = limit.unwrap_or(config.feed_items);
+    if let Some(taxonomy) = taxonomy {
+        read_stdin()
+            .handle_errors(error_handler())
+            .rss_per_tag(&taxonomy, output_template, &config, None)
+            .handle_errors(error_handler())
+            .write_stdio();
+        return;
+    }
+    read_stdin()
+        .handle_errors(error_handler())
+        .feed(&config, None)
         .unwrap()
         .write_stdio();
 }
 
+/// Generate an index doc for each group of docs sharing a meta value
+fn groupby_cmd(
+    meta_path: &str,
+    output_template: &str,
+    template: Option<PathBuf>,
+    terms: Option<PathBuf>,
+) {
+    let term_pages = match terms {
+        Some(terms) => {
+            let paths = docs::expand_globs(vec![terms]).unwrap();
+            docs::read(paths.into_iter())
+                .handle_errors(error_handler())
+                .parse_and_uplift_frontmatter()
+                .pipe(tags::index_term_pages)
+        }
+        None => HashMap::new(),
+    };
+    read_stdin()
+        .handle_errors(error_handler())
+        .generate_meta_group_archives(meta_path, output_template, template)
+        .merge_term_pages(&term_pages)
+        .write_stdio();
+}
+
 /// Parse and uplift frontmatter
-fn frontmatter_cmd() {
-    docs::read_stdin()
-        .panic_at_first_error()
-        .parse_and_uplift_frontmatter()
+fn frontmatter_cmd(formats: &[FrontmatterFormat]) {
+    let formats = if formats.is_empty() {
+        &FrontmatterFormat::ALL
+    } else {
+        formats
+    };
+    read_stdin()
+        .handle_errors(error_handler())
+        .parse_frontmatter_with_formats(formats)
+        .map(|doc| doc.uplift_meta())
+        .write_stdio();
+}
+
+/// Filter docs by value at path
+fn query_cmd(path: &str, eq: Option<String>, contains: Option<String>, not: bool) {
+    let predicate = match (eq, contains) {
+        (Some(value), _) => Predicate::Eq(query::parse_query_value(&value)),
+        (None, Some(value)) => Predicate::Contains(query::parse_query_value(&value)),
+        (None, None) => Predicate::Truthy,
+    };
+    let docs = read_stdin().handle_errors(error_handler());
+    if not {
+        docs.query_not(path, predicate).write_stdio();
+    } else {
+        docs.query(path, predicate).write_stdio();
+    }
+}
+
+/// Project docs to reduced JSON objects
+fn project_cmd(fields: &[String]) {
+    for value in read_stdin().handle_errors(error_handler()).project(fields) {
+        println!("{}", value);
+    }
+}
+
+/// Rewrite output paths with a regex
+fn rename_cmd(from: &str, to: &str) {
+    let pattern = Regex::new(from).expect("Invalid --from regular expression");
+    read_stdin()
+        .handle_errors(error_handler())
+        .rename_output(&pattern, to)
+        .write_stdio();
+}
+
+/// Replace text matching a regex
+fn replace_cmd(regex: &str, replacement: &str, field: &str) {
+    let pattern = Regex::new(regex).expect("Invalid --regex regular expression");
+    read_stdin()
+        .handle_errors(error_handler())
+        .replace_text(field, &pattern, replacement)
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Apply a merge patch to doc meta
+fn merge_meta_cmd(patch_path: &Path, matching: Option<&str>) {
+    let patch = json::read(patch_path).unwrap();
+    let docs = read_stdin().handle_errors(error_handler());
+    match matching {
+        Some(glob_pattern) => docs.merge_meta_matching(patch, glob_pattern).write_stdio(),
+        None => docs.merge_meta(patch).write_stdio(),
+    }
+}
+
+/// Merge static comment data into doc meta
+fn comments_cmd(file: &Path, config: &Config) {
+    let comments = Comments::read(file).unwrap();
+    read_stdin()
+        .handle_errors(error_handler())
+        .merge_comments(&comments, config.trailing_slash)
         .write_stdio();
 }
+
+/// Inject feed, canonical, and description tags into page heads
+fn head_cmd(config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .inject_head_tags(config)
+        .write_stdio();
+}
+
+/// Rewrite HTML using rules from config
+fn rewrite_html_cmd(config: &Config) {
+    let rewriter = HtmlRewriter::new(&config.html_rewrite).unwrap();
+    read_stdin()
+        .handle_errors(error_handler())
+        .rewrite_html(&rewriter)
+        .handle_errors(error_handler())
+        .write_stdio();
+}
+
+/// Compute meta fields from config
+fn compute_cmd(config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .compute_meta(config)
+        .write_stdio();
+}
+
+/// Generate iCalendar file from event docs
+fn ics_cmd(output_path: &Path, config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .ics(&config.site_url, &config.site_title, output_path)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Generate a link graph file
+fn graph_cmd(format: GraphFormat, output_path: Option<PathBuf>, config: &Config) {
+    let output_path = output_path.unwrap_or_else(|| PathBuf::from(format.output_path()));
+    read_stdin()
+        .handle_errors(error_handler())
+        .generate_graph_doc(&config.site_url, format, output_path)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Generate a site report doc
+fn report_cmd(
+    format: SiteReportFormat,
+    taxonomy: &str,
+    output_path: Option<PathBuf>,
+    config: &Config,
+) {
+    let output_path = output_path.unwrap_or_else(|| PathBuf::from(format.output_path()));
+    read_stdin()
+        .handle_errors(error_handler())
+        .generate_report_doc(taxonomy, &config.site_url, format, &output_path)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Generate redirects file from doc aliases and config
+fn redirects_cmd(format: RedirectsFormat, config: &Config) {
+    read_stdin()
+        .handle_errors(error_handler())
+        .redirects(&config.redirects, format)
+        .unwrap()
+        .write_stdio();
+}
+
+/// Generate a starter project
+fn init_cmd(dir: &Path) {
+    let created = scaffold::init_site(dir).unwrap();
+    for path in created {
+        println!("Created {}", path.to_string_lossy());
+    }
+}
+
+/// Import a Jekyll or Hugo project
+fn import_cmd(source: ImportSource, dir: &Path, output_dir: &Path) {
+    let report = match import::import_project(source, dir, output_dir) {
+        Ok(report) => report,
+        Err(err) => {
+            report_error(err);
+            return;
+        }
+    };
+    for path in &report.written {
+        println!("Created {}", output_dir.join(path).to_string_lossy());
+    }
+    for note in &report.notes {
+        eprintln!("Needs attention: {}", note);
+    }
+}
+
+/// Show or check the effective config
+fn config_cmd(config_path: &str, profile: Option<&str>, check: bool, print: bool) {
+    let config = if Path::new(config_path).exists() || profile.is_some() {
+        Config::read_with_profile(config_path, profile)
+    } else {
+        Ok(Config::default())
+    }
+    .and_then(|config| config.merge_env(env::vars()))
+    .unwrap_or_else(|err| {
+        eprintln!("Could not load config from {}: {}", config_path, err);
+        std::process::exit(1);
+    });
+    if print || !check {
+        println!(
+            "{}",
+            json::to_string_pretty(&config.to_json().unwrap()).unwrap()
+        );
+    }
+    if check {
+        match config.validate() {
+            Ok(()) => eprintln!("Config OK"),
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err.msg);
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Diagnose project setup problems
+fn doctor_cmd(
+    config_path: &Path,
+    profile: Option<&str>,
+    data_files: &[PathBuf],
+    output_dir: Option<&Path>,
+) {
+    let checks = doctor::diagnose(config_path, profile, data_files, output_dir);
+    for check in &checks {
+        let label = match check.status {
+            CheckStatus::Pass => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        println!("[{}] {}: {}", label, check.name, check.message);
+    }
+    if checks.iter().any(|check| check.status == CheckStatus::Fail) {
+        std::process::exit(1);
+    }
+}
+
+/// Create a new post from an archetype
+fn new_cmd(title: &str, dir: &Path, template: Option<&Path>) {
+    let path = scaffold::new_post(title, dir, template).unwrap();
+    println!("Created {}", path.to_string_lossy());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_paths(docs: impl Docs) -> Vec<String> {
+        docs.map(|doc| doc.id_path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_docs() {
+        let docs = || {
+            vec![
+                Doc::draft("index.md"),
+                Doc::draft("posts/a.md"),
+                Doc::draft("posts/_b.md"),
+                Doc::draft("posts/index.md"),
+            ]
+            .into_iter()
+        };
+        assert_eq!(id_paths(remove_docs(docs(), false, false)).len(), 4);
+        assert_eq!(
+            id_paths(remove_docs(docs(), true, false)),
+            vec!["index.md", "posts/a.md", "posts/index.md"]
+        );
+        assert_eq!(
+            id_paths(remove_docs(docs(), false, true)),
+            vec!["posts/a.md", "posts/_b.md"]
+        );
+        assert_eq!(
+            id_paths(remove_docs(docs(), true, true)),
+            vec!["posts/a.md"]
+        );
+    }
+}
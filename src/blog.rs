@@ -1,9 +1,33 @@
 use crate::absolutize::AbsolutizableDocs;
+use crate::doc::Doc;
 use crate::docs::{DocResults, Docs};
+use crate::frontmatter::FrontmatterDocs;
+use crate::json::{self, json};
+use crate::markdown::{MarkdownDocs, MarkdownOptions};
 use crate::permalink::PermalinkDocs;
+use crate::stub::Stub;
 use crate::tera::TeraDocs;
+use std::path::Path;
 
 pub trait BlogDocs: Docs {
+    /// Turn Markdown source docs into blog posts without templates:
+    /// parse frontmatter, set permalinks, render Markdown, and absolutize
+    /// URLs against `site_url`. Use `blog_doc` to render templates too.
+    fn markdown_post(
+        self,
+        permalink_template: &str,
+        site_url: &str,
+        options: &MarkdownOptions,
+    ) -> impl Docs {
+        self.parse_and_uplift_frontmatter()
+            .set_permalink(permalink_template)
+            .render_markdown_with(options)
+            .absolutize_urls(site_url)
+    }
+
+    /// Render blog posts: set permalinks, pick templates by parent
+    /// directory, absolutize URLs against `site_url`, and render templates.
+    /// Use `markdown_post` for a pipeline without templates.
     fn blog_doc(
         self,
         permalink_template: &str,
@@ -16,6 +40,96 @@ pub trait BlogDocs: Docs {
             .absolutize_urls(&site_url)
             .render_tera_template(renderer, context)
     }
+
+    /// Give each index doc the `n` most recent docs under its directory as
+    /// stubs in `meta.recent`, newest first. Docs in subdirectories are
+    /// included, so a top-level `index.md` lists the latest posts site-wide.
+    /// Other index docs are never listed.
+    fn inject_recent_into_index(self, n: usize) -> impl Docs {
+        let docs: Vec<Doc> = self.collect();
+        let mut posts: Vec<&Doc> = docs.iter().filter(|doc| !doc.is_index()).collect();
+        posts.sort_by_key(|doc| std::cmp::Reverse(doc.created));
+        let recent: Vec<Option<json::Value>> = docs
+            .iter()
+            .map(|doc| {
+                if !doc.is_index() {
+                    return None;
+                }
+                let dir = doc.id_path.parent().unwrap_or(Path::new(""));
+                let stubs: Vec<Stub> = posts
+                    .iter()
+                    .filter(|post| post.id_path.starts_with(dir))
+                    .take(n)
+                    .map(|post| Stub::from(*post))
+                    .collect();
+                Some(json!(stubs))
+            })
+            .collect();
+        docs.into_iter()
+            .zip(recent)
+            .map(|(mut doc, recent)| match recent {
+                Some(recent) => {
+                    if !doc.meta.is_object() {
+                        doc.meta = json!({});
+                    }
+                    doc.merge_meta(json!({ "recent": recent }))
+                }
+                None => doc,
+            })
+    }
 }
 
 impl<I> BlogDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_markdown_post() {
+        let docs: Vec<Doc> = vec![
+            Doc::draft("posts/hello.md").set_content("---\ntitle: Hello\n---\n![cat](cat.png)")
+        ]
+        .into_iter()
+        .markdown_post(
+            "{parents}/{slug}/index.html",
+            "https://example.com",
+            &MarkdownOptions::default(),
+        )
+        .collect();
+        assert_eq!(docs[0].title, "Hello");
+        assert_eq!(
+            docs[0].output_path,
+            std::path::PathBuf::from("posts/hello/index.html")
+        );
+        assert_eq!(
+            docs[0].content,
+            "<p><img src=\"https://example.com/posts/hello/cat.png\" alt=\"cat\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_inject_recent_into_index() {
+        let day = |d| Utc.with_ymd_and_hms(2024, 1, d, 0, 0, 0).unwrap();
+        let docs = vec![
+            Doc::draft("index.md"),
+            Doc::draft("posts/index.md"),
+            Doc::draft("posts/a.md").set_created(day(1)),
+            Doc::draft("posts/b.md").set_created(day(3)),
+            Doc::draft("about.md").set_created(day(2)),
+        ];
+        let docs: Vec<Doc> = docs.into_iter().inject_recent_into_index(2).collect();
+        let recent_ids = |doc: &Doc| -> Vec<String> {
+            doc.meta["recent"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|stub| stub["id_path"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(recent_ids(&docs[0]), vec!["posts/b.md", "about.md"]);
+        assert_eq!(recent_ids(&docs[1]), vec!["posts/b.md", "posts/a.md"]);
+        assert!(docs[2].meta.get("recent").is_none());
+    }
+}
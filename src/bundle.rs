@@ -0,0 +1,302 @@
+// Page bundles: docs with co-located assets, such as
+// `posts/my-post/index.md` next to `posts/my-post/cat.png`
+use crate::absolutize::rewrite_urls_in_html;
+use crate::doc::Doc;
+use crate::docs::{write_with_report, DocResults, Docs, WriteReport};
+use crate::error::Error;
+use crate::json::{self, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File extensions of content files, which are never treated as assets
+const CONTENT_EXTENSIONS: [&str; 4] = ["md", "markdown", "html", "htm"];
+
+/// Is this path the index file of a page bundle, such as `index.md`?
+pub fn is_bundle_index(path: &Path) -> bool {
+    path.file_stem()
+        .map(|stem| stem == "index")
+        .unwrap_or(false)
+}
+
+fn is_content_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| CONTENT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn has_bundle_index(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                let path = entry.path();
+                path.is_file() && is_bundle_index(&path) && is_content_file(&path)
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Is this a hidden file or directory, such as `.git` or `.env`?
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Do these paths point at the same directory?
+fn is_same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn collect_assets(
+    bundle_dir: &Path,
+    dir: &Path,
+    output_dir: &Path,
+    assets: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if is_hidden(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            // Subdirectories with their own index are bundles of their own
+            if !has_bundle_index(&path) && !is_same_dir(&path, output_dir) {
+                collect_assets(bundle_dir, &path, output_dir, assets)?;
+            }
+        } else if !is_content_file(&path) {
+            if let Ok(relative) = path.strip_prefix(bundle_dir) {
+                assets.push(relative.to_path_buf());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find the assets in a page bundle directory, relative to the directory.
+/// Content files are left out, as are subdirectories that are bundles
+/// of their own, hidden files and directories (`.git`, `.env`), and
+/// `output_dir`, so a build is never copied into itself.
+pub fn find_bundle_assets(bundle_dir: &Path, output_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut assets = Vec::new();
+    collect_assets(bundle_dir, bundle_dir, output_dir, &mut assets)?;
+    assets.sort();
+    Ok(assets)
+}
+
+/// Get the directory assets are copied to for a doc's output path.
+/// Index files share their directory with their assets. Other output
+/// files get a directory named after their file stem.
+///
+/// output_path:
+///     posts/my-post/index.html
+///
+/// assets dir:
+///     posts/my-post
+///
+/// output_path:
+///     posts/my-post.html
+///
+/// assets dir:
+///     posts/my-post
+pub fn to_bundle_output_dir(output_path: &Path) -> PathBuf {
+    let parent = output_path.parent().unwrap_or(Path::new(""));
+    if is_bundle_index(output_path) {
+        return parent.to_path_buf();
+    }
+    match output_path.file_stem() {
+        Some(stem) => parent.join(stem),
+        None => parent.to_path_buf(),
+    }
+}
+
+/// Page bundle info read from `meta.bundle`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bundle {
+    /// The bundle directory the doc was read from
+    pub dir: PathBuf,
+    /// Asset paths, relative to `dir`
+    pub assets: Vec<PathBuf>,
+}
+
+impl Doc {
+    /// If this doc was read from the index file of a page bundle, find the
+    /// assets next to it and record them in `meta.bundle`, as
+    /// `{"dir": "posts/my-post", "assets": ["cat.png"]}`.
+    /// Other docs are returned unchanged, as is a root-level `index.md`,
+    /// since its directory is the whole project.
+    /// See `find_bundle_assets`.
+    ///
+    /// Run after parsing frontmatter, which replaces meta.
+    pub fn read_bundle_assets(mut self, output_dir: &Path) -> Result<Self, Error> {
+        let Some(input_path) = self.input_path.clone() else {
+            return Ok(self);
+        };
+        if !is_bundle_index(&input_path) {
+            return Ok(self);
+        }
+        let dir = input_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if dir.as_os_str().is_empty() || dir == Path::new(".") {
+            return Ok(self);
+        }
+        let assets =
+            find_bundle_assets(&dir, output_dir).map_err(|err| err.with_doc(&self.id_path))?;
+        if assets.is_empty() {
+            return Ok(self);
+        }
+        if !self.meta.is_object() {
+            self.meta = json!({});
+        }
+        Ok(self.merge_meta(json!({
+            "bundle": {
+                "dir": dir,
+                "assets": assets,
+            }
+        })))
+    }
+
+    /// Read page bundle info from `meta.bundle`
+    pub fn get_bundle(&self) -> Option<Bundle> {
+        let bundle = self.meta.get("bundle")?;
+        let dir = PathBuf::from(bundle.get("dir")?.as_str()?);
+        let assets: Vec<PathBuf> = json::from_value(bundle.get("assets")?.clone()).ok()?;
+        Some(Bundle { dir, assets })
+    }
+
+    /// Rewrite relative references to bundle assets in content, so they
+    /// point at the assets where `copy_bundle_assets` puts them.
+    /// Index docs share their directory with their assets, so their
+    /// references are left as they are.
+    pub fn rewrite_bundle_urls(self) -> Self {
+        let Some(bundle) = self.get_bundle() else {
+            return self;
+        };
+        if is_bundle_index(&self.output_path) {
+            return self;
+        }
+        let prefix = to_bundle_output_dir(&self.output_path);
+        let prefix = prefix.file_name().map(PathBuf::from).unwrap_or_default();
+        let content = rewrite_urls_in_html(&self.content, |url| {
+            let path = url.trim_start_matches("./");
+            if bundle.assets.iter().any(|asset| asset == Path::new(path)) {
+                prefix.join(path).to_string_lossy().into_owned()
+            } else {
+                url.to_string()
+            }
+        });
+        self.set_content(content)
+    }
+
+    /// Copy bundle assets into the doc's bundle output directory under
+    /// `output_dir`. See `to_bundle_output_dir`.
+    /// Returns the paths of the copied files.
+    pub fn copy_bundle_assets(&self, output_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let Some(bundle) = self.get_bundle() else {
+            return Ok(Vec::new());
+        };
+        let dest_dir = output_dir.join(to_bundle_output_dir(&self.output_path));
+        let mut copied = Vec::new();
+        for asset in bundle.assets {
+            let dest = dest_dir.join(&asset);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|err| Error::from(err).with_doc(&self.id_path))?;
+            }
+            fs::copy(bundle.dir.join(&asset), &dest)
+                .map_err(|err| Error::from(err).with_doc(&self.id_path))?;
+            copied.push(dest);
+        }
+        Ok(copied)
+    }
+}
+
+pub trait BundleDocs: Docs {
+    /// Record page bundle assets in `meta.bundle` for docs read from bundle
+    /// index files. See `Doc::read_bundle_assets`.
+    fn read_bundle_assets(self, output_dir: &Path) -> impl DocResults {
+        self.map(move |doc| doc.read_bundle_assets(output_dir))
+    }
+
+    /// Write docs under output_dir, copying page bundle assets next to them
+    /// and rewriting relative references to the assets.
+    /// Returns a report, like `Docs::write`.
+    fn write_bundles(self, output_dir: &Path) -> WriteReport {
+        write_with_report(self, output_dir, |doc| {
+            let doc = doc.rewrite_bundle_urls();
+            doc.write(output_dir)
+                .and_then(|path| doc.copy_bundle_assets(output_dir).map(|_| path))
+        })
+    }
+}
+
+impl<I> BundleDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_to_bundle_output_dir() {
+        assert_eq!(
+            to_bundle_output_dir(Path::new("posts/a/index.html")),
+            PathBuf::from("posts/a")
+        );
+        assert_eq!(
+            to_bundle_output_dir(Path::new("posts/a.html")),
+            PathBuf::from("posts/a")
+        );
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let src = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        let bundle_dir = src.path().join("my-post");
+        fs::create_dir_all(bundle_dir.join("img")).unwrap();
+        fs::create_dir_all(bundle_dir.join("child")).unwrap();
+        fs::write(bundle_dir.join("index.md"), "![cat](cat.png)").unwrap();
+        fs::write(bundle_dir.join("cat.png"), "png").unwrap();
+        fs::write(bundle_dir.join("img/dog.jpg"), "jpg").unwrap();
+        fs::write(bundle_dir.join("notes.md"), "not an asset").unwrap();
+        fs::write(bundle_dir.join("child/index.md"), "nested bundle").unwrap();
+        fs::write(bundle_dir.join("child/fish.png"), "png").unwrap();
+        fs::create_dir_all(bundle_dir.join(".git")).unwrap();
+        fs::write(bundle_dir.join(".git/config"), "git").unwrap();
+        fs::write(bundle_dir.join(".env"), "SECRET=1").unwrap();
+        fs::create_dir_all(bundle_dir.join("public")).unwrap();
+        fs::write(bundle_dir.join("public/old.png"), "png").unwrap();
+
+        let doc = Doc::read(bundle_dir.join("index.md"))
+            .unwrap()
+            .read_bundle_assets(&bundle_dir.join("public"))
+            .unwrap();
+        let bundle = doc.get_bundle().unwrap();
+        assert_eq!(
+            bundle.assets,
+            vec![PathBuf::from("cat.png"), PathBuf::from("img/dog.jpg")]
+        );
+
+        let doc = doc
+            .set_output_path("posts/my-post.html")
+            .set_content(r#"<img src="cat.png"><img src="./img/dog.jpg"><img src="x.png">"#)
+            .rewrite_bundle_urls();
+        assert_eq!(
+            doc.content,
+            r#"<img src="my-post/cat.png"><img src="my-post/img/dog.jpg"><img src="x.png">"#
+        );
+        let copied = doc.copy_bundle_assets(out.path()).unwrap();
+        assert_eq!(copied.len(), 2);
+        assert!(out.path().join("posts/my-post/img/dog.jpg").exists());
+    }
+
+    #[test]
+    fn test_root_index_is_not_a_bundle() {
+        let mut doc = Doc::draft("index.md");
+        doc.input_path = Some(PathBuf::from("index.md"));
+        let doc = doc.read_bundle_assets(Path::new("public")).unwrap();
+        assert_eq!(doc.get_bundle(), None);
+    }
+}
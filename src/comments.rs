@@ -0,0 +1,133 @@
+// Merge static comment data into docs, so templates can render comments
+// without a third-party embed
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::json::{self, json};
+use crate::url::{to_permalink_path, TrailingSlash};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Normalize a doc path or URL for matching comment keys.
+/// Drops the scheme and host of URLs, leading and trailing slashes, and a
+/// trailing `index.html`, so `https://example.com/posts/a/`,
+/// `/posts/a/index.html`, and `posts/a` all match.
+fn normalize_key(key: &str) -> String {
+    let key = match key.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |i| &rest[i..]),
+        None => key,
+    };
+    let key = key.split(['?', '#']).next().unwrap_or_default();
+    let key = key.trim_matches('/');
+    let key = key.strip_suffix("index.html").unwrap_or(key);
+    key.trim_end_matches('/').to_string()
+}
+
+/// Comment data, keyed by normalized doc path.
+///
+/// Comments are read from a JSON object mapping doc paths to arrays of
+/// comments. Keys may be id paths, output paths, public paths, or full URLs.
+/// Comments are kept as-is, so any fields can be rendered by templates.
+///
+/// ```json
+/// {
+///   "/posts/hello/": [
+///     {"author": "Ada", "date": "2024-01-02", "content": "<p>Nice!</p>"}
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comments {
+    threads: HashMap<String, Vec<json::Value>>,
+}
+
+impl Comments {
+    /// Read comments from a JSON value. See `Comments`.
+    pub fn from_json(value: json::Value) -> Result<Self, Error> {
+        let json::Value::Object(object) = value else {
+            return Err(Error::value(
+                "Comment data should be a JSON object keyed by doc path",
+            ));
+        };
+        let mut threads: HashMap<String, Vec<json::Value>> = HashMap::new();
+        for (key, value) in object {
+            let json::Value::Array(comments) = value else {
+                return Err(Error::value(format!(
+                    "Comments for \"{}\" should be an array",
+                    key
+                )));
+            };
+            threads
+                .entry(normalize_key(&key))
+                .or_default()
+                .extend(comments);
+        }
+        Ok(Comments { threads })
+    }
+
+    /// Read comments from a JSON file. See `Comments`.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_json(json::read(path)?)
+    }
+
+    /// Get the comments for a doc, matching its id path, output path, or
+    /// public path
+    pub fn get(&self, doc: &Doc, trailing_slash: TrailingSlash) -> Option<&Vec<json::Value>> {
+        let id_path = doc.id_path.to_string_lossy();
+        let output_path = doc.output_path.to_string_lossy();
+        let public_path = to_permalink_path(&doc.output_path, trailing_slash);
+        [id_path.as_ref(), output_path.as_ref(), public_path.as_str()]
+            .iter()
+            .find_map(|path| self.threads.get(&normalize_key(path)))
+    }
+}
+
+impl Doc {
+    /// Merge this doc's comments into `meta.comments`, if it has any.
+    /// See `Comments::get`.
+    pub fn merge_comments(self, comments: &Comments, trailing_slash: TrailingSlash) -> Self {
+        match comments.get(&self, trailing_slash) {
+            Some(thread) => {
+                let thread = thread.clone();
+                self.merge_meta(json!({ "comments": thread }))
+            }
+            None => self,
+        }
+    }
+}
+
+pub trait CommentDocs: Docs {
+    /// Merge each doc's comments into `meta.comments`.
+    /// See `Doc::merge_comments`.
+    fn merge_comments(self, comments: &Comments, trailing_slash: TrailingSlash) -> impl Docs {
+        self.map(move |doc| doc.merge_comments(comments, trailing_slash))
+    }
+}
+
+impl<I> CommentDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_comments() {
+        let comments = Comments::from_json(json!({
+            "https://example.com/posts/a/": [{"author": "Ada"}],
+            "posts/b.md": [{"author": "Grace"}],
+        }))
+        .unwrap();
+        let docs: Vec<Doc> = vec![
+            Doc::draft("posts/a.md").set_output_path("posts/a/index.html"),
+            Doc::draft("posts/b.md").set_output_path("posts/b/index.html"),
+            Doc::draft("posts/c.md").set_output_path("posts/c/index.html"),
+        ]
+        .into_iter()
+        .merge_comments(&comments, TrailingSlash::Always)
+        .collect();
+        assert_eq!(docs[0].meta["comments"], json!([{"author": "Ada"}]));
+        assert_eq!(docs[1].meta["comments"], json!([{"author": "Grace"}]));
+        assert!(docs[2].meta.get("comments").is_none());
+        assert!(Comments::from_json(json!({"a": {}})).is_err());
+    }
+}
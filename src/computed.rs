@@ -0,0 +1,107 @@
+// Compute meta fields from token templates declared in config
+use crate::config::Config;
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::json::{self, json};
+use crate::token_template;
+use std::collections::HashMap;
+
+impl Doc {
+    /// Get the token template parts available to computed meta fields.
+    ///
+    /// Includes all permalink template parts (`slug`, `yyyy`, etc), plus:
+    /// - `id_path`, `output_path`, `title`, `summary`
+    /// - `created`, `modified` (RFC 3339)
+    /// - `site_url` (without trailing slash), `site_title`,
+    ///   `site_description`, `site_author`
+    pub fn get_computed_template_parts(&self, config: &Config) -> HashMap<&str, String> {
+        let mut parts = self.get_permalink_template_parts().unwrap_or_default();
+        parts.insert("id_path", self.id_path.to_string_lossy().into_owned());
+        parts.insert(
+            "output_path",
+            self.output_path.to_string_lossy().into_owned(),
+        );
+        parts.insert("title", self.title.clone());
+        parts.insert("summary", self.summary.clone());
+        parts.insert("created", self.created.to_rfc3339());
+        parts.insert("modified", self.modified.to_rfc3339());
+        parts.insert(
+            "site_url",
+            config.site_url.trim_end_matches('/').to_string(),
+        );
+        parts.insert("site_title", config.site_title.clone());
+        parts.insert("site_description", config.site_description.clone());
+        parts.insert("site_author", config.site_author.clone());
+        parts
+    }
+
+    /// Compute meta fields from a map of meta keys to token templates.
+    /// Example: `canonical: "{site_url}/{output_path}"`.
+    /// Computed values overwrite existing values at the same key.
+    pub fn compute_meta(mut self, computed: &HashMap<String, String>, config: &Config) -> Self {
+        if computed.is_empty() {
+            return self;
+        }
+        let parts = self.get_computed_template_parts(config);
+        let mut patch = json::Map::new();
+        for (key, template) in computed {
+            let value = token_template::render(template.as_str(), &parts);
+            patch.insert(key.clone(), json::Value::String(value));
+        }
+        if !self.meta.is_object() {
+            self.meta = json!({});
+        }
+        self.merge_meta(json::Value::Object(patch))
+    }
+}
+
+pub trait ComputedDocs: Docs {
+    /// Compute meta fields from the `computed` section in config.
+    fn compute_meta(self, config: &Config) -> impl Docs {
+        self.map(move |doc| doc.compute_meta(&config.computed, config))
+    }
+}
+
+impl<I> ComputedDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_meta() {
+        let config = Config {
+            site_url: "https://example.com/".to_string(),
+            computed: HashMap::from([
+                (
+                    "canonical".to_string(),
+                    "{site_url}/{output_path}".to_string(),
+                ),
+                ("share_title".to_string(), "{title|upper}".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let docs = vec![Doc::draft("posts/a.md")
+            .set_output_path("posts/a/index.html")
+            .set_title("Hello")
+            .set_meta(json!({"tags": ["a"]}))];
+
+        let docs: Vec<Doc> = docs.into_iter().compute_meta(&config).collect();
+
+        assert_eq!(
+            docs[0].meta,
+            json!({
+                "tags": ["a"],
+                "canonical": "https://example.com/posts/a/index.html",
+                "share_title": "HELLO"
+            })
+        );
+    }
+
+    #[test]
+    fn test_compute_meta_null_meta() {
+        let computed = HashMap::from([("kind".to_string(), "{parent}".to_string())]);
+        let doc = Doc::draft("notes/a.md").compute_meta(&computed, &Config::default());
+        assert_eq!(doc.meta, json!({"kind": "notes"}));
+    }
+}
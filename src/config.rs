@@ -1,8 +1,29 @@
-use crate::error::{Error, ErrorKind};
+use crate::doc::DEFAULT_MAX_FILE_SIZE;
+use crate::error::{Error, ErrorFormat, ErrorKind, OnError};
+use crate::html_rewrite::RewriteRule;
+use crate::io::FetchCache;
 use crate::json;
+use crate::markdown::MarkdownOptions;
+use crate::permalink::{PermalinkCollisions, PermalinkStyle};
+use crate::pipeline::Step;
+use crate::redirects::Redirect;
+use crate::tera::{to_template_glob, FilterDef};
+use crate::url::TrailingSlash;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::read_to_string;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// Prefix of environment variables that override config fields
+pub const ENV_PREFIX: &str = "LETTERSMITH_";
+
+static SITE_URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[a-zA-Z][a-zA-Z0-9+.-]*://[^/\s?#]+(/\S*)?$")
+        .expect("Site URL regex should be valid")
+});
 
 /// Reads well-known config properties from lettersmith config file
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -11,6 +32,35 @@ pub struct Config {
     #[serde(default = "templates_default")]
     pub templates: String,
 
+    /// Theme template globs or directories, searched in order after
+    /// `templates`. Project templates override theme templates of the same
+    /// name. Example: `"theme_templates": ["themes/plain/templates"]`
+    #[serde(default)]
+    pub theme_templates: Vec<String>,
+
+    /// Extra globs of partials and macro libraries to load alongside
+    /// `templates`, such as a library shared across themes.
+    /// Example: `"template_partials": ["shared/**/*.html"]`
+    #[serde(default)]
+    pub template_partials: Vec<String>,
+
+    /// Macro libraries to import into every template, as a map of
+    /// namespaces to template names. Example: `"template_macros": {"ui": "macros/ui.html"}`
+    #[serde(default)]
+    pub template_macros: BTreeMap<String, String>,
+
+    /// User-defined Tera filters, as token templates or Rhai scripts.
+    /// Example: `"template_filters": {"shout": "{value}!", "wordcount": {"script": "filters/wordcount.rhai"}}`
+    #[serde(default)]
+    pub template_filters: BTreeMap<String, FilterDef>,
+
+    /// Templates for sections of the site, keyed by the glob pattern of the
+    /// id paths they apply to. Docs with a template already, or that match
+    /// no pattern, get a template from their parent directory name.
+    /// Example: `"template_rules": {"notes/**": "note.html"}`
+    #[serde(default)]
+    pub template_rules: BTreeMap<String, String>,
+
     /// The site's URL
     #[serde(default = "site_url_default")]
     pub site_url: String,
@@ -27,6 +77,125 @@ pub struct Config {
     #[serde(default)]
     pub site_author: String,
 
+    /// Directory to write docs to, when not given on the command line
+    #[serde(default = "output_dir_default")]
+    pub output_dir: PathBuf,
+
+    /// Number of most recent docs to include in feeds
+    #[serde(default = "feed_items_default")]
+    pub feed_items: usize,
+
+    /// Output path of the site feed
+    #[serde(default = "feed_path_default")]
+    pub feed_path: PathBuf,
+
+    /// Default permalink template. Overrides `permalink_style` when set.
+    /// Example: `"permalink_template": "{yyyy}/{mm}/{slug}/index.html"`
+    #[serde(default)]
+    pub permalink_template: Option<String>,
+
+    /// Default permalink style ("nice" or "flat")
+    #[serde(default)]
+    pub permalink_style: PermalinkStyle,
+
+    /// Permalink templates for sections of the site, keyed by the glob
+    /// pattern of the id paths they apply to. The most specific pattern
+    /// wins, and other docs get the default permalink template.
+    /// Example: `"permalinks": {"posts/**": "{yyyy}/{slug}/", "pages/**": "{parents}/{slug}/"}`
+    #[serde(default)]
+    pub permalinks: BTreeMap<String, String>,
+
+    /// What to do when two docs produce the same permalink
+    /// ("suffix", "error", or "overwrite")
+    #[serde(default)]
+    pub permalink_collisions: PermalinkCollisions,
+
+    /// How to write URLs for docs output to index.html files in feeds,
+    /// sitemaps, and the `permalink_url` filter ("always", "never", or "index")
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+
+    /// Template for wikilinks that resolve to a doc. Token templates may use
+    /// `{text}`, `{slug}`, `{title}`, `{summary}`, `{output_path}`,
+    /// `{permalink}`, and `{canonical_url}`.
+    /// Example: `"wikilink_template": "<a href=\"{permalink}\">{text}</a>"`
+    #[serde(default)]
+    pub wikilink_template: Option<String>,
+
+    /// Template for wikilinks that don't resolve to a doc. Token templates
+    /// may use `{text}` and `{slug}`.
+    #[serde(default)]
+    pub nolink_template: Option<String>,
+
+    /// Redirects to include in generated hosting redirect files
+    #[serde(default)]
+    pub redirects: Vec<Redirect>,
+
+    /// Meta fields to compute for each doc, as a map of meta keys to token
+    /// templates. Example: `"canonical": "{site_url}/{output_path}"`
+    #[serde(default)]
+    pub computed: HashMap<String, String>,
+
+    /// JSON Schemas for doc meta, keyed by the glob pattern of the id paths
+    /// they apply to. Example: `"schemas": {"posts/*.md": {"required": ["tags"]}}`
+    #[serde(default)]
+    pub schemas: HashMap<String, json::Value>,
+
+    /// Markdown rendering options. Example: `"markdown": {"figures": true}`
+    #[serde(default)]
+    pub markdown: MarkdownOptions,
+
+    /// Rules for rewriting HTML in doc content, applied in order.
+    /// Example: `"html_rewrite": [{"selector": "img", "defaults": {"loading": "lazy"}}]`
+    #[serde(default)]
+    pub html_rewrite: Vec<RewriteRule>,
+
+    /// Directory to cache data fetched from URLs passed to `--data`
+    #[serde(default = "data_cache_dir_default")]
+    pub data_cache_dir: PathBuf,
+
+    /// How long to reuse data fetched from URLs, in seconds
+    #[serde(default = "data_cache_ttl_default")]
+    pub data_cache_ttl: u64,
+
+    /// Cache rendered template output under `data_cache_dir`, so unchanged
+    /// docs skip rendering on rebuilds. See `render_cache::RenderCache`.
+    #[serde(default)]
+    pub template_cache: bool,
+
+    /// Maximum size of files read as docs, in bytes. Larger files are
+    /// skipped with a warning.
+    #[serde(default = "max_file_size_default")]
+    pub max_file_size: u64,
+
+    /// What to do when a doc fails a pipeline step ("abort" or "skip")
+    #[serde(default)]
+    pub on_error: OnError,
+
+    /// How to report errors to stderr ("text" or "json")
+    #[serde(default)]
+    pub error_format: ErrorFormat,
+
+    /// Treat every line of stdin as a doc, reporting blank and non-JSON
+    /// lines as errors, rather than skipping them with a warning
+    #[serde(default)]
+    pub strict_stdin: bool,
+
+    /// Secret used to derive unguessable paths for draft previews.
+    /// See `smith preview-drafts`.
+    #[serde(default)]
+    pub preview_key: String,
+
+    /// Named pipelines, each an ordered list of steps, run in-process by
+    /// `smith build`. Example: `"pipelines": {"posts": [{"step": "read", "glob": "posts/*.md"}, {"step": "markdown"}, {"step": "write"}]}`
+    #[serde(default)]
+    pub pipelines: BTreeMap<String, Vec<Step>>,
+
+    /// Named profiles, each a partial config merged over the base config
+    /// when selected. Example: `"profiles": {"dev": {"site_url": "http://localhost:8080"}}`
+    #[serde(default)]
+    pub profiles: HashMap<String, json::Value>,
+
     /// Open-ended metadata you want to be available in the template
     #[serde(default = "data_default")]
     pub data: json::Value,
@@ -36,10 +205,40 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             templates: templates_default(),
+            theme_templates: Vec::new(),
+            template_partials: Vec::new(),
+            template_macros: BTreeMap::new(),
+            template_filters: BTreeMap::new(),
+            template_rules: BTreeMap::new(),
             site_url: site_url_default(),
             site_title: String::default(),
             site_description: String::default(),
             site_author: String::default(),
+            output_dir: output_dir_default(),
+            feed_items: feed_items_default(),
+            feed_path: feed_path_default(),
+            permalink_template: None,
+            permalink_style: PermalinkStyle::default(),
+            permalinks: BTreeMap::new(),
+            permalink_collisions: PermalinkCollisions::default(),
+            trailing_slash: TrailingSlash::default(),
+            wikilink_template: None,
+            nolink_template: None,
+            redirects: Vec::new(),
+            computed: HashMap::new(),
+            schemas: HashMap::new(),
+            markdown: MarkdownOptions::default(),
+            html_rewrite: Vec::new(),
+            data_cache_dir: data_cache_dir_default(),
+            data_cache_ttl: data_cache_ttl_default(),
+            template_cache: false,
+            max_file_size: max_file_size_default(),
+            on_error: OnError::default(),
+            error_format: ErrorFormat::default(),
+            strict_stdin: false,
+            preview_key: String::default(),
+            pipelines: BTreeMap::new(),
+            profiles: HashMap::new(),
             data: data_default(),
         }
     }
@@ -53,6 +252,30 @@ fn site_url_default() -> String {
     "/".to_string()
 }
 
+fn output_dir_default() -> PathBuf {
+    PathBuf::from("public")
+}
+
+fn feed_items_default() -> usize {
+    24
+}
+
+fn feed_path_default() -> PathBuf {
+    PathBuf::from("feed.xml")
+}
+
+fn data_cache_dir_default() -> PathBuf {
+    PathBuf::from(".lettersmith/cache")
+}
+
+fn data_cache_ttl_default() -> u64 {
+    3600
+}
+
+fn max_file_size_default() -> u64 {
+    DEFAULT_MAX_FILE_SIZE
+}
+
 fn data_default() -> json::Value {
     json::json!({})
 }
@@ -60,14 +283,325 @@ fn data_default() -> json::Value {
 impl Config {
     /// Read config from file at path
     pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::read_with_profile(path, None)
+    }
+
+    /// Read config from file at path, merging the named profile (if any)
+    /// over the base config.
+    pub fn read_with_profile(path: impl AsRef<Path>, profile: Option<&str>) -> Result<Self, Error> {
         let json_string = read_to_string(path)?;
-        let config: Self = serde_json::from_str(&json_string)?;
+        let value: json::Value = serde_json::from_str(&json_string)?;
+        Self::from_json_with_profile(value, profile)
+    }
+
+    /// Create config from a JSON value, merging the named profile (if any)
+    /// over the base config. Profiles are merged deeply, so a profile only
+    /// needs to contain the fields it changes.
+    pub fn from_json_with_profile(
+        value: json::Value,
+        profile: Option<&str>,
+    ) -> Result<Self, Error> {
+        let mut value = value;
+        if let Some(profile) = profile {
+            let patch = value
+                .get("profiles")
+                .and_then(|profiles| profiles.get(profile))
+                .cloned()
+                .ok_or_else(|| {
+                    Error::value(format!("No profile named \"{}\" in config", profile))
+                })?;
+            json::merge(&mut value, patch);
+        }
+        let config: Self = serde_json::from_value(value)?;
+        Ok(config)
+    }
+
+    /// Get the default permalink template: `permalink_template` if set,
+    /// otherwise the template for `permalink_style`.
+    pub fn default_permalink_template(&self) -> String {
+        self.permalink_template
+            .clone()
+            .unwrap_or_else(|| self.permalink_style.template().to_string())
+    }
+
+    /// Merge config overrides from environment variables over this config.
+    /// Variables are named after config fields with the `LETTERSMITH_`
+    /// prefix, e.g. `LETTERSMITH_SITE_URL`.
+    ///
+    /// Values for string fields are always used as strings, so
+    /// `LETTERSMITH_SITE_TITLE=2024` sets the title to `"2024"`. Values for
+    /// other fields are parsed as JSON where possible, and used as strings
+    /// otherwise. Fails with an error naming the variable if a value
+    /// doesn't fit its field.
+    pub fn merge_env(self, vars: impl Iterator<Item = (String, String)>) -> Result<Self, Error> {
+        let mut value = self.to_json()?;
+        let mut config = self;
+        for (key, raw) in vars {
+            let Some(field) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let field = field.to_lowercase();
+            let is_string_field = matches!(
+                value.get(&field),
+                Some(json::Value::String(_)) | Some(json::Value::Null)
+            );
+            let override_value = if is_string_field {
+                json::Value::String(raw.clone())
+            } else {
+                serde_json::from_str(&raw).unwrap_or_else(|_| json::Value::String(raw.clone()))
+            };
+            let mut patched = value.clone();
+            json::merge(&mut patched, json::json!({ field: override_value }));
+            config = serde_json::from_value(patched.clone()).map_err(|err| {
+                Error::value(format!(
+                    "Could not apply {}={} to config: {}",
+                    key, raw, err
+                ))
+            })?;
+            value = patched;
+        }
         Ok(config)
     }
 
+    /// Check config for mistakes that would otherwise surface mid-build:
+    /// - `site_url` must be an absolute URL, like `https://example.com`, or `/`
+    /// - the `templates` glob (or a theme's) must match at least one file
+    /// - `output_dir` must not be inside a directory pipelines read content
+    ///   from, so builds don't read their own output
+    ///
+    /// Returns every problem found.
+    pub fn validate(&self) -> Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+        if self.site_url != "/" && !SITE_URL_REGEX.is_match(&self.site_url) {
+            errors.push(Error::value(format!(
+                "site_url \"{}\" should be an absolute URL, like https://example.com, or /",
+                self.site_url
+            )));
+        }
+        let template_globs = std::iter::once(&self.templates).chain(&self.theme_templates);
+        let mut has_templates = false;
+        for pattern in template_globs {
+            let pattern = to_template_glob(pattern);
+            match glob::glob(&pattern) {
+                Ok(paths) => has_templates |= paths.filter_map(|path| path.ok()).next().is_some(),
+                Err(err) => errors.push(Error::value(format!(
+                    "Invalid template glob \"{}\": {}",
+                    pattern, err
+                ))),
+            }
+        }
+        if !has_templates {
+            errors.push(Error::value(format!(
+                "templates glob \"{}\" does not match any files",
+                self.templates
+            )));
+        }
+        let output_dir = normalize_path(&self.output_dir);
+        for content_dir in self.content_dirs() {
+            if output_dir.starts_with(&content_dir) {
+                errors.push(Error::value(format!(
+                    "output_dir \"{}\" is inside content directory \"{}\"",
+                    self.output_dir.display(),
+                    content_dir.display()
+                )));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Directories pipelines read content from: the part of each `read`
+    /// glob before the first wildcard. Globs reading from the project root
+    /// are left out.
+    fn content_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self
+            .pipelines
+            .values()
+            .flatten()
+            .filter_map(|step| match step {
+                Step::Read { glob } => {
+                    let dir: PathBuf = Path::new(glob)
+                        .components()
+                        .take_while(|component| {
+                            !component
+                                .as_os_str()
+                                .to_string_lossy()
+                                .contains(['*', '?', '['])
+                        })
+                        .collect();
+                    // A glob without wildcards names a file
+                    let dir = if dir == Path::new(glob) {
+                        dir.parent().map(Path::to_path_buf).unwrap_or_default()
+                    } else {
+                        dir
+                    };
+                    Some(normalize_path(&dir))
+                }
+                _ => None,
+            })
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+
+    /// Directory rendered template output is cached in, when
+    /// `template_cache` is on
+    pub fn template_cache_dir(&self) -> PathBuf {
+        self.data_cache_dir.join("templates")
+    }
+
+    /// Get the cache for data fetched from URLs
+    pub fn fetch_cache(&self) -> FetchCache {
+        FetchCache::new(
+            &self.data_cache_dir,
+            Duration::from_secs(self.data_cache_ttl),
+        )
+        .with_max_size(self.max_file_size)
+    }
+
+    /// Get site-wide tokens for substitution in content.
+    /// Includes `site_url` (without trailing slash), `site_title`,
+    /// `site_description`, and `site_author`, plus a `data.<key>` token for
+    /// each string, number, or boolean at the top level of `data`.
+    pub fn get_tokens(&self) -> HashMap<String, String> {
+        let mut tokens = HashMap::from([
+            (
+                "site_url".to_string(),
+                self.site_url.trim_end_matches('/').to_string(),
+            ),
+            ("site_title".to_string(), self.site_title.clone()),
+            (
+                "site_description".to_string(),
+                self.site_description.clone(),
+            ),
+            ("site_author".to_string(), self.site_author.clone()),
+        ]);
+        if let Some(data) = self.data.as_object() {
+            for (key, value) in data {
+                let value = match value {
+                    json::Value::String(value) => value.clone(),
+                    json::Value::Number(value) => value.to_string(),
+                    json::Value::Bool(value) => value.to_string(),
+                    _ => continue,
+                };
+                tokens.insert(format!("data.{}", key), value);
+            }
+        }
+        tokens
+    }
+
     /// Convert this config object into a `json::Value`
     pub fn to_json(&self) -> Result<json::Value, Error> {
         serde_json::to_value(self)
             .map_err(|err| Error::new(ErrorKind::Json(err), "Could not serialize Config to JSON"))
     }
 }
+
+/// Drop `.` components from a relative path, so `./public` and `public`
+/// compare equal
+fn normalize_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn make_config_json() -> json::Value {
+        json!({
+            "site_url": "https://example.com",
+            "site_title": "Example",
+            "data": {"analytics": true, "nav": ["a"]},
+            "profiles": {
+                "dev": {
+                    "site_url": "http://localhost:8080",
+                    "data": {"analytics": false}
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_from_json_with_profile() {
+        let config = Config::from_json_with_profile(make_config_json(), Some("dev")).unwrap();
+        assert_eq!(config.site_url, "http://localhost:8080");
+        assert_eq!(config.site_title, "Example");
+        assert_eq!(config.data, json!({"analytics": false, "nav": ["a"]}));
+    }
+
+    #[test]
+    fn test_from_json_without_profile() {
+        let config = Config::from_json_with_profile(make_config_json(), None).unwrap();
+        assert_eq!(config.site_url, "https://example.com");
+    }
+
+    #[test]
+    fn test_from_json_with_missing_profile() {
+        assert!(Config::from_json_with_profile(make_config_json(), Some("prod")).is_err());
+    }
+
+    #[test]
+    fn test_merge_env() {
+        let vars = vec![
+            (
+                "LETTERSMITH_SITE_URL".to_string(),
+                "https://env.example".to_string(),
+            ),
+            ("LETTERSMITH_FEED_ITEMS".to_string(), "5".to_string()),
+            ("HOME".to_string(), "/root".to_string()),
+        ];
+        let config = Config::default().merge_env(vars.into_iter()).unwrap();
+        assert_eq!(config.site_url, "https://env.example");
+        assert_eq!(config.feed_items, 5);
+
+        // String fields keep values that look like other JSON types
+        let vars = vec![
+            ("LETTERSMITH_SITE_TITLE".to_string(), "2024".to_string()),
+            (
+                "LETTERSMITH_PERMALINK_TEMPLATE".to_string(),
+                "true".to_string(),
+            ),
+        ];
+        let config = Config::default().merge_env(vars.into_iter()).unwrap();
+        assert_eq!(config.site_title, "2024");
+        assert_eq!(config.permalink_template.as_deref(), Some("true"));
+
+        // Values that don't fit their field name the variable
+        let vars = vec![("LETTERSMITH_FEED_ITEMS".to_string(), "many".to_string())];
+        let err = Config::default().merge_env(vars.into_iter()).unwrap_err();
+        assert!(err.msg.contains("LETTERSMITH_FEED_ITEMS=many"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("default.html"), "").unwrap();
+        let templates = dir.path().join("*.html").to_string_lossy().into_owned();
+        let valid = Config {
+            templates: templates.clone(),
+            site_url: "https://example.com/blog/".to_string(),
+            ..Config::default()
+        };
+        assert!(valid.validate().is_ok());
+
+        let config = Config::from_json_with_profile(
+            json!({
+                "templates": dir.path().join("missing/*.html"),
+                "site_url": "example.com",
+                "output_dir": "./content/public",
+                "pipelines": {"site": [{"step": "read", "glob": "content/**/*.md"}]}
+            }),
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.validate().unwrap_err().len(), 3);
+    }
+}
@@ -1,26 +1,78 @@
-use crate::error::Error;
-use crate::html::strip_html;
+use crate::error::{Error, ErrorKind};
+use crate::hash::content_hash;
+use crate::html::{escape_xml, strip_html, truncate_html};
 use crate::io::write_file_deep;
-use crate::json::{self, get_deep, merge};
+use crate::json::{self, get_deep, merge, set_deep};
+use crate::stats;
 use crate::text::{to_slug, truncate_280};
+use crate::token_template::substitute_tokens;
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+
+static PARAGRAPH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<p\b[^>]*>(.*?)</p\s*>").expect("Paragraph regex should be valid")
+});
+
+/// Paragraphs longer than this many characters are too long to use whole as
+/// a summary. See `Doc::auto_summary_paragraph`.
+pub const MAX_SUMMARY_PARAGRAPH_CHARS: usize = 560;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash, Default)]
 pub struct Doc {
     pub id_path: PathBuf,
     pub output_path: PathBuf,
+    // Fields that stubs leave out are defaulted, so that stubs can be read
+    // as docs.
+    #[serde(default)]
     pub input_path: Option<PathBuf>,
+    #[serde(default)]
     pub template_path: Option<PathBuf>,
     pub created: DateTime<Utc>,
     pub modified: DateTime<Utc>,
     pub title: String,
     pub summary: String,
+    #[serde(default)]
     pub content: String,
+    #[serde(default)]
     pub meta: json::Value,
 }
 
+/// Default maximum size of files read as docs, in bytes (10 MB)
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Decode file bytes as text.
+/// Files with NUL bytes near the start are treated as binary, as are files
+/// that aren't valid UTF-8.
+fn read_text(bytes: Vec<u8>) -> Result<String, Error> {
+    let head = &bytes[..bytes.len().min(8000)];
+    if head.contains(&0) {
+        return Err(Error::new(ErrorKind::Binary, "File appears to be binary"));
+    }
+    String::from_utf8(bytes).map_err(|_| Error::new(ErrorKind::Binary, "File is not valid UTF-8"))
+}
+
+/// Replace matches in every string within a JSON value
+fn replace_json_strings(value: &mut json::Value, pattern: &Regex, replacement: &str) {
+    match value {
+        json::Value::String(text) => *text = pattern.replace_all(text, replacement).into_owned(),
+        json::Value::Array(items) => {
+            for item in items {
+                replace_json_strings(item, pattern, replacement);
+            }
+        }
+        json::Value::Object(map) => {
+            for item in map.values_mut() {
+                replace_json_strings(item, pattern, replacement);
+            }
+        }
+        _ => {}
+    }
+}
+
 impl Doc {
     pub fn new(
         id_path: PathBuf,
@@ -59,11 +111,36 @@ impl Doc {
     }
 
     /// Load a document from a file path.
+    /// Files larger than `DEFAULT_MAX_FILE_SIZE` are refused.
     pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::read_with_max_size(path, DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// Load a document from a file path, refusing files over `max_size`
+    /// bytes.
+    ///
+    /// Binary and non-UTF-8 files return an error of kind
+    /// `ErrorKind::Binary`, and oversized files an error of kind
+    /// `ErrorKind::TooLarge`, so that callers can skip them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(path = %path.as_ref().display())))]
+    pub fn read_with_max_size(path: impl AsRef<Path>, max_size: u64) -> Result<Self, Error> {
         let path = path.as_ref();
-        let metadata = std::fs::metadata(path)?;
-        let content = std::fs::read_to_string(path)?;
+        let metadata = std::fs::metadata(path).map_err(|err| Error::from(err).with_doc(path))?;
+        if metadata.len() > max_size {
+            return Err(Error::new(
+                ErrorKind::TooLarge,
+                format!(
+                    "File is {} bytes, over the maximum of {} bytes",
+                    metadata.len(),
+                    max_size
+                ),
+            )
+            .with_doc(path));
+        }
+        let bytes = std::fs::read(path).map_err(|err| Error::from(err).with_doc(path))?;
+        let content = read_text(bytes).map_err(|err| err.with_doc(path))?;
         let title = path.file_stem().unwrap().to_string_lossy().into_owned();
+        stats::record_read();
 
         Ok(Doc::new(
             path.into(),
@@ -79,25 +156,46 @@ impl Doc {
         ))
     }
 
+    /// Get the path this doc is written to under `output_dir`.
+    /// Fails if the output path is absolute or contains `..`, since it would
+    /// be written outside `output_dir`.
+    pub fn get_write_path(&self, output_dir: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let escapes = self
+            .output_path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+        if escapes {
+            return Err(Error::value(format!(
+                "Output path {} is outside the output directory",
+                self.output_path.display()
+            ))
+            .with_doc(&self.id_path));
+        }
+        Ok(output_dir.as_ref().join(&self.output_path))
+    }
+
     /// Write the doc to its output path.
     /// Returns a result containing the write path of the file on success.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id_path = %self.id_path.display())))]
     pub fn write(&self, output_dir: impl AsRef<Path>) -> Result<PathBuf, Error> {
-        let write_path = output_dir.as_ref().join(&self.output_path);
-        write_file_deep(&write_path, &self.content)?;
+        let write_path = self.get_write_path(output_dir)?;
+        write_file_deep(&write_path, &self.content).map_err(|err| err.with_doc(&self.id_path))?;
+        stats::record_written(self.content.len());
         Ok(write_path)
     }
 
     /// Write doc to stdio
     /// - JSON serialized docs are printed to stdout
-    /// - Serialization failures are printed to stderr
+    /// - Serialization failures are logged as `tracing` errors
     pub fn write_stdio(&self) {
         let serialized = serde_json::to_string(self);
         match serialized {
             Ok(json) => {
                 println!("{}", json);
+                stats::record_written(json.len());
             }
             Err(err) => {
-                eprintln!("Error serializing doc: {:?}", err);
+                tracing::error!("Error serializing doc: {:?}", err);
             }
         }
     }
@@ -107,6 +205,50 @@ impl Doc {
         self
     }
 
+    /// Rewrite output path, replacing the first match of `pattern` with
+    /// `replacement`. Replacements may refer to capture groups, e.g. `$1`.
+    pub fn rename_output(self, pattern: &Regex, replacement: &str) -> Self {
+        let output_path = self.output_path.to_string_lossy().into_owned();
+        let renamed = pattern.replace(&output_path, replacement).into_owned();
+        self.set_output_path(renamed)
+    }
+
+    /// Replace every match of `pattern` in a text field with `replacement`.
+    /// Replacements may refer to capture groups, e.g. `$1`.
+    ///
+    /// `field` may be `content`, `title`, `summary`, or a dot-path into meta,
+    /// such as `meta.description`. Meta strings are replaced wherever they
+    /// appear within the value at the path, including in arrays and objects.
+    pub fn replace_text(
+        mut self,
+        field: &str,
+        pattern: &Regex,
+        replacement: &str,
+    ) -> Result<Self, Error> {
+        match field {
+            "content" => {
+                self.content = pattern.replace_all(&self.content, replacement).into_owned()
+            }
+            "title" => self.title = pattern.replace_all(&self.title, replacement).into_owned(),
+            "summary" => {
+                self.summary = pattern.replace_all(&self.summary, replacement).into_owned()
+            }
+            _ => {
+                let path = field.strip_prefix("meta.").ok_or_else(|| {
+                    Error::value(format!(
+                        "Can't replace text in \"{}\". Expected content, title, summary, or meta.<path>",
+                        field
+                    ))
+                })?;
+                if let Some(mut value) = get_deep(&self.meta, path) {
+                    replace_json_strings(&mut value, pattern, replacement);
+                    set_deep(&mut self.meta, path, value);
+                }
+            }
+        }
+        Ok(self)
+    }
+
     pub fn set_created(mut self, created: DateTime<Utc>) -> Self {
         self.created = created;
         self
@@ -133,6 +275,13 @@ impl Doc {
         self
     }
 
+    /// Substitute site-wide tokens (`{{key}}` or `:key:`) in content.
+    /// See `token_template::substitute_tokens`.
+    pub fn substitute_tokens(self, tokens: &HashMap<String, String>) -> Self {
+        let content = substitute_tokens(&self.content, tokens);
+        self.set_content(content)
+    }
+
     pub fn set_summary(mut self, summary: impl Into<String>) -> Self {
         self.summary = summary.into();
         self
@@ -146,10 +295,59 @@ impl Doc {
         }
     }
 
+    /// Set `meta.summary_plain` and `meta.summary_html`, unless meta already
+    /// has them. `summary_plain` is plain text, for meta descriptions.
+    /// `summary_html` keeps inline formatting, for listings and feeds.
+    fn set_summary_variants(self, plain: String, html: String) -> Self {
+        let mut patch = json::Map::new();
+        if get_deep(&self.meta, "summary_plain").is_none() {
+            patch.insert("summary_plain".to_string(), json::Value::String(plain));
+        }
+        if get_deep(&self.meta, "summary_html").is_none() {
+            patch.insert("summary_html".to_string(), json::Value::String(html));
+        }
+        if patch.is_empty() {
+            return self;
+        }
+        self.merge_meta(json::Value::Object(patch))
+    }
+
     /// Generate a summary from content if no summary has already been assigned.
+    /// Also sets `meta.summary_plain` and `meta.summary_html`: the summary as
+    /// plain text, and as HTML with inline formatting kept. An assigned
+    /// summary is used as-is for both, escaped for HTML.
     pub fn auto_summary(self) -> Self {
+        if !self.summary.is_empty() {
+            let plain = self.summary.clone();
+            let html = escape_xml(&plain);
+            return self.set_summary_variants(plain, html);
+        }
         let summary = truncate_280(&strip_html(&self.content));
-        self.set_summary_if_empty(summary)
+        let html = truncate_html(&self.content, 280, "…");
+        self.set_summary(summary.clone())
+            .set_summary_variants(summary, html)
+    }
+
+    /// Generate a summary from the first paragraph of rendered content, if
+    /// no summary has already been assigned. Falls back to `auto_summary`
+    /// when content has no paragraphs, or when the first paragraph is longer
+    /// than `MAX_SUMMARY_PARAGRAPH_CHARS`.
+    pub fn auto_summary_paragraph(self) -> Self {
+        if !self.summary.is_empty() {
+            return self.auto_summary();
+        }
+        let paragraph = PARAGRAPH
+            .captures_iter(&self.content)
+            .map(|caps| (strip_html(&caps[1]).trim().to_string(), caps[1].to_string()))
+            .find(|(text, _)| !text.is_empty());
+        match paragraph {
+            Some((text, html)) if text.chars().count() <= MAX_SUMMARY_PARAGRAPH_CHARS => {
+                let html = truncate_html(&html, usize::MAX, "");
+                self.set_summary(text.clone())
+                    .set_summary_variants(text, html)
+            }
+            _ => self.auto_summary(),
+        }
     }
 
     /// Set template, overwriting whatever was there previously
@@ -216,6 +414,53 @@ impl Doc {
         self
     }
 
+    /// Get the manual ordering weight from `meta.weight` or `meta.order`.
+    /// Defaults to 0. See `docs::read_meta_weight`.
+    pub fn get_weight(&self) -> i64 {
+        crate::docs::read_meta_weight(&self.meta)
+    }
+
+    /// Is this doc a draft? Drafts have a file name starting with an
+    /// underscore, or `draft: true` in meta.
+    pub fn is_draft(&self) -> bool {
+        let underscored = self
+            .id_path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('_'));
+        let marked = get_deep(&self.meta, "draft").is_some_and(|value| value == true);
+        underscored || marked
+    }
+
+    /// Is this doc an index doc? Index docs have a file name of `index`,
+    /// with any extension, like `index.md` or `posts/index.html`.
+    pub fn is_index(&self) -> bool {
+        self.id_path.file_stem().and_then(|stem| stem.to_str()) == Some("index")
+    }
+
+    /// Get the doc's stable identity: `meta.id` if it is set, falling back
+    /// to id_path. Unlike id_path, `meta.id` is kept in frontmatter, so it
+    /// survives files being moved or renamed. See `assign_id`.
+    pub fn get_id(&self) -> String {
+        match self.meta.get("id") {
+            Some(json::Value::String(id)) if !id.is_empty() => id.clone(),
+            _ => self.id_path.to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Assign a stable id in `meta.id` to docs that don't have one yet.
+    /// The id is a hash of the doc's input path (or id_path, for docs without
+    /// one), so it is the same every time the doc is read from the same file.
+    /// Write docs back with their frontmatter to keep the id across renames.
+    pub fn assign_id(mut self) -> Self {
+        if matches!(self.meta.get("id"), Some(json::Value::String(id)) if !id.is_empty()) {
+            return self;
+        }
+        let path = self.input_path.as_ref().unwrap_or(&self.id_path);
+        let id = content_hash(&path.to_string_lossy());
+        set_deep(&mut self.meta, "id", json::Value::String(id));
+        self
+    }
+
     /// Uplift metadata, looking for blessed fields and assigning values to doc:
     /// - title
     /// - summary
@@ -305,6 +550,14 @@ mod tests {
         let path = doc.write(&dir)?;
         assert!(path.exists());
         assert_eq!(fs::read_to_string(path)?, "Content");
+
+        // Output paths may not escape the output directory
+        assert!(doc
+            .clone()
+            .set_output_path("/tmp/a.html")
+            .write(&dir)
+            .is_err());
+        assert!(doc.set_output_path("../a.html").write(&dir).is_err());
         Ok(())
     }
 
@@ -314,6 +567,44 @@ mod tests {
         assert_eq!(doc.get_title_slug(), "my-test-title");
     }
 
+    #[test]
+    fn test_auto_summary_variants() {
+        let doc = Doc::draft("a.md")
+            .set_content("<p>Hello <strong>bold</strong> <a href=\"/x\">world</a>.</p>")
+            .auto_summary();
+        assert_eq!(doc.summary, "Hello bold world.");
+        assert_eq!(doc.meta["summary_plain"], "Hello bold world.");
+        assert_eq!(
+            doc.meta["summary_html"],
+            "Hello <strong>bold</strong> <a href=\"/x\">world</a>."
+        );
+
+        let doc = Doc::draft("b.md")
+            .set_summary("Fish & chips")
+            .set_meta(json::json!({"summary_html": "<em>Fish</em> &amp; chips"}))
+            .auto_summary();
+        assert_eq!(doc.summary, "Fish & chips");
+        assert_eq!(doc.meta["summary_plain"], "Fish & chips");
+        assert_eq!(doc.meta["summary_html"], "<em>Fish</em> &amp; chips");
+    }
+
+    #[test]
+    fn test_auto_summary_paragraph() {
+        let doc = Doc::draft("a.md")
+            .set_content("<h1>Title</h1>\n<p></p>\n<p>First <em>para</em>.</p>\n<p>Second.</p>")
+            .auto_summary_paragraph();
+        assert_eq!(doc.summary, "First para.");
+        assert_eq!(doc.meta["summary_plain"], "First para.");
+        assert_eq!(doc.meta["summary_html"], "First <em>para</em>.");
+
+        let long = "word ".repeat(200);
+        let doc = Doc::draft("b.md")
+            .set_content(format!("<p>{}</p>", long))
+            .auto_summary_paragraph();
+        assert!(doc.summary.ends_with('…'));
+        assert!(doc.summary.chars().count() <= 280);
+    }
+
     #[test]
     fn test_auto_template() {
         let doc = Doc::draft("posts/test.md").auto_template();
@@ -323,6 +614,23 @@ mod tests {
         assert_eq!(doc.template_path, Some(PathBuf::from("default.html")));
     }
 
+    #[test]
+    fn test_get_id() {
+        let doc = Doc::draft("a.md");
+        assert_eq!(doc.get_id(), "a.md");
+        let doc = doc.assign_id();
+        let id = doc.get_id();
+        assert_eq!(id.len(), 16);
+        // Same file, same id. Existing ids are kept across renames.
+        assert_eq!(Doc::draft("a.md").assign_id().get_id(), id);
+        let renamed = Doc {
+            id_path: PathBuf::from("b.md"),
+            ..doc
+        }
+        .assign_id();
+        assert_eq!(renamed.get_id(), id);
+    }
+
     #[test]
     fn test_uplift_meta() {
         let meta = json!({
@@ -351,4 +659,20 @@ mod tests {
         assert_eq!(doc.meta.get("b").unwrap().get("c").unwrap(), 2);
         assert_eq!(doc.meta.get("b").unwrap().get("d").unwrap(), 3);
     }
+
+    #[test]
+    fn test_read_skips_binary_and_large_files() {
+        let dir = tempdir().unwrap();
+        let binary_path = dir.path().join("image.png");
+        fs::write(&binary_path, [0x89, b'P', b'N', b'G', 0, 0, 0]).unwrap();
+        let err = Doc::read(&binary_path).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Binary));
+
+        let text_path = dir.path().join("post.md");
+        fs::write(&text_path, "Hello world").unwrap();
+        let err = Doc::read_with_max_size(&text_path, 5).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::TooLarge));
+        assert!(err.is_unreadable_file());
+        assert_eq!(Doc::read(&text_path).unwrap().content, "Hello world");
+    }
 }
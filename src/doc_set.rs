@@ -0,0 +1,294 @@
+// An owned collection of docs with indexes built on first use, for
+// operations that look across docs more than once
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::graph::Graph;
+use crate::tags::{read_meta_tags, to_tag};
+use crate::wikilink::{SlugResolution, WikilinkDocs};
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Tag index for one taxonomy: term to doc positions, in collection order
+type TagIndex = HashMap<String, Vec<usize>>;
+
+/// An owned collection of docs, with indexes by id path, title slug, and
+/// tag that are built the first time they are needed and reused after.
+///
+/// Use a `DocSet` when several passes look across the same docs, such as
+/// rendering wikilinks and then finding backlinks, related docs, or
+/// siblings, so each pass doesn't rebuild its index from scratch.
+/// `docs` gives a `Docs` iterator, so the usual combinators still apply.
+/// Docs are held behind a shared pointer, so cloning a set is cheap.
+/// Create one by collecting docs, or with `Docs::fork`.
+///
+/// Indexes are built from the docs as they were when the set was created.
+/// Transform docs through `docs` and collect a new set to re-index.
+#[derive(Debug, Clone, Default)]
+pub struct DocSet {
+    docs: Arc<Vec<Doc>>,
+    by_id_path: OnceCell<HashMap<PathBuf, usize>>,
+    by_title_slug: OnceCell<HashMap<String, Doc>>,
+    by_tag: RefCell<HashMap<String, Rc<TagIndex>>>,
+    backlinks: OnceCell<HashMap<usize, Vec<usize>>>,
+}
+
+impl DocSet {
+    pub fn new(docs: Vec<Doc>) -> Self {
+        DocSet {
+            docs: Arc::new(docs),
+            ..Default::default()
+        }
+    }
+
+    /// Iterate over clones of the docs in this set
+    pub fn docs(&self) -> impl Docs + 'static {
+        let docs = Arc::clone(&self.docs);
+        (0..docs.len()).map(move |i| docs[i].clone())
+    }
+
+    /// Iterate over the docs in this set by reference
+    pub fn iter(&self) -> std::slice::Iter<'_, Doc> {
+        self.docs.iter()
+    }
+
+    pub fn as_slice(&self) -> &[Doc] {
+        &self.docs
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    fn id_path_index(&self) -> &HashMap<PathBuf, usize> {
+        self.by_id_path.get_or_init(|| {
+            self.docs
+                .iter()
+                .enumerate()
+                .map(|(i, doc)| (doc.id_path.clone(), i))
+                .collect()
+        })
+    }
+
+    /// Get the position of a doc in this set by id path
+    fn position(&self, doc: &Doc) -> Option<usize> {
+        self.id_path_index().get(&doc.id_path).copied()
+    }
+
+    /// Get a doc by id path. When several docs share an id path, the last
+    /// one wins.
+    pub fn get(&self, id_path: impl AsRef<Path>) -> Option<&Doc> {
+        let i = *self.id_path_index().get(id_path.as_ref())?;
+        self.docs.get(i)
+    }
+
+    /// Index of docs by title slug, as used to resolve wikilinks.
+    /// When several docs share a title slug, the last one wins. Use
+    /// `WikilinkDocs::index_by_title_slug` to be warned about duplicates.
+    pub fn title_slug_index(&self) -> &HashMap<String, Doc> {
+        self.by_title_slug.get_or_init(|| {
+            self.docs()
+                .index_title_slug_candidates()
+                .into_iter()
+                .filter_map(|(slug, candidates)| {
+                    SlugResolution::default()
+                        .resolve(candidates)
+                        .map(|doc| (slug, doc))
+                })
+                .collect()
+        })
+    }
+
+    /// Get a doc by title slug. See `title_slug_index`.
+    pub fn get_by_title_slug(&self, slug: &str) -> Option<&Doc> {
+        self.title_slug_index().get(slug)
+    }
+
+    fn tag_index(&self, taxonomy_key: &str) -> Rc<TagIndex> {
+        if let Some(index) = self.by_tag.borrow().get(taxonomy_key) {
+            return Rc::clone(index);
+        }
+        let mut index: TagIndex = HashMap::new();
+        for (i, doc) in self.docs.iter().enumerate() {
+            for tag in read_meta_tags(&doc.meta, taxonomy_key) {
+                index.entry(tag).or_default().push(i);
+            }
+        }
+        let index = Rc::new(index);
+        self.by_tag
+            .borrow_mut()
+            .insert(taxonomy_key.to_string(), Rc::clone(&index));
+        index
+    }
+
+    /// Get the docs filed under a term of a taxonomy, in collection order.
+    /// The term is normalized like tags are.
+    pub fn tagged(&self, taxonomy_key: &str, term: &str) -> Vec<&Doc> {
+        let index = self.tag_index(taxonomy_key);
+        index
+            .get(&to_tag(term))
+            .map(|positions| positions.iter().map(|&i| &self.docs[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Render wikilinks between the docs in this set, using the default
+    /// template. Like `WikilinkDocs::render_wikilinks_between`, but reuses
+    /// this set's title slug index.
+    pub fn render_wikilinks(&self) -> impl Docs + '_ {
+        let index = self.title_slug_index();
+        self.docs().render_wikilinks(index)
+    }
+
+    /// Get the docs that link to a doc, by wikilink or internal href, in
+    /// collection order. Links are found as in `Graph::build`.
+    pub fn backlinks(&self, doc: &Doc) -> Vec<&Doc> {
+        let backlinks = self.backlinks.get_or_init(|| {
+            let positions: HashMap<String, usize> = self
+                .docs
+                .iter()
+                .enumerate()
+                .map(|(i, doc)| (doc.get_id(), i))
+                .collect();
+            let mut backlinks: HashMap<usize, Vec<usize>> = HashMap::new();
+            for edge in Graph::build(&self.docs, "").edges {
+                if let (Some(&source), Some(&target)) =
+                    (positions.get(&edge.source), positions.get(&edge.target))
+                {
+                    let sources = backlinks.entry(target).or_default();
+                    if !sources.contains(&source) {
+                        sources.push(source);
+                    }
+                }
+            }
+            for sources in backlinks.values_mut() {
+                sources.sort();
+            }
+            backlinks
+        });
+        self.position(doc)
+            .and_then(|i| backlinks.get(&i))
+            .map(|sources| sources.iter().map(|&i| &self.docs[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get up to `limit` docs that share tags with a doc, most shared tags
+    /// first. Ties keep collection order. The doc itself is left out.
+    pub fn related(&self, doc: &Doc, taxonomy_key: &str, limit: usize) -> Vec<&Doc> {
+        let index = self.tag_index(taxonomy_key);
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for tag in doc.get_meta_tags(taxonomy_key) {
+            for &i in index.get(&tag).into_iter().flatten() {
+                if self.docs[i].id_path != doc.id_path {
+                    *shared.entry(i).or_default() += 1;
+                }
+            }
+        }
+        let mut shared: Vec<(usize, usize)> = shared.into_iter().collect();
+        shared.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        shared
+            .into_iter()
+            .take(limit)
+            .map(|(i, _)| &self.docs[i])
+            .collect()
+    }
+
+    /// Get the docs in the same directory as a doc, by id path, in
+    /// collection order. The doc itself is left out.
+    pub fn siblings(&self, doc: &Doc) -> Vec<&Doc> {
+        let parent = doc.id_path.parent();
+        self.docs
+            .iter()
+            .filter(|other| other.id_path != doc.id_path && other.id_path.parent() == parent)
+            .collect()
+    }
+}
+
+impl From<Vec<Doc>> for DocSet {
+    fn from(docs: Vec<Doc>) -> Self {
+        DocSet::new(docs)
+    }
+}
+
+impl FromIterator<Doc> for DocSet {
+    fn from_iter<I: IntoIterator<Item = Doc>>(iter: I) -> Self {
+        DocSet::new(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for DocSet {
+    type Item = Doc;
+    type IntoIter = std::vec::IntoIter<Doc>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Arc::try_unwrap(self.docs)
+            .unwrap_or_else(|docs| docs.as_ref().clone())
+            .into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DocSet {
+    type Item = &'a Doc;
+    type IntoIter = std::slice::Iter<'a, Doc>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.docs.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn id_paths(docs: Vec<&Doc>) -> Vec<String> {
+        docs.iter()
+            .map(|doc| doc.id_path.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_doc_set_indexes() {
+        let set: DocSet = vec![
+            Doc::draft("notes/a.md")
+                .set_title("Alpha")
+                .set_output_path("notes/a/index.html")
+                .set_meta(json!({"tags": ["rust", "web"]}))
+                .set_content("See [[Beta]]."),
+            Doc::draft("notes/b.md")
+                .set_title("Beta")
+                .set_output_path("notes/b/index.html")
+                .set_meta(json!({"tags": ["rust"]})),
+            Doc::draft("notes/c.md")
+                .set_title("Gamma")
+                .set_output_path("notes/c/index.html")
+                .set_meta(json!({"tags": ["rust", "web"]}))
+                .set_content(r#"<a href="/notes/b/">B</a>"#),
+            Doc::draft("d.md").set_title("Delta"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(set.get("notes/b.md").unwrap().title, "Beta");
+        assert_eq!(set.get_by_title_slug("gamma").unwrap().title, "Gamma");
+        assert_eq!(
+            id_paths(set.tagged("tags", "Web")),
+            vec!["notes/a.md", "notes/c.md"]
+        );
+
+        let b = set.get("notes/b.md").unwrap();
+        assert_eq!(id_paths(set.backlinks(b)), vec!["notes/a.md", "notes/c.md"]);
+        let a = set.get("notes/a.md").unwrap();
+        assert_eq!(id_paths(set.related(a, "tags", 1)), vec!["notes/c.md"]);
+        assert_eq!(id_paths(set.siblings(a)), vec!["notes/b.md", "notes/c.md"]);
+
+        let rendered: Vec<Doc> = set.render_wikilinks().collect();
+        assert!(rendered[0].content.contains(r#"href="/notes/b/""#));
+        assert_eq!(set.docs().count(), 4);
+    }
+}
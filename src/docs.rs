@@ -1,39 +1,72 @@
 use crate::doc::Doc;
-use crate::error::Error;
-use crate::io::{dump_errors_to_stderr, panic_at_first_error};
+use crate::doc_set::DocSet;
+use crate::error::{Error, ErrorHandler, ErrorKind};
+use crate::io::{
+    default_threads, dump_errors_to_stderr, map_parallel, panic_at_first_error,
+    write_json_lines_to_stdout,
+};
+use crate::json;
+use crate::stats;
+use crate::stub::Stub;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
 /// Docs trait is any iterator of Docs
 pub trait Docs: Iterator<Item = Doc> + Sized {
-    /// Write docs to file system under output_dir
-    /// Prints a series of confirmation messages
-    fn write(self, output_dir: &Path) {
-        for doc in self {
-            match doc.write(output_dir) {
-                Ok(write_path) => {
-                    println!(
-                        "Wrote {} → {}",
-                        doc.id_path.to_string_lossy(),
-                        write_path.to_string_lossy()
-                    )
-                }
-                Err(err) => eprintln!("{:?}", err),
-            }
-        }
+    /// Write docs to file system under output_dir.
+    /// Files are written in parallel. When several docs share an output
+    /// path, the last one in stream order is written, as if docs were
+    /// written one after another. Docs without an output path are skipped.
+    /// Returns a report of the files written and the docs that failed to
+    /// write, in stream order.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    fn write(self, output_dir: &Path) -> WriteReport {
+        write_with_report(self, output_dir, |doc| doc.write(output_dir))
     }
 
     /// Write docs to stdio
-    /// - JSON serialized docs are printed to stdout
-    /// - Serialization failures are printed to stderr
+    /// - JSON serialized docs are printed to stdout, through a buffered
+    ///   writer. Docs are serialized in parallel, and printed in order.
+    /// - Serialization failures are logged as `tracing` errors
     fn write_stdio(self) {
-        for doc in self {
-            doc.write_stdio();
-        }
+        write_json_lines_to_stdout(self)
+    }
+
+    /// Apply an RFC 7396 merge patch to the meta of every doc.
+    /// Docs without object meta are given an empty object first, so that
+    /// `null` values in the patch are dropped rather than copied.
+    fn merge_meta(self, patch: json::Value) -> impl Docs {
+        self.map(move |doc| merge_meta_patch(doc, &patch))
+    }
+
+    /// Apply an RFC 7396 merge patch to the meta of docs who's id_path
+    /// matches a glob pattern. Other docs are passed through unchanged.
+    fn merge_meta_matching(self, patch: json::Value, glob_pattern: &str) -> impl Docs {
+        let matcher = glob::Pattern::new(glob_pattern).expect("Invalid glob pattern");
+        self.map(move |doc| {
+            if matcher.matches(&doc.id_path.to_string_lossy()) {
+                merge_meta_patch(doc, &patch)
+            } else {
+                doc
+            }
+        })
+    }
+
+    /// Rewrite output paths, replacing the first match of `pattern` with
+    /// `replacement`. Replacements may refer to capture groups, e.g. `$1`.
+    fn rename_output(self, pattern: &Regex, replacement: &str) -> impl Docs {
+        self.map(move |doc| doc.rename_output(pattern, replacement))
+    }
+
+    /// Replace every match of `pattern` in a text field of each doc.
+    /// See `Doc::replace_text` for the fields supported.
+    fn replace_text(self, field: &str, pattern: &Regex, replacement: &str) -> impl DocResults {
+        self.map(move |doc| doc.replace_text(field, pattern, replacement))
     }
 
     /// Filter out docs with a given id_path
@@ -53,44 +86,34 @@ pub trait Docs: Iterator<Item = Doc> + Sized {
         })
     }
 
-    /// Filter out docs who's file name in the id_path starts with an underscore.
+    /// Filter out drafts: docs who's file name in the id_path starts with an
+    /// underscore, or that have `draft: true` in meta. Drafts published as
+    /// previews are kept. See `Doc::is_draft` and `preview::PreviewDocs`.
     fn remove_drafts(self) -> impl Docs {
-        self.filter(|doc| {
-            !Path::new(&doc.id_path)
-                .file_name()
-                .expect("Could not get file name")
-                .to_str()
-                .expect("Could not convert file name to str")
-                .starts_with('_')
-        })
+        self.filter(|doc| doc.is_preview() || !doc.is_draft())
     }
 
     /// Filter out docs who's file name in the id_path is "index".
     fn remove_index(self) -> impl Docs {
-        self.filter(|doc| {
-            Path::new(&doc.id_path)
-                .file_stem()
-                .expect("Could not get file stem from path")
-                != "index"
-        })
+        self.filter(|doc| !doc.is_index())
     }
 
-    /// De-duplicate docs by id_path
+    /// De-duplicate docs by id. Docs are identified by `meta.id`, falling
+    /// back to id_path. See `Doc::get_id`.
     fn dedupe(self) -> impl Docs {
         let mut seen = HashSet::new();
-        self.filter(move |doc| seen.insert(doc.id_path.clone()))
+        self.filter(move |doc| seen.insert(doc.get_id()))
+    }
+
+    /// Assign stable ids to docs that don't have one. See `Doc::assign_id`.
+    fn assign_ids(self) -> impl Docs {
+        self.map(|doc| doc.assign_id())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     fn sorted_by(self, key: SortKey, asc: bool) -> impl Docs {
         let docs: Vec<Doc> = self.collect();
-        let sorted = match key {
-            SortKey::IdPath => sorted_by(docs, |a, b| a.id_path.cmp(&b.id_path), asc),
-            SortKey::OutputPath => sorted_by(docs, |a, b| a.output_path.cmp(&b.output_path), asc),
-            SortKey::Created => sorted_by(docs, |a, b| a.created.cmp(&b.created), asc),
-            SortKey::Modified => sorted_by(docs, |a, b| a.modified.cmp(&b.modified), asc),
-            SortKey::Title => sorted_by(docs, |a, b| a.title.cmp(&b.title), asc),
-        };
-        sorted.into_iter()
+        sorted_by(docs, |a, b| key.compare(a, b), asc).into_iter()
     }
 
     /// Get most recent n docs
@@ -108,6 +131,11 @@ pub trait Docs: Iterator<Item = Doc> + Sized {
         self.map(|doc| doc.set_extension_html())
     }
 
+    /// Substitute site-wide tokens (`{{key}}` or `:key:`) in content.
+    fn substitute_tokens(self, tokens: &HashMap<String, String>) -> impl Docs {
+        self.map(move |doc| doc.substitute_tokens(tokens))
+    }
+
     /// Set template
     fn set_template(self, template_path: impl Into<PathBuf>) -> impl Docs {
         let template_path: PathBuf = template_path.into();
@@ -119,6 +147,121 @@ pub trait Docs: Iterator<Item = Doc> + Sized {
     fn auto_template(self) -> impl Docs {
         self.map(move |doc| doc.auto_template())
     }
+
+    /// Buffer docs so several consumers, such as a feed, a sitemap, and the
+    /// pages themselves, can each iterate them from one read pass.
+    ///
+    /// Docs are held once, behind a shared pointer, so cloning the set is
+    /// cheap. Each call to `DocSet::docs` yields clones of the buffered docs,
+    /// which can be transformed without affecting other consumers.
+    ///
+    /// ```
+    /// use lettersmith::prelude::*;
+    ///
+    /// let posts = vec![Doc::draft("a.md"), Doc::draft("b.md")]
+    ///     .into_iter()
+    ///     .fork();
+    /// let recent: Vec<Doc> = posts.docs().most_recent(1).collect();
+    /// let all: Vec<Doc> = posts.docs().collect();
+    /// assert_eq!((recent.len(), all.len()), (1, 2));
+    /// ```
+    fn fork(self) -> DocSet {
+        self.collect()
+    }
+}
+
+/// Apply a merge patch to doc meta, coercing non-object meta to an empty
+/// object first.
+fn merge_meta_patch(mut doc: Doc, patch: &json::Value) -> Doc {
+    if !doc.meta.is_object() {
+        doc.meta = json::json!({});
+    }
+    doc.merge_meta(patch.clone())
+}
+
+/// The outcome of writing docs to the file system
+#[derive(Debug, Default)]
+pub struct WriteReport {
+    /// Directory docs were written under
+    pub output_dir: PathBuf,
+    /// Paths of the files written
+    pub written: Vec<PathBuf>,
+    /// id_paths of docs that were skipped because they have no output path
+    pub skipped: Vec<PathBuf>,
+    /// Errors for docs that failed to write
+    pub failed: Vec<Error>,
+}
+
+impl WriteReport {
+    /// Were all docs written (or skipped) without errors?
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// Get a one-line summary, such as `Wrote 12 docs to public (1 failed)`
+    pub fn summary(&self) -> String {
+        let mut notes = Vec::new();
+        if !self.skipped.is_empty() {
+            notes.push(format!("{} skipped", self.skipped.len()));
+        }
+        if !self.failed.is_empty() {
+            notes.push(format!("{} failed", self.failed.len()));
+        }
+        let summary = format!(
+            "Wrote {} docs to {}",
+            self.written.len(),
+            self.output_dir.to_string_lossy()
+        );
+        if notes.is_empty() {
+            summary
+        } else {
+            format!("{} ({})", summary, notes.join(", "))
+        }
+    }
+}
+
+/// Write docs in parallel with a write function, collecting a report.
+/// Docs without an output path are skipped. When several docs share an
+/// output path, only the last one in stream order is written, so parallel
+/// writes give the same result as sequential ones. Report entries are in
+/// stream order.
+pub(crate) fn write_with_report<F>(docs: impl Docs, output_dir: &Path, write: F) -> WriteReport
+where
+    F: Fn(Doc) -> Result<PathBuf, Error> + Sync,
+{
+    let mut report = WriteReport {
+        output_dir: output_dir.to_path_buf(),
+        ..Default::default()
+    };
+    // Keep the last doc for each output path, at the position of the first
+    let mut positions: HashMap<PathBuf, usize> = HashMap::new();
+    let mut latest: Vec<Doc> = Vec::new();
+    for doc in docs {
+        if doc.output_path.as_os_str().is_empty() {
+            report.skipped.push(doc.id_path);
+            continue;
+        }
+        match positions.get(&doc.output_path) {
+            Some(&i) => latest[i] = doc,
+            None => {
+                positions.insert(doc.output_path.clone(), latest.len());
+                latest.push(doc);
+            }
+        }
+    }
+    let mut results = map_parallel(
+        latest.into_iter().enumerate(),
+        default_threads(),
+        |(i, doc)| (i, write(doc)),
+    );
+    results.sort_by_key(|(i, _)| *i);
+    for (_, result) in results {
+        match result {
+            Ok(path) => report.written.push(path),
+            Err(err) => report.failed.push(err),
+        }
+    }
+    report
 }
 
 /// Blanket-implement DocIterator for any iterator of docs
@@ -131,6 +274,30 @@ pub trait DocResults: Iterator<Item = Result<Doc, Error>> + Sized {
         dump_errors_to_stderr(self)
     }
 
+    /// Report errors with an error handler, according to its on-error
+    /// policy and format. Returns an Iterator of the successful docs.
+    /// If the policy is to abort, the iterator ends at the first error.
+    fn handle_errors(self, handler: &ErrorHandler) -> impl Docs {
+        self.map_while(move |result| match result {
+            Ok(doc) => Some(Some(doc)),
+            Err(err) => handler.handle(err).ok().map(|_| None),
+        })
+        .flatten()
+    }
+
+    /// Skip files that couldn't be read as text docs because they are binary
+    /// or too large, logging a `tracing` warning for each.
+    /// Other errors are passed through.
+    fn skip_unreadable_files(self) -> impl DocResults {
+        self.filter(|result| match result {
+            Err(err) if err.is_unreadable_file() => {
+                tracing::warn!("Skipping {}", err.to_string().lines().next().unwrap_or(""));
+                false
+            }
+            _ => true,
+        })
+    }
+
     /// Panic at the first error spotted.
     /// Panic prints a debug error to stderr.
     fn panic_at_first_error(self) -> impl Docs {
@@ -140,23 +307,121 @@ pub trait DocResults: Iterator<Item = Result<Doc, Error>> + Sized {
 
 impl<I> DocResults for I where I: Iterator<Item = Result<Doc, Error>> {}
 
+/// Is this path a glob pattern? Paths containing `*`, `?` or `[` are
+/// treated as patterns.
+pub fn is_glob_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Expand glob patterns in a list of paths.
+/// Plain paths are passed through as-is. Patterns are expanded to the
+/// matching paths, in alphabetical order. This lets `smith read posts/*.md`
+/// work in shells that don't expand globs, such as Windows cmd/PowerShell.
+/// Matches that can't be read are skipped, with a `tracing` warning.
+pub fn expand_globs(paths: impl IntoIterator<Item = PathBuf>) -> Result<Vec<PathBuf>, Error> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        if !is_glob_pattern(&path) {
+            expanded.push(path);
+            continue;
+        }
+        for entry in glob::glob(&path.to_string_lossy())? {
+            match entry {
+                Ok(path) => expanded.push(path),
+                Err(err) => tracing::warn!("Could not read glob match: {}", err),
+            }
+        }
+    }
+    Ok(expanded)
+}
+
 /// Load documents from an iterator of paths.
 /// Returns an iterator of doc results.
 pub fn read(paths: impl Iterator<Item = PathBuf>) -> impl DocResults {
     paths.map(|path| Doc::read(path))
 }
 
+/// Load documents from an iterator of paths, refusing files over
+/// `max_size` bytes. Returns an iterator of doc results.
+pub fn read_with_max_size(paths: impl Iterator<Item = PathBuf>, max_size: u64) -> impl DocResults {
+    paths.map(move |path| Doc::read_with_max_size(path, max_size))
+}
+
+/// Read stubs from an iterator of paths, loading only frontmatter and file
+/// stats. See `Stub::read`.
+pub fn read_stubs(
+    paths: impl Iterator<Item = PathBuf>,
+) -> impl Iterator<Item = Result<Stub, Error>> {
+    paths.map(Stub::read)
+}
+
 /// Parse JSON documents from stdin as line-separated JSON.
+/// Blank and non-JSON lines are skipped. See `read_lines`.
 /// Returns an iterator of doc results.
 pub fn read_stdin() -> impl DocResults {
-    io::stdin()
-        .lock()
-        .lines()
-        .filter_map(Result::ok)
-        .map(|line| match serde_json::from_str(&line) {
-            Ok(doc) => Ok(doc),
-            Err(err) => Err(Error::from(err)),
-        })
+    read_stdin_with(false)
+}
+
+/// Parse JSON documents from stdin as line-separated JSON, optionally in
+/// strict mode. See `read_lines`.
+pub fn read_stdin_with(strict: bool) -> impl DocResults {
+    read_lines(io::stdin().lock(), strict)
+}
+
+/// Get the start of a line, for error messages
+fn preview_line(line: &str) -> String {
+    const MAX_CHARS: usize = 60;
+    if line.chars().count() <= MAX_CHARS {
+        return line.to_string();
+    }
+    let preview: String = line.chars().take(MAX_CHARS).collect();
+    preview + "…"
+}
+
+/// Parse line-separated JSON docs from a reader.
+/// Parse errors are reported with the line number and a preview of the
+/// line.
+///
+/// Blank lines are skipped, as are lines that don't start with `{`, such as
+/// stray `echo` output in a pipeline, with a `tracing` warning. In strict
+/// mode, every line must be a doc, and these lines are errors too.
+pub fn read_lines(reader: impl BufRead, strict: bool) -> impl DocResults {
+    reader.lines().enumerate().filter_map(move |(i, line)| {
+        let number = i + 1;
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                let msg = format!("Could not read line {}", number);
+                return Some(Err(Error::new(ErrorKind::Io(err), msg)));
+            }
+        };
+        let trimmed = line.trim();
+        if !strict && trimmed.is_empty() {
+            return None;
+        }
+        if !strict && !trimmed.starts_with('{') {
+            tracing::warn!(
+                "Skipping line {}, which is not a JSON doc: {}",
+                number,
+                preview_line(trimmed)
+            );
+            return None;
+        }
+        match serde_json::from_str(trimmed) {
+            Ok(doc) => {
+                stats::record_read();
+                Some(Ok(doc))
+            }
+            Err(err) => {
+                let msg = format!(
+                    "Could not parse line {} as a JSON doc: {}",
+                    number,
+                    preview_line(trimmed)
+                );
+                Some(Err(Error::new(ErrorKind::Json(err), msg)))
+            }
+        }
+    })
 }
 
 #[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -167,6 +432,23 @@ pub enum SortKey {
     Created,
     Modified,
     Title,
+    /// `weight` (or `order`) in meta, then title
+    Weight,
+}
+
+impl SortKey {
+    /// Compare two docs by this key, in ascending order.
+    /// Keys never look at content, so docs can be compared without it.
+    pub fn compare(&self, a: &Doc, b: &Doc) -> Ordering {
+        match self {
+            SortKey::IdPath => a.id_path.cmp(&b.id_path),
+            SortKey::OutputPath => a.output_path.cmp(&b.output_path),
+            SortKey::Created => a.created.cmp(&b.created),
+            SortKey::Modified => a.modified.cmp(&b.modified),
+            SortKey::Title => a.title.cmp(&b.title),
+            SortKey::Weight => compare_weight(a.get_weight(), &a.title, b.get_weight(), &b.title),
+        }
+    }
 }
 
 impl From<SortKey> for &str {
@@ -177,6 +459,7 @@ impl From<SortKey> for &str {
             SortKey::Modified => "modified",
             SortKey::OutputPath => "output_path",
             SortKey::Title => "title",
+            SortKey::Weight => "weight",
         }
     }
 }
@@ -190,6 +473,7 @@ impl TryFrom<&str> for SortKey {
             "created" => Ok(SortKey::Created),
             "modified" => Ok(SortKey::Modified),
             "title" => Ok(SortKey::Title),
+            "weight" => Ok(SortKey::Weight),
             _ => Err(Error::value(format!(
                 "String {} does not correspond to any SortKey",
                 value
@@ -198,7 +482,32 @@ impl TryFrom<&str> for SortKey {
     }
 }
 
-fn sorted_by<T, F>(mut vec: Vec<T>, mut compare: F, asc: bool) -> Vec<T>
+/// Read a manual ordering weight from meta.
+/// Looks for `weight`, then `order`, and defaults to 0. Lower weights sort
+/// first.
+pub fn read_meta_weight(meta: &json::Value) -> i64 {
+    ["weight", "order"]
+        .iter()
+        .find_map(|key| {
+            let value = meta.get(key)?;
+            value
+                .as_i64()
+                .or_else(|| value.as_f64().map(|weight| weight.round() as i64))
+        })
+        .unwrap_or(0)
+}
+
+/// Compare by weight, breaking ties by title
+pub(crate) fn compare_weight(
+    a_weight: i64,
+    a_title: &str,
+    b_weight: i64,
+    b_title: &str,
+) -> Ordering {
+    a_weight.cmp(&b_weight).then_with(|| a_title.cmp(b_title))
+}
+
+pub(crate) fn sorted_by<T, F>(mut vec: Vec<T>, mut compare: F, asc: bool) -> Vec<T>
 where
     F: FnMut(&T, &T) -> Ordering,
 {
@@ -220,6 +529,25 @@ mod tests {
         Doc::draft(id).set_title(title)
     }
 
+    #[test]
+    fn test_handle_errors() {
+        use crate::error::{ErrorFormat, OnError};
+        let results = || {
+            vec![
+                Ok(make_test_doc("a.md", "A")),
+                Err(Error::value("Bad doc")),
+                Ok(make_test_doc("b.md", "B")),
+            ]
+            .into_iter()
+        };
+        let skip = ErrorHandler::new(OnError::Skip, ErrorFormat::Text, "test");
+        assert_eq!(results().handle_errors(&skip).count(), 2);
+        let abort = ErrorHandler::new(OnError::Abort, ErrorFormat::Text, "test");
+        let docs: Vec<Doc> = results().handle_errors(&abort).collect();
+        assert_eq!(docs, vec![make_test_doc("a.md", "A")]);
+        assert!(abort.aborted());
+    }
+
     #[test]
     fn test_remove_with_id_path() {
         let docs = vec![
@@ -283,6 +611,12 @@ mod tests {
 
         assert_eq!(deduped.len(), 1);
         assert_eq!(deduped[0].id_path, PathBuf::from("doc1.md"));
+
+        let docs = vec![
+            make_test_doc("old.md", "Doc").set_meta(json::json!({"id": "abc"})),
+            make_test_doc("new.md", "Doc").set_meta(json::json!({"id": "abc"})),
+        ];
+        assert_eq!(docs.into_iter().dedupe().count(), 1);
     }
 
     #[test]
@@ -298,9 +632,26 @@ mod tests {
         assert_eq!(sorted[1].title, "B Doc");
     }
 
+    #[test]
+    fn test_sorted_by_weight() {
+        let docs = vec![
+            make_test_doc("a.md", "A").set_meta(json::json!({"weight": 10})),
+            make_test_doc("b.md", "B").set_meta(json::json!({"order": -1})),
+            make_test_doc("c.md", "C"),
+            make_test_doc("d.md", "D").set_meta(json::json!({"weight": 10})),
+        ];
+        let sorted: Vec<String> = docs
+            .into_iter()
+            .sorted_by(SortKey::Weight, true)
+            .map(|doc| doc.title)
+            .collect();
+        assert_eq!(sorted, vec!["B", "C", "A", "D"]);
+    }
+
     #[test]
     fn test_sort_key_from_string() {
         assert_eq!(SortKey::try_from("title").unwrap(), SortKey::Title);
+        assert_eq!(SortKey::try_from("weight").unwrap(), SortKey::Weight);
         assert_eq!(SortKey::try_from("id_path").unwrap(), SortKey::IdPath);
         assert_eq!(
             SortKey::try_from("output_path").unwrap(),
@@ -329,4 +680,141 @@ mod tests {
         eprint!("!!! {:?}", modified[0].output_path);
         assert_eq!(modified[0].output_path.extension().unwrap(), "html");
     }
+
+    #[test]
+    fn test_merge_meta_matching() {
+        let docs = vec![
+            make_test_doc("posts/a.md", "A").set_meta(json::json!({"draft": true, "tags": ["a"]})),
+            make_test_doc("posts/b.md", "B"),
+            make_test_doc("pages/c.md", "C").set_meta(json::json!({"draft": true})),
+        ];
+        let patch = json::json!({"draft": null, "layout": "post"});
+        let docs: Vec<Doc> = docs
+            .into_iter()
+            .merge_meta_matching(patch, "posts/**")
+            .collect();
+        assert_eq!(docs[0].meta, json::json!({"tags": ["a"], "layout": "post"}));
+        assert_eq!(docs[1].meta, json::json!({"layout": "post"}));
+        assert_eq!(docs[2].meta, json::json!({"draft": true}));
+    }
+
+    #[test]
+    fn test_rename_output() {
+        let pattern = Regex::new(r"^notes/(\d+)-").unwrap();
+        let docs: Vec<Doc> = vec![
+            make_test_doc("notes/01-a.md", "A").set_output_path("notes/01-a/index.html"),
+            make_test_doc("posts/b.md", "B").set_output_path("posts/b/index.html"),
+        ]
+        .into_iter()
+        .rename_output(&pattern, "garden/$1/")
+        .collect();
+        assert_eq!(docs[0].output_path, PathBuf::from("garden/01/a/index.html"));
+        assert_eq!(docs[1].output_path, PathBuf::from("posts/b/index.html"));
+    }
+
+    #[test]
+    fn test_replace_text() {
+        let pattern = Regex::new(r"http://old\.example").unwrap();
+        let results: Vec<Result<Doc, Error>> = vec![make_test_doc("a.md", "A")
+            .set_content("See http://old.example/a and http://old.example/b")
+            .set_meta(json::json!({"links": {"home": "http://old.example", "n": 1}}))]
+        .into_iter()
+        .replace_text("content", &pattern, "https://new.example")
+        .map(|result| result.unwrap())
+        .replace_text("meta.links", &pattern, "https://new.example")
+        .collect();
+        let doc = results[0].as_ref().unwrap();
+        assert_eq!(
+            doc.content,
+            "See https://new.example/a and https://new.example/b"
+        );
+        assert_eq!(
+            doc.meta,
+            json::json!({"links": {"home": "https://new.example", "n": 1}})
+        );
+
+        let result = make_test_doc("a.md", "A").replace_text("output_path", &pattern, "x");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_expand_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["b.md", "a.md", "c.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let paths = expand_globs(vec![
+            dir.path().join("*.md"),
+            PathBuf::from("not/a/pattern.md"),
+        ])
+        .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.path().join("a.md"),
+                dir.path().join("b.md"),
+                PathBuf::from("not/a/pattern.md"),
+            ]
+        );
+        assert!(expand_globs(vec![PathBuf::from("[")]).is_err());
+    }
+
+    #[test]
+    fn test_write_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs: Vec<Doc> = (0..20)
+            .map(|i| make_test_doc(&format!("posts/{}.html", i), "Doc").set_content("Hi"))
+            .chain((0..20).map(|i| {
+                make_test_doc(&format!("dupes/{}.html", i), "Dupe")
+                    .set_output_path("posts/7.html")
+                    .set_content(format!("Dupe {}", i))
+            }))
+            .collect();
+        let report = docs.into_iter().write(dir.path());
+        assert!(report.is_ok());
+        // Written in stream order, once per output path
+        let expected: Vec<PathBuf> = (0..20)
+            .map(|i| dir.path().join(format!("posts/{}.html", i)))
+            .collect();
+        assert_eq!(report.written, expected);
+        // The last doc with a shared output path wins
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("posts/7.html")).unwrap(),
+            "Dupe 19"
+        );
+    }
+
+    #[test]
+    fn test_write_report() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("file"), "").unwrap();
+        let docs = vec![
+            make_test_doc("a.html", "A"),
+            make_test_doc("b.html", "B").set_output_path(""),
+            // Can't create a directory where a file is
+            make_test_doc("c.html", "C").set_output_path("file/c.html"),
+        ];
+        let report = docs.into_iter().write(dir.path());
+        assert_eq!(report.written, vec![dir.path().join("a.html")]);
+        assert_eq!(report.skipped, vec![PathBuf::from("b.html")]);
+        assert_eq!(report.failed.len(), 1);
+        assert!(!report.is_ok());
+        assert!(report.summary().ends_with("(1 skipped, 1 failed)"));
+    }
+
+    #[test]
+    fn test_read_lines() {
+        let doc = serde_json::to_string(&make_test_doc("a.md", "A")).unwrap();
+        let input = format!("{}\n\nhello world\n{{\"id_path\": 1}}\n", doc);
+        let results: Vec<Result<Doc, Error>> = read_lines(input.as_bytes(), false).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap().id_path, PathBuf::from("a.md"));
+        let err = results[1].as_ref().unwrap_err();
+        assert!(err.msg.starts_with("Could not parse line 4 as a JSON doc"));
+
+        let results: Vec<Result<Doc, Error>> = read_lines(input.as_bytes(), true).collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[1].as_ref().unwrap_err().msg.contains("line 2"));
+        assert!(results[2].as_ref().unwrap_err().msg.contains("hello world"));
+    }
 }
@@ -0,0 +1,232 @@
+// Diagnose common project setup problems
+use crate::config::Config;
+use crate::error::Error;
+use crate::json;
+use crate::tera;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of a single diagnostic check, with an actionable message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    pub fn new(name: impl Into<String>, status: CheckStatus, message: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn pass(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Check::new(name, CheckStatus::Pass, message)
+    }
+
+    pub fn warn(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Check::new(name, CheckStatus::Warn, message)
+    }
+
+    pub fn fail(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Check::new(name, CheckStatus::Fail, message)
+    }
+}
+
+/// Check that the config file exists and parses.
+/// Returns the check, along with the config to use for later checks.
+pub fn check_config(config_path: &Path, profile: Option<&str>) -> (Check, Config) {
+    let name = "config";
+    if !config_path.exists() {
+        let check = Check::warn(
+            name,
+            format!(
+                "{} not found. Using default config. Run `smith init` to create one, or set the CONFIG env var.",
+                config_path.display()
+            ),
+        );
+        return (check, Config::default());
+    }
+    match Config::read_with_profile(config_path, profile) {
+        Ok(config) => (
+            Check::pass(name, format!("{} parsed", config_path.display())),
+            config,
+        ),
+        Err(err) => (
+            Check::fail(
+                name,
+                format!("Could not parse {}: {}", config_path.display(), err.msg),
+            ),
+            Config::default(),
+        ),
+    }
+}
+
+/// Check that the config's template glob matches at least one file
+pub fn check_template_glob(config: &Config) -> Check {
+    let name = "templates";
+    let paths = match glob::glob(&config.templates) {
+        Ok(paths) => paths,
+        Err(err) => {
+            return Check::fail(
+                name,
+                format!("Invalid templates glob \"{}\": {}", config.templates, err),
+            )
+        }
+    };
+    let count = paths.filter_map(|path| path.ok()).count();
+    if count == 0 {
+        Check::fail(
+            name,
+            format!(
+                "Templates glob \"{}\" matched no files. Check the templates field in config, and that you are running smith from the project directory.",
+                config.templates
+            ),
+        )
+    } else {
+        Check::pass(
+            name,
+            format!("\"{}\" matched {} file(s)", config.templates, count),
+        )
+    }
+}
+
+/// Check that templates compile under Tera
+pub fn check_template_compile(config: &Config) -> Check {
+    let name = "template syntax";
+    match tera::renderer_for_config(config) {
+        Ok(_) => Check::pass(name, "Templates compiled"),
+        Err(err) => Check::fail(name, format!("Templates failed to compile: {}", err)),
+    }
+}
+
+/// Check that a data file exists and parses as JSON
+pub fn check_data_file(path: &Path) -> Check {
+    let name = format!("data {}", path.display());
+    let parsed = fs::read_to_string(path)
+        .map_err(Error::from)
+        .and_then(|text| json::from_str::<json::Value>(&text).map_err(Error::from));
+    match parsed {
+        Ok(_) => Check::pass(name, "Parsed"),
+        Err(err) => Check::fail(name, format!("Could not read data file: {}", err.kind)),
+    }
+}
+
+/// Check that the output directory can be written to.
+/// If the directory doesn't exist yet, checks the nearest existing ancestor,
+/// since `smith write` will create the directory.
+pub fn check_output_dir(output_dir: &Path) -> Check {
+    let name = "output dir";
+    let mut dir = output_dir.to_path_buf();
+    while !dir.exists() {
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => dir = parent.to_path_buf(),
+            _ => {
+                dir = PathBuf::from(".");
+                break;
+            }
+        }
+    }
+    if !dir.is_dir() {
+        return Check::fail(
+            name,
+            format!("{} exists, but is not a directory", dir.display()),
+        );
+    }
+    let probe = dir.join(".lettersmith-doctor");
+    match fs::write(&probe, "") {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Check::pass(name, format!("{} is writable", output_dir.display()))
+        }
+        Err(err) => Check::fail(name, format!("Cannot write to {}: {}", dir.display(), err)),
+    }
+}
+
+/// Run all diagnostic checks for a project.
+/// The output directory defaults to `output_dir` in config.
+pub fn diagnose(
+    config_path: &Path,
+    profile: Option<&str>,
+    data_files: &[PathBuf],
+    output_dir: Option<&Path>,
+) -> Vec<Check> {
+    let (config_check, config) = check_config(config_path, profile);
+    let mut checks = vec![config_check];
+    let glob_check = check_template_glob(&config);
+    let has_templates = glob_check.status == CheckStatus::Pass;
+    checks.push(glob_check);
+    if has_templates {
+        checks.push(check_template_compile(&config));
+    }
+    for path in data_files {
+        checks.push(check_data_file(path));
+    }
+    checks.push(check_output_dir(output_dir.unwrap_or(&config.output_dir)));
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_diagnose() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("lettersmith.json");
+        let templates = dir.path().join("templates/*.html");
+        fs::write(
+            &config_path,
+            json::json!({"templates": templates.to_string_lossy()}).to_string(),
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("templates")).unwrap();
+        fs::write(dir.path().join("templates/default.html"), "{{ doc.title }}").unwrap();
+
+        let checks = diagnose(&config_path, None, &[], Some(&dir.path().join("public")));
+        assert!(checks.iter().all(|check| check.status == CheckStatus::Pass));
+    }
+
+    #[test]
+    fn test_diagnose_failures() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("lettersmith.json");
+        let templates = dir.path().join("templates/*.html");
+        fs::write(
+            &config_path,
+            json::json!({"templates": templates.to_string_lossy()}).to_string(),
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("templates")).unwrap();
+        fs::write(dir.path().join("templates/default.html"), "{{ doc.title").unwrap();
+        let data_path = dir.path().join("missing.json");
+
+        let checks = diagnose(&config_path, None, &[data_path], Some(dir.path()));
+        let failed: Vec<&str> = checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[0], "template syntax");
+    }
+
+    #[test]
+    fn test_check_config_missing() {
+        let dir = tempdir().unwrap();
+        let (check, _) = check_config(&dir.path().join("nope.json"), None);
+        assert_eq!(check.status, CheckStatus::Warn);
+    }
+}
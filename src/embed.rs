@@ -0,0 +1,294 @@
+// Obsidian-style embeds: `![[image.png]]` and `![[note]]`
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::html::escape_xml;
+use crate::text::to_slug;
+use crate::wikilink::WikilinkDocs;
+use regex::{self, Regex};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+static EMBED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"!\[\[([^\]]+)\]\]").expect("Could not parse regular expression"));
+
+const IMAGE_EXTENSIONS: [&str; 9] = [
+    "png", "jpg", "jpeg", "gif", "svg", "webp", "avif", "bmp", "ico",
+];
+
+/// Represents a parsed embed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embed {
+    /// The embedded file or note, e.g. `image.png` or `Some Note`
+    pub target: String,
+    /// Alt text or size hint after a pipe, e.g. `![[image.png|300]]`
+    pub option: Option<String>,
+}
+
+impl Embed {
+    /// Is this an embed of an image file?
+    pub fn is_image(&self) -> bool {
+        is_image_path(&self.target)
+    }
+
+    /// The file name of the embedded file, without any parent directories
+    pub fn file_name(&self) -> String {
+        Path::new(&self.target)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.target.clone())
+    }
+
+    /// The title slug of an embedded note. Heading and block references
+    /// (`Note#Heading`) are ignored, and the whole note is embedded.
+    pub fn slug(&self) -> String {
+        let note = self.target.split('#').next().unwrap_or_default();
+        to_slug(note.trim())
+    }
+}
+
+fn parse_embed(inner: &str) -> Embed {
+    match inner.split_once('|') {
+        Some((target, option)) => Embed {
+            target: target.trim().to_string(),
+            option: Some(option.trim().to_string()),
+        },
+        None => Embed {
+            target: inner.trim().to_string(),
+            option: None,
+        },
+    }
+}
+
+/// Is this path an image file, judging by its extension?
+pub fn is_image_path(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+pub fn find_embeds(text: &str) -> impl Iterator<Item = Embed> + '_ {
+    EMBED.captures_iter(text).map(|caps| parse_embed(&caps[1]))
+}
+
+/// Render an image embed as an `<img>` tag pointing at the copied asset.
+/// A numeric option is treated as a width (`300`) or width and height
+/// (`300x200`). Any other option is treated as alt text.
+fn render_image_embed(embed: &Embed, assets_path: &str) -> String {
+    let src = format!(
+        "{}/{}",
+        assets_path.trim_end_matches('/'),
+        embed.file_name()
+    );
+    let mut attrs = format!(r#"src="{}""#, escape_xml(&src));
+    let size = embed.option.as_deref().and_then(|option| {
+        let (width, height) = option.split_once('x').unwrap_or((option, ""));
+        let width = width.parse::<u32>().ok()?;
+        Some((width, height.parse::<u32>().ok()))
+    });
+    match size {
+        Some((width, height)) => {
+            attrs.push_str(&format!(r#" alt="" width="{}""#, width));
+            if let Some(height) = height {
+                attrs.push_str(&format!(r#" height="{}""#, height));
+            }
+        }
+        None => {
+            let alt = embed.option.as_deref().unwrap_or_default();
+            attrs.push_str(&format!(r#" alt="{}""#, escape_xml(alt)));
+        }
+    }
+    format!("<img {}>", attrs)
+}
+
+/// Render embeds in text.
+/// Image embeds become `<img>` tags pointing at `assets_path`.
+/// Note embeds are replaced with the content of the doc who's title slug
+/// matches, or a `nolink` span if there is no such doc. Embeds within
+/// embedded notes are not expanded, so cycles can't occur.
+pub fn render_embeds(
+    text: &str,
+    assets_path: &str,
+    slug_to_doc_index: &HashMap<String, Doc>,
+) -> String {
+    EMBED
+        .replace_all(text, |caps: &regex::Captures| {
+            let embed = parse_embed(&caps[1]);
+            if embed.is_image() {
+                return render_image_embed(&embed, assets_path);
+            }
+            match slug_to_doc_index.get(&embed.slug()) {
+                Some(doc) => doc.content.clone(),
+                None => format!(
+                    r#"<span class="nolink">{}</span>"#,
+                    escape_xml(&embed.target)
+                ),
+            }
+        })
+        .into_owned()
+}
+
+/// Index the files in a vault directory by their path relative to the vault,
+/// and by their bare file name, the way Obsidian resolves embeds.
+pub fn index_vault_files(vault_dir: &Path) -> Result<HashMap<String, PathBuf>, Error> {
+    let pattern = vault_dir.join("**/*");
+    let mut index = HashMap::new();
+    let mut paths: Vec<PathBuf> = glob::glob(&pattern.to_string_lossy())?
+        .filter_map(|path| path.ok())
+        .filter(|path| path.is_file())
+        .collect();
+    // Sort so the shortest path wins when several files share a name
+    paths.sort_by_key(|path| (path.components().count(), path.clone()));
+    for path in paths {
+        if let Ok(relative) = path.strip_prefix(vault_dir) {
+            index.insert(relative.to_string_lossy().into_owned(), path.clone());
+        }
+        if let Some(name) = path.file_name() {
+            index
+                .entry(name.to_string_lossy().into_owned())
+                .or_insert(path);
+        }
+    }
+    Ok(index)
+}
+
+/// Copy the images embedded in docs from the vault into
+/// `output_dir/assets_path`. Returns the path of each copied file, or an
+/// error for each image that could not be found or copied.
+pub fn copy_embedded_images(
+    docs: &[Doc],
+    vault_dir: &Path,
+    output_dir: &Path,
+    assets_path: &str,
+) -> Result<Vec<Result<PathBuf, Error>>, Error> {
+    let vault_files = index_vault_files(vault_dir)?;
+    let assets_dir = output_dir.join(assets_path);
+    let mut copied = HashSet::new();
+    let mut results = Vec::new();
+    for doc in docs {
+        for embed in doc.find_embeds().filter(|embed| embed.is_image()) {
+            if !copied.insert(embed.target.clone()) {
+                continue;
+            }
+            let source = vault_files
+                .get(&embed.target)
+                .or_else(|| vault_files.get(&embed.file_name()));
+            let result = match source {
+                Some(source) => {
+                    let dest = assets_dir.join(embed.file_name());
+                    fs::create_dir_all(&assets_dir)
+                        .and_then(|_| fs::copy(source, &dest))
+                        .map(|_| dest)
+                        .map_err(Error::from)
+                }
+                None => Err(Error::value(format!(
+                    "Embedded image {} not found in {}",
+                    embed.target,
+                    vault_dir.display()
+                ))),
+            };
+            results.push(result.map_err(|err| err.with_doc(&doc.id_path)));
+        }
+    }
+    Ok(results)
+}
+
+impl Doc {
+    pub fn find_embeds(&self) -> impl Iterator<Item = Embed> + '_ {
+        find_embeds(&self.content)
+    }
+
+    /// Render image and note embeds in content.
+    /// See `render_embeds`.
+    pub fn render_embeds(
+        mut self,
+        assets_path: &str,
+        slug_to_doc_index: &HashMap<String, Doc>,
+    ) -> Self {
+        self.content = render_embeds(&self.content, assets_path, slug_to_doc_index);
+        self
+    }
+}
+
+pub trait EmbedDocs: Docs {
+    /// Render image and note embeds using an index of docs by title slug
+    fn render_embeds(
+        self,
+        assets_path: &str,
+        slug_to_doc_index: &HashMap<String, Doc>,
+    ) -> impl Docs {
+        self.map(move |doc| doc.render_embeds(assets_path, slug_to_doc_index))
+    }
+
+    /// Render embeds between the docs in this iterator.
+    /// A note embed will match if there is a doc in this iterator that has a
+    /// title who's slug matches the sluggified note name.
+    fn render_embeds_between(self, assets_path: &str) -> impl Docs {
+        let docs: Vec<Doc> = self.collect();
+        let index = docs.clone().into_iter().index_by_title_slug();
+        let docs: Vec<Doc> = docs
+            .into_iter()
+            .render_embeds(assets_path, &index)
+            .collect();
+        docs.into_iter()
+    }
+}
+
+impl<I> EmbedDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_embeds() {
+        let text = "See ![[cat.png|300]] and ![[Some Note#Heading]], not [[link]].";
+        let embeds: Vec<Embed> = find_embeds(text).collect();
+        assert_eq!(embeds.len(), 2);
+        assert!(embeds[0].is_image());
+        assert_eq!(embeds[0].option, Some("300".to_string()));
+        assert!(!embeds[1].is_image());
+        assert_eq!(embeds[1].slug(), "some-note");
+    }
+
+    #[test]
+    fn test_render_embeds() {
+        let index = HashMap::from([(
+            "some-note".to_string(),
+            Doc::draft("some-note.md").set_content("Embedded text"),
+        )]);
+        let text = "![[img/cat.png|A cat]]\n![[dog.jpg|300x200]]\n![[Some Note]]\n![[Missing]]";
+        assert_eq!(
+            render_embeds(text, "assets/", &index),
+            concat!(
+                r#"<img src="assets/cat.png" alt="A cat">"#,
+                "\n",
+                r#"<img src="assets/dog.jpg" alt="" width="300" height="200">"#,
+                "\nEmbedded text\n",
+                r#"<span class="nolink">Missing</span>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_copy_embedded_images() {
+        let vault = tempdir().unwrap();
+        let output = tempdir().unwrap();
+        fs::create_dir(vault.path().join("attachments")).unwrap();
+        fs::write(vault.path().join("attachments/cat.png"), "png").unwrap();
+        let docs = vec![Doc::draft("a.md").set_content("![[cat.png]] ![[cat.png]] ![[nope.png]]")];
+        let results = copy_embedded_images(&docs, vault.path(), output.path(), "assets").unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &output.path().join("assets/cat.png")
+        );
+        assert!(output.path().join("assets/cat.png").exists());
+        assert!(results[1].is_err());
+    }
+}
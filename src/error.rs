@@ -1,9 +1,21 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+/// Matches the ` --> line:column` marker in template parse errors
+static TEMPLATE_LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"-->\s*(\d+):(\d+)").expect("Template location regex should be valid")
+});
 
 #[derive(Debug)]
 pub struct Error {
     pub msg: String,
     pub kind: ErrorKind,
+    /// id path of the doc being processed when the error occurred, if any
+    pub doc: Option<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -11,16 +23,47 @@ pub enum ErrorKind {
     Io(std::io::Error),
     Json(serde_json::Error),
     Tera(tera::Error),
+    Yaml(serde_yml::Error),
+    Glob(glob::PatternError),
+    /// File is binary or not valid UTF-8 text
+    Binary,
+    /// File is larger than the maximum size allowed
+    TooLarge,
+    /// Fetching a URL failed, with an HTTP error status or a transport error
+    Http,
     Value,
     Other,
 }
 
+impl ErrorKind {
+    /// Short machine-readable name for this kind of error
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Io(_) => "io",
+            ErrorKind::Json(_) => "json",
+            ErrorKind::Tera(_) => "tera",
+            ErrorKind::Yaml(_) => "yaml",
+            ErrorKind::Glob(_) => "glob",
+            ErrorKind::Binary => "binary",
+            ErrorKind::TooLarge => "too_large",
+            ErrorKind::Http => "http",
+            ErrorKind::Value => "value",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
 impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ErrorKind::Io(err) => write!(f, "{}", err),
             ErrorKind::Json(err) => write!(f, "{}", err),
-            ErrorKind::Tera(err) => write!(f, "{:?}", err),
+            ErrorKind::Tera(err) => write!(f, "{}", err),
+            ErrorKind::Yaml(err) => write!(f, "{}", err),
+            ErrorKind::Glob(err) => write!(f, "{}", err),
+            ErrorKind::Binary => write!(f, "Binary or non-UTF-8 file"),
+            ErrorKind::TooLarge => write!(f, "File too large"),
+            ErrorKind::Http => write!(f, "HTTP error"),
             ErrorKind::Value => write!(f, "{}", "Value error"),
             ErrorKind::Other => write!(f, "{}", "Other"),
         }
@@ -32,6 +75,7 @@ impl Error {
         Error {
             msg: msg.into(),
             kind,
+            doc: None,
         }
     }
 
@@ -39,6 +83,7 @@ impl Error {
         Error {
             msg: msg.into(),
             kind: ErrorKind::Value,
+            doc: None,
         }
     }
 
@@ -46,8 +91,45 @@ impl Error {
         Error {
             msg: msg.into(),
             kind: ErrorKind::Other,
+            doc: None,
         }
     }
+
+    /// Is this error for a file that was skipped because it isn't text
+    /// (binary or too large), rather than a real failure?
+    pub fn is_unreadable_file(&self) -> bool {
+        matches!(self.kind, ErrorKind::Binary | ErrorKind::TooLarge)
+    }
+
+    /// Serialize error to a JSON object with `doc`, `stage`, `kind` and
+    /// `message` fields, for machine-readable error output.
+    /// `stage` names the pipeline step that failed, e.g. `template`.
+    pub fn to_json(&self, stage: &str) -> serde_json::Value {
+        let message = match &self.kind {
+            ErrorKind::Value
+            | ErrorKind::Other
+            | ErrorKind::Binary
+            | ErrorKind::TooLarge
+            | ErrorKind::Http => self.msg.clone(),
+            kind => format!("{}: {}", self.msg, kind),
+        };
+        serde_json::json!({
+            "doc": self.doc,
+            "stage": stage,
+            "kind": self.kind.name(),
+            "message": message,
+        })
+    }
+
+    /// Attach the id path of the doc that caused this error.
+    /// If the error already names a doc, it is left as-is, so the innermost
+    /// (most specific) doc wins.
+    pub fn with_doc(mut self, id_path: impl AsRef<Path>) -> Self {
+        if self.doc.is_none() {
+            self.doc = Some(id_path.as_ref().to_path_buf());
+        }
+        self
+    }
 }
 
 impl std::error::Error for Error {
@@ -56,6 +138,8 @@ impl std::error::Error for Error {
             ErrorKind::Io(error) => Some(error),
             ErrorKind::Json(error) => Some(error),
             ErrorKind::Tera(error) => Some(error),
+            ErrorKind::Yaml(error) => Some(error),
+            ErrorKind::Glob(error) => Some(error),
             _ => None,
         }
     }
@@ -63,6 +147,9 @@ impl std::error::Error for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(doc) = &self.doc {
+            write!(f, "{}: ", doc.display())?;
+        }
         write!(f, "{}", self.msg)?;
         write!(f, "{}", "\n\nSource:\n")?;
         write!(f, "{}", self.kind)?;
@@ -87,3 +174,195 @@ impl From<tera::Error> for Error {
         Error::new(ErrorKind::Tera(error), "Tera template error")
     }
 }
+
+/// Describe the cause of a Tera error, with its line and column if known.
+/// Tera wraps the cause in a generic "Failed to render" error, so the
+/// innermost error is the informative one. Parse errors give a location and
+/// a multi-line excerpt, from which only the location and the final
+/// `= ...` explanation are kept. Render errors have no location.
+fn describe_tera_error(error: &tera::Error) -> (Option<(usize, usize)>, String) {
+    let mut cause: &dyn std::error::Error = error;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    let text = cause.to_string();
+    let Some(caps) = TEMPLATE_LOCATION.captures(&text) else {
+        return (None, text.trim().to_string());
+    };
+    let line = caps[1].parse().unwrap_or_default();
+    let column = caps[2].parse().unwrap_or_default();
+    let detail = text
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("= "))
+        .unwrap_or("Syntax error")
+        .to_string();
+    (Some((line, column)), detail)
+}
+
+impl Error {
+    /// Wrap a Tera error with the name of the template being rendered and,
+    /// for syntax errors, the line and column, e.g.
+    /// `Could not render template "post.html" at line 3, column 10: expected an identifier`.
+    /// Pass `None` as the name for templates rendered from a string.
+    pub fn tera(error: tera::Error, template_name: Option<&str>) -> Self {
+        let (location, detail) = describe_tera_error(&error);
+        let mut msg = match template_name {
+            Some(name) => format!("Could not render template \"{}\"", name),
+            None => "Could not render template string".to_string(),
+        };
+        if let Some((line, column)) = location {
+            msg.push_str(&format!(" at line {}, column {}", line, column));
+        }
+        msg.push_str(&format!(": {}", detail));
+        Error::new(ErrorKind::Tera(error), msg)
+    }
+}
+
+impl From<serde_yml::Error> for Error {
+    fn from(error: serde_yml::Error) -> Self {
+        Error::new(ErrorKind::Yaml(error), "YAML error")
+    }
+}
+
+impl From<glob::PatternError> for Error {
+    fn from(error: glob::PatternError) -> Self {
+        Error::new(ErrorKind::Glob(error), "Invalid glob pattern")
+    }
+}
+
+/// What to do when a pipeline step fails for a doc
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Report the error and stop the build
+    #[default]
+    Abort,
+    /// Report the error, leave the doc out, and keep going
+    Skip,
+}
+
+/// How errors are reported to stderr
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON object per line: `{doc, stage, kind, message}`
+    Json,
+}
+
+/// Reports and handles errors according to an on-error policy and format.
+///
+/// Handling an error never exits the process. Under the abort policy, the
+/// handler returns the error and remembers that the run was aborted, so
+/// callers can stop, flush their output, and then exit. Clones share this
+/// state.
+#[derive(Clone, Debug, Default)]
+pub struct ErrorHandler {
+    pub on_error: OnError,
+    pub format: ErrorFormat,
+    /// Name of the pipeline step being run, reported with each error
+    pub stage: String,
+    aborted: Arc<AtomicBool>,
+}
+
+impl ErrorHandler {
+    pub fn new(on_error: OnError, format: ErrorFormat, stage: impl Into<String>) -> Self {
+        ErrorHandler {
+            on_error,
+            format,
+            stage: stage.into(),
+            aborted: Arc::default(),
+        }
+    }
+
+    /// Copy this handler with a different on-error policy.
+    /// The copy shares this handler's aborted state.
+    pub fn with_on_error(&self, on_error: OnError) -> Self {
+        ErrorHandler {
+            on_error,
+            ..self.clone()
+        }
+    }
+
+    /// Has an error been handled under the abort policy?
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Format an error for reporting
+    pub fn format_error(&self, err: &Error) -> String {
+        match self.format {
+            ErrorFormat::Text => err.to_string(),
+            ErrorFormat::Json => err.to_json(&self.stage).to_string(),
+        }
+    }
+
+    /// Report error to stderr. If the policy is to abort, the error is
+    /// returned, and the handler is marked as aborted.
+    pub fn handle(&self, err: Error) -> Result<(), Error> {
+        crate::stats::record_error();
+        eprintln!("{}", self.format_error(&err));
+        match self.on_error {
+            OnError::Abort => {
+                self.aborted.store(true, Ordering::Relaxed);
+                Err(err)
+            }
+            OnError::Skip => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_doc() {
+        let err = Error::value("Bad value")
+            .with_doc("posts/a.md")
+            .with_doc("posts/b.md");
+        assert_eq!(err.doc, Some(PathBuf::from("posts/a.md")));
+        assert!(err.to_string().starts_with("posts/a.md: Bad value"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let err = Error::value("Bad value").with_doc("posts/a.md");
+        assert_eq!(
+            err.to_json("template"),
+            serde_json::json!({
+                "doc": "posts/a.md",
+                "stage": "template",
+                "kind": "value",
+                "message": "Bad value"
+            })
+        );
+        let handler = ErrorHandler::new(OnError::Skip, ErrorFormat::Json, "template");
+        assert!(!handler.format_error(&err).contains('\n'));
+    }
+
+    #[test]
+    fn test_handle() {
+        let skip = ErrorHandler::new(OnError::Skip, ErrorFormat::Text, "template");
+        assert!(skip.handle(Error::value("Bad value")).is_ok());
+        assert!(!skip.aborted());
+        let abort = ErrorHandler::new(OnError::Abort, ErrorFormat::Text, "template");
+        let shared = abort.clone();
+        let err = abort.handle(Error::value("Bad value")).unwrap_err();
+        assert_eq!(err.msg, "Bad value");
+        assert!(shared.aborted());
+    }
+
+    #[test]
+    fn test_from_glob_error() {
+        let err: Error = glob::Pattern::new("[").unwrap_err().into();
+        assert!(matches!(err.kind, ErrorKind::Glob(_)));
+    }
+}
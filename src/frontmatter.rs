@@ -1,41 +1,196 @@
+use crate::docs::{write_with_report, WriteReport};
+use crate::error::{Error, ErrorKind};
+use crate::io::write_file_deep;
+use crate::json;
+use crate::stats;
 use crate::{doc::Doc, docs::Docs};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 
-static FRONTMATTER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+static YAML_FRONTMATTER: LazyLock<Regex> = LazyLock::new(|| frontmatter_regex("---"));
+static TOML_FRONTMATTER: LazyLock<Regex> = LazyLock::new(|| frontmatter_regex("+++"));
+static JSON_FRONTMATTER: LazyLock<Regex> = LazyLock::new(|| frontmatter_regex(";;;"));
+
+/// Frontmatter formats, identified by the delimiter used to fence them.
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontmatterFormat {
+    /// YAML, fenced with `---`
+    Yaml,
+    /// TOML, fenced with `+++`
+    Toml,
+    /// JSON, fenced with `;;;`
+    Json,
+}
+
+impl FrontmatterFormat {
+    pub const ALL: [FrontmatterFormat; 3] = [
+        FrontmatterFormat::Yaml,
+        FrontmatterFormat::Toml,
+        FrontmatterFormat::Json,
+    ];
+
+    /// Get the fence delimiter for this format
+    pub fn delimiter(&self) -> &'static str {
+        match self {
+            FrontmatterFormat::Yaml => "---",
+            FrontmatterFormat::Toml => "+++",
+            FrontmatterFormat::Json => ";;;",
+        }
+    }
+
+    fn regex(&self) -> &'static Regex {
+        match self {
+            FrontmatterFormat::Yaml => &YAML_FRONTMATTER,
+            FrontmatterFormat::Toml => &TOML_FRONTMATTER,
+            FrontmatterFormat::Json => &JSON_FRONTMATTER,
+        }
+    }
+
+    /// Parse frontmatter text in this format into a JSON value.
+    /// Empty frontmatter parses to `null`.
+    pub fn parse(&self, text: &str) -> Result<json::Value, Error> {
+        if text.trim().is_empty() {
+            return Ok(json::Value::Null);
+        }
+        match self {
+            FrontmatterFormat::Yaml => serde_yml::from_str(text).map_err(|err| {
+                Error::new(ErrorKind::Yaml(err), "Could not parse YAML frontmatter")
+            }),
+            FrontmatterFormat::Toml => toml::from_str(text)
+                .map_err(|err| Error::other(format!("Could not parse TOML frontmatter: {}", err))),
+            FrontmatterFormat::Json => Ok(json::from_str(text)?),
+        }
+    }
+}
+
+/// Build a regex that matches a frontmatter block fenced by `delimiter` at
+/// the start of text. A leading byte order mark and whitespace before the
+/// opening fence are tolerated.
+pub fn frontmatter_regex(delimiter: &str) -> Regex {
+    let delimiter = regex::escape(delimiter);
     // `(?ms)` means "multiline" and "dot matches newline"
-    Regex::new("(?ms)^---\n(.*)---\n?").expect("Could not compile frontmatter Regex")
-});
+    let pattern = format!(
+        r"(?ms)\A\x{{FEFF}}?\s*{0}[ \t]*\r?\n(.*?)^{0}[ \t]*\r?(?:\n|\z)",
+        delimiter
+    );
+    Regex::new(&pattern).expect("Could not compile frontmatter Regex")
+}
 
-pub fn extract_front_matter_and_content(text: &str) -> (String, String) {
-    match FRONTMATTER_REGEX.find(text) {
-        Some(match_result) => {
-            let front_matter = FRONTMATTER_REGEX
-                .captures(text)
-                .and_then(|cap| cap.get(1).map(|m| m.as_str().trim().to_string()))
-                .unwrap_or_else(String::new);
+/// Extract frontmatter matched by a regex built with `frontmatter_regex`.
+/// Returns `None` if the text has no frontmatter block.
+pub fn extract_frontmatter_with_regex(text: &str, regex: &Regex) -> Option<(String, String)> {
+    let captures = regex.captures(text)?;
+    let front_matter = captures
+        .get(1)
+        .map(|m| m.as_str().trim().to_string())
+        .unwrap_or_default();
+    let content = text[captures.get(0)?.end()..].trim().to_string();
+    Some((front_matter, content))
+}
 
-            let content = text[match_result.end()..].trim().to_string();
+/// Extract frontmatter fenced by a custom delimiter, such as `+++`.
+/// Returns `None` if the text has no frontmatter block.
+pub fn extract_frontmatter_with_delimiter(text: &str, delimiter: &str) -> Option<(String, String)> {
+    extract_frontmatter_with_regex(text, &frontmatter_regex(delimiter))
+}
 
-            (front_matter, content)
+/// Extract the first frontmatter block found for any of the given formats.
+/// Returns the format, frontmatter, and content, or `None` if the text has
+/// no frontmatter block.
+pub fn extract_frontmatter_for_formats(
+    text: &str,
+    formats: &[FrontmatterFormat],
+) -> Option<(FrontmatterFormat, String, String)> {
+    formats.iter().find_map(|format| {
+        extract_frontmatter_with_regex(text, format.regex())
+            .map(|(front_matter, content)| (*format, front_matter, content))
+    })
+}
+
+pub fn extract_front_matter_and_content(text: &str) -> (String, String) {
+    extract_frontmatter_with_regex(text, &YAML_FRONTMATTER)
+        .unwrap_or_else(|| (String::new(), text.to_string()))
+}
+
+/// Read just the frontmatter block from the start of a file, without
+/// reading the rest of the file. Returns the block including its fences, or
+/// an empty string if the file doesn't start with a frontmatter fence.
+pub fn read_frontmatter_block(path: impl AsRef<Path>) -> Result<String, Error> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut block = String::new();
+    let mut delimiter: Option<&str> = None;
+    for line in reader.lines() {
+        let line = line?;
+        let trimmed = line.trim_start_matches('\u{FEFF}').trim_end();
+        match delimiter {
+            None if trimmed.is_empty() => continue,
+            None => {
+                let format = FrontmatterFormat::ALL
+                    .iter()
+                    .find(|format| format.delimiter() == trimmed);
+                let Some(format) = format else {
+                    return Ok(String::new());
+                };
+                delimiter = Some(format.delimiter());
+                block.push_str(trimmed);
+                block.push('\n');
+            }
+            Some(delimiter) => {
+                block.push_str(&line);
+                block.push('\n');
+                if trimmed == delimiter {
+                    return Ok(block);
+                }
+            }
         }
-        None => (String::new(), text.to_string()),
     }
+    // Unclosed fence. Not frontmatter.
+    Ok(String::new())
+}
+
+/// Serialize meta to YAML frontmatter and prepend it to content.
+/// If meta is null or an empty object, content is returned as-is.
+pub fn prepend_frontmatter(meta: &json::Value, content: &str) -> Result<String, Error> {
+    let is_empty = match meta {
+        json::Value::Null => true,
+        json::Value::Object(map) => map.is_empty(),
+        _ => false,
+    };
+    if is_empty {
+        return Ok(content.to_string());
+    }
+    let yaml = serde_yml::to_string(meta)
+        .map_err(|err| Error::new(ErrorKind::Yaml(err), "Could not serialize meta to YAML"))?;
+    Ok(format!("---\n{}---\n\n{}", yaml, content))
 }
 
 impl Doc {
-    /// Parses YAML frontmatter from the document's content and assigns it to the `meta` field.
+    /// Parses frontmatter from the document's content and assigns it to the `meta` field.
     ///
     /// Extracts the frontmatter (if present) from the document's content,
-    /// attempts to parse it as YAML, and assigns the resulting data to the `meta` field.
+    /// attempts to parse it as YAML (`---`), TOML (`+++`) or JSON (`;;;`),
+    /// and assigns the resulting data to the `meta` field.
     /// If parsing succeeds, it updates the `meta` field and removes the frontmatter from the content.
     /// If parsing fails, the `meta` field remains unchanged.
-    pub fn parse_frontmatter(mut self) -> Self {
-        let (frontmatter, content) = extract_front_matter_and_content(&self.content);
-        if let Ok(meta) = serde_yml::from_str(&frontmatter) {
-            self.meta = meta;
+    pub fn parse_frontmatter(self) -> Self {
+        self.parse_frontmatter_with_formats(&FrontmatterFormat::ALL)
+    }
+
+    /// Parses frontmatter, recognizing only the given formats.
+    pub fn parse_frontmatter_with_formats(mut self, formats: &[FrontmatterFormat]) -> Self {
+        if let Some((format, frontmatter, content)) =
+            extract_frontmatter_for_formats(&self.content, formats)
+        {
+            if let Ok(meta) = format.parse(&frontmatter) {
+                self.meta = meta;
+            }
+            self.content = content;
         }
-        self.content = content;
         self
     }
 
@@ -44,6 +199,22 @@ impl Doc {
     pub fn parse_and_uplift_frontmatter(self) -> Self {
         self.parse_frontmatter().uplift_meta()
     }
+
+    /// Get content with meta serialized as YAML frontmatter above it.
+    pub fn get_content_with_frontmatter(&self) -> Result<String, Error> {
+        prepend_frontmatter(&self.meta, &self.content).map_err(|err| err.with_doc(&self.id_path))
+    }
+
+    /// Write the doc to its output path, with meta serialized as YAML
+    /// frontmatter above the content.
+    /// Returns a result containing the write path of the file on success.
+    pub fn write_with_frontmatter(&self, output_dir: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let write_path = self.get_write_path(output_dir)?;
+        let content = self.get_content_with_frontmatter()?;
+        write_file_deep(&write_path, &content).map_err(|err| err.with_doc(&self.id_path))?;
+        stats::record_written(content.len());
+        Ok(write_path)
+    }
 }
 
 pub trait FrontmatterDocs: Docs {
@@ -51,9 +222,22 @@ pub trait FrontmatterDocs: Docs {
         self.map(|doc| doc.parse_frontmatter())
     }
 
+    fn parse_frontmatter_with_formats(self, formats: &[FrontmatterFormat]) -> impl Docs {
+        self.map(|doc| doc.parse_frontmatter_with_formats(formats))
+    }
+
     fn parse_and_uplift_frontmatter(self) -> impl Docs {
         self.map(|doc| doc.parse_and_uplift_frontmatter())
     }
+
+    /// Write docs to file system under output_dir, serializing meta back
+    /// to YAML frontmatter above the content.
+    /// Files are written in parallel. Returns a report, like `Docs::write`.
+    fn write_with_frontmatter(self, output_dir: &Path) -> WriteReport {
+        write_with_report(self, output_dir, |doc| {
+            doc.write_with_frontmatter(output_dir)
+        })
+    }
 }
 
 impl<I> FrontmatterDocs for I where I: Docs {}
@@ -95,6 +279,78 @@ It can span multiple lines."#;
         assert_eq!(content, "This is a document without front matter.");
     }
 
+    #[test]
+    fn test_extract_front_matter_ignores_later_rules() {
+        let input = "---\ntitle: Test\n---\nAbove\n\n---\n\nBelow";
+
+        let (front_matter, content) = extract_front_matter_and_content(input);
+
+        assert_eq!(front_matter, "title: Test");
+        assert_eq!(content, "Above\n\n---\n\nBelow");
+    }
+
+    #[test]
+    fn test_extract_front_matter_with_bom_and_whitespace() {
+        let input = "\u{FEFF}\n  ---\r\ntitle: Test\r\n---\r\nContent";
+
+        let (front_matter, content) = extract_front_matter_and_content(input);
+
+        assert_eq!(front_matter, "title: Test");
+        assert_eq!(content, "Content");
+    }
+
+    #[test]
+    fn test_extract_frontmatter_with_delimiter() {
+        let input = "~~~\ntitle: Test\n~~~\nContent";
+        let (front_matter, content) = extract_frontmatter_with_delimiter(input, "~~~").unwrap();
+        assert_eq!(front_matter, "title: Test");
+        assert_eq!(content, "Content");
+    }
+
+    #[test]
+    fn test_parse_frontmatter_toml() {
+        let doc = Doc::draft("test.md")
+            .set_content("+++\ntitle = \"Test\"\ntags = [\"a\"]\n+++\nContent")
+            .parse_frontmatter();
+        assert_eq!(doc.meta, json::json!({"title": "Test", "tags": ["a"]}));
+        assert_eq!(doc.content, "Content");
+    }
+
+    #[test]
+    fn test_parse_frontmatter_json() {
+        let doc = Doc::draft("test.md")
+            .set_content(";;;\n{\"title\": \"Test\"}\n;;;\nContent")
+            .parse_frontmatter();
+        assert_eq!(doc.meta, json::json!({"title": "Test"}));
+        assert_eq!(doc.content, "Content");
+    }
+
+    #[test]
+    fn test_parse_frontmatter_with_formats() {
+        let doc = Doc::draft("test.md")
+            .set_content("+++\ntitle = \"Test\"\n+++\nContent")
+            .parse_frontmatter_with_formats(&[FrontmatterFormat::Yaml]);
+        assert_eq!(doc.meta, json::Value::Null);
+        assert_eq!(doc.content, "+++\ntitle = \"Test\"\n+++\nContent");
+    }
+
+    #[test]
+    fn test_prepend_frontmatter_round_trip() {
+        let meta = json::json!({"title": "Hello", "tags": ["a", "b"]});
+        let text = prepend_frontmatter(&meta, "Body text").unwrap();
+
+        assert!(text.starts_with("---\n"));
+        let doc = Doc::draft("test.md").set_content(text).parse_frontmatter();
+        assert_eq!(doc.meta, meta);
+        assert_eq!(doc.content, "Body text");
+    }
+
+    #[test]
+    fn test_prepend_frontmatter_empty_meta() {
+        let text = prepend_frontmatter(&json::json!({}), "Body text").unwrap();
+        assert_eq!(text, "Body text");
+    }
+
     #[test]
     fn test_extract_front_matter_and_content_empty_frontmatter() {
         let input = "---\n\n---\nContent after empty front matter.";
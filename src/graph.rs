@@ -0,0 +1,302 @@
+// Link graph between docs, for graph views of digital gardens
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::json::{self, json};
+use crate::wikilink::{find_wikilinks, SlugResolution, WikilinkDocs};
+use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+pub(crate) static HREF: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"href\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("Could not parse regular expression")
+});
+
+/// A doc in the link graph
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    /// The doc's id. See `Doc::get_id`.
+    pub id: String,
+    pub title: String,
+    /// The doc's output_path
+    pub url: String,
+}
+
+/// How one doc links to another
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Wikilink,
+    Href,
+}
+
+/// A link from one doc to another, by node id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+}
+
+/// Format to export a link graph in
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphFormat {
+    /// JSON object with `nodes` and `edges` arrays
+    #[default]
+    Json,
+    /// Graphviz DOT
+    Dot,
+}
+
+impl GraphFormat {
+    /// Default output path for this format
+    pub fn output_path(&self) -> &'static str {
+        match self {
+            GraphFormat::Json => "graph.json",
+            GraphFormat::Dot => "graph.dot",
+        }
+    }
+}
+
+/// A link graph between docs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Normalize an href to an output path, if it points within the site.
+/// Strips `site_url`, leading slashes, queries, and fragments, and resolves
+/// directory URLs to their index file.
+pub(crate) fn href_to_output_path(href: &str, site_url: &str) -> Option<String> {
+    let site_url = site_url.trim_end_matches('/');
+    let href = if !site_url.is_empty() && href.starts_with(site_url) {
+        &href[site_url.len()..]
+    } else {
+        href
+    };
+    if href.contains("://") || href.starts_with("mailto:") || href.starts_with('#') {
+        return None;
+    }
+    let path = href.split(['#', '?']).next().unwrap_or_default();
+    let path = path.trim_start_matches("./").trim_start_matches('/');
+    if path.is_empty() {
+        return Some("index.html".to_string());
+    }
+    if path.ends_with('/') {
+        return Some(format!("{}index.html", path));
+    }
+    Some(path.to_string())
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Graph {
+    /// Build a link graph from wikilinks and internal hrefs in doc content.
+    /// Wikilinks are resolved by title slug, as with `render_wikilinks`.
+    /// Hrefs are resolved by output path. `site_url` is stripped from
+    /// absolute hrefs, so links made absolute with `absolutize_urls` are
+    /// found too. Links to missing docs and links from a doc to itself are
+    /// left out.
+    pub fn build(docs: &[Doc], site_url: &str) -> Self {
+        let by_slug: HashMap<String, Doc> = docs
+            .iter()
+            .cloned()
+            .index_title_slug_candidates()
+            .into_iter()
+            .filter_map(|(slug, candidates)| {
+                SlugResolution::default()
+                    .resolve(candidates)
+                    .map(|doc| (slug, doc))
+            })
+            .collect();
+        let by_output_path: HashMap<String, &Doc> = docs
+            .iter()
+            .map(|doc| (doc.output_path.to_string_lossy().into_owned(), doc))
+            .collect();
+
+        let nodes = docs
+            .iter()
+            .map(|doc| GraphNode {
+                id: doc.get_id(),
+                title: doc.title.clone(),
+                url: doc.output_path.to_string_lossy().into_owned(),
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+        let mut edges = Vec::new();
+        for doc in docs {
+            let source = doc.get_id();
+            let wikilink_targets = find_wikilinks(&doc.content)
+                .filter_map(|wikilink| by_slug.get(&wikilink.slug))
+                .map(|target| (target.get_id(), EdgeKind::Wikilink));
+            let href_targets = HREF
+                .captures_iter(&doc.content)
+                .filter_map(|caps| {
+                    let href = caps.get(1).or_else(|| caps.get(2))?.as_str();
+                    by_output_path.get(&href_to_output_path(href, site_url)?)
+                })
+                .map(|target| (target.get_id(), EdgeKind::Href));
+            for (target, kind) in wikilink_targets.chain(href_targets).collect::<Vec<_>>() {
+                if target == source {
+                    continue;
+                }
+                let edge = GraphEdge {
+                    source: source.clone(),
+                    target,
+                    kind,
+                };
+                if seen.insert(edge.clone()) {
+                    edges.push(edge);
+                }
+            }
+        }
+        Graph { nodes, edges }
+    }
+
+    /// Render graph as Graphviz DOT
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for node in &self.nodes {
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\", URL=\"{}\"];\n",
+                escape_dot(&node.id),
+                escape_dot(&node.title),
+                escape_dot(&node.url)
+            ));
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Wikilink => "",
+                EdgeKind::Href => " [style=dashed]",
+            };
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\"{};\n",
+                escape_dot(&edge.source),
+                escape_dot(&edge.target),
+                style
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render graph in the given format
+    pub fn render(&self, format: GraphFormat) -> Result<String, Error> {
+        match format {
+            GraphFormat::Json => Ok(json::to_string_pretty(self)?),
+            GraphFormat::Dot => Ok(self.to_dot()),
+        }
+    }
+}
+
+pub trait GraphDocs: Docs {
+    /// Build a link graph between the docs in this iterator.
+    /// See `Graph::build`.
+    fn graph(self, site_url: &str) -> Graph {
+        let docs: Vec<Doc> = self.collect();
+        Graph::build(&docs, site_url)
+    }
+
+    /// Build a link graph and render it into a single doc
+    fn generate_graph_doc(
+        self,
+        site_url: &str,
+        format: GraphFormat,
+        output_path: impl Into<PathBuf>,
+    ) -> Result<Doc, Error> {
+        let content = self.graph(site_url).render(format)?;
+        let output_path: PathBuf = output_path.into();
+        let now = Utc::now();
+        Ok(Doc::new(
+            output_path.clone(),
+            output_path,
+            None,
+            None,
+            now,
+            now,
+            "".to_string(),
+            "".to_string(),
+            content,
+            json!({}),
+        ))
+    }
+}
+
+impl<I> GraphDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_docs() -> Vec<Doc> {
+        vec![
+            Doc::draft("a.md")
+                .set_title("A")
+                .set_output_path("a/index.html")
+                .set_content(r#"See [[B]], <a href="https://example.com/c/">C</a> and <a href="https://elsewhere.com">out</a>. Also [[A]] and [[Missing]]."#),
+            Doc::draft("b.md")
+                .set_title("B")
+                .set_output_path("b/index.html")
+                .set_content(r#"Back to <a href='/a/index.html#top'>A</a>, twice [[A]] [[A]]"#),
+            Doc::draft("c.md")
+                .set_title("C")
+                .set_output_path("c/index.html"),
+        ]
+    }
+
+    #[test]
+    fn test_build_graph() {
+        let graph = Graph::build(&make_docs(), "https://example.com");
+        assert_eq!(graph.nodes.len(), 3);
+        let edges: Vec<(&str, &str, EdgeKind)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.source.as_str(), edge.target.as_str(), edge.kind))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("a.md", "b.md", EdgeKind::Wikilink),
+                ("a.md", "c.md", EdgeKind::Href),
+                ("b.md", "a.md", EdgeKind::Wikilink),
+                ("b.md", "a.md", EdgeKind::Href),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let graph = Graph::build(&make_docs(), "https://example.com");
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("  \"a.md\" [label=\"A\", URL=\"a/index.html\"];\n"));
+        assert!(dot.contains("  \"a.md\" -> \"c.md\" [style=dashed];\n"));
+    }
+
+    #[test]
+    fn test_href_to_output_path() {
+        assert_eq!(
+            href_to_output_path("/posts/a/", ""),
+            Some("posts/a/index.html".to_string())
+        );
+        assert_eq!(
+            href_to_output_path("https://example.com/", "https://example.com/"),
+            Some("index.html".to_string())
+        );
+        assert_eq!(
+            href_to_output_path("https://other.com/a", "https://example.com"),
+            None
+        );
+        assert_eq!(href_to_output_path("#top", ""), None);
+    }
+}
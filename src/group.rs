@@ -0,0 +1,131 @@
+// Group docs by arbitrary keys and generate an index doc per group
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::json::{self, get_deep};
+use crate::tags::{generate_archives, index_by_tags};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Convert a scalar JSON value to a group key.
+/// Returns `None` for null, arrays and objects, and for empty strings.
+fn to_group_key(value: &json::Value) -> Option<String> {
+    match value {
+        json::Value::String(value) if !value.trim().is_empty() => Some(value.trim().to_string()),
+        json::Value::Number(value) => Some(value.to_string()),
+        json::Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+impl Doc {
+    /// Get group keys from the meta value at a dot-path.
+    /// - Strings, numbers and booleans produce a single key
+    /// - Arrays produce a key for each scalar item
+    /// - Missing values produce no keys
+    pub fn get_meta_group_keys(&self, path: &str) -> Vec<String> {
+        match get_deep(&self.meta, path) {
+            Some(json::Value::Array(values)) => values.iter().filter_map(to_group_key).collect(),
+            Some(value) => to_group_key(&value).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+pub trait GroupDocs: Docs {
+    /// Group docs by the key returned from `key_fn`.
+    /// Docs for which `key_fn` returns `None` are left out.
+    /// Returns a hashmap of doc lists, indexed by key.
+    fn group_by<F>(self, key_fn: F) -> HashMap<String, Vec<Doc>>
+    where
+        F: Fn(&Doc) -> Option<String>,
+    {
+        index_by_tags(self, |doc| key_fn(doc).into_iter().collect())
+    }
+
+    /// Group docs by the value at a meta dot-path.
+    /// Docs with array values are filed under each item in the array.
+    fn group_by_meta(self, path: &str) -> HashMap<String, Vec<Doc>> {
+        index_by_tags(self, |doc| doc.get_meta_group_keys(path))
+    }
+
+    /// Generate one index doc per group, with the group's docs in
+    /// `meta.items`. See `tags::generate_archives` for the tokens available
+    /// to `output_path_template`. `{taxonomy}` is the sluggified `group_name`.
+    fn generate_group_archives<F>(
+        self,
+        key_fn: F,
+        group_name: &str,
+        output_path_template: &str,
+        template_path: Option<PathBuf>,
+    ) -> impl Docs
+    where
+        F: Fn(&Doc) -> Option<String>,
+    {
+        let index = self.group_by(key_fn);
+        generate_archives(index, group_name, output_path_template, template_path)
+    }
+
+    /// Generate one index doc per distinct value at a meta dot-path.
+    fn generate_meta_group_archives(
+        self,
+        path: &str,
+        output_path_template: &str,
+        template_path: Option<PathBuf>,
+    ) -> impl Docs {
+        let index = self.group_by_meta(path);
+        generate_archives(index, path, output_path_template, template_path)
+    }
+}
+
+impl<I> GroupDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn make_docs() -> Vec<Doc> {
+        vec![
+            Doc::draft("a.md").set_meta(json!({"category": "Guides", "year": 2024})),
+            Doc::draft("b.md").set_meta(json!({"category": "Guides", "year": 2023})),
+            Doc::draft("c.md").set_meta(json!({"category": ["News", "Guides"]})),
+            Doc::draft("d.md"),
+        ]
+    }
+
+    #[test]
+    fn test_group_by() {
+        let index = make_docs()
+            .into_iter()
+            .group_by(|doc| doc.meta.get("missing").map(|v| v.to_string()));
+        assert!(index.is_empty());
+
+        let index = make_docs()
+            .into_iter()
+            .group_by(|doc| Some(doc.id_path.to_string_lossy().len().to_string()));
+        assert_eq!(index.get("4").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_group_by_meta() {
+        let index = make_docs().into_iter().group_by_meta("category");
+        assert_eq!(index.get("Guides").unwrap().len(), 3);
+        assert_eq!(index.get("News").unwrap().len(), 1);
+
+        let index = make_docs().into_iter().group_by_meta("year");
+        assert_eq!(index.get("2024").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_generate_meta_group_archives() {
+        let mut archives: Vec<Doc> = make_docs()
+            .into_iter()
+            .generate_meta_group_archives("category", "{term}/index.html", None)
+            .collect();
+        archives.sort_by(|a, b| a.title.cmp(&b.title));
+        assert_eq!(archives.len(), 2);
+        assert_eq!(archives[0].title, "Guides");
+        assert_eq!(archives[0].output_path, PathBuf::from("guides/index.html"));
+        assert_eq!(archives[0].meta["items"].as_array().unwrap().len(), 3);
+    }
+}
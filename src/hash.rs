@@ -0,0 +1,156 @@
+// Stable content hashes for docs, for ETags and change detection
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::io::write_file_deep;
+use crate::json::{self, json};
+use crate::url::{to_permalink_path, TrailingSlash};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Hash content with 64-bit FNV-1a, returning 16 hex digits.
+/// Unlike `DefaultHasher`, the hash is stable across Rust versions and
+/// platforms, so it can be stored and compared between builds.
+pub fn content_hash(content: &str) -> String {
+    let hash = content.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+impl Doc {
+    /// Get the hash of this doc's content. See `content_hash`.
+    pub fn content_hash(&self) -> String {
+        content_hash(&self.content)
+    }
+
+    /// Record the hash of this doc's content in `meta.hash`
+    pub fn set_meta_hash(mut self) -> Self {
+        if !self.meta.is_object() {
+            self.meta = json!({});
+        }
+        let hash = self.content_hash();
+        self.merge_meta(json!({ "hash": hash }))
+    }
+}
+
+/// A build manifest: content hashes of written docs, keyed by output path.
+///
+/// ```json
+/// {"files": {"posts/a/index.html": "af63dc4c8601ec8c"}}
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl Manifest {
+    /// Record a doc's content hash under its output path
+    pub fn insert(&mut self, doc: &Doc) {
+        self.files
+            .insert(doc.output_path.clone(), doc.content_hash());
+    }
+
+    /// Read a manifest from a JSON file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Ok(json::from_str(&content)?)
+    }
+
+    /// Write the manifest to a JSON file
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        write_file_deep(path, &json::to_string_pretty(self)?)
+    }
+
+    /// Render an `ETag` header for each file as a Netlify-style `_headers`
+    /// file, also supported by Cloudflare Pages. Paths are written as
+    /// public paths, following the trailing slash policy.
+    /// https://docs.netlify.com/routing/headers/
+    pub fn render_headers(&self, trailing_slash: TrailingSlash) -> String {
+        self.files
+            .iter()
+            .map(|(output_path, hash)| {
+                format!(
+                    "{}\n  ETag: \"{}\"\n",
+                    to_permalink_path(output_path, trailing_slash),
+                    hash
+                )
+            })
+            .collect()
+    }
+}
+
+impl FromIterator<Doc> for Manifest {
+    fn from_iter<I: IntoIterator<Item = Doc>>(docs: I) -> Self {
+        let mut manifest = Manifest::default();
+        for doc in docs {
+            manifest.insert(&doc);
+        }
+        manifest
+    }
+}
+
+pub trait HashDocs: Docs {
+    /// Record the hash of each doc's content in `meta.hash`
+    fn set_meta_hash(self) -> impl Docs {
+        self.map(|doc| doc.set_meta_hash())
+    }
+
+    /// Generate a `_headers` doc with an `ETag` header for each doc.
+    /// See `Manifest::render_headers`.
+    fn headers(self, trailing_slash: TrailingSlash) -> Doc {
+        let manifest: Manifest = self.collect();
+        let output_path = PathBuf::from("_headers");
+        let now = Utc::now();
+        Doc::new(
+            output_path.clone(),
+            output_path,
+            None,
+            None,
+            now,
+            now,
+            "".to_string(),
+            "".to_string(),
+            manifest.render_headers(trailing_slash),
+            json!({}),
+        )
+    }
+}
+
+impl<I> HashDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        // Known FNV-1a 64 values
+        assert_eq!(content_hash(""), "cbf29ce484222325");
+        assert_eq!(content_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(content_hash("ab"), content_hash("ba"));
+    }
+
+    #[test]
+    fn test_headers() {
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_output_path("a/index.html")
+                .set_content("a"),
+            Doc::draft("b.md").set_output_path("b.css").set_content(""),
+        ];
+        let doc = docs.clone().into_iter().set_meta_hash().next().unwrap();
+        assert_eq!(doc.meta["hash"], "af63dc4c8601ec8c");
+        let headers = docs.into_iter().headers(TrailingSlash::Always);
+        assert_eq!(
+            headers.content,
+            "/a/\n  ETag: \"af63dc4c8601ec8c\"\n/b.css\n  ETag: \"cbf29ce484222325\"\n"
+        );
+    }
+}
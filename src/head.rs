@@ -0,0 +1,226 @@
+// Inject feed autodiscovery, canonical, and description tags into the
+// <head> of rendered pages, so themes don't have to remember them
+use crate::absolutize::to_url;
+use crate::config::Config;
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::html::{escape_xml, head_html, inject_head};
+use crate::json::get_deep;
+use crate::url::permalink_url;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static HEAD_TAG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(link|meta)\b[^>]*>").expect("Head tag regex should be valid")
+});
+
+/// Matches an attribute, with a double-quoted, single-quoted, or unquoted
+/// value
+static ATTR_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+))"#)
+        .expect("Attribute regex should be valid")
+});
+
+/// Media types of feeds, as used for feed autodiscovery links
+const FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+
+/// A `<link>` or `<meta>` tag in a page head, with lowercased tag and
+/// attribute names, and lowercased attribute values
+struct HeadTag {
+    name: String,
+    attrs: HashMap<String, String>,
+}
+
+impl HeadTag {
+    fn attr(&self, name: &str) -> &str {
+        self.attrs.get(name).map(String::as_str).unwrap_or_default()
+    }
+
+    /// Is this a `<link>` whose `rel` lists `rel`?
+    fn is_link_rel(&self, rel: &str) -> bool {
+        self.name == "link"
+            && self
+                .attr("rel")
+                .split_whitespace()
+                .any(|token| token == rel)
+    }
+}
+
+/// Get the `<link>` and `<meta>` tags in a page head
+fn read_head_tags(head: &str) -> Vec<HeadTag> {
+    HEAD_TAG_REGEX
+        .captures_iter(head)
+        .map(|caps| {
+            let attrs = ATTR_REGEX
+                .captures_iter(&caps[0])
+                .map(|attr| {
+                    let value = attr
+                        .get(2)
+                        .or(attr.get(3))
+                        .or(attr.get(4))
+                        .map(|value| value.as_str().trim().to_ascii_lowercase())
+                        .unwrap_or_default();
+                    (attr[1].to_ascii_lowercase(), value)
+                })
+                .collect();
+            HeadTag {
+                name: caps[1].to_ascii_lowercase(),
+                attrs,
+            }
+        })
+        .collect()
+}
+
+/// Get the string value at a meta path, if it is a non-empty string
+fn get_meta_str(doc: &Doc, path: &str) -> Option<String> {
+    get_deep(&doc.meta, path)
+        .and_then(|value| value.as_str().map(str::to_string))
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Get the head tags for a doc, leaving out any the page's `<head>`
+/// already has.
+///
+/// - Feed autodiscovery: `<link rel="alternate" type="application/rss+xml">`
+///   pointing at `feed_path`.
+/// - Canonical link: `meta.canonical`, or the doc's permalink URL. Left
+///   out unless `site_url` is an absolute URL, since canonical links should
+///   be absolute.
+/// - Meta description: `meta.description`, the doc summary, or
+///   `site_description`, whichever is set first.
+pub fn get_head_tags(doc: &Doc, html: &str, config: &Config) -> Vec<String> {
+    let head_tags = read_head_tags(head_html(html));
+    let has_feed_link = head_tags
+        .iter()
+        .any(|tag| tag.is_link_rel("alternate") && FEED_TYPES.contains(&tag.attr("type")));
+    let has_canonical = head_tags.iter().any(|tag| tag.is_link_rel("canonical"));
+    let has_description = head_tags
+        .iter()
+        .any(|tag| tag.name == "meta" && tag.attr("name") == "description");
+    let mut tags = Vec::new();
+    if !has_feed_link {
+        let title = if config.site_title.is_empty() {
+            "RSS".to_string()
+        } else {
+            config.site_title.clone()
+        };
+        tags.push(format!(
+            r#"<link rel="alternate" type="application/rss+xml" title="{}" href="{}">"#,
+            escape_xml(&title),
+            escape_xml(&to_url(&config.feed_path, &config.site_url))
+        ));
+    }
+    if !has_canonical {
+        let canonical = get_meta_str(doc, "canonical").or_else(|| {
+            config
+                .site_url
+                .contains("://")
+                .then(|| permalink_url(&doc.output_path, &config.site_url, config.trailing_slash))
+        });
+        if let Some(canonical) = canonical {
+            tags.push(format!(
+                r#"<link rel="canonical" href="{}">"#,
+                escape_xml(&canonical)
+            ));
+        }
+    }
+    if !has_description {
+        let description = get_meta_str(doc, "description")
+            .or_else(|| Some(doc.summary.clone()).filter(|summary| !summary.trim().is_empty()))
+            .or_else(|| {
+                Some(config.site_description.clone()).filter(|site| !site.trim().is_empty())
+            });
+        if let Some(description) = description {
+            tags.push(format!(
+                r#"<meta name="description" content="{}">"#,
+                escape_xml(description.trim())
+            ));
+        }
+    }
+    tags
+}
+
+impl Doc {
+    /// Add feed autodiscovery, canonical, and description tags to the
+    /// `<head>` of this doc's rendered page. Tags the page already has are
+    /// skipped. See `get_head_tags`.
+    pub fn inject_head_tags(self, config: &Config) -> Self {
+        let tags = get_head_tags(&self, &self.content, config);
+        let content = inject_head(&self.content, &tags);
+        self.set_content(content)
+    }
+}
+
+pub trait HeadDocs: Docs {
+    /// Add feed autodiscovery, canonical, and description tags to the
+    /// `<head>` of each rendered page. Run after templates.
+    /// See `Doc::inject_head_tags`.
+    fn inject_head_tags(self, config: &Config) -> impl Docs {
+        self.map(move |doc| doc.inject_head_tags(config))
+    }
+}
+
+impl<I> HeadDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    #[test]
+    fn test_inject_head_tags() {
+        let config = Config {
+            site_url: "https://example.com".to_string(),
+            site_title: "Ex & Co".to_string(),
+            site_description: "A site".to_string(),
+            ..Default::default()
+        };
+        let doc = Doc::draft("posts/a.md")
+            .set_output_path("posts/a/index.html")
+            .set_summary("About A")
+            .set_content("<html><head><title>A</title></head><body></body></html>")
+            .inject_head_tags(&config);
+        assert_eq!(
+            doc.content,
+            concat!(
+                "<html><head><title>A</title>",
+                "<link rel=\"alternate\" type=\"application/rss+xml\" title=\"Ex &amp; Co\" href=\"https://example.com/feed.xml\">\n",
+                "<link rel=\"canonical\" href=\"https://example.com/posts/a/\">\n",
+                "<meta name=\"description\" content=\"About A\">\n",
+                "</head><body></body></html>"
+            )
+        );
+
+        // Existing tags are kept, and meta overrides defaults
+        let doc = Doc::draft("b.md")
+            .set_meta(json!({"canonical": "https://other.example/b/"}))
+            .set_content(r#"<head><meta name="description" content="B"><link rel="alternate" type="application/rss+xml" href="/b.xml"></head>"#)
+            .inject_head_tags(&config);
+        assert_eq!(
+            doc.content,
+            "<head><meta name=\"description\" content=\"B\"><link rel=\"alternate\" type=\"application/rss+xml\" href=\"/b.xml\"><link rel=\"canonical\" href=\"https://other.example/b/\">\n</head>"
+        );
+
+        // Single-quoted and unquoted attributes count too
+        let html = "<head><link rel='alternate' type='application/atom+xml' href='/atom.xml'><link rel=canonical href=/d/><meta content='D' name='description'></head>";
+        let doc = Doc::draft("d.md")
+            .set_content(html)
+            .inject_head_tags(&config);
+        assert_eq!(doc.content, html);
+
+        // Only tags in the head count as existing
+        let doc = Doc::draft("c.md")
+            .set_summary("C")
+            .set_content(r#"<head><link rel="canonical" href="/c/"></head><body><code>type="application/rss+xml"</code><p name="description"></p></body>"#)
+            .inject_head_tags(&config);
+        assert_eq!(
+            doc.content,
+            "<head><link rel=\"canonical\" href=\"/c/\"><link rel=\"alternate\" type=\"application/rss+xml\" title=\"Ex &amp; Co\" href=\"https://example.com/feed.xml\">\n<meta name=\"description\" content=\"C\">\n</head><body><code>type=\"application/rss+xml\"</code><p name=\"description\"></p></body>"
+        );
+    }
+}
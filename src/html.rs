@@ -2,11 +2,215 @@ use std::sync::LazyLock;
 
 use regex::Regex;
 
+static ENTITY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);")
+        .expect("Could not compile regular expression")
+});
+
 static HTML_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"<[^<]+?>").expect("Could not compile regular expression"));
 
+static HTML_TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<(/?)([a-zA-Z][a-zA-Z0-9]*)\b[^>]*>|<[^>]*>|[^<]+")
+        .expect("Could not compile regular expression")
+});
+
+/// Inline tags kept by `truncate_html`
+const INLINE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "cite", "code", "del", "em", "i", "ins", "kbd", "mark", "q", "s", "small",
+    "span", "strong", "sub", "sup", "u",
+];
+
+/// Truncate HTML to at most `max_chars` characters of text, keeping inline
+/// formatting such as links and emphasis, and adding a suffix if truncated.
+/// Block tags are dropped, whitespace is collapsed, text is cut at a word
+/// boundary, and tags left open are closed, so the result can be placed
+/// inline, e.g. in a listing or feed description.
+pub fn truncate_html(html: &str, max_chars: usize, suffix: &str) -> String {
+    let limit = max_chars.saturating_sub(suffix.chars().count());
+    let mut out = String::new();
+    let mut open: Vec<String> = Vec::new();
+    let mut count = 0;
+    let mut space = false;
+    let mut truncated = false;
+    'tokens: for token in HTML_TOKEN.captures_iter(html) {
+        let Some(name) = token.get(2) else {
+            let text = &token[0];
+            if text.starts_with('<') {
+                continue;
+            }
+            space |= text.starts_with(char::is_whitespace);
+            for (i, word) in text.split_whitespace().enumerate() {
+                let gap = usize::from((space || i > 0) && count > 0);
+                let len = word.chars().count();
+                if count + gap + len > limit {
+                    truncated = true;
+                    break 'tokens;
+                }
+                if gap > 0 {
+                    out.push(' ');
+                }
+                out.push_str(word);
+                count += gap + len;
+                space = false;
+            }
+            space = text.ends_with(char::is_whitespace);
+            continue;
+        };
+        let name = name.as_str().to_ascii_lowercase();
+        let is_close = &token[1] == "/";
+        if !INLINE_TAGS.contains(&name.as_str()) {
+            space = true;
+        } else if is_close {
+            if open.last() == Some(&name) {
+                open.pop();
+                out.push_str(&token[0]);
+            }
+        } else {
+            if space && count > 0 {
+                out.push(' ');
+                count += 1;
+                space = false;
+            }
+            out.push_str(&token[0]);
+            open.push(name);
+        }
+    }
+    if truncated {
+        out.push_str(suffix);
+    }
+    for name in open.iter().rev() {
+        out.push_str(&format!("</{}>", name));
+    }
+    out
+}
+
 /// A very simple tag stripper that removes anything between angle
 /// brackets.
 pub fn strip_html(html_str: &str) -> String {
     HTML_REGEX.replace_all(html_str, "").to_string()
 }
+
+static HEAD_OPEN_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<head[\s>]").expect("Could not compile regular expression"));
+
+/// Get the source of the `<head>` element of an HTML page, from the opening
+/// tag up to `</head>`. Returns an empty string if the page has no head.
+pub fn head_html(html: &str) -> &str {
+    let Some(end) = html.to_ascii_lowercase().find("</head>") else {
+        return "";
+    };
+    let start = HEAD_OPEN_REGEX
+        .find(&html[..end])
+        .map(|open| open.start())
+        .unwrap_or_default();
+    &html[start..end]
+}
+
+/// Insert tags at the end of the `<head>` of an HTML page, one per line.
+/// Content without a `</head>` tag is left as-is.
+pub fn inject_head(html: &str, tags: &[String]) -> String {
+    if tags.is_empty() {
+        return html.to_string();
+    }
+    match html.to_ascii_lowercase().find("</head>") {
+        Some(i) => format!("{}{}\n{}", &html[..i], tags.join("\n"), &html[i..]),
+        None => html.to_string(),
+    }
+}
+
+/// Escape the five XML special characters so that text can be safely
+/// placed in XML element content or attribute values.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unescape XML entities: the five named XML entities, plus numeric
+/// character references like `&#39;` and `&#x2F;`. Unknown entities are
+/// left as they are.
+pub fn unescape_xml(text: &str) -> String {
+    ENTITY_REGEX
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                    None => entity
+                        .strip_prefix('#')
+                        .and_then(|dec| dec.parse().ok())
+                        .and_then(char::from_u32),
+                },
+            };
+            match c {
+                Some(c) => c.to_string(),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_html() {
+        let html = "<h1>Title</h1>\n<p>Some <em>very</em> <a href=\"/x\">linked\ntext</a> here.</p><p>More</p>";
+        assert_eq!(
+            truncate_html(html, 280, "…"),
+            "Title Some <em>very</em> <a href=\"/x\">linked text</a> here. More"
+        );
+        assert_eq!(
+            truncate_html(html, 25, "…"),
+            "Title Some <em>very</em> <a href=\"/x\">linked…</a>"
+        );
+    }
+
+    #[test]
+    fn test_strip_html() {
+        assert_eq!(strip_html("<p>Hello <b>world</b></p>"), "Hello world");
+    }
+
+    #[test]
+    fn test_head_html() {
+        let html = "<html><HEAD><title>A</title></HEAD><body><header></header></body></html>";
+        assert_eq!(head_html(html), "<HEAD><title>A</title>");
+        assert_eq!(head_html("<p>No head</p>"), "");
+        assert_eq!(
+            inject_head(html, &["<meta>".to_string()]),
+            "<html><HEAD><title>A</title><meta>\n</HEAD><body><header></header></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml(r#"Tom & "Jerry's" <show>"#),
+            "Tom &amp; &quot;Jerry&apos;s&quot; &lt;show&gt;"
+        );
+    }
+
+    #[test]
+    fn test_unescape_xml() {
+        assert_eq!(
+            unescape_xml("Tom &amp; &quot;Jerry&#39;s&quot; &lt;a&#x2F;&gt; &nbsp;"),
+            r#"Tom & "Jerry's" <a/> &nbsp;"#
+        );
+    }
+}
@@ -0,0 +1,206 @@
+// Rewrite HTML elements using selector-driven rules
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use lol_html::errors::AttributeNameError;
+use lol_html::html_content::Element;
+use lol_html::{rewrite_str, ElementContentHandlers, RewriteStrSettings, Selector};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// A rule for rewriting the attributes of matching elements.
+///
+/// Example (JSON):
+///
+/// ```json
+/// {"selector": "a[href^=http]", "set": {"rel": "noopener", "target": "_blank"}}
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RewriteRule {
+    /// Selector for elements to rewrite, such as `img`, `table.data`, or
+    /// `a[href^=http]`
+    pub selector: String,
+    /// Attributes to set, replacing existing values
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    /// Attributes to set only if the element doesn't have them already
+    #[serde(default)]
+    pub defaults: BTreeMap<String, String>,
+    /// Classes to add to the element's class attribute
+    #[serde(default)]
+    pub add_class: Vec<String>,
+    /// Attributes to remove
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Apply a rewrite rule to an element
+fn apply_rule(element: &mut Element, rule: &RewriteRule) -> Result<(), AttributeNameError> {
+    for name in &rule.remove {
+        element.remove_attribute(name);
+    }
+    for (name, value) in &rule.set {
+        element.set_attribute(name, value)?;
+    }
+    for (name, value) in &rule.defaults {
+        if !element.has_attribute(name) {
+            element.set_attribute(name, value)?;
+        }
+    }
+    if !rule.add_class.is_empty() {
+        let existing = element.get_attribute("class").unwrap_or_default();
+        let mut classes: Vec<&str> = existing.split_whitespace().collect();
+        for class in &rule.add_class {
+            if !classes.contains(&class.as_str()) {
+                classes.push(class);
+            }
+        }
+        element.set_attribute("class", &classes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Compiled rewrite rules
+pub struct HtmlRewriter {
+    rules: Vec<(Selector, RewriteRule)>,
+}
+
+impl HtmlRewriter {
+    /// Compile rewrite rules, parsing their selectors.
+    /// Selectors are CSS selectors, as supported by `lol_html`.
+    pub fn new(rules: &[RewriteRule]) -> Result<Self, Error> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                let selector = rule.selector.parse::<Selector>().map_err(|err| {
+                    Error::value(format!("Invalid selector \"{}\": {}", rule.selector, err))
+                })?;
+                Ok((selector, rule.clone()))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(HtmlRewriter { rules })
+    }
+
+    /// Rewrite the elements in an HTML string.
+    /// Rules are applied in order, so later rules see the changes made by
+    /// earlier ones. Tags no rule matches are left byte-for-byte unchanged.
+    pub fn rewrite(&self, html: &str) -> Result<String, Error> {
+        let element_content_handlers = self
+            .rules
+            .iter()
+            .map(|(selector, rule)| {
+                (
+                    Cow::Borrowed(selector),
+                    ElementContentHandlers::default().element(move |element: &mut Element| {
+                        apply_rule(element, rule)?;
+                        Ok(())
+                    }),
+                )
+            })
+            .collect();
+        rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers,
+                ..RewriteStrSettings::new()
+            },
+        )
+        .map_err(|err| Error::value(format!("Could not rewrite HTML: {}", err)))
+    }
+}
+
+impl Doc {
+    /// Rewrite HTML in content using compiled rewrite rules
+    pub fn rewrite_html(mut self, rewriter: &HtmlRewriter) -> Result<Self, Error> {
+        self.content = rewriter
+            .rewrite(&self.content)
+            .map_err(|err| err.with_doc(&self.id_path))?;
+        Ok(self)
+    }
+}
+
+pub trait HtmlRewriteDocs: Docs {
+    /// Rewrite HTML in the content of each doc using compiled rewrite rules
+    fn rewrite_html(self, rewriter: &HtmlRewriter) -> impl DocResults {
+        self.map(move |doc| doc.rewrite_html(rewriter))
+    }
+}
+
+impl<I> HtmlRewriteDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(selector: &str) -> RewriteRule {
+        RewriteRule {
+            selector: selector.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rewrite_external_links() {
+        let rewriter = HtmlRewriter::new(&[RewriteRule {
+            set: BTreeMap::from([("target".to_string(), "_blank".to_string())]),
+            ..rule("a[href^=http]")
+        }])
+        .unwrap();
+        let html = r#"<p><a href="https://example.com">Out</a> <a href="/in/">In</a></p>"#;
+        assert_eq!(
+            rewriter.rewrite(html).unwrap(),
+            r#"<p><a href="https://example.com" target="_blank">Out</a> <a href="/in/">In</a></p>"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_defaults_and_classes() {
+        let rewriter = HtmlRewriter::new(&[
+            RewriteRule {
+                defaults: BTreeMap::from([("loading".to_string(), "lazy".to_string())]),
+                ..rule("img")
+            },
+            RewriteRule {
+                add_class: vec!["table".to_string()],
+                ..rule("TABLE")
+            },
+        ])
+        .unwrap();
+        let html = r#"<img src='a.png'><img src="b.png" loading="eager" /><table class="data">"#;
+        assert_eq!(
+            rewriter.rewrite(html).unwrap(),
+            r#"<img src='a.png' loading="lazy"><img src="b.png" loading="eager" /><table class="data table">"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_skips_comments_and_scripts() {
+        let rewriter = HtmlRewriter::new(&[RewriteRule {
+            remove: vec!["style".to_string()],
+            ..rule("*")
+        }])
+        .unwrap();
+        let html =
+            r#"<!-- <b style="x"> --><script>if (a <b) {}</script><b style="x">Hi</b> 1 < 2"#;
+        assert_eq!(
+            rewriter.rewrite(html).unwrap(),
+            r#"<!-- <b style="x"> --><script>if (a <b) {}</script><b>Hi</b> 1 < 2"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_css_selectors() {
+        let rewriter = HtmlRewriter::new(&[RewriteRule {
+            add_class: vec!["lead".to_string()],
+            ..rule("article > p:first-child")
+        }])
+        .unwrap();
+        let html = "<article><p>One</p><p>Two</p></article><p>Three</p>";
+        assert_eq!(
+            rewriter.rewrite(html).unwrap(),
+            r#"<article><p class="lead">One</p><p>Two</p></article><p>Three</p>"#
+        );
+        assert!(HtmlRewriter::new(&[rule("a >")]).is_err());
+    }
+}
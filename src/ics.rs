@@ -0,0 +1,238 @@
+// Generate iCalendar (.ics) files from event docs
+use crate::absolutize::to_url;
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::json::{self, json};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::path::Path;
+
+/// iCalendar content lines should be folded at 75 octets.
+/// https://datatracker.ietf.org/doc/html/rfc5545#section-3.1
+const MAX_LINE_OCTETS: usize = 75;
+
+/// An event start or end, either a moment in time or an all-day date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcsDate {
+    DateTime(DateTime<Utc>),
+    Date(NaiveDate),
+}
+
+impl IcsDate {
+    /// Parse an RFC 3339 datetime (`2024-05-01T18:00:00Z`) or a plain date
+    /// (`2024-05-01`).
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Some(IcsDate::DateTime(datetime.with_timezone(&Utc)));
+        }
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()
+            .map(IcsDate::Date)
+    }
+
+    /// Render as an iCalendar property, e.g. `DTSTART:20240501T180000Z`.
+    pub fn to_property(&self, name: &str) -> String {
+        match self {
+            IcsDate::DateTime(datetime) => {
+                format!("{}:{}", name, datetime.format("%Y%m%dT%H%M%SZ"))
+            }
+            IcsDate::Date(date) => format!("{};VALUE=DATE:{}", name, date.format("%Y%m%d")),
+        }
+    }
+}
+
+/// Escape text for use in an iCalendar TEXT value.
+pub fn escape_ics_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Fold a content line so that no line is longer than 75 octets.
+/// Continuation lines begin with a single space.
+/// Lines are terminated with CRLF.
+pub fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 8);
+    let mut octets = 0;
+    for c in line.chars() {
+        let len = c.len_utf8();
+        if octets + len > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // Leading space counts toward the continuation line's length
+            octets = 1;
+        }
+        folded.push(c);
+        octets += len;
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+impl Doc {
+    /// Get the event start from `event.start` meta, if any.
+    pub fn get_event_start(&self) -> Option<IcsDate> {
+        json::get_deep(&self.meta, "event.start")?
+            .as_str()
+            .and_then(IcsDate::parse)
+    }
+
+    /// Get the event end from `event.end` meta, if any.
+    pub fn get_event_end(&self) -> Option<IcsDate> {
+        json::get_deep(&self.meta, "event.end")?
+            .as_str()
+            .and_then(IcsDate::parse)
+    }
+
+    /// Render doc as an iCalendar VEVENT.
+    /// Returns `None` if the doc does not have an `event.start` in meta.
+    ///
+    /// Also reads `event.location` from meta, if present.
+    pub fn to_ics_event(&self, site_url: &str, stamp: &DateTime<Utc>) -> Option<String> {
+        let start = self.get_event_start()?;
+        let url = to_url(&self.output_path, site_url);
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", url),
+            IcsDate::DateTime(*stamp).to_property("DTSTAMP"),
+            start.to_property("DTSTART"),
+        ];
+        if let Some(end) = self.get_event_end() {
+            lines.push(end.to_property("DTEND"));
+        }
+        lines.push(format!("SUMMARY:{}", escape_ics_text(&self.title)));
+        if !self.summary.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape_ics_text(&self.summary)));
+        }
+        if let Some(location) = json::get_deep(&self.meta, "event.location") {
+            if let Some(location) = location.as_str() {
+                lines.push(format!("LOCATION:{}", escape_ics_text(location)));
+            }
+        }
+        lines.push(format!("URL:{}", url));
+        lines.push("END:VEVENT".to_string());
+        Some(lines.iter().map(|line| fold_ics_line(line)).collect())
+    }
+}
+
+pub trait IcsDocs: Docs {
+    /// Generate an iCalendar doc from docs with `event.start` (and
+    /// optionally `event.end` and `event.location`) in meta.
+    /// Docs without an `event.start` are skipped.
+    fn ics(self, site_url: &str, title: &str, output_path: &Path) -> Result<Doc, Error> {
+        let now = Utc::now();
+        let mut content = String::new();
+        content.push_str(&fold_ics_line("BEGIN:VCALENDAR"));
+        content.push_str(&fold_ics_line("VERSION:2.0"));
+        content.push_str(&fold_ics_line("PRODID:-//Lettersmith//Lettersmith//EN"));
+        content.push_str(&fold_ics_line("CALSCALE:GREGORIAN"));
+        if !title.is_empty() {
+            content.push_str(&fold_ics_line(&format!(
+                "X-WR-CALNAME:{}",
+                escape_ics_text(title)
+            )));
+        }
+        for doc in self {
+            if let Some(event) = doc.to_ics_event(site_url, &now) {
+                content.push_str(&event);
+            }
+        }
+        content.push_str(&fold_ics_line("END:VCALENDAR"));
+
+        Ok(Doc::new(
+            output_path.into(),
+            output_path.into(),
+            None,
+            None,
+            now,
+            now,
+            title.to_string(),
+            "".to_string(),
+            content,
+            json!({}),
+        ))
+    }
+}
+
+impl<I> IcsDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_escape_ics_text() {
+        assert_eq!(
+            escape_ics_text("Rust; Meetup, Part\\2\nNow"),
+            "Rust\\; Meetup\\, Part\\\\2\\nNow"
+        );
+    }
+
+    #[test]
+    fn test_fold_ics_line() {
+        let line = format!("DESCRIPTION:{}", "é".repeat(50));
+        let folded = fold_ics_line(&line);
+        for part in folded.split("\r\n") {
+            assert!(part.len() <= MAX_LINE_OCTETS);
+        }
+        assert_eq!(folded.replace("\r\n ", "").trim_end(), line);
+    }
+
+    #[test]
+    fn test_ics_date_parse() {
+        assert_eq!(
+            IcsDate::parse("2024-05-01T18:00:00-07:00"),
+            Some(IcsDate::DateTime(
+                Utc.with_ymd_and_hms(2024, 5, 2, 1, 0, 0).unwrap()
+            ))
+        );
+        assert_eq!(
+            IcsDate::parse("2024-05-01").unwrap().to_property("DTSTART"),
+            "DTSTART;VALUE=DATE:20240501"
+        );
+        assert_eq!(IcsDate::parse("next tuesday"), None);
+    }
+
+    #[test]
+    fn test_ics() {
+        let docs = vec![
+            Doc::draft("events/meetup.md")
+                .set_output_path("events/meetup/index.html")
+                .set_title("Rust Meetup")
+                .set_meta(json!({
+                    "event": {
+                        "start": "2024-05-01T18:00:00Z",
+                        "end": "2024-05-01T20:00:00Z",
+                        "location": "Library, Room 2"
+                    }
+                })),
+            Doc::draft("posts/not-an-event.md"),
+        ];
+        let doc = docs
+            .into_iter()
+            .ics("https://example.com", "Events", Path::new("events.ics"))
+            .unwrap();
+
+        assert!(doc
+            .content
+            .starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(doc.content.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(doc.content.matches("BEGIN:VEVENT").count(), 1);
+        assert!(doc.content.contains("DTSTART:20240501T180000Z\r\n"));
+        assert!(doc.content.contains("DTEND:20240501T200000Z\r\n"));
+        assert!(doc.content.contains("LOCATION:Library\\, Room 2\r\n"));
+        assert!(doc
+            .content
+            .contains("UID:https://example.com/events/meetup/index.html\r\n"));
+    }
+}
@@ -0,0 +1,640 @@
+// Import Jekyll and Hugo projects, converting content, frontmatter, and
+// permalink settings to their Lettersmith equivalents
+use crate::error::Error;
+use crate::frontmatter::{extract_frontmatter_for_formats, prepend_frontmatter, FrontmatterFormat};
+use crate::io::write_file_deep;
+use crate::json::{self, json};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+static JEKYLL_POST_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2})-(.+)$").expect("Post name regex should be valid")
+});
+
+static PERMALINK_TOKEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r":([a-z_]+)").expect("Permalink token regex should be valid"));
+
+static LIQUID_RAW: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{%-?\s*(?:end)?raw\s*-?%\}").expect("Liquid raw regex should be valid")
+});
+
+static LIQUID_RAW_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\{%-?\s*raw\s*-?%\}(.*?)\{%-?\s*endraw\s*-?%\}")
+        .expect("Liquid raw block regex should be valid")
+});
+
+static LIQUID_HIGHLIGHT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\{%-?\s*highlight\s+(\w+)[^%]*%\}\n?(.*?)\{%-?\s*endhighlight\s*-?%\}")
+        .expect("Liquid highlight regex should be valid")
+});
+
+static HUGO_HIGHLIGHT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)\{\{<\s*highlight\s+"?(\w+)"?[^>]*>\}\}\n?(.*?)\{\{<\s*/highlight\s*>\}\}"#)
+        .expect("Hugo highlight regex should be valid")
+});
+
+static LIQUID_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{%.*?%\}|\{\{.*?\}\}").expect("Liquid tag regex should be valid")
+});
+
+static FENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)```.*?```").expect("Fence regex should be valid"));
+
+/// Static site generators we can import from
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    #[default]
+    Jekyll,
+    Hugo,
+}
+
+/// What an import did, and what it couldn't do
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Files written, relative to the output directory
+    pub written: Vec<PathBuf>,
+    /// Things that need manual attention, such as unconverted template tags
+    pub notes: Vec<String>,
+}
+
+impl ImportReport {
+    fn note(&mut self, note: impl Into<String>) {
+        self.notes.push(note.into());
+    }
+}
+
+/// Convert a Jekyll or Hugo permalink pattern into a Lettersmith permalink
+/// template. Returns `None` if the pattern uses tokens without an
+/// equivalent.
+///
+/// pattern:
+///     /:year/:month/:title/
+///
+/// template:
+///     {yyyy}/{mm}/{slug}/index.html
+pub fn convert_permalink_pattern(pattern: &str) -> Option<String> {
+    let pattern = match pattern {
+        "pretty" => "/:year/:month/:day/:title/",
+        "date" => "/:year/:month/:day/:title:output_ext",
+        "ordinal" => "/:year/:y_day/:title:output_ext",
+        "none" => "/:title:output_ext",
+        pattern => pattern,
+    };
+    // Jekyll categories are dropped, since Lettersmith has no equivalent
+    let pattern = pattern.replace("/:categories", "");
+    let mut convertible = true;
+    let template = PERMALINK_TOKEN.replace_all(&pattern, |caps: &regex::Captures| {
+        match &caps[1] {
+            "year" => "{yyyy}",
+            "short_year" => "{yy}",
+            "month" | "i_month" => "{mm}",
+            "day" | "i_day" => "{dd}",
+            "title" | "slug" | "filename" | "contentbasename" => "{slug}",
+            "section" => "{parents}",
+            "output_ext" => ".html",
+            _ => {
+                convertible = false;
+                ""
+            }
+        }
+        .to_string()
+    });
+    if !convertible {
+        return None;
+    }
+    Some(to_output_path(&template))
+}
+
+/// Convert a URL path into an output path, adding `index.html` to
+/// directory URLs
+fn to_output_path(url: &str) -> String {
+    let path = url.trim_start_matches('/');
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}index.html", path)
+    } else if Path::new(path).extension().is_none() {
+        format!("{}/index.html", path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// Normalize a date from frontmatter to RFC 3339, as Lettersmith expects.
+/// Accepts RFC 3339, `2024-01-02 03:04:05 +0000`, `2024-01-02 03:04:05`,
+/// and `2024-01-02`, along with TOML datetimes.
+pub fn normalize_date(value: &json::Value) -> Option<String> {
+    let text = match value {
+        json::Value::String(text) => text.as_str(),
+        json::Value::Object(map) => map.get("$__toml_private_datetime")?.as_str()?,
+        _ => return None,
+    };
+    let text = text.trim();
+    let date = DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S %z"))
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S"))
+                .ok()
+                .map(|date| date.and_utc())
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|date| date.and_utc())
+        })?;
+    Some(date.to_rfc3339())
+}
+
+/// Move a meta field to a new key, if present
+fn rename_field(meta: &mut json::Map<String, json::Value>, from: &str, to: &str) {
+    if let Some(value) = meta.remove(from) {
+        meta.entry(to).or_insert(value);
+    }
+}
+
+/// Convert a date field to an RFC 3339 `to` field
+fn convert_date_field(
+    meta: &mut json::Map<String, json::Value>,
+    from: &str,
+    to: &str,
+    id: &Path,
+    report: &mut ImportReport,
+) {
+    let Some(value) = meta.remove(from) else {
+        return;
+    };
+    match normalize_date(&value) {
+        Some(date) => {
+            meta.entry(to).or_insert(json::Value::String(date));
+        }
+        None => {
+            report.note(format!(
+                "{}: could not convert {} {} to a date",
+                id.display(),
+                from,
+                value
+            ));
+            meta.insert(from.to_string(), value);
+        }
+    }
+}
+
+/// Convert common frontmatter conventions shared by Jekyll and Hugo:
+/// - `layout` becomes `template`, as `{layout}.html`
+/// - `permalink` and `url` become a `permalink` output path
+/// - `description` becomes `summary`
+fn convert_common_meta(
+    meta: &mut json::Map<String, json::Value>,
+    id: &Path,
+    report: &mut ImportReport,
+) {
+    if let Some(json::Value::String(layout)) = meta.remove("layout") {
+        meta.insert("template".to_string(), json!(format!("{}.html", layout)));
+    }
+    rename_field(meta, "url", "permalink");
+    if let Some(json::Value::String(permalink)) = meta.get("permalink").cloned() {
+        if permalink.contains(':') {
+            report.note(format!(
+                "{}: permalink \"{}\" uses tokens, and was left as-is",
+                id.display(),
+                permalink
+            ));
+        } else {
+            meta.insert("permalink".to_string(), json!(to_output_path(&permalink)));
+        }
+    }
+    rename_field(meta, "description", "summary");
+}
+
+/// Rewrite template tags in content that have a plain equivalent, and note
+/// the rest. Tags inside fenced code blocks and `{% raw %}` blocks are left
+/// alone, and the raw tags themselves are removed.
+fn convert_content(content: &str, id: &Path, report: &mut ImportReport) -> String {
+    let content = LIQUID_HIGHLIGHT.replace_all(content, "```$1\n$2```");
+    let content = HUGO_HIGHLIGHT.replace_all(&content, "```$1\n$2```");
+    let unraw = LIQUID_RAW_BLOCK.replace_all(&content, "");
+    let unraw = LIQUID_RAW.replace_all(&unraw, "");
+    let unfenced = FENCE.replace_all(&unraw, "");
+    let content = LIQUID_RAW_BLOCK.replace_all(&content, "$1");
+    let content = LIQUID_RAW.replace_all(&content, "").into_owned();
+    for tag in LIQUID_TAG.find_iter(&unfenced) {
+        report.note(format!(
+            "{}: template tag needs manual attention: {}",
+            id.display(),
+            tag.as_str()
+        ));
+    }
+    content
+}
+
+/// Parse a content file into meta and content
+fn read_content_file(path: &Path) -> Result<(json::Map<String, json::Value>, String), Error> {
+    let text = fs::read_to_string(path)?;
+    let (meta, content) = match extract_frontmatter_for_formats(&text, &FrontmatterFormat::ALL) {
+        Some((format, frontmatter, content)) => (format.parse(&frontmatter)?, content),
+        None => (json::Value::Null, text),
+    };
+    let meta = match meta {
+        json::Value::Object(meta) => meta,
+        _ => json::Map::new(),
+    };
+    Ok((meta, content))
+}
+
+fn write_content_file(
+    output_dir: &Path,
+    path: &Path,
+    meta: json::Map<String, json::Value>,
+    content: &str,
+    report: &mut ImportReport,
+) -> Result<(), Error> {
+    let text = prepend_frontmatter(&json::Value::Object(meta), content.trim_start())?;
+    write_file_deep(output_dir.join(path), &text)?;
+    report.written.push(path.to_path_buf());
+    Ok(())
+}
+
+/// Find content files (Markdown and HTML) under a directory
+fn find_content_files(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = Vec::new();
+    for ext in ["md", "markdown", "html"] {
+        let pattern = dir.join(format!("**/*.{}", ext));
+        for path in glob::glob(&pattern.to_string_lossy())? {
+            let path = path.map_err(|err| Error::from(std::io::Error::from(err)))?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Read a config file as JSON, from YAML, TOML, or JSON
+fn read_config_file(path: &Path) -> Result<json::Value, Error> {
+    let text = fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => FrontmatterFormat::Toml.parse(&text),
+        Some("json") => FrontmatterFormat::Json.parse(&text),
+        _ => FrontmatterFormat::Yaml.parse(&text),
+    }
+}
+
+fn get_str<'a>(value: &'a json::Value, path: &[&str]) -> Option<&'a str> {
+    path.iter()
+        .try_fold(value, |value, key| value.get(key))?
+        .as_str()
+}
+
+/// Note template files that need porting to Tera by hand
+fn note_templates(dir: &Path, report: &mut ImportReport) -> Result<(), Error> {
+    if dir.is_dir() {
+        let count = find_content_files(dir)?.len();
+        report.note(format!(
+            "{} template file(s) in {} need porting to Tera templates by hand",
+            count,
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Build Lettersmith config from Jekyll's `_config.yml`
+fn jekyll_config(config: &json::Value, report: &mut ImportReport) -> json::Value {
+    let site_url = format!(
+        "{}{}",
+        get_str(config, &["url"]).unwrap_or(""),
+        get_str(config, &["baseurl"]).unwrap_or("")
+    );
+    let mut lettersmith = json!({
+        "site_url": if site_url.is_empty() { "/".to_string() } else { site_url },
+        "site_title": get_str(config, &["title"]).unwrap_or(""),
+        "site_description": get_str(config, &["description"]).unwrap_or(""),
+        "site_author": get_str(config, &["author"])
+            .or(get_str(config, &["author", "name"]))
+            .unwrap_or(""),
+    });
+    // Jekyll's permalink setting applies to posts. Pages keep their paths.
+    let permalink = get_str(config, &["permalink"]).unwrap_or("date");
+    match convert_permalink_pattern(permalink) {
+        Some(template) => lettersmith["permalinks"] = json!({"posts/**": template}),
+        None => report.note(format!(
+            "permalink setting \"{}\" could not be converted",
+            permalink
+        )),
+    }
+    lettersmith
+}
+
+fn import_jekyll(src_dir: &Path, output_dir: &Path) -> Result<ImportReport, Error> {
+    let mut report = ImportReport::default();
+    let config_path = src_dir.join("_config.yml");
+    let config = if config_path.exists() {
+        read_config_file(&config_path)?
+    } else {
+        json::Value::Null
+    };
+    let config = jekyll_config(&config, &mut report);
+    write_file_deep(
+        output_dir.join("lettersmith.json"),
+        &json::to_string_pretty(&config)?,
+    )?;
+    report.written.push(PathBuf::from("lettersmith.json"));
+
+    for path in find_content_files(src_dir)? {
+        let relative = path.strip_prefix(src_dir).unwrap_or(&path);
+        let first = relative
+            .components()
+            .next()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let is_post = first == "_posts";
+        // Skip Jekyll's own directories (layouts, includes, build output)
+        // and dot directories, aside from posts
+        if !is_post && relative.components().count() > 1 && first.starts_with(['_', '.']) {
+            continue;
+        }
+        let (mut meta, content) = read_content_file(&path)?;
+        // Only files with frontmatter are processed by Jekyll
+        if !is_post && meta.is_empty() && !fs::read_to_string(&path)?.starts_with("---") {
+            continue;
+        }
+        let stem = relative
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let ext = match relative.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => "html",
+            _ => "md",
+        };
+        let out_path = if is_post {
+            let (date, slug) = match JEKYLL_POST_NAME.captures(&stem) {
+                Some(caps) => (Some(caps[1].to_string()), caps[2].to_string()),
+                None => (None, stem.clone()),
+            };
+            if let Some(date) = date {
+                meta.entry("date").or_insert(json!(date));
+            }
+            PathBuf::from("posts").join(format!("{}.{}", slug, ext))
+        } else {
+            relative.with_extension(ext)
+        };
+        convert_date_field(&mut meta, "date", "created", relative, &mut report);
+        convert_date_field(
+            &mut meta,
+            "last_modified_at",
+            "modified",
+            relative,
+            &mut report,
+        );
+        if meta.remove("published") == Some(json!(false)) {
+            meta.insert("draft".to_string(), json!(true));
+        }
+        convert_common_meta(&mut meta, relative, &mut report);
+        let content = convert_content(&content, relative, &mut report);
+        write_content_file(output_dir, &out_path, meta, &content, &mut report)?;
+    }
+    note_templates(&src_dir.join("_layouts"), &mut report)?;
+    note_templates(&src_dir.join("_includes"), &mut report)?;
+    Ok(report)
+}
+
+/// Build Lettersmith config from Hugo's config file
+fn hugo_config(config: &json::Value, report: &mut ImportReport) -> json::Value {
+    let mut lettersmith = json!({
+        "site_url": get_str(config, &["baseURL"]).or(get_str(config, &["baseurl"])).unwrap_or("/"),
+        "site_title": get_str(config, &["title"]).unwrap_or(""),
+        "site_description": get_str(config, &["params", "description"]).unwrap_or(""),
+        "site_author": get_str(config, &["params", "author"])
+            .or(get_str(config, &["author", "name"]))
+            .unwrap_or(""),
+    });
+    // Hugo's permalink settings apply to the sections they are keyed by
+    if let Some(permalinks) = config.get("permalinks").and_then(|value| value.as_object()) {
+        let mut rules = json::Map::new();
+        for (section, pattern) in permalinks {
+            let Some(pattern) = pattern.as_str() else {
+                continue;
+            };
+            match convert_permalink_pattern(pattern) {
+                Some(template) => {
+                    rules.insert(format!("{}/**", section), json!(template));
+                }
+                None => report.note(format!(
+                    "permalink setting for section \"{}\" (\"{}\") could not be converted",
+                    section, pattern
+                )),
+            }
+        }
+        if !rules.is_empty() {
+            lettersmith["permalinks"] = json::Value::Object(rules);
+        }
+    }
+    lettersmith
+}
+
+fn import_hugo(src_dir: &Path, output_dir: &Path) -> Result<ImportReport, Error> {
+    let mut report = ImportReport::default();
+    let config_path = [
+        "hugo.toml",
+        "hugo.yaml",
+        "hugo.json",
+        "config.toml",
+        "config.yaml",
+        "config.json",
+    ]
+    .iter()
+    .map(|name| src_dir.join(name))
+    .find(|path| path.exists());
+    let config = match config_path {
+        Some(path) => read_config_file(&path)?,
+        None => json::Value::Null,
+    };
+    let config = hugo_config(&config, &mut report);
+    write_file_deep(
+        output_dir.join("lettersmith.json"),
+        &json::to_string_pretty(&config)?,
+    )?;
+    report.written.push(PathBuf::from("lettersmith.json"));
+
+    let content_dir = src_dir.join("content");
+    for path in find_content_files(&content_dir)? {
+        let relative = path.strip_prefix(&content_dir).unwrap_or(&path);
+        let (mut meta, content) = read_content_file(&path)?;
+        let ext = match relative.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => "html",
+            _ => "md",
+        };
+        // Section list pages become index pages
+        let out_path = if relative.file_stem() == Some("_index".as_ref()) {
+            relative.with_file_name(format!("index.{}", ext))
+        } else {
+            relative.with_extension(ext)
+        };
+        convert_date_field(&mut meta, "publishDate", "created", relative, &mut report);
+        convert_date_field(&mut meta, "date", "created", relative, &mut report);
+        convert_date_field(&mut meta, "lastmod", "modified", relative, &mut report);
+        convert_common_meta(&mut meta, relative, &mut report);
+        let content = convert_content(&content, relative, &mut report);
+        write_content_file(output_dir, &out_path, meta, &content, &mut report)?;
+    }
+    note_templates(&src_dir.join("layouts"), &mut report)?;
+    Ok(report)
+}
+
+/// Import a Jekyll or Hugo project from `src_dir` into `output_dir`:
+/// - Site settings are written to `lettersmith.json`, with permalink
+///   settings as `permalinks` rules for posts (Jekyll) or each section
+///   (Hugo)
+/// - Content files are written with YAML frontmatter, using Lettersmith's
+///   conventions (`created`, `modified`, `template`, `summary`, `draft`,
+///   `permalink`). Jekyll posts move from `_posts/2024-01-02-slug.md` to
+///   `posts/slug.md`, with the date from their file name.
+/// - Code highlighting tags become fenced code blocks, and `{% raw %}`
+///   tags are removed, since Lettersmith doesn't template content.
+///
+/// Returns a report of the files written, and of anything that needs
+/// manual attention, such as other template tags in content, and layouts.
+pub fn import_project(
+    source: ImportSource,
+    src_dir: &Path,
+    output_dir: &Path,
+) -> Result<ImportReport, Error> {
+    match source {
+        ImportSource::Jekyll => import_jekyll(src_dir, output_dir),
+        ImportSource::Hugo => import_hugo(src_dir, output_dir),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_convert_permalink_pattern() {
+        assert_eq!(
+            convert_permalink_pattern("pretty").unwrap(),
+            "{yyyy}/{mm}/{dd}/{slug}/index.html"
+        );
+        assert_eq!(
+            convert_permalink_pattern("/:categories/:year/:title:output_ext").unwrap(),
+            "{yyyy}/{slug}.html"
+        );
+        assert_eq!(
+            convert_permalink_pattern("/:section/:slug/").unwrap(),
+            "{parents}/{slug}/index.html"
+        );
+        assert_eq!(convert_permalink_pattern("/:weekday/:title/"), None);
+    }
+
+    #[test]
+    fn test_normalize_date() {
+        assert_eq!(
+            normalize_date(&json!("2024-01-02 03:04:05 +0100")).unwrap(),
+            "2024-01-02T02:04:05+00:00"
+        );
+        assert_eq!(
+            normalize_date(&json!("2024-01-02")).unwrap(),
+            "2024-01-02T00:00:00+00:00"
+        );
+        assert_eq!(
+            normalize_date(&json!({"$__toml_private_datetime": "2024-01-02T03:04:05Z"})).unwrap(),
+            "2024-01-02T03:04:05+00:00"
+        );
+        assert_eq!(normalize_date(&json!("someday")), None);
+    }
+
+    #[test]
+    fn test_import_jekyll() {
+        let src = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        fs::create_dir_all(src.path().join("_posts")).unwrap();
+        fs::create_dir_all(src.path().join("_layouts")).unwrap();
+        fs::write(
+            src.path().join("_config.yml"),
+            "title: Blog\nurl: https://example.com\npermalink: pretty\n",
+        )
+        .unwrap();
+        fs::write(
+            src.path().join("_posts/2024-01-02-hello.md"),
+            "---\ntitle: Hello\nlayout: post\n---\n{% highlight rust %}\nfn main() {}\n{% endhighlight %}\n{% include note.html %}\n{% raw %}{{ literal }}{% endraw %}\n",
+        )
+        .unwrap();
+        fs::write(src.path().join("_layouts/post.html"), "{{ content }}").unwrap();
+
+        let report = import_project(ImportSource::Jekyll, src.path(), out.path()).unwrap();
+        assert_eq!(
+            report.written,
+            vec![
+                PathBuf::from("lettersmith.json"),
+                PathBuf::from("posts/hello.md")
+            ]
+        );
+        assert_eq!(report.notes.len(), 2);
+
+        let config = crate::config::Config::read(out.path().join("lettersmith.json")).unwrap();
+        assert_eq!(config.site_title, "Blog");
+        assert_eq!(config.permalink_template, None);
+        assert_eq!(
+            config.permalinks["posts/**"],
+            "{yyyy}/{mm}/{dd}/{slug}/index.html"
+        );
+
+        let doc = crate::doc::Doc::read(out.path().join("posts/hello.md"))
+            .unwrap()
+            .parse_and_uplift_frontmatter();
+        assert_eq!(doc.title, "Hello");
+        assert_eq!(doc.created.to_rfc3339(), "2024-01-02T00:00:00+00:00");
+        assert_eq!(doc.template_path, Some(PathBuf::from("post.html")));
+        assert!(doc.content.starts_with("```rust\nfn main() {}\n```"));
+        assert!(doc.content.contains("\n{{ literal }}"));
+        assert!(!doc.content.contains("raw %}"));
+    }
+
+    #[test]
+    fn test_import_hugo() {
+        let src = tempdir().unwrap();
+        let out = tempdir().unwrap();
+        fs::create_dir_all(src.path().join("content/posts")).unwrap();
+        fs::write(
+            src.path().join("hugo.toml"),
+            "baseURL = 'https://example.com/'\ntitle = 'Site'\n[permalinks]\nposts = '/:year/:slug/'\n",
+        )
+        .unwrap();
+        fs::write(
+            src.path().join("content/posts/_index.md"),
+            "+++\ntitle = 'Posts'\n+++\n",
+        )
+        .unwrap();
+        fs::write(
+            src.path().join("content/posts/a.md"),
+            "+++\ntitle = 'A'\ndate = 2024-01-02T03:04:05Z\ndraft = true\n+++\n{{< youtube abc >}}\n",
+        )
+        .unwrap();
+
+        let report = import_project(ImportSource::Hugo, src.path(), out.path()).unwrap();
+        assert!(report.written.contains(&PathBuf::from("posts/index.md")));
+        assert_eq!(report.notes.len(), 1);
+
+        let config = crate::config::Config::read(out.path().join("lettersmith.json")).unwrap();
+        assert_eq!(config.site_url, "https://example.com/");
+        assert_eq!(config.permalink_template, None);
+        assert_eq!(config.permalinks["posts/**"], "{yyyy}/{slug}/index.html");
+
+        let doc = crate::doc::Doc::read(out.path().join("posts/a.md"))
+            .unwrap()
+            .parse_and_uplift_frontmatter();
+        assert_eq!(doc.created.to_rfc3339(), "2024-01-02T03:04:05+00:00");
+        assert_eq!(doc.meta["draft"], json!(true));
+    }
+}
@@ -0,0 +1,492 @@
+// Ingest RSS and Atom feeds as docs, for link blogs and planet-style
+// aggregation sites
+use crate::doc::Doc;
+use crate::docs::DocResults;
+use crate::error::Error;
+use crate::html::{strip_html, unescape_xml};
+use crate::io::{default_threads, map_parallel, read_url_or_path};
+use crate::json::json;
+use crate::text::{to_slug, truncate_280};
+use chrono::{DateTime, Utc};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A feed to ingest, as listed in an OPML file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedSource {
+    pub title: String,
+    /// URL or file path of the feed
+    pub url: String,
+}
+
+/// A parsed feed entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    pub title: String,
+    pub link: String,
+    /// Summary or content HTML
+    pub summary: String,
+    pub date: Option<DateTime<Utc>>,
+}
+
+/// A parsed RSS or Atom feed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    pub title: String,
+    pub entries: Vec<FeedEntry>,
+}
+
+/// Get the local name of an element, without its namespace prefix
+fn local_name(tag: &BytesStart) -> String {
+    tag.local_name().as_ref().to_string()
+}
+
+/// Get an attribute of a tag by local name, unescaped
+fn get_attr(tag: &BytesStart, name: &str) -> Option<String> {
+    tag.attributes()
+        .flatten()
+        .find(|attr| attr.key.local_name().as_ref() == name)
+        .map(|attr| unescape_xml(&attr.value))
+}
+
+fn xml_error(err: impl std::fmt::Display) -> Error {
+    Error::value(format!("Could not parse XML: {}", err))
+}
+
+/// Parse the feeds listed in an OPML file. Outlines without an `xmlUrl`
+/// (such as folders) are skipped.
+pub fn parse_opml(opml: &str) -> Result<Vec<FeedSource>, Error> {
+    let mut reader = Reader::from_str(opml);
+    let mut sources = Vec::new();
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(tag) | Event::Empty(tag) if local_name(&tag) == "outline" => {
+                let Some(url) = get_attr(&tag, "xmlUrl") else {
+                    continue;
+                };
+                let title = get_attr(&tag, "title")
+                    .or(get_attr(&tag, "text"))
+                    .unwrap_or_else(|| url.clone());
+                sources.push(FeedSource { title, url });
+            }
+            Event::Eof => return Ok(sources),
+            _ => {}
+        }
+    }
+}
+
+/// The text of the child elements of an entry, and its links, as read
+#[derive(Debug, Default)]
+struct RawEntry {
+    /// Text of child elements by local name. The first element wins.
+    fields: HashMap<String, String>,
+    /// Text of RSS `<link>` elements
+    link_text: Option<String>,
+    /// `(rel, href)` of Atom `<link>` elements
+    links: Vec<(Option<String>, String)>,
+}
+
+impl RawEntry {
+    fn field(&self, names: &[&str]) -> String {
+        names
+            .iter()
+            .find_map(|name| self.fields.get(*name).filter(|text| !text.is_empty()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the entry's link: the text of an RSS `<link>`, or the `href` of
+    /// an Atom `<link>` (preferring `rel="alternate"`)
+    fn link(&self) -> String {
+        if let Some(link) = self.link_text.as_ref().filter(|link| !link.is_empty()) {
+            return link.clone();
+        }
+        self.links
+            .iter()
+            .find(|(rel, _)| rel.as_ref().is_none_or(|rel| rel == "alternate"))
+            .or(self.links.first())
+            .map(|(_, href)| href.clone())
+            .unwrap_or_default()
+    }
+
+    fn into_entry(self) -> FeedEntry {
+        FeedEntry {
+            title: self.field(&["title"]),
+            link: self.link(),
+            // `content:encoded` has the local name `encoded`
+            summary: self.field(&["description", "summary", "encoded", "content"]),
+            // `dc:date` has the local name `date`
+            date: parse_feed_date(&self.field(&["pubDate", "published", "updated", "date"])),
+        }
+    }
+}
+
+/// Text being read from an element
+#[derive(Debug)]
+struct OpenField {
+    name: String,
+    text: String,
+    /// Depth of markup nested inside the element, such as Atom XHTML content
+    depth: usize,
+}
+
+/// Parse an RFC 2822 (RSS) or RFC 3339 (Atom) date
+fn parse_feed_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Parse an RSS or Atom feed.
+/// Reads the fields needed for aggregation. Namespace prefixes are ignored,
+/// CDATA sections are unwrapped, and markup nested in an element (such as
+/// Atom XHTML content) is kept as HTML.
+pub fn parse_feed(xml: &str) -> Result<Feed, Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut title: Option<String> = None;
+    let mut entries = Vec::new();
+    // Local names of the open elements
+    let mut stack: Vec<String> = Vec::new();
+    let mut entry: Option<RawEntry> = None;
+    let mut field: Option<OpenField> = None;
+    loop {
+        let event = reader.read_event().map_err(xml_error)?;
+        if let Some(open) = field.as_mut() {
+            if open.depth > 0 {
+                // Keep nested markup as is
+                match &event {
+                    Event::Start(tag) => {
+                        open.depth += 1;
+                        open.text.push_str(&format!("<{}>", &**tag));
+                    }
+                    Event::Empty(tag) => open.text.push_str(&format!("<{}/>", &**tag)),
+                    Event::End(tag) => {
+                        open.depth -= 1;
+                        open.text.push_str(&format!("</{}>", tag.name().as_ref()));
+                        if open.depth == 0 {
+                            // Close the outermost nested element
+                            stack.pop();
+                        }
+                        continue;
+                    }
+                    Event::Text(text) => open.text.push_str(text),
+                    Event::CData(text) => open.text.push_str(&escape(&**text)),
+                    Event::GeneralRef(entity) => open.text.push_str(&format!("&{};", &**entity)),
+                    Event::Eof => break,
+                    _ => {}
+                }
+                continue;
+            }
+        }
+        match event {
+            Event::Start(tag) => {
+                let name = local_name(&tag);
+                let parent = stack.last().map(String::as_str);
+                if name == "item" || name == "entry" {
+                    entry = Some(RawEntry::default());
+                } else if let Some(open) = field.as_mut() {
+                    open.depth = 1;
+                    open.text.push_str(&format!("<{}>", &*tag));
+                } else if let Some(entry) = entry.as_mut() {
+                    if name == "link" {
+                        if let Some(href) = get_attr(&tag, "href") {
+                            entry.links.push((get_attr(&tag, "rel"), href));
+                        }
+                    }
+                    if matches!(parent, Some("item" | "entry")) {
+                        field = Some(OpenField {
+                            name: name.clone(),
+                            text: String::new(),
+                            depth: 0,
+                        });
+                    }
+                } else if name == "title"
+                    && title.is_none()
+                    && matches!(parent, Some("channel" | "feed"))
+                {
+                    field = Some(OpenField {
+                        name: name.clone(),
+                        text: String::new(),
+                        depth: 0,
+                    });
+                }
+                stack.push(name);
+            }
+            Event::Empty(tag) => {
+                if let Some(open) = field.as_mut() {
+                    open.text.push_str(&format!("<{}/>", &*tag));
+                } else if let Some(entry) = entry.as_mut() {
+                    if local_name(&tag) == "link" {
+                        if let Some(href) = get_attr(&tag, "href") {
+                            entry.links.push((get_attr(&tag, "rel"), href));
+                        }
+                    }
+                }
+            }
+            Event::Text(text) => {
+                if let Some(open) = field.as_mut() {
+                    open.text.push_str(&unescape_xml(&text));
+                }
+            }
+            Event::CData(text) => {
+                if let Some(open) = field.as_mut() {
+                    open.text.push_str(&text);
+                }
+            }
+            Event::GeneralRef(entity) => {
+                if let Some(open) = field.as_mut() {
+                    open.text
+                        .push_str(&unescape_xml(&format!("&{};", &*entity)));
+                }
+            }
+            Event::End(_) => {
+                let name = stack.pop().unwrap_or_default();
+                if let Some(open) = field.take() {
+                    let text = open.text.trim().to_string();
+                    match entry.as_mut() {
+                        Some(entry) if open.name == "link" => {
+                            entry.link_text.get_or_insert(text);
+                        }
+                        Some(entry) => {
+                            entry.fields.entry(open.name).or_insert(text);
+                        }
+                        None => title = Some(text),
+                    }
+                } else if name == "item" || name == "entry" {
+                    if let Some(entry) = entry.take() {
+                        entries.push(entry.into_entry());
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(Feed {
+        title: title.unwrap_or_default(),
+        entries,
+    })
+}
+
+impl FeedEntry {
+    /// Convert a feed entry into a doc.
+    ///
+    /// - id_path and output_path: `{feed slug}/{entry slug}.html`
+    /// - content: the entry's summary HTML
+    /// - summary: the summary as plain text, truncated to 280 characters
+    /// - created and modified: the entry's date, or now if it has none
+    /// - meta: `{"link": "...", "feed": {"title": "...", "url": "..."}}`
+    pub fn to_doc(&self, feed_title: &str, feed_url: &str) -> Doc {
+        let feed_slug = to_slug(feed_title);
+        let entry_slug = match to_slug(&self.title) {
+            slug if slug.is_empty() => to_slug(&self.link),
+            slug => slug,
+        };
+        let path = PathBuf::from(format!("{}/{}.html", feed_slug, entry_slug));
+        let date = self.date.unwrap_or_else(Utc::now);
+        Doc::new(
+            path.clone(),
+            path,
+            None,
+            None,
+            date,
+            date,
+            self.title.clone(),
+            truncate_280(unescape_xml(&strip_html(&self.summary)).trim()),
+            self.summary.clone(),
+            json!({
+                "link": self.link,
+                "feed": {
+                    "title": feed_title,
+                    "url": feed_url,
+                }
+            }),
+        )
+    }
+}
+
+/// Fetch a feed and convert its entries into docs.
+/// The feed's own title is used when it has one, falling back to the title
+/// from the OPML file.
+pub fn ingest_feed(source: &FeedSource) -> Result<Vec<Doc>, Error> {
+    let xml = read_url_or_path(&source.url)?;
+    let feed = parse_feed(&xml)?;
+    let title = if feed.title.is_empty() {
+        source.title.clone()
+    } else {
+        feed.title
+    };
+    Ok(feed
+        .entries
+        .iter()
+        .map(|entry| entry.to_doc(&title, &source.url))
+        .collect())
+}
+
+/// Fetch feeds in parallel and convert their entries into docs.
+/// Docs are returned in the order of their feeds. Feeds that fail to fetch
+/// are returned as errors.
+pub fn ingest_feeds(sources: Vec<FeedSource>) -> impl DocResults {
+    let mut results = map_parallel(
+        sources.into_iter().enumerate(),
+        default_threads(),
+        |(i, source)| {
+            let result = ingest_feed(&source).map_err(|mut err| {
+                err.msg = format!("Feed {}: {}", source.url, err.msg);
+                err
+            });
+            (i, result)
+        },
+    );
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().flat_map(|(_, result)| match result {
+        Ok(docs) => docs.into_iter().map(Ok).collect(),
+        Err(err) => vec![Err(err)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+  <title>Fish &amp; Chips</title>
+  <link>https://fish.example</link>
+  <item>
+    <title>Cod</title>
+    <link>https://fish.example/cod</link>
+    <description><![CDATA[<p>Flaky &amp; white</p>]]></description>
+    <pubDate>Tue, 02 Jan 2024 03:04:05 +0000</pubDate>
+  </item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Birds</title>
+  <entry>
+    <title>Owl</title>
+    <link rel="self" href="https://birds.example/owl.xml"/>
+    <link rel="alternate" href="https://birds.example/owl"/>
+    <summary>Wise &lt;bird&gt;</summary>
+    <updated>2024-01-02T03:04:05Z</updated>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn test_parse_opml() {
+        let opml = r#"<opml><body>
+            <outline text="Folder">
+              <outline text="Fish" type="rss" xmlUrl="https://fish.example/feed.xml"/>
+              <outline title="Birds" xmlUrl='https://birds.example/atom.xml'/>
+            </outline>
+        </body></opml>"#;
+        assert_eq!(
+            parse_opml(opml).unwrap(),
+            vec![
+                FeedSource {
+                    title: "Fish".to_string(),
+                    url: "https://fish.example/feed.xml".to_string()
+                },
+                FeedSource {
+                    title: "Birds".to_string(),
+                    url: "https://birds.example/atom.xml".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_feed() {
+        let date = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let rss = parse_feed(RSS).unwrap();
+        assert_eq!(rss.title, "Fish & Chips");
+        assert_eq!(
+            rss.entries,
+            vec![FeedEntry {
+                title: "Cod".to_string(),
+                link: "https://fish.example/cod".to_string(),
+                summary: "<p>Flaky &amp; white</p>".to_string(),
+                date: Some(date),
+            }]
+        );
+        let atom = parse_feed(ATOM).unwrap();
+        assert_eq!(atom.title, "Birds");
+        assert_eq!(atom.entries[0].link, "https://birds.example/owl");
+        assert_eq!(atom.entries[0].summary, "Wise <bird>");
+        assert_eq!(atom.entries[0].date, Some(date));
+    }
+
+    #[test]
+    fn test_parse_feed_namespaces_and_markup() {
+        let rss = r#"<rss xmlns:content="http://purl.org/rss/1.0/modules/content/"
+            xmlns:dc="http://purl.org/dc/elements/1.1/"><channel>
+          <title><![CDATA[Fish]]></title>
+          <image><title>Logo</title></image>
+          <item>
+            <title><![CDATA[Cod </title> & chips]]></title>
+            <link>https://fish.example/cod</link>
+            <content:encoded><![CDATA[<p>Flaky</p>]]></content:encoded>
+            <dc:date>2024-01-02T03:04:05Z</dc:date>
+          </item>
+        </channel></rss>"#;
+        let rss = parse_feed(rss).unwrap();
+        assert_eq!(rss.title, "Fish");
+        assert_eq!(rss.entries[0].title, "Cod </title> & chips");
+        assert_eq!(rss.entries[0].summary, "<p>Flaky</p>");
+        assert!(rss.entries[0].date.is_some());
+
+        let atom = r#"<a:feed xmlns:a="http://www.w3.org/2005/Atom">
+          <a:title>Birds</a:title>
+          <a:entry>
+            <a:title>Owl</a:title>
+            <a:link href="https://birds.example/owl" rel="alternate"/>
+            <a:content type="xhtml"><div><p>Wise &amp; <em>old</em></p></div></a:content>
+          </a:entry>
+        </a:feed>"#;
+        let atom = parse_feed(atom).unwrap();
+        assert_eq!(atom.title, "Birds");
+        assert_eq!(atom.entries[0].link, "https://birds.example/owl");
+        assert_eq!(
+            atom.entries[0].summary,
+            "<div><p>Wise &amp; <em>old</em></p></div>"
+        );
+
+        assert!(parse_feed("<rss><channel><title>Fish</channel></rss>").is_err());
+    }
+
+    #[test]
+    fn test_ingest_feeds() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fish.xml");
+        std::fs::write(&path, RSS).unwrap();
+        let sources = vec![
+            FeedSource {
+                title: "Fish".to_string(),
+                url: path.to_string_lossy().into_owned(),
+            },
+            FeedSource {
+                title: "Missing".to_string(),
+                url: dir
+                    .path()
+                    .join("missing.xml")
+                    .to_string_lossy()
+                    .into_owned(),
+            },
+        ];
+        let results: Vec<Result<Doc, Error>> = ingest_feeds(sources).collect();
+        assert_eq!(results.len(), 2);
+        let doc = results[0].as_ref().unwrap();
+        assert_eq!(doc.output_path, PathBuf::from("fish--chips/cod.html"));
+        assert_eq!(doc.summary, "Flaky & white");
+        assert_eq!(doc.meta["link"], "https://fish.example/cod");
+        assert!(results[1].is_err());
+    }
+}
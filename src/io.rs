@@ -1,7 +1,17 @@
-use crate::error::Error;
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
+use crate::doc::DEFAULT_MAX_FILE_SIZE;
+use crate::error::{Error, ErrorKind};
+use crate::hash::content_hash;
+use crate::stats;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Number of items serialized together by `write_json_lines`
+const JSON_LINES_BATCH_SIZE: usize = 256;
 
 /// Filter out errors and log them to stderr.
 /// Returns a new iterator of only the successful values.
@@ -34,7 +44,104 @@ where
     })
 }
 
+/// Map items in parallel over a bounded pool of `threads` worker threads.
+/// Items are pulled from the iterator on the calling thread, so the iterator
+/// itself does not need to be `Send`. Results are returned in completion
+/// order, not input order.
+pub fn map_parallel<T, R, F>(items: impl Iterator<Item = T>, threads: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let threads = threads.max(1);
+    let (item_tx, item_rx) = mpsc::sync_channel::<T>(threads * 2);
+    let (result_tx, result_rx) = mpsc::channel::<R>();
+    let item_rx = Mutex::new(item_rx);
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_tx = result_tx.clone();
+            let item_rx = &item_rx;
+            let f = &f;
+            scope.spawn(move || loop {
+                // Hold the lock only long enough to take the next item
+                let item = item_rx.lock().expect("Worker lock poisoned").recv();
+                match item {
+                    Ok(item) => {
+                        let _ = result_tx.send(f(item));
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+        for item in items {
+            item_tx.send(item).expect("Worker threads exited early");
+        }
+        drop(item_tx);
+    });
+    drop(result_tx);
+    result_rx.into_iter().collect()
+}
+
+/// Write items to `writer` as line-separated JSON.
+/// Items are serialized in batches, in parallel over `threads` worker
+/// threads, and written in order. Serialization failures are logged as
+/// `tracing` errors, and the item is left out.
+///
+/// Returns the first write error, such as a closed pipe, and stops writing.
+pub fn write_json_lines<T: Serialize + Send>(
+    items: impl Iterator<Item = T>,
+    threads: usize,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let mut items = items.enumerate();
+    loop {
+        let batch: Vec<(usize, T)> = items.by_ref().take(JSON_LINES_BATCH_SIZE).collect();
+        if batch.is_empty() {
+            break;
+        }
+        let mut lines = map_parallel(batch.into_iter(), threads, |(i, item)| {
+            (i, serde_json::to_string(&item))
+        });
+        lines.sort_by_key(|(i, _)| *i);
+        for (_, line) in lines {
+            match line {
+                Ok(json) => {
+                    writer.write_all(json.as_bytes())?;
+                    writer.write_all(b"\n")?;
+                    stats::record_written(json.len());
+                }
+                Err(err) => tracing::error!("Error serializing JSON: {:?}", err),
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Write items to stdout as line-separated JSON, through a locked, buffered
+/// writer. See `write_json_lines`.
+/// A closed pipe (such as `| head`) means the reader is done, so it ends
+/// the write quietly. Other write errors are logged as `tracing` errors.
+pub fn write_json_lines_to_stdout<T: Serialize + Send>(items: impl Iterator<Item = T>) {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    if let Err(err) = write_json_lines(items, default_threads(), &mut stdout) {
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            tracing::error!("Error writing to stdout: {}", err);
+        }
+    }
+}
+
+/// Default number of worker threads for parallel I/O
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
 /// Write content to a file, creating directories if necessary.
+/// The file is written atomically: content is written to a temporary file
+/// in the same directory, which is then renamed over the destination, so
+/// readers never see a partially-written file.
 ///
 /// # Arguments
 ///
@@ -45,17 +152,170 @@ where
 ///
 /// This function will return an error if the file cannot be created or written to,
 /// or if the directories cannot be created.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(path = %path.as_ref().display())))]
 pub fn write_file_deep<P: AsRef<Path>>(path: P, content: &str) -> Result<(), Error> {
+    let path = path.as_ref();
+    // Write the content to a temp file, then move it into place.
+    let mut file = temp_file_for(path)?;
+    file.write_all(content.as_bytes())?;
+    file.persist(path).map_err(|err| err.error)?;
+
+    Ok(())
+}
+
+/// Create a temp file next to `path`, creating directories if necessary,
+/// for writing content that is then moved into place with `persist`.
+/// Temp files are private by default, so the file gets the permissions of
+/// the file it replaces, or the usual 0666 masked by the umask.
+pub(crate) fn temp_file_for(path: &Path) -> Result<tempfile::NamedTempFile, Error> {
     // Create parent directories if they don't exist
-    if let Some(parent) = path.as_ref().parent() {
-        fs::create_dir_all(parent)?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let file = builder.tempfile_in(parent)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.as_file().set_permissions(metadata.permissions())?;
     }
+    Ok(file)
+}
 
-    // Write the content to the file
-    let mut file = File::create(path)?;
-    file.write_all(content.as_bytes())?;
+/// Is this string an http or https URL?
+pub fn is_http_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
 
-    Ok(())
+/// Fetch the body of an http(s) URL as text.
+/// Follows redirects, and fails on HTTP error statuses, if the request
+/// takes longer than 30 seconds, or if the body is larger than
+/// `DEFAULT_MAX_FILE_SIZE`. See `fetch_url_with_max_size`.
+pub fn fetch_url(url: &str) -> Result<String, Error> {
+    fetch_url_with_max_size(url, DEFAULT_MAX_FILE_SIZE)
+}
+
+/// Fetch the body of an http(s) URL as text, failing with
+/// `ErrorKind::TooLarge` if it is larger than `max_size` bytes.
+/// Request failures are `ErrorKind::Http` errors.
+pub fn fetch_url_with_max_size(url: &str, max_size: u64) -> Result<String, Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .build();
+    let response = agent.get(url).call().map_err(|err| match err {
+        ureq::Error::Status(status, response) => Error::new(
+            ErrorKind::Http,
+            format!(
+                "Could not fetch {}: HTTP {} {}",
+                url,
+                status,
+                response.status_text()
+            ),
+        ),
+        ureq::Error::Transport(err) => {
+            Error::new(ErrorKind::Http, format!("Could not fetch {}: {}", url, err))
+        }
+    })?;
+    let mut body = Vec::new();
+    // Read one byte past the limit, to tell if the body is over it
+    response
+        .into_reader()
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > max_size {
+        return Err(Error::new(
+            ErrorKind::TooLarge,
+            format!(
+                "Response from {} is larger than the maximum size of {} bytes",
+                url, max_size
+            ),
+        ));
+    }
+    String::from_utf8(body).map_err(|_| {
+        Error::new(
+            ErrorKind::Binary,
+            format!("Response from {} is not UTF-8 text", url),
+        )
+    })
+}
+
+/// An on-disk cache for fetched URLs.
+/// Responses are kept in `dir`, and reused until they are older than `ttl`.
+/// Responses larger than `max_size` bytes are refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchCache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+    pub max_size: u64,
+}
+
+impl Default for FetchCache {
+    fn default() -> Self {
+        FetchCache::new(".lettersmith/cache", Duration::from_secs(3600))
+    }
+}
+
+impl FetchCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        FetchCache {
+            dir: dir.into(),
+            ttl,
+            max_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+
+    /// Set the maximum size of responses, in bytes
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Get the path a URL is cached at. Paths are named by `content_hash`
+    /// of the URL, so they stay the same across Rust versions.
+    pub fn path_for(&self, url: &str) -> PathBuf {
+        self.dir.join(content_hash(url))
+    }
+
+    /// Fetch a URL, reusing the cached response if it is fresher than the
+    /// TTL. If fetching fails and a stale response is cached, the stale
+    /// response is used, with a warning emitted as a `tracing` event, so
+    /// builds keep working offline.
+    pub fn fetch(&self, url: &str) -> Result<String, Error> {
+        let path = self.path_for(url);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age < self.ttl) {
+            return Ok(fs::read_to_string(&path)?);
+        }
+        match fetch_url_with_max_size(url, self.max_size) {
+            Ok(body) => {
+                write_file_deep(&path, &body)?;
+                Ok(body)
+            }
+            Err(err) if age.is_some() => {
+                tracing::warn!("{}. Using cached copy.", err.msg);
+                Ok(fs::read_to_string(&path)?)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Read text from an http(s) URL, or else from a file path
+pub fn read_url_or_path(source: &str) -> Result<String, Error> {
+    if is_http_url(source) {
+        fetch_url(source)
+    } else {
+        Ok(fs::read_to_string(source)?)
+    }
 }
 
 #[cfg(test)]
@@ -72,6 +332,106 @@ mod tests {
         write_file_deep(&file_path, content).unwrap();
 
         assert!(file_path.exists());
-        assert_eq!(fs::read_to_string(file_path).unwrap(), content);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), content);
+
+        // Overwrites existing files, leaving no temp files behind
+        write_file_deep(&file_path, "Goodbye").unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "Goodbye");
+        assert_eq!(
+            fs::read_dir(dir.path().join("deep/nested"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_file_deep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("a.html");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        // New files get the same permissions as files made by `fs::write`:
+        // 0666 masked by the umask
+        write_file_deep(&file_path, "a").unwrap();
+        let plain_path = dir.path().join("plain.html");
+        fs::write(&plain_path, "a").unwrap();
+        assert_eq!(mode(&file_path), mode(&plain_path));
+
+        // Replaced files keep their permissions
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_file_deep(&file_path, "b").unwrap();
+        assert_eq!(mode(&file_path), 0o640);
+    }
+
+    #[test]
+    fn test_write_json_lines() {
+        let mut out = Vec::new();
+        write_json_lines(0..1000, 4, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 1000);
+        assert_eq!(lines[0], "0");
+        assert_eq!(lines[999], "999");
+        assert!(lines
+            .iter()
+            .enumerate()
+            .all(|(i, line)| *line == i.to_string()));
+    }
+
+    #[test]
+    fn test_map_parallel() {
+        let mut results = map_parallel(1..=100, 4, |n| n * 2);
+        results.sort();
+        assert_eq!(results, (1..=100).map(|n| n * 2).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_fetch_url_errors() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let responses = [
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 20\r\nConnection: close\r\n\r\n01234567890123456789",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let err = fetch_url(&format!("{}/missing", base)).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Http));
+        assert!(err.msg.ends_with("HTTP 404 Not Found"));
+        let err = fetch_url_with_max_size(&format!("{}/big", base), 10).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::TooLarge));
+        server.join().unwrap();
+        let err = fetch_url("http://127.0.0.1:1/").unwrap_err();
+        assert_eq!(err.kind.name(), "http");
+    }
+
+    #[test]
+    fn test_fetch_cache() {
+        let dir = tempdir().unwrap();
+        let url = "http://127.0.0.1:1/data.json";
+        let cache = FetchCache::new(dir.path(), Duration::from_secs(3600));
+        // Cache paths are stable across builds
+        assert_eq!(cache.path_for(url), dir.path().join("fefeb52c6f013326"));
+        write_file_deep(cache.path_for(url), r#"{"a": 1}"#).unwrap();
+        // Fresh responses are reused without fetching
+        assert_eq!(cache.fetch(url).unwrap(), r#"{"a": 1}"#);
+        // Stale responses are used when fetching fails
+        let stale = FetchCache::new(dir.path(), Duration::ZERO);
+        assert_eq!(stale.fetch(url).unwrap(), r#"{"a": 1}"#);
+        assert!(stale.fetch("http://127.0.0.1:1/other.json").is_err());
     }
 }
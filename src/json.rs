@@ -1,10 +1,10 @@
 use crate::error::{Error, ErrorKind};
-use crate::io::write_file_deep;
+use crate::io::{is_http_url, write_file_deep, FetchCache};
 use crate::json;
 use serde::Serialize;
 pub use serde_json::from_str;
 pub use serde_json::from_value;
-pub use serde_json::{json, to_string_pretty, Value};
+pub use serde_json::{json, to_string_pretty, Map, Value};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
@@ -32,17 +32,42 @@ where
 /// Read a series of paths to JSON files into hashmap of `data` for templates.
 /// Returns a Result of `HashMap<String, json::Value>`, where string keys
 /// are the file stems of the JSON files.
+///
+/// Paths may also be http(s) URLs, which are fetched using the default
+/// `FetchCache`. See `read_data_map` to configure caching.
 pub fn read_json_files_as_data_map(
-    paths: &Vec<PathBuf>,
+    paths: &[PathBuf],
+) -> Result<HashMap<String, json::Value>, Error> {
+    read_data_map(paths, &FetchCache::default())
+}
+
+/// Read a series of JSON file paths or http(s) URLs into a hashmap of `data`
+/// for templates, keyed by the file stem of each path or the last segment
+/// of each URL. For example, `https://api.github.com/users/me/repos` is
+/// keyed as `repos`. URLs are fetched through `cache`.
+pub fn read_data_map(
+    sources: &[PathBuf],
+    cache: &FetchCache,
 ) -> Result<HashMap<String, json::Value>, Error> {
     let mut data: HashMap<String, json::Value> = HashMap::new();
-    for path in paths {
-        let stem = path
+    for source in sources {
+        let stem = source
             .file_stem()
             .ok_or(Error::new(ErrorKind::Other, "Could not unwrap file stem"))?
             .to_string_lossy()
             .into_owned();
-        let value = read(path)?;
+        let source_str = source.to_string_lossy();
+        let value = if is_http_url(&source_str) {
+            let body = cache.fetch(&source_str)?;
+            serde_json::from_str(&body).map_err(|err| {
+                Error::new(
+                    ErrorKind::Json(err),
+                    format!("Invalid JSON from {}", source_str),
+                )
+            })?
+        } else {
+            read(source)?
+        };
         data.insert(stem, value);
     }
     Ok(data)
@@ -80,6 +105,24 @@ pub fn get_deep(value: &Value, prop: &str) -> Option<Value> {
     Some(current.to_owned())
 }
 
+/// Set a deep property on a JSON value using dot notation, creating
+/// intermediate objects as needed. Non-object values along the path are
+/// replaced with objects.
+pub fn set_deep(value: &mut Value, prop: &str, new_value: Value) {
+    let mut current = value;
+    for key in prop.split('.') {
+        if !current.is_object() {
+            *current = Value::Object(Map::new());
+        }
+        current = current
+            .as_object_mut()
+            .expect("Value should be an object")
+            .entry(key)
+            .or_insert(Value::Null);
+    }
+    *current = new_value;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +147,20 @@ mod tests {
         assert_eq!(get_deep(&json, "nonexistent"), None);
         assert_eq!(get_deep(&json, "a.nonexistent"), None);
     }
+
+    #[test]
+    fn test_set_deep() {
+        let mut value = json!({"a": {"b": 1}, "x": 42});
+        set_deep(&mut value, "a.c", json!("c"));
+        set_deep(&mut value, "x.y", json!(true));
+        set_deep(&mut value, "new.deep.key", json!([1]));
+        assert_eq!(
+            value,
+            json!({
+                "a": {"b": 1, "c": "c"},
+                "x": {"y": true},
+                "new": {"deep": {"key": [1]}}
+            })
+        );
+    }
 }
@@ -1,23 +1,59 @@
 pub use tap::pipe;
+pub mod a11y;
 pub mod absolutize;
 pub mod blog;
+pub mod bundle;
 pub mod cli;
+pub mod comments;
+pub mod computed;
 pub mod config;
 pub mod doc;
+pub mod doc_set;
 pub mod docs;
+pub mod doctor;
+pub mod embed;
 pub mod error;
 pub mod frontmatter;
+pub mod graph;
+pub mod group;
+pub mod hash;
+pub mod head;
 pub mod html;
+pub mod html_rewrite;
+pub mod ics;
+pub mod import;
+pub mod ingest;
 pub mod io;
 pub mod json;
+pub mod linkify;
 pub mod markdown;
+pub mod microformats;
+pub mod nav;
+pub mod paginate;
 pub mod permalink;
+pub mod pipeline;
 pub mod prelude;
+pub mod preview;
+#[cfg(feature = "prose")]
+pub mod prose;
+pub mod query;
+pub mod redirects;
+pub mod render_cache;
+pub mod report;
 pub mod rss;
+pub mod scaffold;
+pub mod schema;
+#[cfg(feature = "script")]
+pub mod script;
 pub mod sitemap;
+pub mod snippet;
+pub mod spill;
 pub mod stash;
+pub mod stats;
+pub mod stub;
 pub mod tags;
 pub mod tera;
 pub mod text;
 pub mod token_template;
+pub mod url;
 pub mod wikilink;
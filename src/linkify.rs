@@ -0,0 +1,318 @@
+// Turn bare URLs and hashtags in content into links
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::html::escape_xml;
+use crate::json;
+use crate::snippet::{is_closing_fence, parse_fence};
+use crate::tags::to_tag;
+use crate::text::to_slug;
+use crate::token_template;
+use regex::{self, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// The regex crate has no lookbehind, so the preceding whitespace (or start
+// of text) is captured and written back. Requiring it skips URLs that are
+// already the target of a Markdown link, autolink, or HTML attribute.
+static BARE_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(^|\s)(https?://[^\s<>"'\]\)]+)"#).expect("Could not parse regular expression")
+});
+
+static HASHTAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(^|\s)#([\p{L}_][\p{L}\p{N}_-]*)").expect("Could not parse regular expression")
+});
+
+/// What to do with hashtags in content once they are extracted into meta
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum HashtagAction {
+    /// Leave hashtags in content as they are
+    #[default]
+    Keep,
+    /// Remove hashtags from content
+    Strip,
+    /// Convert hashtags into links to their tag archive
+    Link,
+}
+
+/// Find the end of a run of exactly `len` backticks in `text`
+fn find_closing_backticks(text: &str, len: usize) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(i) = text[offset..].find('`') {
+        let start = offset + i;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == len {
+            return Some(start);
+        }
+        offset = start + run;
+    }
+    None
+}
+
+/// Apply `f` to the parts of a paragraph that aren't inline code spans
+fn map_inline_prose(text: &str, f: &mut impl FnMut(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('`') {
+        let run = rest[i..].len() - rest[i..].trim_start_matches('`').len();
+        let after = &rest[i + run..];
+        match find_closing_backticks(after, run) {
+            Some(j) => {
+                let end = i + run + j + run;
+                out.push_str(&f(&rest[..i]));
+                out.push_str(&rest[i..end]);
+                rest = &rest[end..];
+            }
+            None => {
+                // Unmatched backticks are literal text
+                out.push_str(&f(&rest[..i + run]));
+                rest = after;
+            }
+        }
+    }
+    out.push_str(&f(rest));
+    out
+}
+
+/// Apply `f` to the prose in Markdown text, skipping fenced code blocks and
+/// inline code spans.
+pub fn map_prose(text: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut prose = String::new();
+    let mut lines = text.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let Some(fence) = parse_fence(line) else {
+            prose.push_str(line);
+            continue;
+        };
+        out.push_str(&map_inline_prose(&prose, &mut f));
+        prose.clear();
+        out.push_str(line);
+        for line in lines.by_ref() {
+            out.push_str(line);
+            if is_closing_fence(line, &fence) {
+                break;
+            }
+        }
+    }
+    out.push_str(&map_inline_prose(&prose, &mut f));
+    out
+}
+
+/// Convert bare `http://` and `https://` URLs into anchors.
+/// Trailing punctuation is left outside the link.
+pub fn linkify_urls(text: &str) -> String {
+    map_prose(text, |prose| {
+        BARE_URL
+            .replace_all(prose, |caps: &regex::Captures| {
+                let url = caps[2].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+                let trailing = &caps[2][url.len()..];
+                let url = escape_xml(url);
+                format!(r#"{}<a href="{}">{}</a>{}"#, &caps[1], url, url, trailing)
+            })
+            .into_owned()
+    })
+}
+
+/// Find hashtags in Markdown text, skipping code.
+/// Tags are normalized with `to_tag` and deduplicated.
+pub fn find_hashtags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    map_prose(text, |prose| {
+        for caps in HASHTAG.captures_iter(prose) {
+            let tag = to_tag(&caps[2]);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        prose.to_string()
+    });
+    tags
+}
+
+/// Convert hashtags into links to their tag archive.
+/// The href is rendered from `href_template`, with the tokens:
+/// - `taxonomy`: the sluggified taxonomy key
+/// - `term`: the sluggified tag
+pub fn linkify_hashtags(text: &str, taxonomy_key: &str, href_template: &str) -> String {
+    let taxonomy_slug = to_slug(taxonomy_key);
+    map_prose(text, |prose| {
+        HASHTAG
+            .replace_all(prose, |caps: &regex::Captures| {
+                let mut parts = HashMap::new();
+                parts.insert("taxonomy", taxonomy_slug.clone());
+                parts.insert("term", to_slug(&to_tag(&caps[2])));
+                let href = token_template::render(href_template, &parts);
+                format!(
+                    r#"{}<a class="hashtag" href="{}">#{}</a>"#,
+                    &caps[1],
+                    escape_xml(&href),
+                    &caps[2]
+                )
+            })
+            .into_owned()
+    })
+}
+
+/// Remove hashtags from Markdown text, skipping code.
+/// Whitespace before a hashtag is removed with it, so tags trailing a line
+/// don't leave trailing spaces behind. Line breaks are kept.
+pub fn strip_hashtags(text: &str) -> String {
+    map_prose(text, |prose| {
+        HASHTAG
+            .replace_all(prose, |caps: &regex::Captures| {
+                let space = &caps[1];
+                match space.rfind('\n') {
+                    Some(i) => space[..=i].to_string(),
+                    None => String::new(),
+                }
+            })
+            .into_owned()
+    })
+}
+
+impl Doc {
+    /// Convert bare URLs in content into anchors.
+    /// See `linkify_urls`.
+    pub fn linkify_urls(mut self) -> Self {
+        self.content = linkify_urls(&self.content);
+        self
+    }
+
+    /// Find hashtags in content (outside code), and add them to the taxonomy
+    /// at `taxonomy_key` in meta, normalized with `to_tag`, so they are filed
+    /// under their archive by `generate_tag_archives`.
+    ///
+    /// Hashtags are then kept, stripped, or linked in content, according to
+    /// `action`. Links are rendered from `href_template`. See
+    /// `linkify_hashtags`.
+    pub fn extract_hashtags(
+        mut self,
+        taxonomy_key: &str,
+        action: HashtagAction,
+        href_template: &str,
+    ) -> Self {
+        let hashtags = find_hashtags(&self.content);
+        match action {
+            HashtagAction::Keep => {}
+            HashtagAction::Strip => self.content = strip_hashtags(&self.content),
+            HashtagAction::Link => {
+                self.content = linkify_hashtags(&self.content, taxonomy_key, href_template)
+            }
+        }
+        if hashtags.is_empty() {
+            return self;
+        }
+        let existing = self.get_meta_tags(taxonomy_key);
+        if !self.meta.is_object() {
+            self.meta = json::json!({});
+        }
+        let meta = self.meta.as_object_mut().expect("Meta is an object");
+        let tags = meta
+            .entry(taxonomy_key)
+            .or_insert_with(|| json::Value::Array(Vec::new()));
+        // Expand comma-separated tag strings, so hashtags can be appended
+        if let json::Value::String(tag_string) = tags {
+            *tags = tag_string
+                .split(',')
+                .map(|tag| tag.trim())
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| json::Value::String(tag.to_string()))
+                .collect();
+        }
+        if let json::Value::Array(tags) = tags {
+            for hashtag in hashtags {
+                if !existing.contains(&hashtag) {
+                    tags.push(json::Value::String(hashtag));
+                }
+            }
+        }
+        self
+    }
+
+    /// Convert hashtags in content into links to their tag archive, and
+    /// add them to the taxonomy at `taxonomy_key` in meta, so they are filed
+    /// under their archive by `generate_tag_archives`.
+    pub fn linkify_hashtags(self, taxonomy_key: &str, href_template: &str) -> Self {
+        self.extract_hashtags(taxonomy_key, HashtagAction::Link, href_template)
+    }
+}
+
+pub trait LinkifyDocs: Docs {
+    /// Convert bare URLs in content into anchors
+    fn linkify_urls(self) -> impl Docs {
+        self.map(|doc| doc.linkify_urls())
+    }
+
+    /// Convert hashtags in content into links to their tag archive, and add
+    /// them to the taxonomy in meta
+    fn linkify_hashtags(self, taxonomy_key: &str, href_template: &str) -> impl Docs {
+        self.map(move |doc| doc.linkify_hashtags(taxonomy_key, href_template))
+    }
+
+    /// Add hashtags in content to the taxonomy in meta, then keep, strip,
+    /// or link them. See `Doc::extract_hashtags`.
+    fn extract_hashtags(
+        self,
+        taxonomy_key: &str,
+        action: HashtagAction,
+        href_template: &str,
+    ) -> impl Docs {
+        self.map(move |doc| doc.extract_hashtags(taxonomy_key, action, href_template))
+    }
+}
+
+impl<I> LinkifyDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    #[test]
+    fn test_linkify_urls() {
+        let text = "See https://example.com/a, or [this](https://b.com).\n\n```\nhttps://code.com\n```\n`https://inline.com` http://c.com";
+        assert_eq!(
+            linkify_urls(text),
+            "See <a href=\"https://example.com/a\">https://example.com/a</a>, or [this](https://b.com).\n\n```\nhttps://code.com\n```\n`https://inline.com` <a href=\"http://c.com\">http://c.com</a>"
+        );
+    }
+
+    #[test]
+    fn test_find_hashtags() {
+        let text = "#Rust is fun. #rust #static_sites\n# Heading\n`#code` issue #12";
+        assert_eq!(find_hashtags(text), vec!["rust", "static_sites"]);
+    }
+
+    #[test]
+    fn test_doc_linkify_hashtags() {
+        let doc = Doc::draft("a.md")
+            .set_meta(json!({"tags": ["rust"]}))
+            .set_content("Building a #StaticSite in #rust")
+            .linkify_hashtags("tags", "{taxonomy}/{term}/index.html");
+        assert_eq!(
+            doc.content,
+            r#"Building a <a class="hashtag" href="tags/staticsite/index.html">#StaticSite</a> in <a class="hashtag" href="tags/rust/index.html">#rust</a>"#
+        );
+        assert_eq!(doc.meta["tags"], json!(["rust", "staticsite"]));
+    }
+
+    #[test]
+    fn test_extract_hashtags_strip() {
+        let doc = Doc::draft("a.md")
+            .set_meta(json!({"tags": "notes"}))
+            .set_content("Shipped it! #Rust #ssg\n\n```\n#not_a_tag\n```\n#done")
+            .extract_hashtags("tags", HashtagAction::Strip, "");
+        assert_eq!(doc.content, "Shipped it!\n\n```\n#not_a_tag\n```\n");
+        assert_eq!(doc.meta["tags"], json!(["notes", "rust", "ssg", "done"]));
+        let doc = Doc::draft("b.md").set_content("No tags").extract_hashtags(
+            "tags",
+            HashtagAction::Keep,
+            "",
+        );
+        assert_eq!(doc.meta, Doc::draft("b.md").meta);
+    }
+}
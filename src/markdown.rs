@@ -1,15 +1,251 @@
 use crate::doc::Doc;
 use crate::docs::Docs;
-use crate::html::strip_html;
-use pulldown_cmark::{html, Parser};
+use crate::html::{escape_xml, strip_html};
+use crate::stats;
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static FOOTNOTE_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r##"<sup class="footnote-reference"(?: id="[^"]*")?><a href="#([^"]*)">([^<]*)</a></sup>"##,
+    )
+    .expect("Could not parse regular expression")
+});
+
+static FOOTNOTE_DEFINITION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?s)<div class="footnote-definition" id="([^"]*)"><sup class="footnote-definition-label">([^<]*)</sup>\n?(.*?)</div>\n?"#,
+    )
+    .expect("Could not parse regular expression")
+});
+
+/// Closing tags of the blocks footnote references are found in
+static BLOCK_END: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"</(p|li|td|th|dd|h[1-6])>").expect("Could not parse regular expression")
+});
+
+/// Options for rendering Markdown
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkdownOptions {
+    /// Render images that have a title, and that sit alone in their own
+    /// paragraph, as `<figure>` elements with the title as `<figcaption>`.
+    #[serde(default)]
+    pub figures: bool,
+
+    /// Render footnotes, written as `text[^1]` with `[^1]: The note` below.
+    #[serde(default)]
+    pub footnotes: bool,
+
+    /// Add links from each footnote back to where it is referenced.
+    /// Applies when footnotes are on.
+    #[serde(default)]
+    pub footnote_backrefs: bool,
+
+    /// Repeat each footnote in an `<aside class="footnote-popover">` after
+    /// the block that first references it, for sidenote and popover themes.
+    /// Applies when footnotes are on.
+    #[serde(default)]
+    pub footnote_popovers: bool,
+
+    /// Use the first paragraph of rendered content as the summary, rather
+    /// than the first 280 characters. See `Doc::auto_summary_paragraph`.
+    #[serde(default)]
+    pub summary_paragraph: bool,
+}
 
 pub fn render_markdown(markdown: &str) -> String {
-    let parser = Parser::new(markdown);
+    render_markdown_with(markdown, &MarkdownOptions::default())
+}
+
+/// Render Markdown to HTML with options
+pub fn render_markdown_with(markdown: &str, options: &MarkdownOptions) -> String {
+    let mut parser_options = Options::empty();
+    if options.footnotes {
+        parser_options.insert(Options::ENABLE_FOOTNOTES);
+    }
+    let parser = Parser::new_ext(markdown, parser_options);
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    if options.figures {
+        let events = to_figures(parser.collect());
+        html::push_html(&mut html_output, events.into_iter());
+    } else {
+        html::push_html(&mut html_output, parser);
+    }
+    if options.footnotes && options.footnote_backrefs {
+        html_output = add_footnote_backrefs(&html_output);
+    }
+    if options.footnotes && options.footnote_popovers {
+        html_output = add_footnote_popovers(&html_output);
+    }
     html_output
 }
 
+/// Get the id of the nth reference to a footnote (counting from 1)
+fn footnote_reference_id(name: &str, n: usize) -> String {
+    if n == 1 {
+        format!("fnref-{}", name)
+    } else {
+        format!("fnref-{}-{}", name, n)
+    }
+}
+
+/// Add back-reference links to rendered footnotes.
+/// Each footnote reference is given an id, and each footnote definition
+/// gets a `↩` link back to every reference to it.
+pub fn add_footnote_backrefs(html: &str) -> String {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let html = FOOTNOTE_REFERENCE.replace_all(html, |caps: &Captures| {
+        let count = counts.entry(caps[1].to_string()).or_default();
+        *count += 1;
+        format!(
+            r##"<sup class="footnote-reference" id="{}"><a href="#{}">{}</a></sup>"##,
+            footnote_reference_id(&caps[1], *count),
+            &caps[1],
+            &caps[2]
+        )
+    });
+    FOOTNOTE_DEFINITION
+        .replace_all(&html, |caps: &Captures| {
+            let name = &caps[1];
+            let backrefs: Vec<String> = (1..=counts.get(name).copied().unwrap_or(0))
+                .map(|n| {
+                    let text = if n == 1 {
+                        "↩".to_string()
+                    } else {
+                        format!("↩<sup>{}</sup>", n)
+                    };
+                    format!(
+                        r##"<a href="#{}" class="footnote-backref">{}</a>"##,
+                        footnote_reference_id(name, n),
+                        text
+                    )
+                })
+                .collect();
+            let body = caps[3].trim_end();
+            let body = match body.strip_suffix("</p>") {
+                Some(body) if !backrefs.is_empty() => {
+                    format!("{} {}</p>", body, backrefs.join(" "))
+                }
+                _ if !backrefs.is_empty() => format!("{}\n{}", body, backrefs.join(" ")),
+                _ => body.to_string(),
+            };
+            format!(
+                "<div class=\"footnote-definition\" id=\"{}\"><sup class=\"footnote-definition-label\">{}</sup>\n{}\n</div>\n",
+                name, &caps[2], body
+            )
+        })
+        .into_owned()
+}
+
+/// Add inline popover markup for rendered footnotes.
+/// Each footnote's content is repeated in an
+/// `<aside class="footnote-popover" id="fnpop-{name}" role="note">` after
+/// the block holding its first reference, for themes that show footnotes
+/// as sidenotes or popovers. Asides are placed inside list items and table
+/// cells, rather than after them, to keep the markup valid.
+pub fn add_footnote_popovers(html: &str) -> String {
+    let definitions: HashMap<String, (String, String)> = FOOTNOTE_DEFINITION
+        .captures_iter(html)
+        .map(|caps| {
+            (
+                caps[1].to_string(),
+                (caps[2].to_string(), caps[3].trim_end().to_string()),
+            )
+        })
+        .collect();
+    let definitions_start = FOOTNOTE_DEFINITION
+        .find(html)
+        .map_or(html.len(), |m| m.start());
+    let mut seen = Vec::new();
+    let mut insertions: Vec<(usize, String)> = Vec::new();
+    for caps in FOOTNOTE_REFERENCE.captures_iter(&html[..definitions_start]) {
+        let name = caps[1].to_string();
+        let Some((label, body)) = definitions.get(&name) else {
+            continue;
+        };
+        if seen.contains(&name) {
+            continue;
+        }
+        let end = caps.get(0).expect("Match has a whole capture").end();
+        let position = match BLOCK_END.captures(&html[end..]) {
+            Some(block) => {
+                let tag = block.get(0).expect("Match has a whole capture");
+                if matches!(&block[1], "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+                    end + tag.end()
+                } else {
+                    end + tag.start()
+                }
+            }
+            None => definitions_start,
+        };
+        insertions.push((
+            position,
+            format!(
+                "\n<aside class=\"footnote-popover\" id=\"fnpop-{}\" role=\"note\"><span class=\"footnote-popover-label\">{}</span>\n{}\n</aside>",
+                name, label, body
+            ),
+        ));
+        seen.push(name);
+    }
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for (position, aside) in insertions {
+        out.push_str(&html[last..position]);
+        out.push_str(&aside);
+        last = position;
+    }
+    out.push_str(&html[last..]);
+    out
+}
+
+/// If the events at `start` are a paragraph holding only a titled image,
+/// returns the image title and the index of the closing paragraph event.
+fn match_captioned_image(events: &[Event], start: usize) -> Option<(String, usize)> {
+    if !matches!(events.get(start)?, Event::Start(Tag::Paragraph)) {
+        return None;
+    }
+    let title = match events.get(start + 1)? {
+        Event::Start(Tag::Image { title, .. }) if !title.is_empty() => title.to_string(),
+        _ => return None,
+    };
+    let image_end =
+        (start + 2..events.len()).find(|&i| matches!(events[i], Event::End(TagEnd::Image)))?;
+    match events.get(image_end + 1)? {
+        Event::End(TagEnd::Paragraph) => Some((title, image_end + 1)),
+        _ => None,
+    }
+}
+
+/// Replace paragraphs holding only a titled image with figures
+fn to_figures(events: Vec<Event>) -> Vec<Event> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut i = 0;
+    while i < events.len() {
+        match match_captioned_image(&events, i) {
+            Some((caption, end)) => {
+                out.push(Event::Html("<figure>".into()));
+                out.extend(events[i + 1..end].iter().cloned());
+                out.push(Event::Html(
+                    format!(
+                        "<figcaption>{}</figcaption></figure>\n",
+                        escape_xml(&caption)
+                    )
+                    .into(),
+                ));
+                i = end + 1;
+            }
+            None => {
+                out.push(events[i].clone());
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
 pub fn strip_markdown(markdown: &str) -> String {
     strip_html(&render_markdown(markdown))
 }
@@ -17,10 +253,21 @@ pub fn strip_markdown(markdown: &str) -> String {
 impl Doc {
     /// Render content with Markdown, and generate automatic summaries
     pub fn render_markdown(self) -> Self {
-        let content = render_markdown(&self.content);
-        self.set_content(content)
-            .auto_summary()
-            .set_extension_html()
+        self.render_markdown_with(&MarkdownOptions::default())
+    }
+
+    /// Render content with Markdown using options, and generate automatic
+    /// summaries
+    pub fn render_markdown_with(self, options: &MarkdownOptions) -> Self {
+        let content = render_markdown_with(&self.content, options);
+        stats::record_rendered();
+        let doc = self.set_content(content);
+        let doc = if options.summary_paragraph {
+            doc.auto_summary_paragraph()
+        } else {
+            doc.auto_summary()
+        };
+        doc.set_extension_html()
     }
 }
 
@@ -28,6 +275,11 @@ pub trait MarkdownDocs: Docs {
     fn render_markdown(self) -> impl Docs {
         self.map(|doc| doc.render_markdown())
     }
+
+    /// Render Markdown using options
+    fn render_markdown_with(self, options: &MarkdownOptions) -> impl Docs {
+        self.map(move |doc| doc.render_markdown_with(options))
+    }
 }
 
 /// Blanket-implement DocIterator for any iterator of docs
@@ -68,4 +320,46 @@ mod tests {
         assert_eq!(rendered[0].content, "<h1>One</h1>\n");
         assert_eq!(rendered[1].content, "<h2>Two</h2>\n");
     }
+
+    #[test]
+    fn test_render_markdown_figures() {
+        let options = MarkdownOptions {
+            figures: true,
+            ..Default::default()
+        };
+        let input = "![A cat](cat.png \"My cat\")\n\nInline ![dog](dog.png \"Dog\") image\n\n![Untitled](x.png)";
+        assert_eq!(
+            render_markdown_with(input, &options),
+            concat!(
+                "<figure><img src=\"cat.png\" alt=\"A cat\" title=\"My cat\" /><figcaption>My cat</figcaption></figure>\n",
+                "<p>Inline <img src=\"dog.png\" alt=\"dog\" title=\"Dog\" /> image</p>\n",
+                "<p><img src=\"x.png\" alt=\"Untitled\" /></p>\n"
+            )
+        );
+        // Off by default
+        assert!(!render_markdown(input).contains("<figure>"));
+    }
+
+    #[test]
+    fn test_render_markdown_footnotes() {
+        let options = MarkdownOptions {
+            footnotes: true,
+            footnote_backrefs: true,
+            footnote_popovers: true,
+            ..Default::default()
+        };
+        let input = "Hello[^a] and[^a].\n\n- Item[^b]\n\n[^a]: Note *A*.\n[^b]: Note B.\n";
+        assert_eq!(
+            render_markdown_with(input, &options),
+            concat!(
+                "<p>Hello<sup class=\"footnote-reference\" id=\"fnref-a\"><a href=\"#a\">1</a></sup> and<sup class=\"footnote-reference\" id=\"fnref-a-2\"><a href=\"#a\">1</a></sup>.</p>\n",
+                "<aside class=\"footnote-popover\" id=\"fnpop-a\" role=\"note\"><span class=\"footnote-popover-label\">1</span>\n<p>Note <em>A</em>. <a href=\"#fnref-a\" class=\"footnote-backref\">↩</a> <a href=\"#fnref-a-2\" class=\"footnote-backref\">↩<sup>2</sup></a></p>\n</aside>\n",
+                "<ul>\n<li>Item<sup class=\"footnote-reference\" id=\"fnref-b\"><a href=\"#b\">2</a></sup>\n<aside class=\"footnote-popover\" id=\"fnpop-b\" role=\"note\"><span class=\"footnote-popover-label\">2</span>\n<p>Note B. <a href=\"#fnref-b\" class=\"footnote-backref\">↩</a></p>\n</aside></li>\n</ul>\n",
+                "<div class=\"footnote-definition\" id=\"a\"><sup class=\"footnote-definition-label\">1</sup>\n<p>Note <em>A</em>. <a href=\"#fnref-a\" class=\"footnote-backref\">↩</a> <a href=\"#fnref-a-2\" class=\"footnote-backref\">↩<sup>2</sup></a></p>\n</div>\n",
+                "<div class=\"footnote-definition\" id=\"b\"><sup class=\"footnote-definition-label\">2</sup>\n<p>Note B. <a href=\"#fnref-b\" class=\"footnote-backref\">↩</a></p>\n</div>\n",
+            )
+        );
+        // Footnotes are off by default
+        assert!(!render_markdown(input).contains("footnote-reference"));
+    }
 }
@@ -0,0 +1,126 @@
+// Microformats2 markup for IndieWeb compatibility
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::html::escape_xml;
+use crate::url::{permalink_url, TrailingSlash};
+
+impl Doc {
+    /// Read the author of a doc from `meta.author`, falling back to
+    /// `default_author`.
+    pub fn get_author(&self, default_author: &str) -> String {
+        self.meta
+            .get("author")
+            .and_then(|author| author.as_str())
+            .unwrap_or(default_author)
+            .to_string()
+    }
+
+    /// Wrap rendered content in an `h-entry`, with properties driven by doc
+    /// fields:
+    /// - `p-name` from title
+    /// - `p-summary` from summary, if any
+    /// - `u-url` from output path, under `site_url`
+    /// - `dt-published` and `dt-updated` from created and modified dates
+    /// - `p-author h-card` from `meta.author`, or `default_author`
+    /// - `p-category` for each tag in `meta.tags`
+    /// - `e-content` wrapping the content
+    ///
+    /// Run after Markdown rendering, and before templating, so templates can
+    /// place the entry within the page layout.
+    pub fn wrap_h_entry(
+        self,
+        site_url: &str,
+        trailing_slash: TrailingSlash,
+        default_author: &str,
+    ) -> Self {
+        let url = escape_xml(&permalink_url(&self.output_path, site_url, trailing_slash));
+        let mut html = String::from("<article class=\"h-entry\">\n");
+        html.push_str(&format!(
+            "<h1 class=\"p-name\"><a class=\"u-url\" href=\"{}\">{}</a></h1>\n",
+            url,
+            escape_xml(&self.title)
+        ));
+        if !self.summary.is_empty() {
+            html.push_str(&format!(
+                "<p class=\"p-summary\" hidden>{}</p>\n",
+                escape_xml(&self.summary)
+            ));
+        }
+        html.push_str(&format!(
+            "<time class=\"dt-published\" datetime=\"{}\">{}</time>\n",
+            self.created.to_rfc3339(),
+            self.created.format("%B %-d, %Y")
+        ));
+        if self.modified != self.created {
+            html.push_str(&format!(
+                "<time class=\"dt-updated\" datetime=\"{}\" hidden></time>\n",
+                self.modified.to_rfc3339()
+            ));
+        }
+        let author = self.get_author(default_author);
+        if !author.is_empty() {
+            html.push_str(&format!(
+                "<a class=\"p-author h-card\" href=\"{}\">{}</a>\n",
+                escape_xml(site_url),
+                escape_xml(&author)
+            ));
+        }
+        for tag in self.get_meta_tags("tags") {
+            html.push_str(&format!(
+                "<data class=\"p-category\" value=\"{}\"></data>\n",
+                escape_xml(&tag)
+            ));
+        }
+        html.push_str(&format!(
+            "<div class=\"e-content\">\n{}\n</div>\n</article>",
+            self.content
+        ));
+        self.set_content(html)
+    }
+}
+
+pub trait MicroformatsDocs: Docs {
+    /// Wrap rendered content in `h-entry` microformats markup.
+    /// See `Doc::wrap_h_entry`.
+    fn wrap_h_entry(
+        self,
+        site_url: &str,
+        trailing_slash: TrailingSlash,
+        default_author: &str,
+    ) -> impl Docs {
+        self.map(move |doc| doc.wrap_h_entry(site_url, trailing_slash, default_author))
+    }
+}
+
+impl<I> MicroformatsDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_wrap_h_entry() {
+        let created = Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap();
+        let doc = Doc::draft("posts/a.md")
+            .set_title("Fish & Chips")
+            .set_output_path("posts/a/index.html")
+            .set_created(created)
+            .set_modified(created)
+            .set_content("<p>Tasty</p>")
+            .set_meta(json!({"tags": ["food"]}));
+        let doc = doc.wrap_h_entry("https://example.com", TrailingSlash::Always, "Jane");
+        assert_eq!(
+            doc.content,
+            concat!(
+                "<article class=\"h-entry\">\n",
+                "<h1 class=\"p-name\"><a class=\"u-url\" href=\"https://example.com/posts/a/\">Fish &amp; Chips</a></h1>\n",
+                "<time class=\"dt-published\" datetime=\"2024-03-05T00:00:00+00:00\">March 5, 2024</time>\n",
+                "<a class=\"p-author h-card\" href=\"https://example.com\">Jane</a>\n",
+                "<data class=\"p-category\" value=\"food\"></data>\n",
+                "<div class=\"e-content\">\n<p>Tasty</p>\n</div>\n</article>"
+            )
+        );
+    }
+}
@@ -0,0 +1,195 @@
+// Build navigation menus from docs that opt in via `menu` meta
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::json;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A single entry in a navigation menu.
+/// Entries are nested by section. An entry for a section index page
+/// (e.g. `posts/index.html`) holds the entries for pages under that section
+/// in `children`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NavItem {
+    pub id_path: PathBuf,
+    pub output_path: PathBuf,
+    pub title: String,
+    pub weight: i64,
+    pub children: Vec<NavItem>,
+}
+
+/// Navigation menus, indexed by menu name (e.g. `main`, `footer`).
+pub type Nav = HashMap<String, Vec<NavItem>>;
+
+/// Get the directory of an output path.
+/// For index pages, this is the directory of the section they introduce.
+fn get_section_dir(output_path: &Path) -> PathBuf {
+    output_path.parent().unwrap_or(Path::new("")).to_path_buf()
+}
+
+/// Is this output path an index page?
+fn is_index(output_path: &Path) -> bool {
+    output_path.file_stem().and_then(|stem| stem.to_str()) == Some("index")
+}
+
+/// Insert item into a list of nav items, nesting it under the index entry
+/// whose section contains it. Root index pages (e.g. `index.html`) are not
+/// treated as sections, since every page would fall under them.
+fn insert_nested(items: &mut Vec<NavItem>, item: NavItem) {
+    let parent = items.iter_mut().find(|parent| {
+        let section_dir = get_section_dir(&parent.output_path);
+        is_index(&parent.output_path)
+            && !section_dir.as_os_str().is_empty()
+            && parent.id_path != item.id_path
+            && item.output_path.starts_with(&section_dir)
+    });
+    match parent {
+        Some(parent) => insert_nested(&mut parent.children, item),
+        None => items.push(item),
+    }
+}
+
+/// Sort nav items by weight, then title, recursively.
+fn sort_nav_items(items: &mut [NavItem]) {
+    items.sort_by(|a, b| a.weight.cmp(&b.weight).then_with(|| a.title.cmp(&b.title)));
+    for item in items.iter_mut() {
+        sort_nav_items(&mut item.children);
+    }
+}
+
+/// Build nested menus from a flat list of nav items, indexed by menu name.
+pub fn build_nav(entries: Vec<(String, NavItem)>) -> Nav {
+    let mut entries = entries;
+    // Insert shallow paths first, so sections exist before their pages.
+    entries.sort_by_key(|(_, item)| {
+        let depth = item.output_path.components().count();
+        (depth, !is_index(&item.output_path))
+    });
+    let mut nav = Nav::new();
+    for (menu, item) in entries {
+        insert_nested(nav.entry(menu).or_default(), item);
+    }
+    for items in nav.values_mut() {
+        sort_nav_items(items);
+    }
+    nav
+}
+
+impl Doc {
+    /// Get the menus this doc opts into, with a nav item for each.
+    ///
+    /// Reads `meta.menu`, which may be:
+    /// - An object of menu names to entry settings, e.g.
+    ///   `menu: {main: {weight: 10, title: "Home"}}`
+    /// - A menu name string, e.g. `menu: main`
+    /// - An array of menu names, e.g. `menu: [main, footer]`
+    ///
+    /// Entry settings are optional. `weight` defaults to the doc's weight
+    /// (see `Doc::get_weight`), and `title` defaults to the doc title.
+    pub fn get_nav_items(&self) -> Vec<(String, NavItem)> {
+        let to_item = |settings: Option<&json::Value>| {
+            let settings = settings.and_then(|settings| settings.as_object());
+            let weight = settings
+                .and_then(|settings| settings.get("weight"))
+                .and_then(|weight| weight.as_i64())
+                .unwrap_or_else(|| self.get_weight());
+            let title = settings
+                .and_then(|settings| settings.get("title"))
+                .and_then(|title| title.as_str())
+                .unwrap_or(&self.title)
+                .to_string();
+            NavItem {
+                id_path: self.id_path.clone(),
+                output_path: self.output_path.clone(),
+                title,
+                weight,
+                children: Vec::new(),
+            }
+        };
+        match self.meta.get("menu") {
+            Some(json::Value::Object(menus)) => menus
+                .iter()
+                .map(|(menu, settings)| (menu.clone(), to_item(Some(settings))))
+                .collect(),
+            Some(json::Value::String(menu)) => vec![(menu.clone(), to_item(None))],
+            Some(json::Value::Array(menus)) => menus
+                .iter()
+                .filter_map(|menu| menu.as_str())
+                .map(|menu| (menu.to_string(), to_item(None)))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub trait NavDocs: Docs {
+    /// Build navigation menus from docs that opt in via `menu` meta.
+    /// Menu items are nested by section and sorted by weight.
+    fn nav(self) -> Nav {
+        let entries = self.flat_map(|doc| doc.get_nav_items()).collect();
+        build_nav(entries)
+    }
+}
+
+impl<I> NavDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn make_docs() -> Vec<Doc> {
+        vec![
+            Doc::draft("index.md")
+                .set_output_path("index.html")
+                .set_title("Home")
+                .set_meta(json!({"menu": {"main": {"weight": -10}}})),
+            Doc::draft("about.md")
+                .set_output_path("about/index.html")
+                .set_title("About")
+                .set_meta(json!({"menu": {"main": {"weight": 20}}})),
+            Doc::draft("posts/index.md")
+                .set_output_path("posts/index.html")
+                .set_title("Posts")
+                .set_meta(json!({"menu": {"main": {"weight": 10}, "footer": {}}})),
+            Doc::draft("posts/b.md")
+                .set_output_path("posts/b.html")
+                .set_title("B")
+                .set_meta(json!({"menu": "main"})),
+            Doc::draft("posts/a.md")
+                .set_output_path("posts/a.html")
+                .set_title("A")
+                .set_meta(json!({"menu": {"main": {"title": "Post A"}}})),
+            Doc::draft("hidden.md").set_title("Hidden"),
+        ]
+    }
+
+    #[test]
+    fn test_nav_sorted_by_weight() {
+        let nav = make_docs().into_iter().nav();
+        let main = nav.get("main").unwrap();
+        let titles: Vec<&str> = main.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, vec!["Home", "Posts", "About"]);
+        assert_eq!(nav.get("footer").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_nav_falls_back_to_doc_weight() {
+        let doc = Doc::draft("a.md").set_meta(json!({"menu": "main", "weight": 5}));
+        let items = doc.get_nav_items();
+        assert_eq!(items[0].1.weight, 5);
+    }
+
+    #[test]
+    fn test_nav_nested_by_section() {
+        let nav = make_docs().into_iter().nav();
+        let posts = &nav.get("main").unwrap()[1];
+        let titles: Vec<&str> = posts
+            .children
+            .iter()
+            .map(|item| item.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["B", "Post A"]);
+    }
+}
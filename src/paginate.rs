@@ -0,0 +1,158 @@
+// Split long docs into several pages
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::json::{self, json};
+use std::path::{Path, PathBuf};
+
+/// Default marker for splitting docs into pages
+pub const PAGE_MARKER: &str = "<!--page-->";
+
+/// Get the output path for page `n` of a doc.
+/// Page 1 keeps the doc's output path.
+///
+/// output_path:
+///     essay/index.html
+///
+/// page_path (n = 2):
+///     essay/2/index.html
+///
+/// output_path:
+///     essay.html
+///
+/// page_path (n = 2):
+///     essay-2.html
+pub fn to_page_path(output_path: &Path, n: usize) -> PathBuf {
+    if n <= 1 {
+        return output_path.to_path_buf();
+    }
+    let file_name = output_path.file_name().unwrap_or_default();
+    let stem = output_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    if stem == "index" {
+        return output_path.with_file_name(n.to_string()).join(file_name);
+    }
+    match output_path.extension() {
+        Some(ext) => {
+            output_path.with_file_name(format!("{}-{}.{}", stem, n, ext.to_string_lossy()))
+        }
+        None => output_path.with_file_name(format!("{}-{}", stem, n)),
+    }
+}
+
+impl Doc {
+    /// Split a doc into pages at each occurrence of `marker` in content.
+    /// Each page has its own output path (see `to_page_path`), and pagination
+    /// info in `meta.page`:
+    /// - `number`: the 1-based page number
+    /// - `total`: the number of pages
+    /// - `prev`, `next`: output paths of the neighboring pages, or null
+    /// - `canonical`: output path of the first page
+    ///
+    /// Pages share the id_path of the doc they were split from.
+    /// Docs without the marker are returned unchanged.
+    pub fn split_pages(self, marker: &str) -> Vec<Doc> {
+        if !self.content.contains(marker) {
+            return vec![self];
+        }
+        let parts: Vec<String> = self
+            .content
+            .split(marker)
+            .map(|part| part.trim().to_string())
+            .collect();
+        let total = parts.len();
+        let paths: Vec<PathBuf> = (1..=total)
+            .map(|n| to_page_path(&self.output_path, n))
+            .collect();
+        let path_value = |i: usize| -> json::Value {
+            paths
+                .get(i)
+                .map(|path| json::Value::String(path.to_string_lossy().into_owned()))
+                .unwrap_or(json::Value::Null)
+        };
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(i, part)| {
+                let mut page = self.clone().set_content(part).set_output_path(&paths[i]);
+                if !page.meta.is_object() {
+                    page.meta = json!({});
+                }
+                let prev = if i == 0 {
+                    json::Value::Null
+                } else {
+                    path_value(i - 1)
+                };
+                page.merge_meta(json!({
+                    "page": {
+                        "number": i + 1,
+                        "total": total,
+                        "prev": prev,
+                        "next": path_value(i + 1),
+                        "canonical": path_value(0),
+                    }
+                }))
+            })
+            .collect()
+    }
+}
+
+pub trait PaginateDocs: Docs {
+    /// Split docs into pages at each occurrence of `marker` in content.
+    /// See `Doc::split_pages`.
+    fn split_pages(self, marker: &str) -> impl Docs {
+        self.flat_map(move |doc| doc.split_pages(marker))
+    }
+}
+
+impl<I> PaginateDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_page_path() {
+        assert_eq!(
+            to_page_path(Path::new("essay/index.html"), 2),
+            PathBuf::from("essay/2/index.html")
+        );
+        assert_eq!(
+            to_page_path(Path::new("essay.html"), 3),
+            PathBuf::from("essay-3.html")
+        );
+        assert_eq!(
+            to_page_path(Path::new("essay.html"), 1),
+            PathBuf::from("essay.html")
+        );
+    }
+
+    #[test]
+    fn test_split_pages() {
+        let docs = vec![
+            Doc::draft("essay.md")
+                .set_output_path("essay/index.html")
+                .set_content("One\n<!--page-->\nTwo\n<!--page-->\nThree"),
+            Doc::draft("short.md").set_content("Short"),
+        ];
+        let pages: Vec<Doc> = docs.into_iter().split_pages(PAGE_MARKER).collect();
+        assert_eq!(pages.len(), 4);
+        assert_eq!(pages[1].content, "Two");
+        assert_eq!(pages[1].output_path, PathBuf::from("essay/2/index.html"));
+        assert_eq!(
+            pages[1].meta["page"],
+            json!({
+                "number": 2,
+                "total": 3,
+                "prev": "essay/index.html",
+                "next": "essay/3/index.html",
+                "canonical": "essay/index.html"
+            })
+        );
+        assert_eq!(pages[0].meta["page"]["prev"], json::Value::Null);
+        assert_eq!(pages[2].meta["page"]["next"], json::Value::Null);
+        assert_eq!(pages[3].meta, Doc::draft("short.md").meta);
+    }
+}
@@ -1,9 +1,48 @@
+use crate::config::Config;
 use crate::doc::Doc;
-use crate::docs::Docs;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::json;
 use crate::text::to_slug;
 use crate::token_template;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Matches the key of `{meta.*}` permalink tokens, before any filters
+static META_TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\{\s*(meta\.[^{}|\s]+)").expect("Meta token regex should be valid")
+});
+
+/// Get a meta value as a permalink path segment.
+/// Strings, numbers and booleans are sluggified. For arrays, such as tags,
+/// the first item is used. Other values give `None`.
+fn to_permalink_segment(value: &json::Value) -> Option<String> {
+    match value {
+        json::Value::String(value) => Some(to_slug(value)),
+        json::Value::Number(value) => Some(to_slug(&value.to_string())),
+        json::Value::Bool(value) => Some(value.to_string()),
+        json::Value::Array(values) => values.first().and_then(to_permalink_segment),
+        _ => None,
+    }
+}
+
+/// Keep a rendered permalink inside the output directory by dropping empty,
+/// `.` and `..` segments, and any leading `/`.
+///
+/// permalink:
+///     /rust//hello/../index.html
+///
+/// output_path:
+///     rust/hello/index.html
+pub fn to_relative_output_path(permalink: &str) -> PathBuf {
+    permalink
+        .split(['/', '\\'])
+        .filter(|segment| !matches!(*segment, "" | "." | ".."))
+        .collect()
+}
 
 /// Sluggify all the normal components of a path.
 pub fn sluggify_path(path: &Path) -> PathBuf {
@@ -48,6 +87,149 @@ pub fn to_nice_path(path: &Path) -> Option<PathBuf> {
     }
 }
 
+/// Makes a path into a "flat path". Flat paths are sluggified and end with
+/// an `.html` extension, so you reference them `like/this.html`.
+///
+/// path:
+///     some/File.md
+///
+/// flat_path:
+///     some/file.html
+pub fn to_flat_path(path: &Path) -> PathBuf {
+    sluggify_path(path).with_extension("html")
+}
+
+/// Disambiguate a path by appending `-n` to its slug.
+/// Nice paths get the suffix on their directory, flat paths on their stem.
+///
+/// path:
+///     some/file/index.html
+///
+/// suffixed_path (n = 2):
+///     some/file-2/index.html
+///
+/// path:
+///     some/file.html
+///
+/// suffixed_path (n = 2):
+///     some/file-2.html
+pub fn suffix_path(path: &Path, n: usize) -> PathBuf {
+    let is_index = path.file_stem().and_then(|stem| stem.to_str()) == Some("index");
+    if is_index {
+        if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+            if let Some(dir_name) = dir.file_name() {
+                let suffixed_dir =
+                    dir.with_file_name(format!("{}-{}", dir_name.to_string_lossy(), n));
+                return suffixed_dir.join(name);
+            }
+        }
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let suffixed_name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    path.with_file_name(suffixed_name)
+}
+
+/// What to do when two docs produce the same permalink.
+/// - `Suffix` appends `-2`, `-3`, etc. to later docs (`slug-2/index.html`)
+/// - `Error` reports later docs as errors
+/// - `Overwrite` leaves permalinks as-is, so later docs overwrite earlier ones
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PermalinkCollisions {
+    #[default]
+    Suffix,
+    Error,
+    Overwrite,
+}
+
+/// Style of permalink to generate.
+/// - `Nice` permalinks end in an index file (`slug/index.html`)
+/// - `Flat` permalinks end in an html file (`slug.html`)
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PermalinkStyle {
+    #[default]
+    Nice,
+    Flat,
+}
+
+impl PermalinkStyle {
+    /// Get the default permalink template for this style.
+    pub fn template(&self) -> &'static str {
+        match self {
+            PermalinkStyle::Nice => "{parents}/{slug}/index.html",
+            PermalinkStyle::Flat => "{parents}/{slug}.html",
+        }
+    }
+}
+
+/// Permalink templates keyed by the glob pattern of the id paths they apply
+/// to, so one pipeline can give posts and pages different permalinks.
+///
+/// The most specific (longest) matching pattern wins. Docs that match no
+/// pattern get the default template. Templates ending in `/` get
+/// `index.html` appended, so `{yyyy}/{slug}/` gives nice permalinks.
+#[derive(Debug, Clone)]
+pub struct PermalinkRules {
+    rules: Vec<(glob::Pattern, String)>,
+    default: String,
+}
+
+/// Complete templates ending in `/` with `index.html`
+fn to_permalink_template(template: &str) -> String {
+    if template.ends_with('/') {
+        format!("{}index.html", template)
+    } else {
+        template.to_string()
+    }
+}
+
+impl PermalinkRules {
+    /// Compile rules from a map of id path glob patterns to permalink
+    /// templates, and a default template
+    pub fn compile(rules: &BTreeMap<String, String>, default: &str) -> Result<Self, Error> {
+        let mut compiled = Vec::new();
+        for (pattern, template) in rules {
+            compiled.push((
+                glob::Pattern::new(pattern)?,
+                to_permalink_template(template),
+            ));
+        }
+        // Most specific patterns first. Sorting is stable, so patterns of
+        // equal length stay in key order.
+        compiled.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+        Ok(PermalinkRules {
+            rules: compiled,
+            default: to_permalink_template(default),
+        })
+    }
+
+    /// Compile the `permalinks` rules in config, falling back to the
+    /// default permalink template in config
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        Self::compile(&config.permalinks, &config.default_permalink_template())
+    }
+
+    /// Get the permalink template for a doc
+    pub fn template_for(&self, doc: &Doc) -> &str {
+        let id_path = doc.id_path.to_string_lossy();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&id_path))
+            .map_or(&self.default, |(_, template)| template)
+    }
+}
+
 impl Doc {
     /// Extracts permalink template parts from a document.
     ///
@@ -82,6 +264,8 @@ impl Doc {
         map.insert("nice", nice_name.to_string_lossy().to_string());
         // Name excluding extension
         map.insert("stem", stem.to_string_lossy().to_string());
+        // URL-friendly stem
+        map.insert("slug", to_slug(&stem.to_string_lossy()));
         map.insert("ext", ext.to_string_lossy().to_string());
         // All parents
         map.insert("parents", parents.to_string_lossy().to_string());
@@ -94,6 +278,31 @@ impl Doc {
         Some(map)
     }
 
+    /// Extracts the `{meta.*}` tokens used in a permalink template, such as
+    /// `{meta.category}` or `{meta.series.name}`, from the document's meta.
+    /// Values are sluggified. See `to_permalink_segment`.
+    ///
+    /// Missing meta values, and values that sluggify to nothing (`""`,
+    /// `"!!!"`), are given as empty strings, so they fall back to a default
+    /// when there is one, e.g. `{meta.category|default:misc}`. Without a
+    /// default, the empty path segment is dropped by `set_permalink`.
+    pub fn get_meta_permalink_parts<'a>(
+        &self,
+        permalink_template: &'a str,
+    ) -> HashMap<&'a str, String> {
+        META_TOKEN
+            .captures_iter(permalink_template)
+            .filter_map(|caps| {
+                let key = caps.get(1)?.as_str();
+                let path = key.strip_prefix("meta.")?;
+                let segment = json::get_deep(&self.meta, path)
+                    .and_then(|value| to_permalink_segment(&value))
+                    .unwrap_or_default();
+                Some((key, segment))
+            })
+            .collect()
+    }
+
     /// Sets the document's permalink (output path) using a provided template.
     ///
     /// The template can include placeholders that will be replaced with
@@ -108,6 +317,15 @@ impl Doc {
     /// - {yy}: Year (2 digits)
     /// - {mm}: Month (2 digits)
     /// - {dd}: Day (2 digits)
+    /// - {meta.key}: Sluggified meta value, e.g. `{meta.category}`. See
+    ///   `get_meta_permalink_parts`.
+    ///
+    /// Placeholders may use filters and defaults, such as `{parent|default:posts}`.
+    /// See `token_template::render`.
+    ///
+    /// The rendered path is kept relative to the output directory: empty,
+    /// `.` and `..` segments are dropped, as is any leading `/`. See
+    /// `to_relative_output_path`.
     ///
     /// # Arguments
     ///
@@ -117,9 +335,18 @@ impl Doc {
     ///
     /// Returns `Self` with the updated output path.
     pub fn set_permalink(self, permalink_template: impl Into<String>) -> Self {
-        let parts = self.get_permalink_template_parts().unwrap_or_default();
-        let output_path = token_template::render(permalink_template, &parts);
-        self.set_output_path(output_path)
+        let permalink_template: String = permalink_template.into();
+        let mut parts = self.get_permalink_template_parts().unwrap_or_default();
+        parts.extend(self.get_meta_permalink_parts(&permalink_template));
+        let output_path = token_template::render(permalink_template.as_str(), &parts);
+        self.set_output_path(to_relative_output_path(&output_path))
+    }
+
+    /// Set permalink using the template of the most specific matching rule.
+    /// See `PermalinkRules`.
+    pub fn apply_permalink_rules(self, rules: &PermalinkRules) -> Self {
+        let template = rules.template_for(&self).to_string();
+        self.set_permalink(template)
     }
 
     /// Set blog-style permalink (`yyyy/mm/dd/slug/index.html`)
@@ -131,6 +358,28 @@ impl Doc {
     pub fn set_page_permalink(self) -> Self {
         self.set_permalink("{parents}/{slug}/index.html")
     }
+
+    /// Convert output path to a nice path (`slug/index.html`).
+    pub fn nice_permalink(self) -> Self {
+        match to_nice_path(&self.output_path) {
+            Some(nice_path) => self.set_output_path(nice_path),
+            None => self,
+        }
+    }
+
+    /// Convert output path to a flat path (`slug.html`).
+    pub fn flat_permalink(self) -> Self {
+        let flat_path = to_flat_path(&self.output_path);
+        self.set_output_path(flat_path)
+    }
+
+    /// Convert output path to the given permalink style.
+    pub fn set_permalink_style(self, style: PermalinkStyle) -> Self {
+        match style {
+            PermalinkStyle::Nice => self.nice_permalink(),
+            PermalinkStyle::Flat => self.flat_permalink(),
+        }
+    }
 }
 
 pub trait PermalinkDocs: Docs {
@@ -140,6 +389,16 @@ pub trait PermalinkDocs: Docs {
         self.map(move |doc| doc.set_permalink(&permalink_template))
     }
 
+    /// Set permalinks from the `permalinks` rules in config, so posts and
+    /// pages can get different permalinks in one pipeline. Docs matching no
+    /// rule get the default permalink template. See `PermalinkRules`.
+    ///
+    /// Fails if a rule's glob pattern is invalid.
+    fn apply_permalink_rules(self, config: &Config) -> Result<impl Docs, Error> {
+        let rules = PermalinkRules::from_config(config)?;
+        Ok(self.map(move |doc| doc.apply_permalink_rules(&rules)))
+    }
+
     /// Set blog-style permalink (`yyyy/mm/dd/slug/index.html`)
     fn set_blog_permalink(self) -> impl Docs {
         self.map(|doc| doc.set_blog_permalink())
@@ -149,6 +408,57 @@ pub trait PermalinkDocs: Docs {
     fn set_page_permalink(self) -> impl Docs {
         self.map(|doc| doc.set_page_permalink())
     }
+
+    /// Convert output paths to nice paths (`slug/index.html`)
+    fn nice_permalinks(self) -> impl Docs {
+        self.map(|doc| doc.nice_permalink())
+    }
+
+    /// Convert output paths to flat paths (`slug.html`)
+    fn flat_permalinks(self) -> impl Docs {
+        self.map(|doc| doc.flat_permalink())
+    }
+
+    /// Convert output paths to the given permalink style
+    fn set_permalink_style(self, style: PermalinkStyle) -> impl Docs {
+        self.map(move |doc| doc.set_permalink_style(style))
+    }
+
+    /// De-duplicate permalinks (output paths) across docs.
+    /// The first doc to claim a permalink keeps it. Later docs with the same
+    /// permalink are handled according to `collisions`. Suffixed docs have
+    /// their final permalink recorded in `meta.permalink`.
+    fn dedupe_permalinks(self, collisions: PermalinkCollisions) -> impl DocResults {
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        self.map(move |doc| {
+            if seen.insert(doc.output_path.clone()) {
+                return Ok(doc);
+            }
+            match collisions {
+                PermalinkCollisions::Overwrite => Ok(doc),
+                PermalinkCollisions::Error => Err(Error::value(format!(
+                    "Permalink {} is already used by another doc",
+                    doc.output_path.display()
+                ))
+                .with_doc(&doc.id_path)),
+                PermalinkCollisions::Suffix => {
+                    let mut n = 2;
+                    let mut output_path = suffix_path(&doc.output_path, n);
+                    while !seen.insert(output_path.clone()) {
+                        n += 1;
+                        output_path = suffix_path(&doc.output_path, n);
+                    }
+                    let mut doc = doc.set_output_path(&output_path);
+                    if !doc.meta.is_object() {
+                        doc.meta = json::json!({});
+                    }
+                    Ok(doc.merge_meta(json::json!({
+                        "permalink": output_path.to_string_lossy()
+                    })))
+                }
+            }
+        })
+    }
 }
 
 impl<I> PermalinkDocs for I where I: Docs {}
@@ -172,6 +482,7 @@ mod tests {
         assert_eq!(parts.get("name"), Some(&"test-file.md".to_string()));
         assert_eq!(parts.get("nice"), Some(&"test-file/index.html".to_string()));
         assert_eq!(parts.get("stem"), Some(&"test-file".to_string()));
+        assert_eq!(parts.get("slug"), Some(&"test-file".to_string()));
         assert_eq!(parts.get("ext"), Some(&"md".to_string()));
         assert_eq!(parts.get("parents"), Some(&"foo-bar/baz".to_string()));
         assert_eq!(parts.get("parent"), Some(&"baz".to_string()));
@@ -196,6 +507,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_permalink_meta_tokens() {
+        let doc = Doc::draft("posts/hello.md").set_meta(json::json!({
+            "category": "Rust Tips",
+            "tags": ["Web Dev", "rust"],
+            "series": {"part": 2}
+        }));
+        let permalink_doc = doc.clone().set_permalink(
+            "{meta.category}/{meta.tags}/{meta.series.part}/{meta.missing|default:misc}/{slug}/index.html",
+        );
+        assert_eq!(
+            permalink_doc.output_path,
+            PathBuf::from("rust-tips/web-dev/2/misc/hello/index.html")
+        );
+    }
+
+    #[test]
+    fn test_permalink_meta_tokens_empty_or_missing() {
+        let template = "{meta.category}/{slug}/index.html";
+        for meta in [
+            json::json!({"category": ""}),
+            json::json!({"category": "!!!"}),
+            json::json!({}),
+        ] {
+            let doc = Doc::draft("posts/hello.md")
+                .set_meta(meta)
+                .set_permalink(template);
+            assert_eq!(doc.output_path, PathBuf::from("hello/index.html"));
+        }
+        let doc = Doc::draft("posts/hello.md")
+            .set_meta(json::json!({"category": "!!!"}))
+            .set_permalink("{meta.category|default:misc}/{slug}/index.html");
+        assert_eq!(doc.output_path, PathBuf::from("misc/hello/index.html"));
+    }
+
+    #[test]
+    fn test_to_relative_output_path() {
+        assert_eq!(
+            to_relative_output_path("/rust//hello/../index.html"),
+            PathBuf::from("rust/hello/index.html")
+        );
+        assert_eq!(
+            to_relative_output_path("../../etc/passwd"),
+            PathBuf::from("etc/passwd")
+        );
+    }
+
+    #[test]
+    fn test_apply_permalink_rules() {
+        let mut config = Config::default();
+        config.permalinks = BTreeMap::from([
+            ("posts/**".to_string(), "{yyyy}/{slug}/".to_string()),
+            (
+                "posts/notes/**".to_string(),
+                "notes/{slug}.html".to_string(),
+            ),
+            ("pages/**".to_string(), "{parents}/{slug}/".to_string()),
+        ]);
+        let created = Utc.with_ymd_and_hms(2023, 5, 15, 0, 0, 0).unwrap();
+        let docs: Vec<Doc> = vec![
+            Doc::draft("posts/hello.md").set_created(created),
+            Doc::draft("posts/notes/idea.md"),
+            Doc::draft("pages/about/team.md"),
+            Doc::draft("misc/other.md"),
+        ]
+        .into_iter()
+        .apply_permalink_rules(&config)
+        .unwrap()
+        .collect();
+        let paths: Vec<PathBuf> = docs.into_iter().map(|doc| doc.output_path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("2023/hello/index.html"),
+                PathBuf::from("notes/idea.html"),
+                PathBuf::from("pages/about/team/index.html"),
+                PathBuf::from("misc/other/index.html"),
+            ]
+        );
+    }
+
     #[test]
     fn test_nice_path() {
         let path = Path::new("foo bar/Baz/Some.md");
@@ -217,10 +609,82 @@ mod tests {
         assert_eq!(nice, PathBuf::from("foo/index.html"));
     }
 
+    #[test]
+    fn test_flat_path() {
+        let path = Path::new("foo bar/Baz/Some.md");
+        let flat = to_flat_path(path);
+        assert_eq!(flat, PathBuf::from("foo-bar/baz/some.html"));
+    }
+
+    #[test]
+    fn test_nice_permalinks() {
+        let docs = vec![Doc::draft("posts/Hello.md"), Doc::draft("posts/index.md")];
+        let docs: Vec<Doc> = docs.into_iter().nice_permalinks().collect();
+        assert_eq!(docs[0].output_path, PathBuf::from("posts/hello/index.html"));
+        assert_eq!(docs[1].output_path, PathBuf::from("posts/index.html"));
+    }
+
+    #[test]
+    fn test_set_permalink_style_flat() {
+        let doc = Doc::draft("posts/Hello.md").set_permalink_style(PermalinkStyle::Flat);
+        assert_eq!(doc.output_path, PathBuf::from("posts/hello.html"));
+    }
+
     #[test]
     fn test_sluggify_path() {
         let path = Path::new("Foo bar/baZ/INDEX.md");
         let sluggified_path = sluggify_path(path);
         assert_eq!(sluggified_path, PathBuf::from("foo-bar/baz/index.md"));
     }
+
+    #[test]
+    fn test_suffix_path() {
+        assert_eq!(
+            suffix_path(Path::new("posts/hello/index.html"), 2),
+            PathBuf::from("posts/hello-2/index.html")
+        );
+        assert_eq!(
+            suffix_path(Path::new("posts/hello.html"), 3),
+            PathBuf::from("posts/hello-3.html")
+        );
+        assert_eq!(
+            suffix_path(Path::new("index.html"), 2),
+            PathBuf::from("index-2.html")
+        );
+    }
+
+    #[test]
+    fn test_dedupe_permalinks() {
+        let docs = vec![
+            Doc::draft("a/hello.md").set_output_path("hello/index.html"),
+            Doc::draft("b/hello.md").set_output_path("hello/index.html"),
+            Doc::draft("c/hello.md").set_output_path("hello/index.html"),
+            Doc::draft("hello-2.md").set_output_path("hello-2/index.html"),
+        ];
+        let docs: Vec<Doc> = docs
+            .into_iter()
+            .dedupe_permalinks(PermalinkCollisions::Suffix)
+            .map(|result| result.unwrap())
+            .collect();
+        assert_eq!(docs[0].output_path, PathBuf::from("hello/index.html"));
+        assert_eq!(docs[1].output_path, PathBuf::from("hello-2/index.html"));
+        assert_eq!(docs[2].output_path, PathBuf::from("hello-3/index.html"));
+        assert_eq!(docs[2].meta["permalink"], "hello-3/index.html");
+        // A later doc that claims an already-suffixed path is suffixed too
+        assert_eq!(docs[3].output_path, PathBuf::from("hello-2-2/index.html"));
+    }
+
+    #[test]
+    fn test_dedupe_permalinks_error() {
+        let docs = vec![
+            Doc::draft("a/hello.md").set_output_path("hello.html"),
+            Doc::draft("b/hello.md").set_output_path("hello.html"),
+        ];
+        let results: Vec<Result<Doc, Error>> = docs
+            .into_iter()
+            .dedupe_permalinks(PermalinkCollisions::Error)
+            .collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
 }
@@ -0,0 +1,410 @@
+// Build sites in-process, without stdio plumbing
+use crate::config::Config;
+use crate::doc::Doc;
+use crate::docs::{self, DocResults, Docs, WriteReport};
+use crate::error::{Error, ErrorHandler};
+use crate::json;
+use crate::nav::NavDocs;
+use crate::permalink::{PermalinkDocs, PermalinkRules};
+use crate::render_cache::{RenderCache, RenderCacheDocs};
+use crate::tera::{self, TeraDocs};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A declarative builder for a complete build.
+///
+/// Each step mirrors the `smith` command of the same name, and reads its
+/// options from config. Docs stream lazily from step to step, aside from
+/// `template`, which collects docs so templates can see the whole site.
+///
+/// Failed docs are reported with an error handler set up from the
+/// `on_error` and `error_format` config, and left out of the build (or
+/// abort it).
+///
+/// ```no_run
+/// use lettersmith::prelude::*;
+/// use lettersmith::pipeline::Pipeline;
+/// use std::path::Path;
+///
+/// Pipeline::new(Config::default())
+///     .read("posts/*.md")
+///     .frontmatter()
+///     .markdown()
+///     .permalink("{parents}/{slug}/index.html")
+///     .template()
+///     .write(Path::new("public"));
+/// ```
+pub struct Pipeline {
+    config: Config,
+    handler: ErrorHandler,
+    data: HashMap<String, json::Value>,
+    docs: Box<dyn Iterator<Item = Doc>>,
+    reports: Vec<WriteReport>,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline
+    pub fn new(config: Config) -> Self {
+        let handler = ErrorHandler::new(config.on_error, config.error_format, "pipeline");
+        Pipeline {
+            config,
+            handler,
+            data: HashMap::new(),
+            docs: Box::new(std::iter::empty()),
+            reports: Vec::new(),
+        }
+    }
+
+    /// The config this pipeline was created with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Set the error handler, e.g. to report a different stage name
+    pub fn error_handler(mut self, handler: ErrorHandler) -> Self {
+        self.handler = handler;
+        self
+    }
+
+    /// Set data available to templates as `data`
+    pub fn data(mut self, data: HashMap<String, json::Value>) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Append docs to the pipeline.
+    /// Once the build is aborted, no more docs are passed on.
+    pub fn docs(mut self, docs: impl Iterator<Item = Doc> + 'static) -> Self {
+        let handler = self.handler.clone();
+        self.docs = Box::new(
+            self.docs
+                .chain(docs)
+                .take_while(move |_| !handler.aborted()),
+        );
+        self
+    }
+
+    /// Append doc results to the pipeline, reporting errors with the
+    /// pipeline's error handler
+    pub fn doc_results(self, results: impl DocResults + 'static) -> Self {
+        let handler = self.handler.clone();
+        self.docs(
+            results
+                .map_while(move |result| match result {
+                    Ok(doc) => Some(Some(doc)),
+                    Err(err) => handler.handle(err).ok().map(|_| None),
+                })
+                .flatten(),
+        )
+    }
+
+    /// Report an error with the pipeline's error handler. If the policy is
+    /// to abort, the docs stream is ended.
+    fn report(mut self, err: Error) -> Self {
+        if self.handler.handle(err).is_err() {
+            self.docs = Box::new(std::iter::empty());
+        }
+        self
+    }
+
+    /// Apply a transform to the docs stream
+    pub fn then<I>(mut self, step: impl FnOnce(Box<dyn Iterator<Item = Doc>>) -> I) -> Self
+    where
+        I: Iterator<Item = Doc> + 'static,
+    {
+        let docs = std::mem::replace(&mut self.docs, Box::new(std::iter::empty()));
+        self.docs = Box::new(step(docs));
+        self
+    }
+
+    /// Apply a fallible transform to the docs stream, reporting errors with
+    /// the pipeline's error handler
+    pub fn try_then<I>(mut self, step: impl FnOnce(Box<dyn Iterator<Item = Doc>>) -> I) -> Self
+    where
+        I: DocResults + 'static,
+    {
+        let docs = std::mem::replace(&mut self.docs, Box::new(std::iter::empty()));
+        self.doc_results(step(docs))
+    }
+
+    /// Read docs from files matching a glob pattern.
+    /// Binary files, and files over `max_file_size`, are skipped.
+    pub fn read(self, glob_pattern: &str) -> Self {
+        match docs::expand_globs([PathBuf::from(glob_pattern)]) {
+            Ok(paths) => {
+                let results =
+                    docs::read_with_max_size(paths.into_iter(), self.config.max_file_size)
+                        .skip_unreadable_files();
+                self.doc_results(results)
+            }
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Read line-separated JSON docs from stdin, in strict mode if
+    /// `strict_stdin` is set in config. See `docs::read_lines`.
+    pub fn read_stdin(self) -> Self {
+        let strict = self.config.strict_stdin;
+        self.doc_results(docs::read_stdin_with(strict))
+    }
+
+    /// Parse and uplift frontmatter
+    pub fn frontmatter(self) -> Self {
+        self.then(|docs| docs.map(|doc| doc.parse_and_uplift_frontmatter()))
+    }
+
+    /// Render Markdown content, with the `markdown` options in config
+    pub fn markdown(self) -> Self {
+        let options = self.config.markdown.clone();
+        self.then(move |docs| docs.map(move |doc| doc.render_markdown_with(&options)))
+    }
+
+    /// Set output paths from a permalink template, handling collisions
+    /// according to `permalink_collisions` in config
+    pub fn permalink(self, permalink_template: &str) -> Self {
+        let permalink_template = permalink_template.to_string();
+        let collisions = self.config.permalink_collisions;
+        self.try_then(move |docs| {
+            docs.map(move |doc| doc.set_permalink(permalink_template.clone()))
+                .dedupe_permalinks(collisions)
+        })
+    }
+
+    /// Set output paths from the `permalinks` rules in config, falling back
+    /// to the default permalink template, and handling collisions according
+    /// to `permalink_collisions`. See `PermalinkRules`.
+    pub fn permalink_rules(self) -> Self {
+        let collisions = self.config.permalink_collisions;
+        match PermalinkRules::from_config(&self.config) {
+            Ok(rules) => self.try_then(move |docs| {
+                docs.map(move |doc| doc.apply_permalink_rules(&rules))
+                    .dedupe_permalinks(collisions)
+            }),
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Render docs with the Tera templates in config, as `smith template`
+    /// does. Templates have access to `doc`, `site` (with `site.pages`),
+    /// `data`, `nav`, and the `pages()` function. Output is cached if
+    /// `template_cache` is on in config.
+    pub fn template(mut self) -> Self {
+        let docs: Vec<Doc> =
+            std::mem::replace(&mut self.docs, Box::new(std::iter::empty())).collect();
+        match render_templates(docs, &self.config, &self.data) {
+            Ok(results) => self.doc_results(results.into_iter()),
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Write docs to files under output_dir, returning a report.
+    /// See `Docs::write`.
+    pub fn write(self, output_dir: &Path) -> WriteReport {
+        self.docs.write(output_dir)
+    }
+
+    /// Write docs to stdout as line-separated JSON
+    pub fn write_stdio(self) {
+        self.docs.write_stdio()
+    }
+
+    /// Get the docs stream out of the pipeline
+    pub fn into_docs(self) -> impl Docs {
+        self.docs
+    }
+}
+
+/// A pipeline step declared in config. Each step mirrors the `Pipeline`
+/// method of the same name.
+///
+/// Example config:
+/// ```json
+/// "pipelines": {
+///   "posts": [
+///     {"step": "read", "glob": "posts/*.md"},
+///     {"step": "frontmatter"},
+///     {"step": "markdown"},
+///     {"step": "permalink", "template": "{yyyy}/{slug}/index.html"},
+///     {"step": "template", "data": ["data/menu.json"]},
+///     {"step": "write", "output_dir": "public"}
+///   ]
+/// }
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    Read {
+        glob: String,
+    },
+    Frontmatter,
+    Markdown,
+    /// Defaults to the `permalinks` rules in config, then
+    /// `permalink_template`, or the template for `permalink_style`
+    Permalink {
+        #[serde(default)]
+        template: Option<String>,
+    },
+    /// JSON files or URLs to include in the template context as `data`
+    Template {
+        #[serde(default)]
+        data: Vec<PathBuf>,
+    },
+    /// Defaults to `output_dir` in config
+    Write {
+        #[serde(default)]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+impl Pipeline {
+    /// Run a step declared in config.
+    /// Reports from `write` steps are kept, and returned by `run`.
+    pub fn step(mut self, step: &Step) -> Self {
+        match step {
+            Step::Read { glob } => self.read(glob),
+            Step::Frontmatter => self.frontmatter(),
+            Step::Markdown => self.markdown(),
+            Step::Permalink { template } => match template {
+                Some(template) => self.permalink(template),
+                None => self.permalink_rules(),
+            },
+            Step::Template { data } => {
+                match json::read_data_map(data, &self.config.fetch_cache()) {
+                    Ok(data) => self.data(data).template(),
+                    Err(err) => self.report(err),
+                }
+            }
+            Step::Write { output_dir } => {
+                let config = self.config.clone();
+                let handler = self.handler.clone();
+                let mut reports = std::mem::take(&mut self.reports);
+                reports.push(self.write(output_dir.as_ref().unwrap_or(&config.output_dir)));
+                let mut pipeline = Pipeline::new(config).error_handler(handler);
+                pipeline.reports = reports;
+                pipeline
+            }
+        }
+    }
+
+    /// Run a list of steps declared in config.
+    /// A `write` step writes the docs so far, and leaves the pipeline empty,
+    /// so later steps can start a new batch with `read`. Docs left after
+    /// the last step are written to stdout as JSON.
+    /// Returns a report for each `write` step.
+    pub fn run(self, steps: &[Step]) -> Vec<WriteReport> {
+        let mut pipeline = steps
+            .iter()
+            .fold(self, |pipeline, step| pipeline.step(step));
+        let reports = std::mem::take(&mut pipeline.reports);
+        pipeline.write_stdio();
+        reports
+    }
+}
+
+/// Render docs with templates, in two passes: the first collects nav menus
+/// and `site.pages` from the full set of docs, and the second renders.
+/// Templates are assigned from `template_rules` in config, then by parent
+/// directory. See `TemplateRules`.
+/// If `template_cache` is on in config, unchanged docs reuse their cached
+/// output. See `RenderCache`.
+pub fn render_templates(
+    docs: Vec<Doc>,
+    config: &Config,
+    data: &HashMap<String, json::Value>,
+) -> Result<Vec<Result<Doc, Error>>, Error> {
+    let mut renderer = tera::renderer_for_config(config)?;
+    let mut context = tera::context();
+    context.insert("data", data);
+    context.insert("nav", &docs.iter().cloned().nav());
+    context.insert("site", &tera::site_context(config, &docs)?);
+    renderer.register_function("pages", tera::PagesFunction::new(&docs));
+    let docs = docs
+        .into_iter()
+        .apply_template_rules(config)?
+        .auto_template();
+    if config.template_cache {
+        let cache = RenderCache::for_config(config, &renderer, &context)?;
+        return Ok(docs
+            .render_tera_template_cached(&renderer, &context, &cache)
+            .collect());
+    }
+    Ok(docs.render_tera_template(&renderer, &context).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pipeline() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.md"), "---\ntitle: Hello\n---\n# Heading").unwrap();
+        let pattern = dir.path().join("*.md");
+        let docs: Vec<Doc> = Pipeline::new(Config::default())
+            .read(&pattern.to_string_lossy())
+            .frontmatter()
+            .markdown()
+            .permalink("{slug}/index.html")
+            .then(|docs| docs.map(|doc| doc.set_summary("Summary")))
+            .into_docs()
+            .collect();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].title, "Hello");
+        assert_eq!(docs[0].content, "<h1>Heading</h1>\n");
+        assert_eq!(docs[0].output_path, PathBuf::from("a/index.html"));
+        assert_eq!(docs[0].summary, "Summary");
+    }
+
+    #[test]
+    fn test_parse_steps() {
+        let steps: Vec<Step> = json::from_str(
+            r#"[
+                {"step": "read", "glob": "posts/*.md"},
+                {"step": "frontmatter"},
+                {"step": "permalink"},
+                {"step": "write"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                Step::Read {
+                    glob: "posts/*.md".to_string()
+                },
+                Step::Frontmatter,
+                Step::Permalink { template: None },
+                Step::Write { output_dir: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_run_steps() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("a.md"), "# A").unwrap();
+        let output_dir = dir.path().join("public");
+        let steps = vec![
+            Step::Read {
+                glob: dir.path().join("*.md").to_string_lossy().into_owned(),
+            },
+            Step::Markdown,
+            Step::Permalink {
+                template: Some("{slug}.html".to_string()),
+            },
+            Step::Write {
+                output_dir: Some(output_dir.clone()),
+            },
+        ];
+        let reports = Pipeline::new(Config::default()).run(&steps);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].is_ok());
+        assert_eq!(reports[0].written, vec![output_dir.join("a.html")]);
+        assert_eq!(
+            std::fs::read_to_string(output_dir.join("a.html")).unwrap(),
+            "<h1>A</h1>\n"
+        );
+    }
+}
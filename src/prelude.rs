@@ -1,15 +1,45 @@
+pub use crate::a11y::A11yDocs;
 pub use crate::blog::BlogDocs;
+pub use crate::bundle::BundleDocs;
+pub use crate::comments::CommentDocs;
+pub use crate::computed::ComputedDocs;
 pub use crate::config::Config;
 pub use crate::doc::Doc;
-pub use crate::docs::{self, DocResults, Docs};
+pub use crate::doc_set::DocSet;
+pub use crate::docs::{self, DocResults, Docs, WriteReport};
+pub use crate::embed::EmbedDocs;
 pub use crate::error::{Error, ErrorKind};
 pub use crate::frontmatter::FrontmatterDocs;
+pub use crate::graph::GraphDocs;
+pub use crate::group::GroupDocs;
+pub use crate::hash::HashDocs;
+pub use crate::head::HeadDocs;
+pub use crate::html_rewrite::HtmlRewriteDocs;
+pub use crate::ics::IcsDocs;
 pub use crate::json;
+pub use crate::linkify::LinkifyDocs;
 pub use crate::markdown::MarkdownDocs;
+pub use crate::microformats::MicroformatsDocs;
+pub use crate::nav::NavDocs;
+pub use crate::paginate::PaginateDocs;
 pub use crate::permalink::PermalinkDocs;
+pub use crate::pipeline::Pipeline;
+pub use crate::preview::PreviewDocs;
+#[cfg(feature = "prose")]
+pub use crate::prose::ProseDocs;
+pub use crate::query::QueryDocs;
+pub use crate::redirects::RedirectsDocs;
+pub use crate::render_cache::RenderCacheDocs;
+pub use crate::report::ReportDocs;
 pub use crate::rss::RssDocs;
+pub use crate::schema::SchemaDocs;
+#[cfg(feature = "script")]
+pub use crate::script::ScriptDocs;
 pub use crate::sitemap::SitemapDocs;
+pub use crate::snippet::SnippetDocs;
+pub use crate::spill::SpillDocs;
 pub use crate::stash::{self, StashDocs};
+pub use crate::stub::{Stub, StubDocs, Stubs};
 pub use crate::tags::TaggedDocs;
 pub use crate::tera::{self, TeraDocs};
 pub use tap::Pipe;
@@ -0,0 +1,174 @@
+// Publish drafts under hard-to-guess preview paths, so draft links can be
+// shared without listing drafts in feeds or sitemaps
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::html::{head_html, inject_head};
+use crate::json::{self, json};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use std::path::PathBuf;
+
+/// Directory draft previews are written under
+pub const PREVIEW_DIR: &str = "preview";
+
+const NOINDEX_META: &str = r#"<meta name="robots" content="noindex">"#;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn preview_mac(key: &str, id: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC should accept keys of any length");
+    mac.update(id.as_bytes());
+    mac
+}
+
+/// Get the preview token for a doc id: an HMAC-SHA256 of the id, keyed
+/// with a secret, as hex. The same key and id always give the same token,
+/// so preview links stay stable between builds. Without the key, knowing
+/// one doc's token doesn't help work out any other.
+pub fn preview_token(key: &str, id: &str) -> String {
+    preview_mac(key, id)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Check a preview token for a doc id, in constant time
+pub fn verify_preview_token(key: &str, id: &str, token: &str) -> bool {
+    let Some(bytes) = decode_hex(token) else {
+        return false;
+    };
+    preview_mac(key, id).verify_slice(&bytes).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Add a `noindex` robots meta tag to the `<head>` of an HTML page.
+/// Content without a `</head>` tag, or that already has a robots meta tag,
+/// is left as-is.
+pub fn inject_noindex(html: &str) -> String {
+    if head_html(html)
+        .to_ascii_lowercase()
+        .contains(r#"name="robots""#)
+    {
+        return html.to_string();
+    }
+    inject_head(html, &[NOINDEX_META.to_string()])
+}
+
+impl Doc {
+    /// Is this doc a draft preview? See `set_preview`.
+    pub fn is_preview(&self) -> bool {
+        json::get_deep(&self.meta, "preview").is_some_and(|value| value == true)
+    }
+
+    /// Publish this doc as a preview.
+    ///
+    /// - output_path is moved under `preview/{token}/`. See `preview_token`.
+    /// - `meta.preview` is set to `true`, which leaves the doc out of
+    ///   sitemaps and feeds, and keeps it through `remove_drafts`.
+    /// - `meta.robots` is set to `"noindex"`, for templates to render.
+    /// - A `noindex` robots meta tag is added to content that is already a
+    ///   full HTML page. See `inject_noindex`.
+    pub fn set_preview(mut self, key: &str) -> Self {
+        let token = preview_token(key, &self.get_id());
+        self.output_path = PathBuf::from(PREVIEW_DIR)
+            .join(token)
+            .join(&self.output_path);
+        self.content = inject_noindex(&self.content);
+        if !self.meta.is_object() {
+            self.meta = json!({});
+        }
+        self.merge_meta(json!({"preview": true, "robots": "noindex"}))
+    }
+}
+
+pub trait PreviewDocs: Docs {
+    /// Publish drafts as previews, under paths derived from a secret key.
+    /// Other docs are passed through. See `Doc::set_preview`.
+    ///
+    /// Run after permalinks are set, since previews move the output path.
+    fn preview_drafts(self, key: &str) -> impl Docs {
+        self.map(move |doc| {
+            if doc.is_draft() {
+                doc.set_preview(key)
+            } else {
+                doc
+            }
+        })
+    }
+}
+
+impl<I> PreviewDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rss::RssDocs;
+    use crate::sitemap::SitemapDocs;
+    use crate::url::TrailingSlash;
+
+    #[test]
+    fn test_preview_drafts() {
+        let docs: Vec<Doc> = vec![
+            Doc::draft("posts/_a.md")
+                .set_output_path("posts/a/index.html")
+                .set_content("<html><head><title>A</title></head></html>"),
+            Doc::draft("posts/b.md")
+                .set_output_path("posts/b/index.html")
+                .set_meta(json!({"draft": true})),
+            Doc::draft("posts/c.md").set_output_path("posts/c/index.html"),
+        ]
+        .into_iter()
+        .preview_drafts("secret")
+        .remove_drafts()
+        .collect();
+        assert_eq!(docs.len(), 3);
+        let token = preview_token("secret", "posts/_a.md");
+        assert_ne!(token, preview_token("other", "posts/_a.md"));
+        assert_eq!(token.len(), 64);
+        assert!(verify_preview_token("secret", "posts/_a.md", &token));
+        assert!(!verify_preview_token("secret", "posts/b.md", &token));
+        assert!(!verify_preview_token("secret", "posts/_a.md", "zz"));
+        assert_eq!(
+            docs[0].output_path,
+            PathBuf::from(format!("preview/{}/posts/a/index.html", token))
+        );
+        assert_eq!(
+            docs[0].content,
+            "<html><head><title>A</title><meta name=\"robots\" content=\"noindex\">\n</head></html>"
+        );
+        assert_eq!(docs[1].meta["robots"], "noindex");
+        assert!(!docs[2].is_preview());
+
+        let sitemap = docs
+            .clone()
+            .into_iter()
+            .sitemap("https://example.com", TrailingSlash::Always)
+            .unwrap();
+        assert!(!sitemap.content.contains("preview/"));
+        let feed = docs
+            .into_iter()
+            .rss(
+                "",
+                TrailingSlash::Always,
+                "",
+                "",
+                "",
+                "feed.xml".as_ref(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(feed.content.matches("<item>").count(), 1);
+    }
+}
@@ -0,0 +1,210 @@
+// Spellcheck doc content against a word list
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::html::{strip_html, unescape_xml};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static CODE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(pre|code|script|style)\b[^>]*>.*?</(?:pre|code|script|style)\s*>")
+        .expect("Code regex should be valid")
+});
+
+/// Tags at block and cell boundaries, which separate words even when no
+/// whitespace is written between them, as in `<td>a</td><td>b</td>`
+static BOUNDARY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)</?(?:address|article|aside|blockquote|br|caption|dd|div|dl|dt|figcaption|figure|footer|h[1-6]|header|hr|li|nav|ol|p|section|table|tbody|td|tfoot|th|thead|tr|ul)\b[^>]*>",
+    )
+    .expect("Boundary regex should be valid")
+});
+
+static WORD_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"[\p{L}\p{N}]+(?:['’][\p{L}]+)*").expect("Word regex should be valid")
+});
+
+/// Characters of context to show on each side of an unknown word
+const CONTEXT_CHARS: usize = 30;
+
+/// A set of known words. Words are matched case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Create a dictionary from a list of words
+    pub fn from_words<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Self {
+        let mut dictionary = Dictionary::default();
+        dictionary.add_words(words);
+        dictionary
+    }
+
+    /// Add words to the dictionary
+    pub fn add_words<S: AsRef<str>>(&mut self, words: impl IntoIterator<Item = S>) {
+        self.words
+            .extend(words.into_iter().map(|word| word.as_ref().to_lowercase()));
+    }
+
+    /// Add the words in a word list file, with one word per line.
+    /// Blank lines and lines starting with `#` are skipped. Hunspell `.dic`
+    /// files also work: the leading word count line is ignored, and affix
+    /// flags after a `/` are stripped.
+    ///
+    /// Affix flags are not expanded, so only the listed stems are known.
+    /// `cat/S` adds `cat`, but not `cats`. To accept inflected forms, use an
+    /// expanded word list, such as the output of Hunspell's `unmunch`.
+    pub fn add_file(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let content = fs::read_to_string(path)?;
+        self.add_words(content.lines().filter_map(|line| {
+            let word = line.split('/').next().unwrap_or_default().trim();
+            let is_count = word.chars().all(|c| c.is_ascii_digit());
+            if word.is_empty() || word.starts_with('#') || is_count {
+                None
+            } else {
+                Some(word)
+            }
+        }));
+        Ok(())
+    }
+
+    /// Read a dictionary from word list files, such as a system dictionary
+    /// plus a project word list. See `add_file`.
+    pub fn read<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Result<Self, Error> {
+        let mut dictionary = Dictionary::default();
+        for path in paths {
+            dictionary.add_file(path)?;
+        }
+        Ok(dictionary)
+    }
+
+    /// Is the word known? Possessive `'s` is ignored.
+    pub fn contains(&self, word: &str) -> bool {
+        let word = word.to_lowercase().replace('’', "'");
+        if self.words.contains(&word) {
+            return true;
+        }
+        word.strip_suffix("'s")
+            .is_some_and(|stem| self.words.contains(stem))
+    }
+}
+
+/// An unknown word found in a doc
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProseIssue {
+    pub word: String,
+    /// The text around the word
+    pub context: String,
+}
+
+/// Get the text around a byte range, collapsing whitespace
+fn get_context(text: &str, start: usize, end: usize) -> String {
+    let before: String = text[..start]
+        .chars()
+        .rev()
+        .take(CONTEXT_CHARS)
+        .collect::<Vec<char>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let after: String = text[end..].chars().take(CONTEXT_CHARS).collect();
+    let context = format!("{}{}{}", before, &text[start..end], after);
+    context.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Spellcheck the text of HTML content against a dictionary, returning
+/// unknown words in the order they appear. Code blocks, inline code,
+/// scripts, and styles are skipped, as are words containing digits.
+pub fn lint_prose(html: &str, dictionary: &Dictionary) -> Vec<ProseIssue> {
+    let html = CODE_REGEX.replace_all(html, " ");
+    let html = BOUNDARY_REGEX.replace_all(&html, " ");
+    let text = unescape_xml(&strip_html(&html));
+    WORD_REGEX
+        .find_iter(&text)
+        .filter(|word| !word.as_str().chars().any(|c| c.is_numeric()))
+        .filter(|word| !dictionary.contains(word.as_str()))
+        .map(|word| ProseIssue {
+            word: word.as_str().to_string(),
+            context: get_context(&text, word.start(), word.end()),
+        })
+        .collect()
+}
+
+impl Doc {
+    /// Spellcheck this doc's content. See `lint_prose`.
+    pub fn get_prose_issues(&self, dictionary: &Dictionary) -> Vec<ProseIssue> {
+        lint_prose(&self.content, dictionary)
+    }
+
+    /// Spellcheck this doc's content.
+    /// Returns an error listing every unknown word if any are found.
+    pub fn lint_prose(self, dictionary: &Dictionary) -> Result<Self, Error> {
+        let issues = self.get_prose_issues(dictionary);
+        if issues.is_empty() {
+            return Ok(self);
+        }
+        let details: Vec<String> = issues
+            .iter()
+            .map(|issue| format!("  \"{}\" in \"{}\"", issue.word, issue.context))
+            .collect();
+        Err(Error::value(format!("Unknown words:\n{}", details.join("\n"))).with_doc(&self.id_path))
+    }
+}
+
+pub trait ProseDocs: Docs {
+    /// Spellcheck each doc's content against a dictionary.
+    /// Docs with unknown words become errors listing each word in context.
+    fn lint_prose(self, dictionary: &Dictionary) -> impl DocResults {
+        self.map(move |doc| doc.lint_prose(dictionary))
+    }
+}
+
+impl<I> ProseDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lint_prose() {
+        let dir = tempdir().unwrap();
+        let dic = dir.path().join("en.dic");
+        fs::write(&dic, "5\nthe/S\ncat\nsat\non\nmat\n").unwrap();
+        let words = dir.path().join("words.txt");
+        fs::write(&words, "# Project words\nLettersmith\n").unwrap();
+        let dictionary = Dictionary::read([&dic, &words]).unwrap();
+        let html = "<p>The cat's mat sat on teh mat in 2024.</p>\n<pre><code>fn lettersmith()</code></pre>\n<p>LETTERSMITH sat</p>";
+        assert_eq!(
+            lint_prose(html, &dictionary),
+            vec![
+                ProseIssue {
+                    word: "teh".to_string(),
+                    context: "The cat's mat sat on teh mat in 2024. LETTERSMITH".to_string()
+                },
+                ProseIssue {
+                    word: "in".to_string(),
+                    context: "The cat's mat sat on teh mat in 2024. LETTERSMITH sat".to_string()
+                },
+            ]
+        );
+        let err = Doc::draft("a.md")
+            .set_content(html)
+            .lint_prose(&dictionary)
+            .unwrap_err();
+        assert!(err.msg.starts_with("Unknown words:\n  \"teh\" in "));
+
+        // Cells and list items are separate words
+        let html =
+            "<table><tr><td>cat</td><td>mat</td></tr></table><ul><li>on</li><li>the</li></ul>";
+        assert!(lint_prose(html, &dictionary).is_empty());
+
+        // Affix flags are stripped, but not expanded
+        assert!(dictionary.contains("the"));
+        assert!(!dictionary.contains("thes"));
+    }
+}
@@ -0,0 +1,185 @@
+// Filter and project docs by values found at dot-paths, such as `meta.tags`
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::json::{self, get_deep, set_deep};
+
+/// A test to apply to the value found at a path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    /// Value exists and is not `null` or `false`
+    Truthy,
+    /// Value is equal to the given value
+    Eq(json::Value),
+    /// Value is an array containing the given value, a string containing the
+    /// given substring, or an object containing the given key
+    Contains(json::Value),
+}
+
+impl Predicate {
+    /// Test a value against this predicate.
+    /// Missing values are represented by `None`.
+    pub fn test(&self, value: Option<&json::Value>) -> bool {
+        match (self, value) {
+            (_, None) => false,
+            (Predicate::Truthy, Some(value)) => {
+                !matches!(value, json::Value::Null | json::Value::Bool(false))
+            }
+            (Predicate::Eq(expected), Some(value)) => value == expected,
+            (Predicate::Contains(needle), Some(json::Value::Array(items))) => {
+                items.contains(needle)
+            }
+            (Predicate::Contains(json::Value::String(needle)), Some(json::Value::String(hay))) => {
+                hay.contains(needle.as_str())
+            }
+            (Predicate::Contains(json::Value::String(key)), Some(json::Value::Object(map))) => {
+                map.contains_key(key)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parse a value given on the command line.
+/// Values that parse as JSON (numbers, booleans, quoted strings, etc) are
+/// used as-is. Anything else is treated as a plain string.
+pub fn parse_query_value(value: &str) -> json::Value {
+    json::from_str(value).unwrap_or_else(|_| json::Value::String(value.to_string()))
+}
+
+impl Doc {
+    /// Get a value from the doc using a dot-separated path, such as
+    /// `title` or `meta.author.name`. A leading `.` is allowed, jq-style.
+    pub fn get_path(&self, path: &str) -> Option<json::Value> {
+        let path = path.trim().trim_start_matches('.');
+        if path.is_empty() {
+            return serde_json::to_value(self).ok();
+        }
+        if let Some(meta_path) = path.strip_prefix("meta.") {
+            return get_deep(&self.meta, meta_path);
+        }
+        let value = serde_json::to_value(self).ok()?;
+        get_deep(&value, path)
+    }
+
+    /// Test the value at path against a predicate
+    pub fn matches_query(&self, path: &str, predicate: &Predicate) -> bool {
+        predicate.test(self.get_path(path).as_ref())
+    }
+
+    /// Project doc into a reduced JSON object containing only the fields
+    /// at the given dot-paths. Nested paths like `meta.tags` produce nested
+    /// objects. Missing fields are left out.
+    pub fn project(&self, fields: &[String]) -> json::Value {
+        let mut projected = json::Value::Object(json::Map::new());
+        for field in fields {
+            let path = field.trim().trim_start_matches('.');
+            if let Some(value) = self.get_path(path) {
+                set_deep(&mut projected, path, value);
+            }
+        }
+        projected
+    }
+}
+
+pub trait QueryDocs: Docs {
+    /// Keep only docs where the value at path matches predicate
+    fn query(self, path: &str, predicate: Predicate) -> impl Docs {
+        let path = path.to_string();
+        self.filter(move |doc| doc.matches_query(&path, &predicate))
+    }
+
+    /// Remove docs where the value at path matches predicate
+    fn query_not(self, path: &str, predicate: Predicate) -> impl Docs {
+        let path = path.to_string();
+        self.filter(move |doc| !doc.matches_query(&path, &predicate))
+    }
+
+    /// Project each doc into a reduced JSON object containing only the
+    /// fields at the given dot-paths.
+    fn project(self, fields: &[String]) -> impl Iterator<Item = json::Value> {
+        self.map(move |doc| doc.project(fields))
+    }
+}
+
+impl<I> QueryDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn make_docs() -> Vec<Doc> {
+        vec![
+            Doc::draft("a.md")
+                .set_title("A")
+                .set_meta(json!({"tags": ["rust", "ssg"], "draft": false})),
+            Doc::draft("b.md")
+                .set_title("B")
+                .set_meta(json!({"tags": ["python"], "draft": true})),
+            Doc::draft("c.md").set_title("C"),
+        ]
+    }
+
+    #[test]
+    fn test_get_path() {
+        let doc = Doc::draft("a.md").set_meta(json!({"author": {"name": "Ada"}}));
+        assert_eq!(doc.get_path(".meta.author.name"), Some(json!("Ada")));
+        assert_eq!(doc.get_path("id_path"), Some(json!("a.md")));
+        assert_eq!(doc.get_path("meta.missing"), None);
+    }
+
+    #[test]
+    fn test_project() {
+        let doc = Doc::draft("a.md")
+            .set_title("A")
+            .set_content("Long content")
+            .set_meta(json!({"tags": ["rust"], "author": "Ada"}));
+        let fields = vec![
+            "id_path".to_string(),
+            "title".to_string(),
+            "meta.tags".to_string(),
+            "meta.missing".to_string(),
+        ];
+        assert_eq!(
+            doc.project(&fields),
+            json!({"id_path": "a.md", "title": "A", "meta": {"tags": ["rust"]}})
+        );
+    }
+
+    #[test]
+    fn test_query_contains() {
+        let docs: Vec<Doc> = make_docs()
+            .into_iter()
+            .query("meta.tags", Predicate::Contains(json!("rust")))
+            .collect();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].title, "A");
+    }
+
+    #[test]
+    fn test_query_truthy_and_not() {
+        let drafts: Vec<Doc> = make_docs()
+            .into_iter()
+            .query("meta.draft", Predicate::Truthy)
+            .collect();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].title, "B");
+
+        let published: Vec<Doc> = make_docs()
+            .into_iter()
+            .query_not("meta.draft", Predicate::Truthy)
+            .collect();
+        assert_eq!(published.len(), 2);
+    }
+
+    #[test]
+    fn test_query_eq() {
+        let docs: Vec<Doc> = make_docs()
+            .into_iter()
+            .query("title", Predicate::Eq(parse_query_value("C")))
+            .collect();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(parse_query_value("true"), json!(true));
+        assert_eq!(parse_query_value("10"), json!(10));
+    }
+}
@@ -0,0 +1,179 @@
+// Generate hosting-level redirect files (Netlify `_redirects`, `vercel.json`)
+use crate::absolutize::to_url;
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::json::{self, json};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A single redirect rule
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Redirect {
+    /// Path to redirect from
+    pub from: String,
+    /// Path or URL to redirect to
+    pub to: String,
+    /// HTTP status code
+    #[serde(default = "status_default")]
+    pub status: u16,
+}
+
+fn status_default() -> u16 {
+    301
+}
+
+impl Redirect {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Redirect {
+            from: from.into(),
+            to: to.into(),
+            status: status_default(),
+        }
+    }
+}
+
+/// Hosting provider redirect file formats
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectsFormat {
+    /// Netlify `_redirects` file
+    Netlify,
+    /// Vercel `vercel.json` file
+    Vercel,
+}
+
+impl RedirectsFormat {
+    /// Get the conventional output path for this format
+    pub fn output_path(&self) -> &'static str {
+        match self {
+            RedirectsFormat::Netlify => "_redirects",
+            RedirectsFormat::Vercel => "vercel.json",
+        }
+    }
+
+    /// Render redirects to a string in this format
+    pub fn render(&self, redirects: &[Redirect]) -> Result<String, Error> {
+        match self {
+            RedirectsFormat::Netlify => Ok(render_netlify_redirects(redirects)),
+            RedirectsFormat::Vercel => render_vercel_json(redirects),
+        }
+    }
+}
+
+/// Render redirects as a Netlify `_redirects` file.
+/// https://docs.netlify.com/routing/redirects/
+pub fn render_netlify_redirects(redirects: &[Redirect]) -> String {
+    redirects
+        .iter()
+        .map(|redirect| format!("{} {} {}\n", redirect.from, redirect.to, redirect.status))
+        .collect()
+}
+
+/// Render redirects as a `vercel.json` file.
+/// https://vercel.com/docs/projects/project-configuration#redirects
+pub fn render_vercel_json(redirects: &[Redirect]) -> Result<String, Error> {
+    let redirects: Vec<json::Value> = redirects
+        .iter()
+        .map(|redirect| {
+            json!({
+                "source": redirect.from,
+                "destination": redirect.to,
+                "statusCode": redirect.status,
+            })
+        })
+        .collect();
+    let content = json::to_string_pretty(&json!({ "redirects": redirects }))?;
+    Ok(content)
+}
+
+impl Doc {
+    /// Get redirects from the paths listed in the `aliases` meta field to
+    /// this doc's output path.
+    pub fn get_alias_redirects(&self) -> Vec<Redirect> {
+        let Some(json::Value::Array(aliases)) = self.meta.get("aliases") else {
+            return Vec::new();
+        };
+        let to = to_url(&self.output_path, "/");
+        aliases
+            .iter()
+            .filter_map(|alias| alias.as_str())
+            .map(|alias| Redirect::new(to_url(Path::new(alias), "/"), to.clone()))
+            .collect()
+    }
+}
+
+pub trait RedirectsDocs: Docs {
+    /// Generate a redirects file doc from doc `aliases` meta, plus any
+    /// additional redirects (such as the redirects table in config).
+    fn redirects(self, redirects: &[Redirect], format: RedirectsFormat) -> Result<Doc, Error> {
+        let mut all_redirects: Vec<Redirect> = redirects.to_vec();
+        for doc in self {
+            all_redirects.extend(doc.get_alias_redirects());
+        }
+        let content = format.render(&all_redirects)?;
+        let output_path = PathBuf::from(format.output_path());
+        let now = Utc::now();
+        Ok(Doc::new(
+            output_path.clone(),
+            output_path,
+            None,
+            None,
+            now,
+            now,
+            "".to_string(),
+            "".to_string(),
+            content,
+            json!({}),
+        ))
+    }
+}
+
+impl<I> RedirectsDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_docs() -> Vec<Doc> {
+        vec![Doc::draft("posts/new.md")
+            .set_output_path("posts/new/index.html")
+            .set_meta(json!({"aliases": ["old/post.html", "/older/"]}))]
+    }
+
+    #[test]
+    fn test_netlify_redirects() {
+        let config_redirects = vec![Redirect {
+            from: "/blog/*".to_string(),
+            to: "/posts/:splat".to_string(),
+            status: 302,
+        }];
+        let doc = make_docs()
+            .into_iter()
+            .redirects(&config_redirects, RedirectsFormat::Netlify)
+            .unwrap();
+        assert_eq!(doc.output_path, PathBuf::from("_redirects"));
+        assert_eq!(
+            doc.content,
+            "/blog/* /posts/:splat 302\n/old/post.html /posts/new/index.html 301\n/older/ /posts/new/index.html 301\n"
+        );
+    }
+
+    #[test]
+    fn test_vercel_redirects() {
+        let doc = make_docs()
+            .into_iter()
+            .redirects(&[], RedirectsFormat::Vercel)
+            .unwrap();
+        let value: json::Value = json::from_str(&doc.content).unwrap();
+        assert_eq!(
+            value["redirects"][0],
+            json!({
+                "source": "/old/post.html",
+                "destination": "/posts/new/index.html",
+                "statusCode": 301
+            })
+        );
+    }
+}
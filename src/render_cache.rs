@@ -0,0 +1,552 @@
+// Cache rendered template output on disk, so unchanged docs skip Tera
+// rendering on rebuilds
+use crate::config::Config;
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::hash::content_hash;
+use crate::io::write_file_deep;
+use crate::tera::{Context, FilterDef, Tera};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+
+/// A cache of rendered template output, keyed by a hash of the templates,
+/// the parts of the shared template context the doc's template reads, and
+/// the doc being rendered.
+///
+/// Keys are worked out once per template, when the cache is opened, so
+/// looking up a doc only costs hashing the doc. A template reads the context
+/// variables and `site` fields named in it, or in the templates it extends,
+/// includes, or imports macros from, such as `site.site_title` or
+/// `site.pages`. Calling `pages()` reads `site.pages`. Naming a variable
+/// whole, such as `{% set s = site %}`, reads all of it, and
+/// `__tera_context` reads the whole context.
+///
+/// So editing one doc changes the key of that doc, and of docs whose
+/// templates list pages, and no others. Any change to a template file gives
+/// every doc a new key. Caches opened with `for_config` also give every doc
+/// a new key when `template_filters`, a filter script, or
+/// `template_macros` change, since those aren't part of template sources. The `now` context variable is left out of the key,
+/// or no output would ever be reused. Cache files are not cleaned up, so
+/// clear the cache directory now and then.
+#[derive(Debug, Clone)]
+pub struct RenderCache {
+    dir: PathBuf,
+    /// Key salt for each template, by name
+    salts: HashMap<String, String>,
+    /// Key salt for templates the renderer doesn't know, from the whole
+    /// context
+    fallback_salt: String,
+}
+
+/// Hash the templates known to a renderer. Templates read from files are
+/// hashed by their source. Built-in templates are hashed by crate version,
+/// since they only change between releases.
+fn hash_templates(renderer: &Tera) -> String {
+    let mut names: Vec<&String> = renderer.templates.keys().collect();
+    names.sort();
+    let mut sources = String::new();
+    for name in names {
+        let template = &renderer.templates[name];
+        let source = match &template.path {
+            Some(path) => fs::read_to_string(path).unwrap_or_default(),
+            None => format!("builtin {}", env!("CARGO_PKG_VERSION")),
+        };
+        sources.push_str(&format!("{}\n{}\n", name, content_hash(&source)));
+    }
+    content_hash(&sources)
+}
+
+/// Hash the template settings in config that aren't part of template
+/// sources: `template_filters`, with the contents of filter scripts, and the
+/// `template_macros` namespaces.
+fn hash_template_config(config: &Config) -> Result<String, Error> {
+    let mut settings = String::new();
+    for (name, def) in &config.template_filters {
+        settings.push_str(&format!(
+            "filter {}={}\n",
+            name,
+            serde_json::to_string(def)?
+        ));
+        if let FilterDef::Script { script } = def {
+            let source = fs::read_to_string(script).unwrap_or_default();
+            settings.push_str(&format!("script {}\n", content_hash(&source)));
+        }
+    }
+    for (namespace, file) in &config.template_macros {
+        settings.push_str(&format!("macros {}={}\n", namespace, file));
+    }
+    Ok(content_hash(&settings))
+}
+
+/// Hash a template context, leaving out `now`
+fn hash_context(context: &Context) -> Result<String, Error> {
+    let mut value = context.clone().into_json();
+    if let Some(object) = value.as_object_mut() {
+        object.remove("now");
+    }
+    Ok(content_hash(&serde_json::to_string(&value)?))
+}
+
+/// Magic variable that gives templates the whole context
+const WHOLE_CONTEXT: &str = "__tera_context";
+
+/// Get the context path a variable reads, to one level below the top, so
+/// `site.pages.0.title` reads `site.pages`, and `site` reads `site`
+fn context_path(ident: &str) -> String {
+    let mut parts = ident.splitn(3, '.');
+    let top = parts.next().unwrap_or_default();
+    let top_end = top.find('[').unwrap_or(top.len());
+    if top_end < top.len() {
+        // Indexed with brackets, such as `site[key]`. Read all of it.
+        return top[..top_end].to_string();
+    }
+    match parts.next() {
+        Some(field) => {
+            let field_end = field.find('[').unwrap_or(field.len());
+            format!("{}.{}", top, &field[..field_end])
+        }
+        None => top.to_string(),
+    }
+}
+
+/// Collect the context paths read by template nodes, and the names of the
+/// templates they include
+fn collect_nodes(nodes: &[Node], paths: &mut BTreeSet<String>, includes: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => collect_expr(expr, paths),
+            Node::MacroDefinition(_, definition, _) => {
+                for expr in definition.args.values().flatten() {
+                    collect_expr(expr, paths);
+                }
+                collect_nodes(&definition.body, paths, includes);
+            }
+            Node::Include(_, names, _) => includes.extend(names.iter().cloned()),
+            Node::Set(_, set) => collect_expr(&set.value, paths),
+            Node::FilterSection(_, section, _) => {
+                collect_call(&section.filter, paths);
+                collect_nodes(&section.body, paths, includes);
+            }
+            Node::Block(_, block, _) => collect_nodes(&block.body, paths, includes),
+            Node::Forloop(_, forloop, _) => {
+                collect_expr(&forloop.container, paths);
+                collect_nodes(&forloop.body, paths, includes);
+                if let Some(body) = &forloop.empty_body {
+                    collect_nodes(body, paths, includes);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in &condition.conditions {
+                    collect_expr(expr, paths);
+                    collect_nodes(body, paths, includes);
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    collect_nodes(body, paths, includes);
+                }
+            }
+            Node::Super
+            | Node::Text(_)
+            | Node::Extends(..)
+            | Node::ImportMacro(..)
+            | Node::Raw(..)
+            | Node::Break(_)
+            | Node::Continue(_)
+            | Node::Comment(..) => {}
+        }
+    }
+}
+
+fn collect_call(call: &FunctionCall, paths: &mut BTreeSet<String>) {
+    if call.name == "pages" {
+        paths.insert("site.pages".to_string());
+    }
+    for expr in call.args.values() {
+        collect_expr(expr, paths);
+    }
+}
+
+fn collect_expr(expr: &Expr, paths: &mut BTreeSet<String>) {
+    for filter in &expr.filters {
+        collect_call(filter, paths);
+    }
+    collect_expr_val(&expr.val, paths);
+}
+
+fn collect_expr_val(val: &ExprVal, paths: &mut BTreeSet<String>) {
+    match val {
+        ExprVal::Ident(ident) => {
+            paths.insert(context_path(ident));
+        }
+        ExprVal::Math(math) => {
+            collect_expr(&math.lhs, paths);
+            collect_expr(&math.rhs, paths);
+        }
+        ExprVal::Logic(logic) => {
+            collect_expr(&logic.lhs, paths);
+            collect_expr(&logic.rhs, paths);
+        }
+        ExprVal::Test(test) => {
+            paths.insert(context_path(&test.ident));
+            for expr in &test.args {
+                collect_expr(expr, paths);
+            }
+        }
+        ExprVal::MacroCall(call) => {
+            for expr in call.args.values() {
+                collect_expr(expr, paths);
+            }
+        }
+        ExprVal::FunctionCall(call) => collect_call(call, paths),
+        ExprVal::Array(exprs) => {
+            for expr in exprs {
+                collect_expr(expr, paths);
+            }
+        }
+        ExprVal::StringConcat(concat) => {
+            for val in &concat.values {
+                collect_expr_val(val, paths);
+            }
+        }
+        ExprVal::In(expr) => {
+            collect_expr(&expr.lhs, paths);
+            collect_expr(&expr.rhs, paths);
+        }
+        ExprVal::String(_) | ExprVal::Int(_) | ExprVal::Float(_) | ExprVal::Bool(_) => {}
+    }
+}
+
+/// Get the context paths read by a template, including the templates it
+/// extends, includes, and imports macros from
+fn template_context_paths(renderer: &Tera, name: &str) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    let mut seen = HashSet::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        let Some(template) = renderer.templates.get(&name) else {
+            continue;
+        };
+        collect_nodes(&template.ast, &mut paths, &mut pending);
+        pending.extend(template.parents.iter().cloned());
+        pending.extend(
+            template
+                .imported_macro_files
+                .iter()
+                .map(|(file, _)| file.clone()),
+        );
+    }
+    paths
+}
+
+impl RenderCache {
+    /// Open a render cache in `dir`, for rendering with this renderer and
+    /// shared context
+    pub fn new(dir: impl Into<PathBuf>, renderer: &Tera, context: &Context) -> Result<Self, Error> {
+        Self::with_templates_hash(dir, renderer, context, hash_templates(renderer))
+    }
+
+    /// Open the render cache in config's `template_cache_dir`, for a
+    /// renderer created with `tera::renderer_for_config`. Keys also cover
+    /// the template filters and macro namespaces in config.
+    pub fn for_config(config: &Config, renderer: &Tera, context: &Context) -> Result<Self, Error> {
+        let templates_hash = content_hash(&format!(
+            "{}{}",
+            hash_templates(renderer),
+            hash_template_config(config)?
+        ));
+        Self::with_templates_hash(
+            config.template_cache_dir(),
+            renderer,
+            context,
+            templates_hash,
+        )
+    }
+
+    fn with_templates_hash(
+        dir: impl Into<PathBuf>,
+        renderer: &Tera,
+        context: &Context,
+        templates_hash: String,
+    ) -> Result<Self, Error> {
+        let fallback_salt = format!("{}{}", templates_hash, hash_context(context)?);
+        let context_json = context.clone().into_json();
+        // Hash each context path once, since many templates read the same ones
+        let mut path_hashes: HashMap<String, String> = HashMap::new();
+        let mut salts = HashMap::new();
+        for name in renderer.templates.keys() {
+            let paths = template_context_paths(renderer, name);
+            if paths.contains(WHOLE_CONTEXT) {
+                salts.insert(name.clone(), fallback_salt.clone());
+                continue;
+            }
+            let mut salt = templates_hash.clone();
+            for path in paths {
+                if path == "now" || path.starts_with("now.") {
+                    continue;
+                }
+                let hash = match path_hashes.get(&path) {
+                    Some(hash) => hash.clone(),
+                    None => {
+                        let value = path
+                            .split('.')
+                            .try_fold(&context_json, |value, key| value.get(key));
+                        let hash = match value {
+                            Some(value) => content_hash(&serde_json::to_string(value)?),
+                            None => String::new(),
+                        };
+                        path_hashes.insert(path.clone(), hash.clone());
+                        hash
+                    }
+                };
+                salt.push_str(&format!("\n{}={}", path, hash));
+            }
+            salts.insert(name.clone(), content_hash(&salt));
+        }
+        Ok(RenderCache {
+            dir: dir.into(),
+            salts,
+            fallback_salt,
+        })
+    }
+
+    /// The directory cached output is stored in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the path a doc's rendered output is cached at
+    pub fn path_for(&self, doc: &Doc) -> Result<PathBuf, Error> {
+        let template_name = doc
+            .template_path
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let salt = self
+            .salts
+            .get(&template_name)
+            .unwrap_or(&self.fallback_salt);
+        let doc_json = serde_json::to_string(doc)?;
+        let key = content_hash(&format!("{}{}", salt, doc_json));
+        Ok(self.dir.join(format!("{}.html", key)))
+    }
+
+    /// Get a doc's cached output, if any
+    pub fn get(&self, doc: &Doc) -> Option<String> {
+        fs::read_to_string(self.path_for(doc).ok()?).ok()
+    }
+
+    /// Store a doc's rendered output
+    pub fn put(&self, doc: &Doc, content: &str) -> Result<(), Error> {
+        write_file_deep(self.path_for(doc)?, content)
+    }
+}
+
+impl Doc {
+    /// Render the Tera template at `template_path`, like
+    /// `render_tera_template_in`, reusing cached output when this doc, the
+    /// templates, and the context the template reads are unchanged.
+    /// Failing to write the cache is an error, so a broken cache doesn't
+    /// go unnoticed.
+    pub fn render_tera_template_cached(
+        self,
+        renderer: &Tera,
+        context: &mut Context,
+        cache: &RenderCache,
+    ) -> Result<Self, Error> {
+        if self.template_path.is_none() {
+            return Ok(self);
+        }
+        if let Some(content) = cache.get(&self) {
+            return Ok(self.set_content(content));
+        }
+        let key_doc = self.clone();
+        let doc = self.render_tera_template_in(renderer, context)?;
+        cache.put(&key_doc, &doc.content).map_err(|err| {
+            Error::value(format!("Could not cache rendered output: {}", err.msg))
+                .with_doc(&doc.id_path)
+        })?;
+        Ok(doc)
+    }
+}
+
+pub trait RenderCacheDocs: Docs {
+    /// Render docs with templates, reusing cached output for unchanged docs.
+    /// See `Doc::render_tera_template_cached`. Like
+    /// `TeraDocs::render_tera_template`, the context is copied once.
+    fn render_tera_template_cached(
+        self,
+        renderer: &Tera,
+        context: &Context,
+        cache: &RenderCache,
+    ) -> impl DocResults {
+        let mut context = context.clone();
+        self.map(move |doc| doc.render_tera_template_cached(renderer, &mut context, cache))
+    }
+}
+
+impl<I> RenderCacheDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render_tera_template_cached() {
+        let dir = tempdir().unwrap();
+        let mut renderer = Tera::default();
+        renderer
+            .add_raw_template("page.html", "<h1>{{ doc.title }}</h1>{{ data.x }}")
+            .unwrap();
+        let mut context = crate::tera::context();
+        context.insert("data", &json::json!({"x": 1}));
+        let cache = RenderCache::new(dir.path(), &renderer, &context).unwrap();
+        let doc = Doc::draft("a.md").set_title("A").set_template("page.html");
+
+        let first = doc
+            .clone()
+            .render_tera_template_cached(&renderer, &mut context, &cache)
+            .unwrap();
+        assert_eq!(first.content, "<h1>A</h1>1");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // A cache hit skips rendering, and uses the cached output
+        fs::write(cache.path_for(&doc).unwrap(), "cached").unwrap();
+        let second = doc
+            .clone()
+            .render_tera_template_cached(&renderer, &mut context, &cache)
+            .unwrap();
+        assert_eq!(second.content, "cached");
+
+        // Changing the context or the doc gives a new key
+        context.insert("data", &json::json!({"x": 2}));
+        let cache = RenderCache::new(dir.path(), &renderer, &context).unwrap();
+        let third = doc
+            .clone()
+            .render_tera_template_cached(&renderer, &mut context, &cache)
+            .unwrap();
+        assert_eq!(third.content, "<h1>A</h1>2");
+        let fourth = doc
+            .set_title("B")
+            .render_tera_template_cached(&renderer, &mut context, &cache)
+            .unwrap();
+        assert_eq!(fourth.content, "<h1>B</h1>2");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[test]
+    fn test_render_cache_keys_on_template_config() {
+        let dir = tempdir().unwrap();
+        let script = dir.path().join("shout.rhai");
+        fs::write(&script, "value + \"!\"").unwrap();
+        let mut renderer = Tera::default();
+        renderer
+            .add_raw_template("page.html", "{{ doc.title }}")
+            .unwrap();
+        let context = crate::tera::context();
+        let doc = Doc::draft("a.md").set_template("page.html");
+        let key = |config: &Config| {
+            RenderCache::for_config(config, &renderer, &context)
+                .unwrap()
+                .path_for(&doc)
+                .unwrap()
+        };
+        let config = Config {
+            data_cache_dir: dir.path().join("cache"),
+            template_filters: [(
+                "shout".to_string(),
+                FilterDef::Script {
+                    script: script.clone(),
+                },
+            )]
+            .into(),
+            ..Default::default()
+        };
+        let before = key(&config);
+        assert_eq!(key(&config), before);
+
+        // Editing a filter script gives a new key
+        fs::write(&script, "value + \"!!\"").unwrap();
+        let edited = key(&config);
+        assert_ne!(edited, before);
+
+        // So do filter definitions and macro namespaces
+        let mut filters = config.clone();
+        filters.template_filters.insert(
+            "wrap".to_string(),
+            FilterDef::Template("[{value}]".to_string()),
+        );
+        assert_ne!(key(&filters), edited);
+        let mut macros = config.clone();
+        macros
+            .template_macros
+            .insert("ui".to_string(), "macros/ui.html".to_string());
+        assert_ne!(key(&macros), edited);
+    }
+
+    #[test]
+    fn test_render_cache_keys_on_context_read() {
+        let dir = tempdir().unwrap();
+        let mut renderer = Tera::default();
+        renderer
+            .add_raw_templates(vec![
+                (
+                    "base.html",
+                    "{{ site.site_title }}{% block body %}{% endblock %}",
+                ),
+                (
+                    "page.html",
+                    r#"{% extends "base.html" %}{% block body %}{{ doc.title }}{% endblock %}"#,
+                ),
+                (
+                    "list.html",
+                    "{% for page in site.pages %}{{ page.title }}{% endfor %}",
+                ),
+                ("pages.html", "{{ pages() | length }}"),
+                ("all.html", "{% set s = site %}{{ s.site_title }}"),
+            ])
+            .unwrap();
+        let context = |title: &str, pages: &[&str]| {
+            let mut context = crate::tera::context();
+            let pages: Vec<json::Value> = pages
+                .iter()
+                .map(|title| json::json!({"title": title}))
+                .collect();
+            context.insert("site", &json::json!({"site_title": title, "pages": pages}));
+            context
+        };
+        let key = |context: &Context, template: &str| {
+            let cache = RenderCache::new(dir.path(), &renderer, context).unwrap();
+            cache
+                .path_for(&Doc::draft("a.md").set_template(template))
+                .unwrap()
+        };
+        let before = context("Site", &["A", "B"]);
+        let edited = context("Site", &["A", "C"]);
+        let renamed = context("Renamed", &["A", "B"]);
+
+        // Editing another doc only changes keys for templates that list pages
+        assert_eq!(key(&before, "page.html"), key(&edited, "page.html"));
+        assert_ne!(key(&before, "list.html"), key(&edited, "list.html"));
+        assert_ne!(key(&before, "pages.html"), key(&edited, "pages.html"));
+        // Fields read by a parent template are part of the key
+        assert_ne!(key(&before, "page.html"), key(&renamed, "page.html"));
+        assert_eq!(key(&before, "list.html"), key(&renamed, "list.html"));
+        // Reading `site` whole reads every field
+        assert_ne!(key(&before, "all.html"), key(&edited, "all.html"));
+    }
+
+    #[test]
+    fn test_context_path() {
+        assert_eq!(context_path("site.pages.0.title"), "site.pages");
+        assert_eq!(context_path("site"), "site");
+        assert_eq!(context_path("site[key].x"), "site");
+        assert_eq!(context_path("data.menu[0]"), "data.menu");
+    }
+}
@@ -0,0 +1,305 @@
+// Site report: an internal page summarizing content, for sites without
+// analytics
+use crate::doc::Doc;
+use crate::docs::Docs;
+use crate::error::Error;
+use crate::graph::{href_to_output_path, HREF};
+use crate::html::{escape_xml, strip_html};
+use crate::json::{self, json};
+use crate::stats::BuildReport;
+use crate::tags::TaggedDocs;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Format to render a site report in
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteReportFormat {
+    /// A standalone HTML page
+    #[default]
+    Html,
+    /// A single JSON object
+    Json,
+}
+
+impl SiteReportFormat {
+    /// Default output path for this format
+    pub fn output_path(&self) -> &'static str {
+        match self {
+            SiteReportFormat::Html => "_report/index.html",
+            SiteReportFormat::Json => "_report/report.json",
+        }
+    }
+}
+
+/// Word count for a doc
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DocWords {
+    pub id_path: PathBuf,
+    pub title: String,
+    pub words: usize,
+}
+
+/// An internal link to a page that isn't in the site
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BrokenLink {
+    /// id_path of the doc containing the link
+    pub source: PathBuf,
+    pub href: String,
+}
+
+/// A summary of a site's content
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SiteReport {
+    pub generated_at: DateTime<Utc>,
+    pub docs: usize,
+    pub words: usize,
+    /// Number of docs created each year
+    pub posts_per_year: BTreeMap<i32, usize>,
+    pub taxonomy: String,
+    /// Number of docs filed under each term of the taxonomy
+    pub terms: BTreeMap<String, usize>,
+    /// Word counts, from longest to shortest doc
+    pub word_counts: Vec<DocWords>,
+    pub broken_links: Vec<BrokenLink>,
+    /// Statistics recorded by this process so far
+    pub build: BuildReport,
+}
+
+/// Count the words in HTML or plain text content
+pub fn count_words(content: &str) -> usize {
+    strip_html(content).split_whitespace().count()
+}
+
+/// Does this site path look like a page, rather than an asset such as an
+/// image or stylesheet?
+fn is_page_path(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    name.ends_with(".html") || !name.contains('.')
+}
+
+/// Find links to site pages that no doc is written to.
+/// Only root-relative hrefs (`/a/`) and hrefs under `site_url` are checked,
+/// and links to assets are skipped, since assets aren't docs.
+fn find_broken_links(docs: &[Doc], site_url: &str) -> Vec<BrokenLink> {
+    let site_url = site_url.trim_end_matches('/');
+    let output_paths: HashSet<String> = docs
+        .iter()
+        .map(|doc| doc.output_path.to_string_lossy().into_owned())
+        .collect();
+    let mut broken = Vec::new();
+    for doc in docs {
+        for caps in HREF.captures_iter(&doc.content) {
+            let Some(href) = caps.get(1).or_else(|| caps.get(2)).map(|m| m.as_str()) else {
+                continue;
+            };
+            let is_internal = (href.starts_with('/') && !href.starts_with("//"))
+                || (!site_url.is_empty() && href.starts_with(site_url));
+            if !is_internal {
+                continue;
+            }
+            let Some(path) = href_to_output_path(href, site_url) else {
+                continue;
+            };
+            let resolves = output_paths.contains(&path)
+                || output_paths.contains(&format!("{}/index.html", path));
+            if is_page_path(&path) && !resolves {
+                broken.push(BrokenLink {
+                    source: doc.id_path.clone(),
+                    href: href.to_string(),
+                });
+            }
+        }
+    }
+    broken
+}
+
+impl SiteReport {
+    /// Summarize docs: posts per year, the number of docs filed under each
+    /// term of a taxonomy, word counts, and broken internal links.
+    /// Build statistics recorded so far are included.
+    pub fn build(docs: &[Doc], taxonomy_key: &str, site_url: &str) -> Self {
+        let mut posts_per_year = BTreeMap::new();
+        for doc in docs {
+            *posts_per_year.entry(doc.created.year()).or_default() += 1;
+        }
+        let terms = docs
+            .iter()
+            .cloned()
+            .index_by_tag(taxonomy_key)
+            .into_iter()
+            .map(|(term, docs)| (term, docs.len()))
+            .collect();
+        let mut word_counts: Vec<DocWords> = docs
+            .iter()
+            .map(|doc| DocWords {
+                id_path: doc.id_path.clone(),
+                title: doc.title.clone(),
+                words: count_words(&doc.content),
+            })
+            .collect();
+        word_counts.sort_by(|a, b| b.words.cmp(&a.words).then(a.id_path.cmp(&b.id_path)));
+        SiteReport {
+            generated_at: Utc::now(),
+            docs: docs.len(),
+            words: word_counts.iter().map(|count| count.words).sum(),
+            posts_per_year,
+            taxonomy: taxonomy_key.to_string(),
+            terms,
+            word_counts,
+            broken_links: find_broken_links(docs, site_url),
+            build: BuildReport::snapshot(),
+        }
+    }
+
+    /// Render report as a standalone HTML page
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"robots\" content=\"noindex\">\n<title>Site report</title>\n</head>\n<body>\n<h1>Site report</h1>\n",
+        );
+        html.push_str(&format!(
+            "<p>{} docs, {} words. Generated {}.</p>\n",
+            self.docs,
+            self.words,
+            self.generated_at.to_rfc3339()
+        ));
+
+        html.push_str("<h2>Posts per year</h2>\n<table>\n");
+        for (year, count) in &self.posts_per_year {
+            html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", year, count));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str(&format!(
+            "<h2>{}</h2>\n<table>\n",
+            escape_xml(&self.taxonomy)
+        ));
+        for (term, count) in &self.terms {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                escape_xml(term),
+                count
+            ));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Word counts</h2>\n<table>\n");
+        for count in &self.word_counts {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_xml(&count.title),
+                escape_xml(&count.id_path.to_string_lossy()),
+                count.words
+            ));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str(&format!(
+            "<h2>Broken links ({})</h2>\n<ul>\n",
+            self.broken_links.len()
+        ));
+        for link in &self.broken_links {
+            html.push_str(&format!(
+                "<li>{}: {}</li>\n",
+                escape_xml(&link.source.to_string_lossy()),
+                escape_xml(&link.href)
+            ));
+        }
+        html.push_str("</ul>\n");
+
+        html.push_str(&format!(
+            "<h2>Build</h2>\n<pre>{}</pre>\n</body>\n</html>\n",
+            escape_xml(&self.build.to_table())
+        ));
+        html
+    }
+
+    /// Render report in the given format
+    pub fn render(&self, format: SiteReportFormat) -> Result<String, Error> {
+        match format {
+            SiteReportFormat::Html => Ok(self.to_html()),
+            SiteReportFormat::Json => Ok(json::to_string_pretty(self)?),
+        }
+    }
+}
+
+pub trait ReportDocs: Docs {
+    /// Summarize the docs in this iterator. See `SiteReport::build`.
+    fn site_report(self, taxonomy_key: &str, site_url: &str) -> SiteReport {
+        let docs: Vec<Doc> = self.collect();
+        SiteReport::build(&docs, taxonomy_key, site_url)
+    }
+
+    /// Summarize docs and render the report into a single doc
+    fn generate_report_doc(
+        self,
+        taxonomy_key: &str,
+        site_url: &str,
+        format: SiteReportFormat,
+        output_path: &Path,
+    ) -> Result<Doc, Error> {
+        let report = self.site_report(taxonomy_key, site_url);
+        let content = report.render(format)?;
+        Ok(Doc::new(
+            output_path.into(),
+            output_path.into(),
+            None,
+            None,
+            report.generated_at,
+            report.generated_at,
+            "Site report".to_string(),
+            "".to_string(),
+            content,
+            json!({}),
+        ))
+    }
+}
+
+impl<I> ReportDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_site_report() {
+        let date = |year| Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_output_path("a/index.html")
+                .set_created(date(2023))
+                .set_meta(json!({"tags": ["rust"]}))
+                .set_content(r#"<p>See <a href="/b/">B</a>, <a href="/gone/">gone</a>, <a href="/style.css">css</a> and <a href="https://example.com/missing.html">missing</a>.</p>"#),
+            Doc::draft("b.md")
+                .set_output_path("b/index.html")
+                .set_created(date(2024))
+                .set_meta(json!({"tags": ["rust", "web"]}))
+                .set_content("One two"),
+        ];
+        let report = SiteReport::build(&docs, "tags", "https://example.com");
+        assert_eq!(report.docs, 2);
+        assert_eq!(
+            report.posts_per_year,
+            BTreeMap::from([(2023, 1), (2024, 1)])
+        );
+        assert_eq!(
+            report.terms,
+            BTreeMap::from([("rust".to_string(), 2), ("web".to_string(), 1)])
+        );
+        assert_eq!(report.word_counts[0].id_path, PathBuf::from("a.md"));
+        assert_eq!(report.word_counts[1].words, 2);
+        let hrefs: Vec<&str> = report
+            .broken_links
+            .iter()
+            .map(|link| link.href.as_str())
+            .collect();
+        assert_eq!(hrefs, vec!["/gone/", "https://example.com/missing.html"]);
+        let html = report.render(SiteReportFormat::Html).unwrap();
+        assert!(html.contains("<h2>Broken links (2)</h2>"));
+    }
+}
@@ -1,75 +1,483 @@
-use crate::tera::{Context, Tera};
+use crate::absolutize::to_url;
+use crate::config::Config;
+use crate::docs::{sorted_by, DocResults, SortKey};
+use crate::tags::TaggedDocs;
+use crate::text::to_slug;
+use crate::token_template;
+use crate::url::{permalink_url, TrailingSlash};
 use crate::{doc::Doc, docs::Docs, error::Error, json::json};
 use chrono::{DateTime, Utc};
-use std::path::Path;
-
-const RSS_TEMPLATE: &str = r#"
-<rss version="2.0">
-<channel>
-  <title>{{ doc.title | escape }}</title>
-  <link>{{ site_url }}</link>
-  <description>{{ description | escape }}</description>
-  <generator>{{ generator }}</generator>
-  <lastBuildDate>
-    {{ doc.modified | date(format="%a, %d %b %Y %H:%M:%S %Z") }}
-  </lastBuildDate>
-  {% for rdoc in recent %}
-  <item>
-    <title>{{ rdoc.title }}</title>
-    <link>{{site_url}}/{{ rdoc.output_path }}</link>
-    <guid>{{site_url}}/{{ rdoc.output_path }}</guid>
-    <description>{{ rdoc.content | escape }}</description>
-    <content:encoded><![CDATA[
-      {{ rdoc.content }}
-    ]]></content:encoded>
-    <pubDate>{{ doc.created | date(format="%a, %d %b %Y %H:%M:%S %Z") }}</pubDate>
-    {% if rdoc.meta.author %}
-      <author>{{ rdoc.meta.author | escape }}</author>
-    {% elsif author %}
-      <author>{{ author | escape }}</author>
-    {% endif %}
-  </item>
-  {% endfor %}
-</channel>
-</rss>
-"#;
+use quick_xml::events::{BytesCData, BytesDecl, BytesText, Event};
+use quick_xml::Writer;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const RSS_NAMESPACES: [(&str, &str); 3] = [
+    ("xmlns:content", "http://purl.org/rss/1.0/modules/content/"),
+    ("xmlns:dc", "http://purl.org/dc/elements/1.1/"),
+    ("xmlns:atom", "http://www.w3.org/2005/Atom"),
+];
+
+/// Format a date as an RFC 822 date, as required by RSS 2.0.
+/// Example: `Tue, 10 Jun 2003 04:00:00 +0000`.
+pub fn to_rfc822(date: &DateTime<Utc>) -> String {
+    date.to_rfc2822()
+}
+
+/// Is this character allowed in an XML 1.0 document?
+/// Control characters other than tab, newline, and carriage return are not,
+/// nor are the noncharacters U+FFFE and U+FFFF.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r') || (c >= ' ' && c != '\u{FFFE}' && c != '\u{FFFF}')
+}
+
+/// Remove characters that are not allowed in XML, so they can't make a
+/// feed invalid
+pub fn strip_invalid_xml_chars(text: &str) -> String {
+    text.chars().filter(|c| is_xml_char(*c)).collect()
+}
+
+/// Split text into the contents of adjacent `<![CDATA[...]]>` sections.
+/// A `]]>` in the text would end a section early, so it is split across
+/// two sections (`]]]]><![CDATA[>`), which readers join back together.
+/// Characters not allowed in XML are removed.
+pub fn cdata_sections(text: &str) -> Vec<String> {
+    let text = strip_invalid_xml_chars(text);
+    let parts: Vec<&str> = text.split("]]>").collect();
+    let last = parts.len() - 1;
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let open = if i > 0 { ">" } else { "" };
+            let close = if i < last { "]]" } else { "" };
+            format!("{open}{part}{close}")
+        })
+        .collect()
+}
+
+/// A feed item, with all fields pre-formatted for the RSS writer.
+/// The description is `meta.summary_html` if set, then the summary, then
+/// the content. Text is stripped of characters not allowed in XML, and
+/// content is split into CDATA sections.
+#[derive(Debug, Clone)]
+struct RssItem {
+    title: String,
+    link: String,
+    description: String,
+    content: Vec<String>,
+    pub_date: String,
+    author: Option<String>,
+}
+
+impl RssItem {
+    fn from_doc(doc: &Doc, site_url: &str, trailing_slash: TrailingSlash, author: &str) -> Self {
+        let summary_html = doc
+            .meta
+            .get("summary_html")
+            .and_then(|value| value.as_str());
+        let description = match summary_html {
+            Some(html) if !html.is_empty() => html.to_string(),
+            _ if doc.summary.is_empty() => doc.content.clone(),
+            _ => doc.summary.clone(),
+        };
+        let author = match doc.meta.get("author").and_then(|value| value.as_str()) {
+            Some(author) => Some(author.to_string()),
+            None if !author.is_empty() => Some(author.to_string()),
+            None => None,
+        };
+        RssItem {
+            title: strip_invalid_xml_chars(&doc.title),
+            link: permalink_url(&doc.output_path, site_url, trailing_slash),
+            description: strip_invalid_xml_chars(&description),
+            content: cdata_sections(&doc.content),
+            pub_date: to_rfc822(&doc.created),
+            author: author.map(|author| strip_invalid_xml_chars(&author)),
+        }
+    }
+}
+
+/// Write a text-only element, escaping its text
+fn write_text_element<W: Write>(writer: &mut Writer<W>, name: &str, text: &str) -> io::Result<()> {
+    writer
+        .create_element(name)
+        .write_text_content(BytesText::new(text))?;
+    Ok(())
+}
+
+/// Write one `<item>` of a feed
+fn write_rss_item<W: Write>(writer: &mut Writer<W>, item: &RssItem) -> io::Result<()> {
+    writer
+        .create_element("item")
+        .write_inner_content(|writer| {
+            write_text_element(writer, "title", &item.title)?;
+            write_text_element(writer, "link", &item.link)?;
+            writer
+                .create_element("guid")
+                .with_attribute(("isPermaLink", "true"))
+                .write_text_content(BytesText::new(&item.link))?;
+            write_text_element(writer, "description", &item.description)?;
+            writer
+                .create_element("content:encoded")
+                .write_inner_content(|writer| {
+                    for section in &item.content {
+                        writer.write_event(Event::CData(BytesCData::new(section.as_str())))?;
+                    }
+                    Ok(())
+                })?;
+            write_text_element(writer, "pubDate", &item.pub_date)?;
+            if let Some(author) = &item.author {
+                write_text_element(writer, "dc:creator", author)?;
+            }
+            Ok(())
+        })?;
+    Ok(())
+}
+
+/// Render an RSS 2.0 feed doc from pre-formatted items
+fn render_rss(
+    items: Vec<RssItem>,
+    site_url: &str,
+    title: &str,
+    description: &str,
+    output_path: &Path,
+    last_build_date: Option<DateTime<Utc>>,
+) -> Result<Doc, Error> {
+    let last_build_date = last_build_date.unwrap_or_else(Utc::now);
+    let feed_url = to_url(output_path, site_url);
+
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer
+        .create_element("rss")
+        .with_attribute(("version", "2.0"))
+        .with_attributes(RSS_NAMESPACES)
+        .write_inner_content(|writer| {
+            writer
+                .create_element("channel")
+                .write_inner_content(|writer| {
+                    write_text_element(writer, "title", title)?;
+                    write_text_element(writer, "link", site_url)?;
+                    writer
+                        .create_element("atom:link")
+                        .with_attribute(("href", feed_url.as_str()))
+                        .with_attribute(("rel", "self"))
+                        .with_attribute(("type", "application/rss+xml"))
+                        .write_empty()?;
+                    write_text_element(writer, "description", description)?;
+                    write_text_element(writer, "generator", "Lettersmith")?;
+                    write_text_element(writer, "lastBuildDate", &to_rfc822(&last_build_date))?;
+                    for item in &items {
+                        write_rss_item(writer, item)?;
+                    }
+                    Ok(())
+                })?;
+            Ok(())
+        })?;
+    let mut content = String::from_utf8(writer.into_inner())
+        .map_err(|err| Error::value(format!("Could not write RSS feed: {err}")))?;
+    content.push('\n');
+
+    Ok(Doc::new(
+        output_path.into(),
+        output_path.into(),
+        None,
+        None,
+        last_build_date,
+        last_build_date,
+        title.to_string(),
+        "".to_string(),
+        content,
+        json!({}),
+    ))
+}
 
 pub trait RssDocs: Docs {
+    /// Generate an RSS 2.0 feed doc from the 24 most recent docs.
+    /// Item links follow the `trailing_slash` policy.
+    /// Draft previews are left out.
+    #[allow(clippy::too_many_arguments)]
     fn rss(
         self,
         site_url: &str,
+        trailing_slash: TrailingSlash,
         title: &str,
         description: &str,
         author: &str,
         output_path: &Path,
         last_build_date: Option<DateTime<Utc>>,
     ) -> Result<Doc, Error> {
-        let last_build_date = last_build_date.unwrap_or_else(|| Utc::now());
-        let recent: Vec<Doc> = self.most_recent(24).collect();
-
-        let mut renderer = Tera::default();
-        let mut context = Context::new();
-        context.insert("site_url", site_url);
-        context.insert("recent", &recent);
-        context.insert("description", description);
-        context.insert("author", author);
-        context.insert("generator", "Lettersmith");
-
-        let rss_doc = Doc::new(
-            output_path.into(),
-            output_path.into(),
-            None,
-            None,
+        let items: Vec<RssItem> = self
+            .filter(|doc| !doc.is_preview())
+            .most_recent(24)
+            .map(|doc| RssItem::from_doc(&doc, site_url, trailing_slash, author))
+            .collect();
+        render_rss(
+            items,
+            site_url,
+            title,
+            description,
+            output_path,
             last_build_date,
+        )
+    }
+
+    /// Generate an RSS 2.0 feed doc with the site and feed settings in
+    /// config: the `feed_items` most recent docs, written to `feed_path`.
+    /// Draft previews are left out.
+    fn feed(self, config: &Config, last_build_date: Option<DateTime<Utc>>) -> Result<Doc, Error> {
+        let items: Vec<RssItem> = self
+            .filter(|doc| !doc.is_preview())
+            .most_recent(config.feed_items)
+            .map(|doc| {
+                RssItem::from_doc(
+                    &doc,
+                    &config.site_url,
+                    config.trailing_slash,
+                    &config.site_author,
+                )
+            })
+            .collect();
+        render_rss(
+            items,
+            &config.site_url,
+            &config.site_title,
+            &config.site_description,
+            &config.feed_path,
             last_build_date,
-            title.to_string(),
-            "".to_string(),
-            "".to_string(),
-            json!({}),
+        )
+    }
+
+    /// Like `feed`, but yields these docs followed by the feed doc, so one
+    /// pass produces both. Only the docs that appear in the feed are
+    /// copied.
+    fn with_feed(self, config: &Config, last_build_date: Option<DateTime<Utc>>) -> impl DocResults {
+        let docs: Vec<Doc> = self.collect();
+        let recent = sorted_by(
+            docs.iter().filter(|doc| !doc.is_preview()).collect(),
+            |a, b| SortKey::Created.compare(a, b),
+            false,
         );
+        let feed = recent
+            .into_iter()
+            .take(config.feed_items)
+            .cloned()
+            .feed(config, last_build_date);
+        docs.into_iter().map(Ok).chain(std::iter::once(feed))
+    }
 
-        rss_doc.render_tera_str(&mut renderer, RSS_TEMPLATE, &context)
+    /// Generate an RSS 2.0 feed doc for each term in a taxonomy, with the
+    /// site and feed settings in config. Each feed holds the `feed_items`
+    /// most recent docs filed under its term, and is titled
+    /// `{site_title}: {term}`. Feeds are returned in term order.
+    /// Draft previews are left out.
+    ///
+    /// Output paths are rendered from `path_template`, with the tokens:
+    /// - `taxonomy`: the sluggified taxonomy key
+    /// - `term`: the sluggified term
+    ///
+    /// Example: `tags/{term}/feed.xml`
+    fn rss_per_tag(
+        self,
+        taxonomy_key: &str,
+        path_template: &str,
+        config: &Config,
+        last_build_date: Option<DateTime<Utc>>,
+    ) -> impl DocResults {
+        let index: BTreeMap<String, Vec<Doc>> = self
+            .filter(|doc| !doc.is_preview())
+            .index_by_tag(taxonomy_key)
+            .into_iter()
+            .collect();
+        let taxonomy_slug = to_slug(taxonomy_key);
+        let path_template = path_template.to_string();
+        let config = config.clone();
+        index.into_iter().map(move |(term, docs)| {
+            let mut parts = HashMap::new();
+            parts.insert("taxonomy", taxonomy_slug.clone());
+            parts.insert("term", to_slug(&term));
+            let output_path: PathBuf = token_template::render(&path_template, &parts).into();
+            let title = format!("{}: {}", config.site_title, term);
+            let items: Vec<RssItem> = docs
+                .into_iter()
+                .most_recent(config.feed_items)
+                .map(|doc| {
+                    RssItem::from_doc(
+                        &doc,
+                        &config.site_url,
+                        config.trailing_slash,
+                        &config.site_author,
+                    )
+                })
+                .collect();
+            render_rss(
+                items,
+                &config.site_url,
+                &title,
+                &config.site_description,
+                &output_path,
+                last_build_date,
+            )
+        })
     }
 }
 
 impl<I> RssDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_to_rfc822() {
+        let date = Utc.with_ymd_and_hms(2003, 6, 10, 4, 0, 0).unwrap();
+        assert_eq!(to_rfc822(&date), "Tue, 10 Jun 2003 04:00:00 +0000");
+    }
+
+    #[test]
+    fn test_rss() {
+        let created = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let docs = vec![Doc::draft("posts/a.md")
+            .set_output_path("posts/a/index.html")
+            .set_title("Fish & Chips")
+            .set_content("<p>Tasty</p>")
+            .set_created(created)];
+
+        let feed = docs
+            .into_iter()
+            .rss(
+                "https://example.com/",
+                TrailingSlash::Index,
+                "My <Blog>",
+                "A blog",
+                "Jane",
+                Path::new("feed.xml"),
+                Some(created),
+            )
+            .unwrap();
+
+        assert!(feed.content.starts_with("<?xml"));
+        assert!(feed.content.contains("xmlns:content="));
+        assert!(feed.content.contains("<title>My &lt;Blog&gt;</title>"));
+        assert!(feed.content.contains("<title>Fish &amp; Chips</title>"));
+        assert!(feed
+            .content
+            .contains("<link>https://example.com/posts/a/index.html</link>"));
+        assert!(feed
+            .content
+            .contains("<pubDate>Tue, 2 Jan 2024 03:04:05 +0000</pubDate>"));
+        assert!(feed.content.contains("<dc:creator>Jane</dc:creator>"));
+    }
+
+    #[test]
+    fn test_rss_escapes_cdata() {
+        let docs = vec![Doc::draft("a.md")
+            .set_output_path("a/index.html")
+            .set_title("A\u{0}\u{1b}")
+            .set_content("<pre>x[[0]]>1\u{8}</pre>")];
+        let feed = docs
+            .into_iter()
+            .rss(
+                "https://example.com",
+                TrailingSlash::Always,
+                "Site",
+                "",
+                "",
+                "feed.xml".as_ref(),
+                None,
+            )
+            .unwrap();
+        assert!(feed.content.contains("<title>A</title>"));
+        assert!(feed.content.contains(
+            "<content:encoded><![CDATA[<pre>x[[0]]]]><![CDATA[>1</pre>]]></content:encoded>"
+        ));
+    }
+
+    #[test]
+    fn test_rss_escapes_attributes() {
+        let docs = vec![Doc::draft("a.md").set_output_path("a.html")];
+        let feed = docs
+            .into_iter()
+            .rss(
+                "https://example.com/?a=1&b=\"2\"",
+                TrailingSlash::Always,
+                "Site",
+                "",
+                "",
+                "feed.xml".as_ref(),
+                None,
+            )
+            .unwrap();
+        assert!(feed
+            .content
+            .contains("<link>https://example.com/?a=1&amp;b=&quot;2&quot;</link>"));
+        assert!(!feed.content.contains("b=\"2\""));
+    }
+
+    #[test]
+    fn test_rss_trailing_slash() {
+        let docs = vec![Doc::draft("posts/a.md").set_output_path("posts/a/index.html")];
+        let feed = docs
+            .into_iter()
+            .rss(
+                "https://example.com",
+                TrailingSlash::Always,
+                "Blog",
+                "",
+                "",
+                Path::new("feed.xml"),
+                None,
+            )
+            .unwrap();
+        assert!(feed
+            .content
+            .contains("<link>https://example.com/posts/a/</link>"));
+        assert!(feed
+            .content
+            .contains(r#"href="https://example.com/feed.xml""#));
+    }
+
+    #[test]
+    fn test_feed() {
+        let docs: Vec<Doc> = ["a.md", "b.md", "c.md"].iter().map(Doc::draft).collect();
+        let config = Config {
+            site_title: "Blog".to_string(),
+            feed_items: 2,
+            feed_path: PathBuf::from("posts/feed.xml"),
+            ..Config::default()
+        };
+        let feed = docs.into_iter().feed(&config, None).unwrap();
+        assert_eq!(feed.output_path, PathBuf::from("posts/feed.xml"));
+        assert_eq!(feed.content.matches("<item>").count(), 2);
+    }
+
+    #[test]
+    fn test_rss_per_tag() {
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_title("A")
+                .set_meta(json!({"tags": ["Rust", "web"]})),
+            Doc::draft("b.md")
+                .set_title("B")
+                .set_meta(json!({"tags": ["rust"]})),
+        ];
+        let config = Config {
+            site_title: "Blog".to_string(),
+            ..Config::default()
+        };
+        let feeds: Vec<Doc> = docs
+            .into_iter()
+            .rss_per_tag("tags", "{taxonomy}/{term}/feed.xml", &config, None)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(feeds.len(), 2);
+        assert_eq!(feeds[0].output_path, PathBuf::from("tags/rust/feed.xml"));
+        assert!(feeds[0].content.contains("<title>Blog: rust</title>"));
+        assert_eq!(feeds[0].content.matches("<item>").count(), 2);
+        assert_eq!(feeds[1].output_path, PathBuf::from("tags/web/feed.xml"));
+        assert_eq!(feeds[1].content.matches("<item>").count(), 1);
+    }
+}
@@ -0,0 +1,251 @@
+// Utilities for scaffolding new sites and content files
+use crate::error::Error;
+use crate::io::write_file_deep;
+use crate::text::to_slug;
+use crate::token_template;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+/// Default archetype used for new posts when no archetype template is given.
+pub const DEFAULT_ARCHETYPE: &str = "---
+title: {title_quoted}
+created: {created}
+draft: true
+---
+
+";
+
+const STARTER_CONFIG: &str = r#"{
+  "site_url": "http://localhost:8080",
+  "site_title": "My Site",
+  "site_description": "A site built with Lettersmith",
+  "site_author": "",
+  "templates": "templates/*.html"
+}
+"#;
+
+const STARTER_DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{ doc.title }} | {{ site.site_title }}</title>
+  <meta name="description" content="{{ doc.summary }}">
+</head>
+<body>
+  <main>
+    <h1>{{ doc.title }}</h1>
+    {{ doc.content | safe }}
+  </main>
+</body>
+</html>
+"#;
+
+const STARTER_POSTS_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{ doc.title }} | {{ site.site_title }}</title>
+  <meta name="description" content="{{ doc.summary }}">
+</head>
+<body>
+  <article>
+    <h1>{{ doc.title }}</h1>
+    <time datetime="{{ doc.created }}">{{ doc.created | date(format="%B %d, %Y") }}</time>
+    {{ doc.content | safe }}
+  </article>
+</body>
+</html>
+"#;
+
+const STARTER_POST: &str = r#"---
+title: Hello World
+created: 2024-01-01T00:00:00Z
+---
+
+This is an example post. Edit it, or create a new one with:
+
+```
+smith new "My Post" --dir posts
+```
+"#;
+
+const STARTER_BUILD_SCRIPT: &str = r#"#!/bin/sh
+# Build the site into public/.
+# Each smith command reads docs as JSON lines from stdin, transforms them,
+# and writes them to stdout for the next command in the pipeline.
+set -e
+
+smith read posts/*.md \
+  | smith frontmatter \
+  | smith markdown \
+  | smith blog \
+  | smith write public
+"#;
+
+/// Starter project files, as `(path, content)` pairs relative to the
+/// project directory.
+pub const STARTER_FILES: [(&str, &str); 5] = [
+    ("lettersmith.json", STARTER_CONFIG),
+    ("templates/default.html", STARTER_DEFAULT_TEMPLATE),
+    ("templates/posts.html", STARTER_POSTS_TEMPLATE),
+    ("posts/hello-world.md", STARTER_POST),
+    ("build.sh", STARTER_BUILD_SCRIPT),
+];
+
+/// Generate a starter project in `dir`: a config file, templates, an
+/// example post, and a build script demonstrating a pipeline.
+/// Files that already exist are left untouched.
+///
+/// Returns the paths of the files created.
+pub fn init_site(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut created = Vec::new();
+    for (file, content) in STARTER_FILES {
+        let path = dir.as_ref().join(file);
+        if path.exists() {
+            continue;
+        }
+        write_file_deep(&path, content)?;
+        created.push(path);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let build_script = dir.as_ref().join("build.sh");
+        if created.contains(&build_script) {
+            std::fs::set_permissions(&build_script, std::fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(created)
+}
+
+/// Render an archetype template for a new post.
+///
+/// Template variables available are:
+/// - `title`: the title of the post
+/// - `title_quoted`: the title as a double-quoted string, safe for use in
+///   YAML or TOML frontmatter
+/// - `slug`: the sluggified title
+/// - `created`: the creation date, in RFC 3339 format
+/// - `yyyy`, `mm`, `dd`: the year, month and day of the creation date
+pub fn render_archetype(archetype: &str, title: &str, created: DateTime<Utc>) -> String {
+    let mut parts: HashMap<&str, String> = HashMap::new();
+    parts.insert("title", title.to_string());
+    parts.insert(
+        "title_quoted",
+        serde_json::to_string(title).unwrap_or_else(|_| format!("\"{}\"", title)),
+    );
+    parts.insert("slug", to_slug(title));
+    parts.insert("created", created.to_rfc3339());
+    parts.insert("yyyy", created.format("%Y").to_string());
+    parts.insert("mm", created.format("%m").to_string());
+    parts.insert("dd", created.format("%d").to_string());
+    token_template::render(archetype, &parts)
+}
+
+/// Create a new post file in `dir`, named after the sluggified title.
+/// The file is rendered from the archetype template at `archetype_path`,
+/// or from `DEFAULT_ARCHETYPE` if none is given.
+/// New posts get the archetype's file extension, or `.md` by default.
+///
+/// Returns the path of the file created.
+/// Returns an error rather than overwriting an existing file.
+pub fn new_post(
+    title: &str,
+    dir: impl AsRef<Path>,
+    archetype_path: Option<&Path>,
+) -> Result<PathBuf, Error> {
+    let (archetype, extension) = match archetype_path {
+        Some(path) => {
+            let archetype = read_to_string(path)?;
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "md".to_string());
+            (archetype, extension)
+        }
+        None => (DEFAULT_ARCHETYPE.to_string(), "md".to_string()),
+    };
+    let slug = to_slug(title);
+    if slug.is_empty() {
+        return Err(Error::value(format!(
+            "Could not create file name from title \"{}\"",
+            title
+        )));
+    }
+    let path = dir.as_ref().join(format!("{}.{}", slug, extension));
+    if path.exists() {
+        return Err(Error::value(format!(
+            "File already exists: {}",
+            path.to_string_lossy()
+        )));
+    }
+    let content = render_archetype(&archetype, title, Utc::now());
+    write_file_deep(&path, &content)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc::Doc;
+    use chrono::TimeZone;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render_archetype_default() {
+        let created = Utc.with_ymd_and_hms(2024, 3, 4, 0, 0, 0).unwrap();
+        let content = render_archetype(DEFAULT_ARCHETYPE, "Hello: \"World\"", created);
+        let doc = Doc::draft("test.md")
+            .set_content(content)
+            .parse_and_uplift_frontmatter();
+        assert_eq!(doc.title, "Hello: \"World\"");
+        assert_eq!(doc.created, created);
+        assert_eq!(doc.meta.get("draft").unwrap(), true);
+    }
+
+    #[test]
+    fn test_init_site() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("lettersmith.json"), "{}").unwrap();
+
+        let created = init_site(dir.path()).unwrap();
+
+        assert_eq!(created.len(), STARTER_FILES.len() - 1);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lettersmith.json")).unwrap(),
+            "{}"
+        );
+        assert!(dir.path().join("templates/posts.html").exists());
+    }
+
+    #[test]
+    fn test_starter_files_parse() {
+        let config: crate::config::Config = serde_json::from_str(STARTER_CONFIG).unwrap();
+        assert_eq!(config.site_title, "My Site");
+        let doc = Doc::draft("posts/hello-world.md")
+            .set_content(STARTER_POST)
+            .parse_and_uplift_frontmatter();
+        assert_eq!(doc.title, "Hello World");
+    }
+
+    #[test]
+    fn test_new_post() {
+        let dir = tempdir().unwrap();
+        let archetype_path = dir.path().join("post.markdown");
+        fs::write(&archetype_path, "# {title}\n{yyyy}").unwrap();
+
+        let path = new_post("My Post", dir.path().join("posts"), Some(&archetype_path)).unwrap();
+        assert_eq!(path, dir.path().join("posts/my-post.markdown"));
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# My Post\n"));
+
+        let result = new_post("My Post", dir.path().join("posts"), Some(&archetype_path));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// Validate doc meta against JSON Schemas
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::json;
+use jsonschema::JSONSchema;
+use std::collections::HashMap;
+
+/// A single schema violation found in a doc's meta
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaViolation {
+    /// JSON pointer to the offending value within meta, e.g. `/tags/0`
+    pub path: String,
+    pub message: String,
+}
+
+/// JSON Schemas for meta, compiled and keyed by the glob pattern of the
+/// id paths they apply to.
+pub struct MetaSchemas {
+    schemas: Vec<(String, glob::Pattern, JSONSchema)>,
+}
+
+impl MetaSchemas {
+    /// Compile schemas from a map of id path glob patterns to JSON Schemas.
+    pub fn compile(schemas: &HashMap<String, json::Value>) -> Result<Self, Error> {
+        let mut compiled = Vec::new();
+        for (pattern, schema) in schemas {
+            let matcher = glob::Pattern::new(pattern)?;
+            let schema = JSONSchema::compile(schema).map_err(|err| {
+                Error::value(format!("Invalid schema for \"{}\": {}", pattern, err))
+            })?;
+            compiled.push((pattern.clone(), matcher, schema));
+        }
+        // Sort for stable reporting order
+        compiled.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(MetaSchemas { schemas: compiled })
+    }
+}
+
+impl Doc {
+    /// Check meta against every schema whose glob matches this doc's id path.
+    /// Returns the violations found, if any.
+    pub fn get_meta_violations(&self, schemas: &MetaSchemas) -> Vec<MetaViolation> {
+        let id_path = self.id_path.to_string_lossy();
+        let mut violations = Vec::new();
+        for (_, matcher, schema) in &schemas.schemas {
+            if !matcher.matches(&id_path) {
+                continue;
+            }
+            if let Err(errors) = schema.validate(&self.meta) {
+                for error in errors {
+                    violations.push(MetaViolation {
+                        path: error.instance_path.to_string(),
+                        message: error.to_string(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Validate meta against matching schemas.
+    /// Returns an error listing every violation if meta is invalid.
+    pub fn validate_meta(self, schemas: &MetaSchemas) -> Result<Self, Error> {
+        let violations = self.get_meta_violations(schemas);
+        if violations.is_empty() {
+            return Ok(self);
+        }
+        let details: Vec<String> = violations
+            .iter()
+            .map(|violation| format!("  meta{}: {}", violation.path, violation.message))
+            .collect();
+        Err(Error::value(format!("Invalid meta:\n{}", details.join("\n"))).with_doc(&self.id_path))
+    }
+}
+
+pub trait SchemaDocs: Docs {
+    /// Validate each doc's meta against the schemas matching its id path.
+    /// Docs with invalid meta become errors describing each violation.
+    fn validate_meta(self, schemas: &MetaSchemas) -> impl DocResults {
+        self.map(move |doc| doc.validate_meta(schemas))
+    }
+}
+
+impl<I> SchemaDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::json;
+
+    fn make_schemas() -> MetaSchemas {
+        MetaSchemas::compile(&HashMap::from([(
+            "posts/*".to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "tags": {"type": "array", "items": {"type": "string"}}
+                },
+                "required": ["tags"]
+            }),
+        )]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_meta() {
+        let schemas = make_schemas();
+        let results: Vec<Result<Doc, Error>> = vec![
+            Doc::draft("posts/a.md").set_meta(json!({"tags": ["a"]})),
+            Doc::draft("posts/b.md").set_meta(json!({"tag": ["a"]})),
+            Doc::draft("pages/c.md").set_meta(json!({"tag": ["a"]})),
+        ]
+        .into_iter()
+        .validate_meta(&schemas)
+        .collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn test_get_meta_violations_path() {
+        let schemas = make_schemas();
+        let doc = Doc::draft("posts/a.md").set_meta(json!({"tags": ["a", 1]}));
+        let violations = doc.get_meta_violations(&schemas);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/tags/1");
+    }
+
+    #[test]
+    fn test_compile_invalid_schema() {
+        let schemas = HashMap::from([("*".to_string(), json!({"type": 12}))]);
+        assert!(MetaSchemas::compile(&schemas).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// Run user scripts against docs, using Rhai
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use crate::json::json;
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::fs;
+use std::path::Path;
+
+/// A compiled Rhai script for transforming docs.
+///
+/// The script is run once per doc, with the doc exposed as a map in the
+/// `doc` variable. The value the script evaluates to replaces the doc.
+/// If it evaluates to nothing (`()`), the `doc` variable is used, so
+/// scripts can modify `doc` in place:
+///
+/// ```rhai
+/// doc.title = doc.title.to_upper();
+/// doc.meta.word_count = doc.content.split(" ").len();
+/// ```
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    /// Compile a script from source
+    pub fn compile(source: &str) -> Result<Self, Error> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile(source)
+            .map_err(|err| Error::value(format!("Could not compile script: {}", err)))?;
+        Ok(Script { engine, ast })
+    }
+
+    /// Read and compile a script file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        Self::compile(&source)
+            .map_err(|err| Error::value(format!("{}: {}", path.display(), err.msg)))
+    }
+
+    /// Run the script against a doc, returning the transformed doc.
+    /// Docs without object meta are given an empty object first, so
+    /// scripts can assign meta fields.
+    pub fn transform(&self, mut doc: Doc) -> Result<Doc, Error> {
+        if !doc.meta.is_object() {
+            doc.meta = json!({});
+        }
+        let id_path = doc.id_path.clone();
+        let fail = |msg: String| Error::value(msg).with_doc(&id_path);
+        let value = rhai::serde::to_dynamic(&doc)
+            .map_err(|err| fail(format!("Could not convert doc for script: {}", err)))?;
+        let mut scope = Scope::new();
+        scope.push("doc", value);
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| fail(format!("Script error: {}", err)))?;
+        let result = if result.is_unit() {
+            scope.get_value::<Dynamic>("doc").unwrap_or_default()
+        } else {
+            result
+        };
+        rhai::serde::from_dynamic(&result)
+            .map_err(|err| fail(format!("Script did not return a valid doc: {}", err)))
+    }
+}
+
+/// Scripts can be used as Tera filters. The script runs with the filtered
+/// value in `value` and filter arguments in `args`, and evaluates to the
+/// filtered value.
+impl ::tera::Filter for Script {
+    fn filter(
+        &self,
+        value: &::tera::Value,
+        args: &std::collections::HashMap<String, ::tera::Value>,
+    ) -> ::tera::Result<::tera::Value> {
+        let mut scope = Scope::new();
+        scope.push(
+            "value",
+            rhai::serde::to_dynamic(value).map_err(|err| ::tera::Error::msg(err.to_string()))?,
+        );
+        scope.push(
+            "args",
+            rhai::serde::to_dynamic(args).map_err(|err| ::tera::Error::msg(err.to_string()))?,
+        );
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|err| ::tera::Error::msg(format!("Script error: {}", err)))?;
+        rhai::serde::from_dynamic(&result).map_err(|err| ::tera::Error::msg(err.to_string()))
+    }
+}
+
+impl Doc {
+    /// Transform this doc with a script. See `Script`.
+    pub fn run_script(self, script: &Script) -> Result<Self, Error> {
+        script.transform(self)
+    }
+}
+
+pub trait ScriptDocs: Docs {
+    /// Transform docs with a script. See `Script`.
+    fn run_script(self, script: &Script) -> impl DocResults {
+        self.map(move |doc| doc.run_script(script))
+    }
+}
+
+impl<I> ScriptDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_script_in_place() {
+        let script = Script::compile(
+            r#"
+            doc.title = doc.title.to_upper();
+            doc.meta.draft = true;
+            "#,
+        )
+        .unwrap();
+        let doc = Doc::draft("a.md")
+            .set_title("Hello")
+            .set_meta(json!({"tags": ["a"]}))
+            .run_script(&script)
+            .unwrap();
+        assert_eq!(doc.title, "HELLO");
+        assert_eq!(doc.meta, json!({"tags": ["a"], "draft": true}));
+    }
+
+    #[test]
+    fn test_run_script_returns_doc() {
+        let script = Script::compile(r#"let d = doc; d.content = "Replaced"; d"#).unwrap();
+        let doc = Doc::draft("a.md").run_script(&script).unwrap();
+        assert_eq!(doc.content, "Replaced");
+    }
+
+    #[test]
+    fn test_run_script_errors() {
+        let script = Script::compile("42").unwrap();
+        assert!(Doc::draft("a.md").run_script(&script).is_err());
+        assert!(Script::compile("let = ;").is_err());
+    }
+
+    #[test]
+    fn test_script_filter() {
+        use ::tera::Filter;
+        let script = Script::compile(r#"value.len() + args.offset"#).unwrap();
+        let args = std::collections::HashMap::from([("offset".to_string(), json!(1))]);
+        assert_eq!(script.filter(&json!("abc"), &args).unwrap(), json!(4));
+    }
+}
@@ -1,27 +1,106 @@
 use crate::doc::Doc;
-use crate::docs::Docs;
+use crate::docs::{DocResults, Docs};
 use crate::error::Error;
-use crate::json::json;
-use crate::tera::{Context, Tera};
-use chrono::Utc;
+use crate::json::{self, json};
+use crate::stub::Stub;
+use crate::tera::{self, Context, Tera};
+use crate::url::TrailingSlash;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use std::path::PathBuf;
 
 const SITEMAP_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
 <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
-  {% for doc in data.sitemap_items %}
+  {% for item in sitemap_items %}
   <url>
-    <loc>{{ doc.output_path | to_url(base_url) }}</loc>
-    <lastmod>{{ doc.modified | date }}</lastmod>
+    <loc>{{ item.output_path | permalink_url(base_url=base_url, trailing_slash=trailing_slash) | escape_xml }}</loc>
+    <lastmod>{{ item.modified | date }}</lastmod>
+    {% if item.changefreq %}<changefreq>{{ item.changefreq }}</changefreq>{% endif %}
+    {% if item.priority %}<priority>{{ item.priority }}</priority>{% endif %}
   </url>
   {% endfor %}
 </urlset>"#;
 
+/// Valid values for `<changefreq>`, per the sitemap protocol.
+const CHANGEFREQS: [&str; 7] = [
+    "always", "hourly", "daily", "weekly", "monthly", "yearly", "never",
+];
+
+/// A sitemap entry, with options read from the doc's `sitemap` meta.
+#[derive(Serialize, Debug, Clone)]
+struct SitemapItem {
+    output_path: PathBuf,
+    modified: DateTime<Utc>,
+    changefreq: Option<String>,
+    priority: Option<String>,
+}
+
+impl Doc {
+    /// Check `sitemap.exclude` meta to see if doc should be left out of
+    /// the sitemap. Draft previews are always left out.
+    pub fn is_sitemap_excluded(&self) -> bool {
+        self.is_preview()
+            || json::get_deep(&self.meta, "sitemap.exclude")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false)
+    }
+
+    /// Read `sitemap.changefreq` meta.
+    /// Returns `None` if missing, or if not a valid changefreq value.
+    pub fn get_sitemap_changefreq(&self) -> Option<String> {
+        let changefreq = json::get_deep(&self.meta, "sitemap.changefreq")?;
+        let changefreq = changefreq.as_str()?.trim().to_lowercase();
+        if CHANGEFREQS.contains(&changefreq.as_str()) {
+            Some(changefreq)
+        } else {
+            None
+        }
+    }
+
+    /// Read `sitemap.priority` meta.
+    /// Returns `None` if missing, or if not a number between 0.0 and 1.0.
+    pub fn get_sitemap_priority(&self) -> Option<f64> {
+        let priority = json::get_deep(&self.meta, "sitemap.priority")?.as_f64()?;
+        if (0.0..=1.0).contains(&priority) {
+            Some(priority)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&Doc> for SitemapItem {
+    fn from(doc: &Doc) -> Self {
+        SitemapItem {
+            output_path: doc.output_path.clone(),
+            modified: doc.modified,
+            changefreq: doc.get_sitemap_changefreq(),
+            priority: doc
+                .get_sitemap_priority()
+                .map(|priority| format!("{:.1}", priority)),
+        }
+    }
+}
+
 pub trait SitemapDocs: Docs {
-    /// Generate a sitemap doc given an iterator of docs
-    fn sitemap(self, base_url: &str) -> Result<Doc, Error> {
+    /// Generate a sitemap doc given an iterator of docs.
+    ///
+    /// Docs may set the following meta fields:
+    /// - `sitemap.exclude`: `true` to leave the doc out of the sitemap.
+    ///   Draft previews are always left out.
+    /// - `sitemap.changefreq`: one of `always`, `hourly`, `daily`, `weekly`,
+    ///   `monthly`, `yearly`, `never`
+    /// - `sitemap.priority`: a number between `0.0` and `1.0`
+    ///
+    /// URLs follow the `trailing_slash` policy.
+    fn sitemap(self, base_url: &str, trailing_slash: TrailingSlash) -> Result<Doc, Error> {
         // The sitemap spec limits each sitemap to 50k entries.
         // https://www.sitemaps.org/protocol.html
-        let stubs_50k: Vec<Doc> = self.take(50000).collect();
+        let items_50k: Vec<SitemapItem> = self
+            .filter(|doc| !doc.is_sitemap_excluded())
+            .take(50000)
+            .map(|doc| SitemapItem::from(&doc))
+            .collect();
         let output_path = "sitemap.xml".to_string();
         let now = Utc::now();
 
@@ -38,12 +117,76 @@ pub trait SitemapDocs: Docs {
             meta: json!({}),
         };
 
-        let mut renderer = Tera::default();
+        let mut renderer = tera::decorate_renderer(Tera::default());
         let mut context = Context::new();
         context.insert("base_url", base_url);
-        context.insert("sitemap_items", &stubs_50k);
+        context.insert("trailing_slash", &trailing_slash);
+        context.insert("sitemap_items", &items_50k);
         sitemap.render_tera_str(&mut renderer, SITEMAP_TEMPLATE, &context)
     }
+
+    /// Like `sitemap`, but yields these docs followed by the sitemap doc,
+    /// so one pass produces both. The sitemap is built from stubs, so
+    /// content isn't copied.
+    fn with_sitemap(self, base_url: &str, trailing_slash: TrailingSlash) -> impl DocResults {
+        let docs: Vec<Doc> = self.collect();
+        let sitemap = docs
+            .iter()
+            .map(|doc| Doc::from(Stub::from(doc)))
+            .sitemap(base_url, trailing_slash);
+        docs.into_iter().map(Ok).chain(std::iter::once(sitemap))
+    }
 }
 
 impl<I> SitemapDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap() {
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_output_path("a/index.html")
+                .set_meta(json!({"sitemap": {"priority": 0.8, "changefreq": "Weekly"}})),
+            Doc::draft("b.md")
+                .set_output_path("b/index.html")
+                .set_meta(json!({"sitemap": {"exclude": true}})),
+            Doc::draft("c.md")
+                .set_output_path("c/index.html")
+                .set_meta(json!({"sitemap": {"priority": 4, "changefreq": "sometimes"}})),
+        ];
+
+        let sitemap = docs
+            .into_iter()
+            .sitemap("https://example.com", TrailingSlash::Index)
+            .unwrap();
+
+        assert!(sitemap
+            .content
+            .contains("<loc>https://example.com/a/index.html</loc>"));
+        assert!(sitemap.content.contains("<changefreq>weekly</changefreq>"));
+        assert!(sitemap.content.contains("<priority>0.8</priority>"));
+        assert!(!sitemap.content.contains("b/index.html"));
+        assert!(sitemap
+            .content
+            .contains("<loc>https://example.com/c/index.html</loc>"));
+        assert_eq!(sitemap.content.matches("<priority>").count(), 1);
+        assert_eq!(sitemap.content.matches("<changefreq>").count(), 1);
+    }
+
+    #[test]
+    fn test_sitemap_trailing_slash() {
+        let docs = vec![
+            Doc::draft("index.md").set_output_path("index.html"),
+            Doc::draft("a.md").set_output_path("a/index.html"),
+        ];
+        let sitemap = docs
+            .into_iter()
+            .sitemap("https://example.com", TrailingSlash::Never)
+            .unwrap();
+        assert!(sitemap.content.contains("<loc>https://example.com/</loc>"));
+        assert!(sitemap.content.contains("<loc>https://example.com/a</loc>"));
+    }
+}
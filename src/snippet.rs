@@ -0,0 +1,265 @@
+// Inline lines from source files into fenced code blocks
+use crate::doc::Doc;
+use crate::docs::{DocResults, Docs};
+use crate::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A fenced code block opening line, such as ```` ```rust file=src/main.rs ````
+pub(crate) struct Fence<'a> {
+    marker: char,
+    len: usize,
+    info: &'a str,
+}
+
+pub(crate) fn parse_fence(line: &str) -> Option<Fence<'_>> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let marker = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.len() - rest.trim_start_matches(marker).len();
+    if len < 3 {
+        return None;
+    }
+    Some(Fence {
+        marker,
+        len,
+        info: rest[len..].trim(),
+    })
+}
+
+pub(crate) fn is_closing_fence(line: &str, fence: &Fence) -> bool {
+    match parse_fence(line) {
+        Some(closing) => {
+            closing.marker == fence.marker && closing.len >= fence.len && closing.info.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// A snippet directive parsed from a fence info string,
+/// e.g. `rust file=src/main.rs lines=10-30`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetDirective {
+    pub lang: String,
+    pub file: PathBuf,
+    /// 1-based, inclusive line range. `None` means to the start or end.
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+}
+
+impl SnippetDirective {
+    /// Parse a directive from a fence info string.
+    /// Returns `Ok(None)` if the info string has no `file=` attribute.
+    pub fn parse(info: &str) -> Result<Option<Self>, Error> {
+        let mut lang = String::new();
+        let mut file = None;
+        let mut start = None;
+        let mut end = None;
+        for (i, token) in info.split_whitespace().enumerate() {
+            match token.split_once('=') {
+                Some(("file", value)) => file = Some(PathBuf::from(value)),
+                Some(("lines", value)) => {
+                    let invalid = || Error::value(format!("Invalid lines range \"{}\"", value));
+                    let parse_line = |s: &str| -> Result<Option<usize>, Error> {
+                        if s.is_empty() {
+                            return Ok(None);
+                        }
+                        s.parse::<usize>()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .map(Some)
+                            .ok_or_else(invalid)
+                    };
+                    match value.split_once('-') {
+                        Some((from, to)) => {
+                            start = parse_line(from)?;
+                            end = parse_line(to)?;
+                        }
+                        None => {
+                            start = parse_line(value)?;
+                            end = start;
+                        }
+                    }
+                }
+                Some(_) => {}
+                None if i == 0 => lang = token.to_string(),
+                None => {}
+            }
+        }
+        Ok(file.map(|file| SnippetDirective {
+            lang,
+            file,
+            start,
+            end,
+        }))
+    }
+}
+
+/// Resolve a snippet path relative to the project root, making sure it
+/// can't escape the root through absolute paths, `..`, or symlinks.
+pub fn resolve_snippet_path(root: &Path, file: &Path) -> Result<PathBuf, Error> {
+    let escapes = file
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(Error::value(format!(
+            "Snippet path {} must be relative to the project root, without ..",
+            file.display()
+        )));
+    }
+    let root = root.canonicalize()?;
+    let path = root.join(file).canonicalize()?;
+    if !path.starts_with(&root) {
+        return Err(Error::value(format!(
+            "Snippet path {} resolves outside the project root",
+            file.display()
+        )));
+    }
+    Ok(path)
+}
+
+/// Read the lines of a snippet file selected by a directive
+fn read_snippet(root: &Path, directive: &SnippetDirective) -> Result<String, Error> {
+    let path = resolve_snippet_path(root, &directive.file)?;
+    let text = fs::read_to_string(&path)?;
+    if directive.start.is_none() && directive.end.is_none() {
+        return Ok(text.trim_end_matches('\n').to_string());
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let start = directive.start.unwrap_or(1);
+    let end = directive.end.unwrap_or(lines.len()).min(lines.len());
+    if start > end {
+        return Err(Error::value(format!(
+            "Lines {}-{} are out of range for {} ({} lines)",
+            start,
+            end,
+            directive.file.display(),
+            lines.len()
+        )));
+    }
+    Ok(lines[start - 1..end].join("\n"))
+}
+
+/// Replace the bodies of fenced code blocks that have a `file=` attribute
+/// with lines read from that file. Paths are relative to `root`.
+///
+/// ````markdown
+/// ```rust file=src/main.rs lines=10-30
+/// ```
+/// ````
+///
+/// `lines` is 1-based and inclusive, and may be a single line (`lines=5`)
+/// or open-ended (`lines=10-`). Omit it to include the whole file.
+pub fn include_snippets(markdown: &str, root: &Path) -> Result<String, Error> {
+    let mut out = String::with_capacity(markdown.len());
+    let mut lines = markdown.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let Some(fence) = parse_fence(line) else {
+            out.push_str(line);
+            continue;
+        };
+        match SnippetDirective::parse(fence.info)? {
+            Some(directive) => {
+                // Skip the original body and closing fence
+                for line in lines.by_ref() {
+                    if is_closing_fence(line, &fence) {
+                        break;
+                    }
+                }
+                let snippet = read_snippet(root, &directive)?;
+                // Lengthen the fence if the snippet contains one
+                let longest = snippet
+                    .lines()
+                    .filter_map(parse_fence)
+                    .filter(|inner| inner.marker == fence.marker)
+                    .map(|inner| inner.len + 1)
+                    .max()
+                    .unwrap_or(0);
+                let marker = fence.marker.to_string().repeat(fence.len.max(longest));
+                out.push_str(&format!(
+                    "{}{}\n{}\n{}\n",
+                    marker, directive.lang, snippet, marker
+                ));
+            }
+            None => {
+                // Copy ordinary code blocks through untouched
+                out.push_str(line);
+                for line in lines.by_ref() {
+                    out.push_str(line);
+                    if is_closing_fence(line, &fence) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+impl Doc {
+    /// Inline file snippets into fenced code blocks in content.
+    /// See `include_snippets`.
+    pub fn include_snippets(self, root: &Path) -> Result<Self, Error> {
+        let content =
+            include_snippets(&self.content, root).map_err(|err| err.with_doc(&self.id_path))?;
+        Ok(self.set_content(content))
+    }
+}
+
+pub trait SnippetDocs: Docs {
+    /// Inline file snippets into fenced code blocks in each doc's content.
+    /// Paths are relative to `root`.
+    fn include_snippets(self, root: &Path) -> impl DocResults {
+        self.map(move |doc| doc.include_snippets(root))
+    }
+}
+
+impl<I> SnippetDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_include_snippets() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "one\ntwo\nthree\nfour\n").unwrap();
+        let markdown =
+            "Intro\n\n```rust file=src/main.rs lines=2-3\nstale\n```\n\n```\nplain\n```\n";
+        assert_eq!(
+            include_snippets(markdown, dir.path()).unwrap(),
+            "Intro\n\n```rust\ntwo\nthree\n```\n\n```\nplain\n```\n"
+        );
+        let markdown = "~~~ file=src/main.rs lines=4-\n~~~\n";
+        assert_eq!(
+            include_snippets(markdown, dir.path()).unwrap(),
+            "~~~\nfour\n~~~\n"
+        );
+    }
+
+    #[test]
+    fn test_include_snippets_rejects_escapes() {
+        let dir = tempdir().unwrap();
+        let markdown = "```text file=../secret.txt\n```\n";
+        assert!(include_snippets(markdown, dir.path()).is_err());
+        let markdown = "```text file=/etc/passwd\n```\n";
+        assert!(include_snippets(markdown, dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_parse_directive() {
+        let directive = SnippetDirective::parse("rust file=a.rs lines=7")
+            .unwrap()
+            .unwrap();
+        assert_eq!(directive.lang, "rust");
+        assert_eq!(directive.start, Some(7));
+        assert_eq!(directive.end, Some(7));
+        assert_eq!(SnippetDirective::parse("rust").unwrap(), None);
+        assert!(SnippetDirective::parse("rust file=a.rs lines=x").is_err());
+    }
+}
@@ -0,0 +1,482 @@
+// Spill doc content to temp files, to bound memory use in steps that
+// collect docs, such as sorting
+use crate::doc::Doc;
+use crate::docs::{sorted_by, DocResults, Docs, SortKey};
+use crate::error::Error;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use tempfile::{NamedTempFile, TempPath};
+
+/// Default number of docs sorted in memory at once by `sorted_by_external`
+pub const EXTERNAL_SORT_CHUNK_SIZE: usize = 1000;
+
+/// Doc content, either held in memory or spilled to a temp file.
+/// Spilled content is deleted from disk when dropped. Spilled files are
+/// closed until content is loaded, so spilling many docs doesn't use up
+/// file descriptors.
+#[derive(Debug)]
+pub enum Content {
+    Inline(String),
+    OnDisk(TempPath),
+}
+
+impl Content {
+    /// Spill content to a temp file in `dir` if it is larger than
+    /// `threshold` bytes. Smaller content is kept inline.
+    pub fn spill(content: String, threshold: usize, dir: &Path) -> Result<Self, Error> {
+        if content.len() <= threshold {
+            return Ok(Content::Inline(content));
+        }
+        let mut file = NamedTempFile::new_in(dir)?;
+        file.write_all(content.as_bytes())?;
+        Ok(Content::OnDisk(file.into_temp_path()))
+    }
+
+    /// Is the content spilled to disk?
+    pub fn is_on_disk(&self) -> bool {
+        matches!(self, Content::OnDisk(_))
+    }
+
+    /// Get the content, reading it back from disk if it was spilled
+    pub fn load(self) -> Result<String, Error> {
+        match self {
+            Content::Inline(content) => Ok(content),
+            Content::OnDisk(path) => Ok(fs::read_to_string(&path)?),
+        }
+    }
+}
+
+/// A doc with its content split out, so that large content can be held on
+/// disk. The `doc` has empty content until it is loaded.
+#[derive(Debug)]
+pub struct SpilledDoc {
+    pub doc: Doc,
+    pub content: Content,
+}
+
+impl SpilledDoc {
+    /// Load content back into the doc
+    pub fn load(self) -> Result<Doc, Error> {
+        let id_path = self.doc.id_path.clone();
+        let content = self.content.load().map_err(|err| err.with_doc(&id_path))?;
+        Ok(self.doc.set_content(content))
+    }
+}
+
+impl Doc {
+    /// Split content out of the doc, spilling it to a temp file in `dir` if
+    /// it is larger than `threshold` bytes. See `Content::spill`.
+    pub fn spill_content(mut self, threshold: usize, dir: &Path) -> Result<SpilledDoc, Error> {
+        let content = std::mem::take(&mut self.content);
+        let content =
+            Content::spill(content, threshold, dir).map_err(|err| err.with_doc(&self.id_path))?;
+        Ok(SpilledDoc { doc: self, content })
+    }
+}
+
+/// Maximum number of sorted runs merged at once by `sorted_by_external`.
+/// More runs are merged in passes, so the sort never holds more than this
+/// many files open.
+pub const EXTERNAL_SORT_FAN_IN: usize = 64;
+
+/// Write sorted docs to a temp file in `dir`, as line-separated JSON.
+/// The file is closed once written.
+fn write_sorted_run(docs: impl IntoIterator<Item = Doc>, dir: &Path) -> Result<TempPath, Error> {
+    let mut file = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(&mut file);
+    for doc in docs {
+        serde_json::to_writer(&mut writer, &doc)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    drop(writer);
+    Ok(file.into_temp_path())
+}
+
+/// The next doc of a sorted run, ordered for a min-heap
+struct RunHead {
+    doc: Doc,
+    run: usize,
+    key: SortKey,
+    asc: bool,
+}
+
+impl RunHead {
+    fn compare(&self, other: &Self) -> Ordering {
+        let ord = self.key.compare(&self.doc, &other.doc);
+        let ord = if self.asc { ord } else { ord.reverse() };
+        // Earlier runs hold earlier docs, so ties go to them, keeping the
+        // sort stable
+        ord.then_with(|| self.run.cmp(&other.run))
+    }
+}
+
+impl PartialEq for RunHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RunHead {}
+
+impl PartialOrd for RunHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunHead {
+    // BinaryHeap is a max-heap, so reverse the order to pop the least doc
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare(other).reverse()
+    }
+}
+
+/// A sorted run being read back from its temp file
+struct Run {
+    lines: Lines<BufReader<File>>,
+    // Delete the temp file when the run is dropped
+    _path: TempPath,
+}
+
+/// Merges sorted runs of docs from temp files
+struct RunMerge {
+    key: SortKey,
+    asc: bool,
+    runs: Vec<Option<Run>>,
+    heap: BinaryHeap<RunHead>,
+    errors: Vec<Error>,
+}
+
+impl RunMerge {
+    fn new(paths: Vec<TempPath>, key: SortKey, asc: bool) -> Self {
+        let mut merge = RunMerge {
+            key,
+            asc,
+            runs: Vec::new(),
+            heap: BinaryHeap::new(),
+            errors: Vec::new(),
+        };
+        for path in paths {
+            match File::open(&path) {
+                Ok(file) => merge.runs.push(Some(Run {
+                    lines: BufReader::new(file).lines(),
+                    _path: path,
+                })),
+                Err(err) => merge.errors.push(Error::from(err)),
+            }
+        }
+        for run in 0..merge.runs.len() {
+            merge.pull(run);
+        }
+        merge
+    }
+
+    /// Read the next doc from a run onto the heap.
+    /// Lines that fail to parse are recorded as errors, and reading carries
+    /// on with the next line. A run that fails to read is closed.
+    fn pull(&mut self, run: usize) {
+        while let Some(current) = self.runs[run].as_mut() {
+            let Some(line) = current.lines.next() else {
+                // Close finished runs
+                self.runs[run] = None;
+                return;
+            };
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    self.errors.push(Error::value(format!(
+                        "Could not read sorted docs from a temp file: {}",
+                        err
+                    )));
+                    self.runs[run] = None;
+                    return;
+                }
+            };
+            match serde_json::from_str::<Doc>(&line) {
+                Ok(doc) => {
+                    self.heap.push(RunHead {
+                        doc,
+                        run,
+                        key: self.key,
+                        asc: self.asc,
+                    });
+                    return;
+                }
+                Err(err) => self.errors.push(Error::value(format!(
+                    "Could not read a sorted doc back from a temp file: {}",
+                    err
+                ))),
+            }
+        }
+    }
+}
+
+impl Iterator for RunMerge {
+    type Item = Result<Doc, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.errors.pop() {
+            return Some(Err(err));
+        }
+        let head = self.heap.pop()?;
+        self.pull(head.run);
+        Some(Ok(head.doc))
+    }
+}
+
+/// Merge sorted runs in passes until at most `fan_in` are left, so no more
+/// than `fan_in` files are open at once. Runs are merged in order, keeping
+/// the sort stable. Errors from merging are added to `errors`.
+fn merge_runs_to_fan_in(
+    mut paths: Vec<TempPath>,
+    key: SortKey,
+    asc: bool,
+    dir: &Path,
+    fan_in: usize,
+    errors: &mut Vec<Error>,
+) -> Vec<TempPath> {
+    let fan_in = fan_in.max(2);
+    while paths.len() > fan_in {
+        let mut merged = Vec::new();
+        let mut rest = paths.into_iter().peekable();
+        while rest.peek().is_some() {
+            let group: Vec<TempPath> = rest.by_ref().take(fan_in).collect();
+            let docs = RunMerge::new(group, key, asc).filter_map(|result| match result {
+                Ok(doc) => Some(doc),
+                Err(err) => {
+                    errors.push(err);
+                    None
+                }
+            });
+            match write_sorted_run(docs, dir) {
+                Ok(path) => merged.push(path),
+                Err(err) => errors.push(Error::value(format!(
+                    "Could not write sorted docs to a temp file: {}",
+                    err.msg
+                ))),
+            }
+        }
+        paths = merged;
+    }
+    paths
+}
+
+pub trait SpillDocs: Docs {
+    /// Split content out of docs, spilling content larger than `threshold`
+    /// bytes to temp files in `dir`. See `Doc::spill_content`.
+    fn spill_content(
+        self,
+        threshold: usize,
+        dir: &Path,
+    ) -> impl Iterator<Item = Result<SpilledDoc, Error>> {
+        self.map(move |doc| doc.spill_content(threshold, dir))
+    }
+
+    /// Sort docs by key, like `Docs::sorted_by`, while holding content
+    /// larger than `threshold` bytes in temp files in `dir`.
+    /// Content is loaded back as sorted docs are pulled from the iterator,
+    /// so only small docs are held in memory at once.
+    /// Docs that fail to spill are returned as errors first.
+    fn sorted_by_spilling(
+        self,
+        key: SortKey,
+        asc: bool,
+        threshold: usize,
+        dir: &Path,
+    ) -> impl DocResults {
+        let mut spilled = Vec::new();
+        let mut errors = Vec::new();
+        for result in self.spill_content(threshold, dir) {
+            match result {
+                Ok(doc) => spilled.push(doc),
+                Err(err) => errors.push(err),
+            }
+        }
+        let sorted = sorted_by(spilled, |a, b| key.compare(&a.doc, &b.doc), asc);
+        errors
+            .into_iter()
+            .map(Err)
+            .chain(sorted.into_iter().map(SpilledDoc::load))
+    }
+
+    /// Sort docs by key, like `Docs::sorted_by`, without holding them all in
+    /// memory. Docs are sorted in chunks of `EXTERNAL_SORT_CHUNK_SIZE`, which
+    /// are written to temp files in `dir`, then merged as docs are pulled
+    /// from the iterator. The sort is stable.
+    fn sorted_by_external(self, key: SortKey, asc: bool, dir: &Path) -> impl DocResults {
+        self.sorted_by_external_chunked(key, asc, dir, EXTERNAL_SORT_CHUNK_SIZE)
+    }
+
+    /// Sort docs with `sorted_by_external`, holding up to `chunk_size` docs
+    /// in memory at once
+    fn sorted_by_external_chunked(
+        self,
+        key: SortKey,
+        asc: bool,
+        dir: &Path,
+        chunk_size: usize,
+    ) -> impl DocResults {
+        self.sorted_by_external_with(key, asc, dir, chunk_size, EXTERNAL_SORT_FAN_IN)
+    }
+
+    /// Sort docs with `sorted_by_external`, holding up to `chunk_size` docs
+    /// in memory, and merging up to `fan_in` sorted runs, at once
+    fn sorted_by_external_with(
+        mut self,
+        key: SortKey,
+        asc: bool,
+        dir: &Path,
+        chunk_size: usize,
+        fan_in: usize,
+    ) -> impl DocResults {
+        let chunk_size = chunk_size.max(1);
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            let chunk: Vec<Doc> = self.by_ref().take(chunk_size).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            let sorted = sorted_by(chunk, |a, b| key.compare(a, b), asc);
+            match write_sorted_run(sorted, dir) {
+                Ok(file) => files.push(file),
+                Err(err) => errors.push(Error::value(format!(
+                    "Could not write sorted docs to a temp file: {}",
+                    err.msg
+                ))),
+            }
+        }
+        let files = merge_runs_to_fan_in(files, key, asc, dir, fan_in, &mut errors);
+        errors
+            .into_iter()
+            .map(Err)
+            .chain(RunMerge::new(files, key, asc))
+    }
+}
+
+impl<I> SpillDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sorted_by_spilling() {
+        let dir = tempdir().unwrap();
+        let date = |day| Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap();
+        let docs = vec![
+            Doc::draft("a.md").set_created(date(2)).set_content("small"),
+            Doc::draft("b.md")
+                .set_created(date(3))
+                .set_content("large ".repeat(100)),
+            Doc::draft("c.md").set_created(date(1)).set_content(""),
+        ];
+        let spilled: Vec<SpilledDoc> = docs
+            .clone()
+            .into_iter()
+            .spill_content(64, dir.path())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(!spilled[0].content.is_on_disk());
+        assert!(spilled[1].content.is_on_disk());
+        assert_eq!(spilled[1].doc.content, "");
+        drop(spilled);
+        // Temp files are cleaned up when dropped
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let sorted: Vec<Doc> = docs
+            .into_iter()
+            .sorted_by_spilling(SortKey::Created, false, 64, dir.path())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let ids: Vec<&str> = sorted
+            .iter()
+            .map(|doc| doc.id_path.to_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["b.md", "a.md", "c.md"]);
+        assert_eq!(sorted[0].content, "large ".repeat(100));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_spilled_content_is_closed() {
+        let dir = tempdir().unwrap();
+        let open_fds = || fs::read_dir("/proc/self/fd").unwrap().count();
+        let before = open_fds();
+        let spilled: Vec<SpilledDoc> = (0..200)
+            .map(|i| Doc::draft(format!("{}.md", i)).set_content("large ".repeat(20)))
+            .spill_content(64, dir.path())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // Other tests may open files meanwhile, so allow some slack
+        assert!(open_fds() < before + 50);
+        assert_eq!(
+            spilled.into_iter().next().unwrap().load().unwrap().content,
+            "large ".repeat(20)
+        );
+    }
+
+    #[test]
+    fn test_sorted_by_external() {
+        let dir = tempdir().unwrap();
+        // Titles collide, to check the sort is stable
+        let docs: Vec<Doc> = (0..25)
+            .map(|i| {
+                Doc::draft(format!("{:02}.md", i)).set_title(format!("Title {}", (i * 7) % 10))
+            })
+            .collect();
+        let expected: Vec<Doc> = docs
+            .clone()
+            .into_iter()
+            .sorted_by(SortKey::Title, false)
+            .collect();
+        let sorted: Vec<Doc> = docs
+            .into_iter()
+            .sorted_by_external_chunked(SortKey::Title, false, dir.path(), 4)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(sorted, expected);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // Merging in passes gives the same stable order
+        let sorted: Vec<Doc> = expected
+            .clone()
+            .into_iter()
+            .rev()
+            .sorted_by_external_with(SortKey::Title, false, dir.path(), 2, 3)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let resorted: Vec<Doc> = expected
+            .into_iter()
+            .rev()
+            .sorted_by(SortKey::Title, false)
+            .collect();
+        assert_eq!(sorted, resorted);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_run_merge_reports_bad_lines() {
+        let dir = tempdir().unwrap();
+        let mut file = NamedTempFile::new_in(dir.path()).unwrap();
+        let doc = |id: &str| serde_json::to_string(&Doc::draft(id)).unwrap();
+        writeln!(file, "{}\nnot json\n{}", doc("a.md"), doc("b.md")).unwrap();
+        let results: Vec<Result<Doc, Error>> =
+            RunMerge::new(vec![file.into_temp_path()], SortKey::IdPath, true).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        let ids: Vec<PathBuf> = results
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .map(|doc| doc.id_path)
+            .collect();
+        assert_eq!(ids, vec![PathBuf::from("a.md"), PathBuf::from("b.md")]);
+    }
+}
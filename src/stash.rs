@@ -2,24 +2,172 @@
 use crate::doc::Doc;
 use crate::docs::Docs;
 use crate::error::Error;
-use crate::io::write_file_deep;
+use crate::io::{temp_file_for, write_file_deep};
+use std::collections::HashSet;
 use std::fs::read_to_string;
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
 
-/// Read JSON Doc archive at path to a vec of Docs
+/// Is this path `-`, meaning stdin or stdout, by unix convention?
+pub fn is_stdio_path(path: impl AsRef<Path>) -> bool {
+    path.as_ref() == Path::new("-")
+}
+
+/// Read stash text from a file, or from stdin if path is `-`
+pub(crate) fn read_stash_text(path: impl AsRef<Path>) -> Result<String, Error> {
+    if is_stdio_path(&path) {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        return Ok(text);
+    }
+    Ok(read_to_string(path)?)
+}
+
+/// Write stash text to a file, or to stdout if path is `-`
+pub(crate) fn write_stash_text(path: impl AsRef<Path>, text: &str) -> Result<(), Error> {
+    if is_stdio_path(&path) {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.write_all(b"\n")?;
+        return Ok(());
+    }
+    write_file_deep(path, text)
+}
+
+/// Read JSON Doc archive at path to a vec of Docs.
+/// Reads from stdin if path is `-`.
 pub fn read(path: impl AsRef<Path>) -> Result<Vec<Doc>, Error> {
-    let json_string = read_to_string(path)?;
+    let json_string = read_stash_text(path)?;
     let docs: Vec<Doc> = serde_json::from_str(&json_string)?;
     return Ok(docs);
 }
 
+/// Writes docs to a JSON stash file one at a time, so a stream can be
+/// stashed without holding every doc in memory. The stash is written to a
+/// temp file, and moved into place by `finish`, so readers never see a
+/// partial stash.
+pub struct StashWriter {
+    path: PathBuf,
+    file: io::BufWriter<NamedTempFile>,
+    count: usize,
+}
+
+impl StashWriter {
+    /// Start writing a stash to a file path
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = io::BufWriter::new(temp_file_for(path)?);
+        file.write_all(b"[")?;
+        Ok(StashWriter {
+            path: path.to_path_buf(),
+            file,
+            count: 0,
+        })
+    }
+
+    /// Add a doc to the stash
+    pub fn write(&mut self, doc: &Doc) -> Result<(), Error> {
+        if self.count > 0 {
+            self.file.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut self.file, doc)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Finish the stash, and move it into place
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.file.write_all(b"]")?;
+        let file = self.file.into_inner().map_err(|err| err.into_error())?;
+        file.persist(&self.path).map_err(|err| err.error)?;
+        Ok(())
+    }
+}
+
 pub trait StashDocs: Docs {
+    /// Write docs to a JSON stash file.
+    /// Writes to stdout if path is `-`.
     fn write_stash(self, path: &Path) -> Result<(), Error> {
         let docs: Vec<Doc> = self.collect();
         let json = serde_json::to_string(&docs)?;
-        write_file_deep(path, &json)?;
+        write_stash_text(path, &json)?;
         Ok(())
     }
+
+    /// Merge docs from stashes into this stream, for joining generated docs
+    /// (tag archives, feeds) back into the main stream. Docs from the stashes
+    /// follow these docs, in the order given. Docs are de-duplicated by
+    /// id_path, and the first doc with an id_path wins.
+    ///
+    /// Stashes are read up front, so a missing or invalid stash fails before
+    /// any docs are yielded.
+    fn merge_stashes<P: AsRef<Path>>(self, paths: &[P]) -> Result<impl Docs, Error> {
+        let mut stashed = Vec::new();
+        for path in paths {
+            stashed.extend(read(path)?);
+        }
+        let mut seen = HashSet::new();
+        Ok(self
+            .chain(stashed)
+            .filter(move |doc| seen.insert(doc.id_path.clone())))
+    }
 }
 
 impl<I> StashDocs for I where I: Iterator<Item = Doc> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_merge_stashes() {
+        let dir = tempdir().unwrap();
+        let stash = dir.path().join("tags.json");
+        vec![
+            Doc::draft("tags/rust.html"),
+            Doc::draft("a.md").set_title("Stashed"),
+        ]
+        .into_iter()
+        .write_stash(&stash)
+        .unwrap();
+        let docs: Vec<Doc> = vec![Doc::draft("a.md").set_title("Main"), Doc::draft("b.md")]
+            .into_iter()
+            .merge_stashes(&[&stash])
+            .unwrap()
+            .collect();
+        let ids: Vec<&Path> = docs.iter().map(|doc| doc.id_path.as_path()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                Path::new("a.md"),
+                Path::new("b.md"),
+                Path::new("tags/rust.html")
+            ]
+        );
+        assert_eq!(docs[0].title, "Main");
+        assert!(vec![Doc::draft("a.md")]
+            .into_iter()
+            .merge_stashes(&[dir.path().join("missing.json")])
+            .is_err());
+    }
+
+    #[test]
+    fn test_stash_writer() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("stash/docs.json");
+        let mut stash = StashWriter::create(&path).unwrap();
+        stash.write(&Doc::draft("a.md")).unwrap();
+        // Nothing is in place until the stash is finished
+        assert!(!path.exists());
+        stash.write(&Doc::draft("b.md")).unwrap();
+        stash.finish().unwrap();
+        let ids: Vec<PathBuf> = read(&path)
+            .unwrap()
+            .into_iter()
+            .map(|doc| doc.id_path)
+            .collect();
+        assert_eq!(ids, vec![PathBuf::from("a.md"), PathBuf::from("b.md")]);
+    }
+}
@@ -0,0 +1,149 @@
+// Build statistics, recorded process-wide as docs flow through the pipeline
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+static DOCS_READ: AtomicUsize = AtomicUsize::new(0);
+static DOCS_RENDERED: AtomicUsize = AtomicUsize::new(0);
+static DOCS_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+static BYTES_WRITTEN: AtomicUsize = AtomicUsize::new(0);
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+static STAGES: Mutex<Vec<StageTiming>> = Mutex::new(Vec::new());
+
+/// Record a doc read from a file or stdin
+pub fn record_read() {
+    DOCS_READ.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a doc rendered with Markdown or a template
+pub fn record_rendered() {
+    DOCS_RENDERED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a doc written to a file or stdout, and the bytes written
+pub fn record_written(bytes: usize) {
+    DOCS_WRITTEN.fetch_add(1, Ordering::Relaxed);
+    BYTES_WRITTEN.fetch_add(bytes, Ordering::Relaxed);
+}
+
+/// Record an error reported for a doc
+pub fn record_error() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record the time spent in a named stage
+pub fn record_stage(name: impl Into<String>, elapsed: Duration) {
+    let mut stages = STAGES.lock().expect("Stage timings lock poisoned");
+    stages.push(StageTiming {
+        name: name.into(),
+        elapsed_ms: elapsed.as_secs_f64() * 1000.0,
+    });
+}
+
+/// Time spent in a named stage
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StageTiming {
+    pub name: String,
+    pub elapsed_ms: f64,
+}
+
+/// How to print a build report
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    /// Human-readable table
+    #[default]
+    Text,
+    /// A single JSON object
+    Json,
+}
+
+/// A snapshot of build statistics
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct BuildReport {
+    pub docs_read: usize,
+    pub docs_rendered: usize,
+    pub docs_written: usize,
+    pub bytes_written: usize,
+    pub errors: usize,
+    pub stages: Vec<StageTiming>,
+}
+
+impl BuildReport {
+    /// Take a snapshot of the statistics recorded so far
+    pub fn snapshot() -> Self {
+        BuildReport {
+            docs_read: DOCS_READ.load(Ordering::Relaxed),
+            docs_rendered: DOCS_RENDERED.load(Ordering::Relaxed),
+            docs_written: DOCS_WRITTEN.load(Ordering::Relaxed),
+            bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+            errors: ERRORS.load(Ordering::Relaxed),
+            stages: STAGES.lock().expect("Stage timings lock poisoned").clone(),
+        }
+    }
+
+    /// Render report as a plain text table
+    pub fn to_table(&self) -> String {
+        let mut rows = vec![
+            ("docs read".to_string(), self.docs_read.to_string()),
+            ("docs rendered".to_string(), self.docs_rendered.to_string()),
+            ("docs written".to_string(), self.docs_written.to_string()),
+            ("bytes written".to_string(), self.bytes_written.to_string()),
+            ("errors".to_string(), self.errors.to_string()),
+        ];
+        for stage in &self.stages {
+            rows.push((
+                format!("{} (ms)", stage.name),
+                format!("{:.1}", stage.elapsed_ms),
+            ));
+        }
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        rows.iter()
+            .map(|(label, value)| format!("{:<width$}  {}", label, value, width = width))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Render report in the given format
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Text => self.to_table(),
+            ReportFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_table() {
+        let report = BuildReport {
+            docs_read: 2,
+            bytes_written: 1024,
+            stages: vec![StageTiming {
+                name: "template".to_string(),
+                elapsed_ms: 12.34,
+            }],
+            ..Default::default()
+        };
+        let table = report.to_table();
+        assert!(table.contains("docs read      2"));
+        assert!(table.contains("template (ms)  12.3"));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let report = BuildReport {
+            docs_written: 3,
+            ..Default::default()
+        };
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(ReportFormat::Json)).unwrap();
+        assert_eq!(json["docs_written"], 3);
+    }
+}
@@ -0,0 +1,297 @@
+// Stubs are lightweight docs without content
+use crate::doc::Doc;
+use crate::docs::{compare_weight, read_meta_weight, sorted_by, Docs, SortKey};
+use crate::error::Error;
+use crate::frontmatter::read_frontmatter_block;
+use crate::io::write_json_lines_to_stdout;
+use crate::json::{self, json};
+use crate::stash::write_stash_text;
+use crate::stats;
+use crate::tags::{index_by_tags, read_meta_tags};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A stub is a lightweight summary of a doc, leaving out content.
+/// Stubs are useful for indexes, listings and feeds, where full content
+/// isn't needed.
+///
+/// Stubs serialize to JSON that can be read back as a `Doc` (with empty
+/// content), so stub streams can be piped through any doc command.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct Stub {
+    pub id_path: PathBuf,
+    pub output_path: PathBuf,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+    pub title: String,
+    pub summary: String,
+    pub meta: json::Value,
+}
+
+impl From<Doc> for Stub {
+    fn from(doc: Doc) -> Self {
+        Stub {
+            id_path: doc.id_path,
+            output_path: doc.output_path,
+            created: doc.created,
+            modified: doc.modified,
+            title: doc.title,
+            summary: doc.summary,
+            meta: doc.meta,
+        }
+    }
+}
+
+impl From<&Doc> for Stub {
+    fn from(doc: &Doc) -> Self {
+        Stub {
+            id_path: doc.id_path.clone(),
+            output_path: doc.output_path.clone(),
+            created: doc.created,
+            modified: doc.modified,
+            title: doc.title.clone(),
+            summary: doc.summary.clone(),
+            meta: doc.meta.clone(),
+        }
+    }
+}
+
+impl From<Stub> for Doc {
+    fn from(stub: Stub) -> Self {
+        Doc {
+            id_path: stub.id_path,
+            output_path: stub.output_path,
+            created: stub.created,
+            modified: stub.modified,
+            title: stub.title,
+            summary: stub.summary,
+            meta: stub.meta,
+            ..Default::default()
+        }
+    }
+}
+
+impl Stub {
+    /// Read a stub from a file, reading only its frontmatter and file stats.
+    /// Frontmatter fields such as `title`, `summary`, `created` and
+    /// `permalink` are uplifted, as with `Doc::parse_and_uplift_frontmatter`.
+    /// Content is never loaded, which makes this fast for index and feed
+    /// pipelines over large content trees.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let metadata = std::fs::metadata(path).map_err(|err| Error::from(err).with_doc(path))?;
+        let block = read_frontmatter_block(path).map_err(|err| err.with_doc(path))?;
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        stats::record_read();
+        let doc = Doc::new(
+            path.into(),
+            path.into(),
+            Some(path.to_owned()),
+            None,
+            metadata.created()?.into(),
+            metadata.modified()?.into(),
+            title,
+            "".to_string(),
+            block,
+            json::Value::Null,
+        )
+        .parse_and_uplift_frontmatter();
+        Ok(Stub::from(doc))
+    }
+
+    /// Get tags from a taxonomy stored at a meta key.
+    /// Sluggifies tags to normalize them for string-matching.
+    pub fn get_meta_tags(&self, taxonomy_key: &str) -> Vec<String> {
+        read_meta_tags(&self.meta, taxonomy_key)
+    }
+}
+
+/// Stubs trait is any iterator of Stubs
+pub trait Stubs: Iterator<Item = Stub> + Sized {
+    /// Write stubs to stdio
+    /// - JSON serialized stubs are printed to stdout, through a buffered
+    ///   writer. See `Docs::write_stdio`.
+    /// - Serialization failures are logged as `tracing` errors
+    fn write_stdio(self) {
+        write_json_lines_to_stdout(self)
+    }
+
+    /// Write stubs to a JSON stash file.
+    /// Writes to stdout if path is `-`.
+    fn write_stash(self, path: &Path) -> Result<(), Error> {
+        let stubs: Vec<Stub> = self.collect();
+        let json = serde_json::to_string(&stubs)?;
+        write_stash_text(path, &json)?;
+        Ok(())
+    }
+
+    /// Filter stubs who's id_path matches a glob pattern.
+    fn filter_matching(self, glob_pattern: &str) -> impl Stubs {
+        let matcher = glob::Pattern::new(glob_pattern).expect("Invalid glob pattern");
+        self.filter(move |stub| {
+            matcher.matches(
+                stub.id_path
+                    .to_str()
+                    .expect("Could not convert path to string"),
+            )
+        })
+    }
+
+    fn sorted_by(self, key: SortKey, asc: bool) -> impl Stubs {
+        let stubs: Vec<Stub> = self.collect();
+        let sorted = match key {
+            SortKey::IdPath => sorted_by(stubs, |a, b| a.id_path.cmp(&b.id_path), asc),
+            SortKey::OutputPath => sorted_by(stubs, |a, b| a.output_path.cmp(&b.output_path), asc),
+            SortKey::Created => sorted_by(stubs, |a, b| a.created.cmp(&b.created), asc),
+            SortKey::Modified => sorted_by(stubs, |a, b| a.modified.cmp(&b.modified), asc),
+            SortKey::Title => sorted_by(stubs, |a, b| a.title.cmp(&b.title), asc),
+            SortKey::Weight => sorted_by(
+                stubs,
+                |a, b| {
+                    compare_weight(
+                        read_meta_weight(&a.meta),
+                        &a.title,
+                        read_meta_weight(&b.meta),
+                        &b.title,
+                    )
+                },
+                asc,
+            ),
+        };
+        sorted.into_iter()
+    }
+
+    /// Get most recent n stubs
+    fn most_recent(self, n: usize) -> impl Stubs {
+        self.sorted_by(SortKey::Created, false).take(n)
+    }
+
+    /// Index stubs by taxonomy.
+    /// Returns a hashmap of stub lists, indexed by term.
+    fn index_by_tag(self, taxonomy_key: &str) -> HashMap<String, Vec<Stub>> {
+        index_by_tags(self, |stub| stub.get_meta_tags(taxonomy_key))
+    }
+
+    /// Creates a stub index from stubs and generates a single JSON doc
+    /// containing the JSON-serialized index.
+    fn generate_tag_index_doc(
+        self,
+        taxonomy_key: &str,
+        output_path: impl Into<PathBuf>,
+    ) -> Result<Doc, Error> {
+        let index = self.index_by_tag(taxonomy_key);
+        let json_string = json::to_string_pretty(&index)?;
+        let created = Utc::now();
+        let output_path: PathBuf = output_path.into();
+        Ok(Doc::new(
+            output_path.clone(),
+            output_path,
+            None,
+            None,
+            created,
+            created,
+            taxonomy_key.into(),
+            "".into(),
+            json_string,
+            json!({}),
+        ))
+    }
+
+    /// Convert stubs back to docs with empty content
+    fn to_docs(self) -> impl Docs {
+        self.map(Doc::from)
+    }
+}
+
+impl<I> Stubs for I where I: Iterator<Item = Stub> {}
+
+pub trait StubDocs: Docs {
+    /// Convert docs to stubs, dropping content
+    fn to_stubs(self) -> impl Stubs {
+        self.map(Stub::from)
+    }
+}
+
+impl<I> StubDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn make_stubs() -> Vec<Stub> {
+        vec![
+            Doc::draft("posts/a.md")
+                .set_title("A")
+                .set_content("Content A")
+                .set_created(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+                .set_meta(json!({"tags": ["Rust", "rust", "ssg"]})),
+            Doc::draft("pages/b.md")
+                .set_title("B")
+                .set_created(Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap())
+                .set_meta(json!({"tags": ["rust"]})),
+        ]
+        .into_iter()
+        .to_stubs()
+        .collect()
+    }
+
+    #[test]
+    fn test_stub_round_trip_as_doc() {
+        let stub = make_stubs().remove(0);
+        let json = serde_json::to_string(&stub).unwrap();
+        let doc: Doc = serde_json::from_str(&json).unwrap();
+        assert_eq!(doc.title, "A");
+        assert_eq!(doc.content, "");
+        assert_eq!(Stub::from(doc), stub);
+    }
+
+    #[test]
+    fn test_most_recent() {
+        let stubs: Vec<Stub> = make_stubs().into_iter().most_recent(1).collect();
+        assert_eq!(stubs.len(), 1);
+        assert_eq!(stubs[0].title, "B");
+    }
+
+    #[test]
+    fn test_filter_matching() {
+        let stubs: Vec<Stub> = make_stubs()
+            .into_iter()
+            .filter_matching("posts/*")
+            .collect();
+        assert_eq!(stubs.len(), 1);
+        assert_eq!(stubs[0].title, "A");
+    }
+
+    #[test]
+    fn test_read_stub() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("post.md");
+        std::fs::write(
+            &path,
+            "---\ntitle: Hello\ntags: [a]\n---\n\nLong content\n---\nMore",
+        )
+        .unwrap();
+        let stub = Stub::read(&path).unwrap();
+        assert_eq!(stub.title, "Hello");
+        assert_eq!(stub.meta, json!({"title": "Hello", "tags": ["a"]}));
+
+        let plain_path = dir.path().join("plain.md");
+        std::fs::write(&plain_path, "No frontmatter").unwrap();
+        let stub = Stub::read(&plain_path).unwrap();
+        assert_eq!(stub.title, "plain");
+        assert_eq!(stub.meta, json::Value::Null);
+    }
+
+    #[test]
+    fn test_index_by_tag() {
+        let index = make_stubs().into_iter().index_by_tag("tags");
+        assert_eq!(index.get("rust").unwrap().len(), 2);
+        assert_eq!(index.get("ssg").unwrap().len(), 1);
+    }
+}
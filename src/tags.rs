@@ -1,11 +1,13 @@
 use crate::doc::Doc;
-use crate::docs::Docs;
+use crate::docs::{DocResults, Docs};
 use crate::error::Error;
 use crate::json::{self, json};
+use crate::stub::Stub;
 use crate::text::{remove_non_slug_chars, to_slug};
 use crate::token_template;
-use chrono::Utc;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use tap::Pipe;
 
@@ -37,22 +39,201 @@ pub fn get_union_for_index_keys(
     union
 }
 
+/// Read tags from a taxonomy stored at a meta key.
+/// Tags may be an array of strings, or a single comma-separated string
+/// (`"rust, ssg"`).
+/// Sluggifies tags to normalize them for string-matching, and removes
+/// duplicates.
+pub fn read_meta_tags(meta: &json::Value, taxonomy_key: &str) -> Vec<String> {
+    let terms: Vec<&str> = match meta.get(taxonomy_key) {
+        Some(json::Value::Array(tag_values)) => tag_values
+            .iter()
+            .filter_map(|value| value.as_str())
+            .collect(),
+        Some(json::Value::String(tag_string)) => tag_string.split(',').collect(),
+        _ => Vec::new(),
+    };
+    let mut tag_strings: Vec<String> = Vec::new();
+    for term in terms {
+        let tag = to_tag(term);
+        if !tag.is_empty() && !tag_strings.contains(&tag) {
+            tag_strings.push(tag);
+        }
+    }
+    tag_strings
+}
+
+/// Index items by the tags returned for each item.
+/// Returns a hashmap of item lists, indexed by tag.
+pub fn index_by_tags<T, F>(items: impl Iterator<Item = T>, get_tags: F) -> HashMap<String, Vec<T>>
+where
+    T: Clone,
+    F: Fn(&T) -> Vec<String>,
+{
+    let mut index: HashMap<String, Vec<T>> = HashMap::new();
+    for item in items {
+        for tag in get_tags(&item) {
+            index.entry(tag).or_default().push(item.clone());
+        }
+    }
+    index
+}
+
+/// The stubs filed under a term in a `TagIndex`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct TagIndexTerm {
+    /// Total number of docs filed under the term, including any left out of
+    /// `items` by a limit
+    pub count: usize,
+    /// Stubs, most recently created first
+    pub items: Vec<Stub>,
+}
+
+/// A serializable tag index, as written by `generate_tag_index_doc`.
+///
+/// ```json
+/// {
+///   "generated_at": "2024-01-01T00:00:00Z",
+///   "taxonomy": "tags",
+///   "terms": {
+///     "rust": {"count": 12, "items": [...]}
+///   }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TagIndex {
+    pub generated_at: DateTime<Utc>,
+    pub taxonomy: String,
+    pub terms: BTreeMap<String, TagIndexTerm>,
+}
+
+impl TagIndex {
+    /// Build a tag index from an index of docs by term.
+    /// Docs are stored as stubs, sorted by created date, most recent first.
+    /// If a limit is given, only that many stubs are kept per term.
+    pub fn new(
+        index: HashMap<String, Vec<Doc>>,
+        taxonomy_key: &str,
+        limit: Option<usize>,
+        generated_at: DateTime<Utc>,
+    ) -> Self {
+        let terms = index
+            .into_iter()
+            .map(|(term, mut docs)| {
+                docs.sort_by(|a, b| {
+                    b.created
+                        .cmp(&a.created)
+                        .then_with(|| a.id_path.cmp(&b.id_path))
+                });
+                let count = docs.len();
+                let items = docs
+                    .into_iter()
+                    .take(limit.unwrap_or(usize::MAX))
+                    .map(Stub::from)
+                    .collect();
+                (term, TagIndexTerm { count, items })
+            })
+            .collect();
+        TagIndex {
+            generated_at,
+            taxonomy: taxonomy_key.to_string(),
+            terms,
+        }
+    }
+
+    /// Convert back to an index of docs by term, with empty doc content
+    pub fn into_doc_index(self) -> HashMap<String, Vec<Doc>> {
+        self.terms
+            .into_iter()
+            .map(|(term, entry)| (term, entry.items.into_iter().map(Doc::from).collect()))
+            .collect()
+    }
+}
+
+/// Generate archive docs from an index of docs by term.
+/// Each archive doc is titled after its term, and holds the docs filed under
+/// that term in `meta.items`. Archives are returned in term order.
+///
+/// Output paths are rendered from `output_path_template`, with the tokens:
+/// - `taxonomy`: the sluggified taxonomy key
+/// - `term`: the sluggified term
+pub fn generate_archives(
+    index: HashMap<String, Vec<Doc>>,
+    taxonomy_key: &str,
+    output_path_template: &str,
+    template_path: Option<PathBuf>,
+) -> impl Docs {
+    let taxonomy_slug = to_slug(taxonomy_key);
+    let output_path_template = output_path_template.to_string();
+    let mut index: Vec<(String, Vec<Doc>)> = index.into_iter().collect();
+    index.sort_by(|a, b| a.0.cmp(&b.0));
+    index.into_iter().map(move |(term, docs)| {
+        let mut parts = HashMap::new();
+        parts.insert("taxonomy", taxonomy_slug.clone());
+        parts.insert("term", to_slug(&term));
+        let output_path: PathBuf = token_template::render(&output_path_template, &parts).into();
+        let meta = json!({ "items": docs });
+        let now = Utc::now();
+        Doc::new(
+            output_path.clone(),
+            output_path.clone(),
+            None,
+            template_path.clone(),
+            now,
+            now,
+            term,
+            "".to_string(),
+            "".to_string(),
+            meta,
+        )
+    })
+}
+
+/// Get the key a term page is matched by: the term as a tag, with dashes
+/// read as spaces, so `static sites`, `static-sites` and `static_sites` all
+/// match. See `to_tag`.
+fn to_term_key(term: &str) -> String {
+    to_tag(&term.replace('-', " "))
+}
+
+/// Index term pages by the file stem of their id path, normalized like
+/// tags, so that `tags/Rust.md` is found under `rust`, and
+/// `tags/static-sites.md` or `tags/static sites.md` under `static_sites`.
+pub fn index_term_pages(term_pages: impl Docs) -> HashMap<String, Doc> {
+    term_pages
+        .filter_map(|doc| {
+            let stem = doc.id_path.file_stem()?.to_string_lossy().into_owned();
+            Some((to_term_key(&stem), doc))
+        })
+        .collect()
+}
+
 impl Doc {
+    /// Merge a term page into a generated archive doc.
+    /// The term page's content, summary, and template are copied over, along
+    /// with its title if it has one. Its meta is merged into the archive's
+    /// meta, except for `items`, which always holds the archived docs.
+    pub fn merge_term_page(mut self, term_page: &Doc) -> Self {
+        if !term_page.title.is_empty() {
+            self.title = term_page.title.clone();
+        }
+        self.content = term_page.content.clone();
+        self.summary = term_page.summary.clone();
+        if term_page.template_path.is_some() {
+            self.template_path = term_page.template_path.clone();
+        }
+        if let json::Value::Object(term_meta) = &term_page.meta {
+            let mut patch = term_meta.clone();
+            patch.remove("items");
+            json::merge(&mut self.meta, json::Value::Object(patch));
+        }
+        self
+    }
+
     /// Get get tags from a taxonomy stored at a meta key.
     /// Sluggifies tags to normalize them for string-matching.
     pub fn get_meta_tags(&self, taxonomy_key: &str) -> Vec<String> {
-        match self.meta.get(taxonomy_key) {
-            Some(json::Value::Array(tag_values)) => {
-                let mut tag_strings: Vec<String> = tag_values
-                    .iter()
-                    .filter_map(|value| value.as_str())
-                    .map(|value| to_tag(value))
-                    .collect();
-                tag_strings.dedup();
-                tag_strings
-            }
-            _ => Vec::new(),
-        }
+        read_meta_tags(&self.meta, taxonomy_key)
     }
 
     /// Given an index, check the taxonomy keys in meta, pluck the related docs
@@ -75,24 +256,13 @@ pub trait TaggedDocs: Docs {
     /// Returns a hashmap of doc lists, indexed by term.
     /// Terms are sluggified to normalize them for lookup by key.
     fn index_by_tag(self, taxonomy_key: &str) -> HashMap<String, Vec<Doc>> {
-        let mut tax_index: HashMap<String, Vec<Doc>> = HashMap::new();
-        for doc in self {
-            if let Some(json::Value::Array(terms)) = doc.meta.get(taxonomy_key) {
-                for term in terms {
-                    if let Some(term) = term.as_str() {
-                        tax_index
-                            .entry(to_tag(term))
-                            .or_insert_with(Vec::new)
-                            .push(doc.clone());
-                    }
-                }
-            }
-        }
-        tax_index
+        index_by_tags(self, |doc| doc.get_meta_tags(taxonomy_key))
     }
 
     /// Creates a doc index from docs and generates a single JSON doc containing
-    /// the JSON-serialized index.
+    /// the JSON-serialized index. See `TagIndex` for the format.
+    /// Docs are stored as stubs, without content. If a limit is given, only
+    /// the most recent `limit` stubs are kept per term.
     ///
     /// Tip: this method can be used to generate JSON index files which can be pulled in as
     /// site-level template data.
@@ -100,10 +270,16 @@ pub trait TaggedDocs: Docs {
         self,
         taxonomy_key: &str,
         output_path: impl Into<PathBuf>,
+        limit: Option<usize>,
     ) -> Result<Doc, Error> {
-        let index = self.index_by_tag(taxonomy_key);
-        let json_string = json::to_string_pretty(&index)?;
         let created = Utc::now();
+        let index = TagIndex::new(
+            self.index_by_tag(taxonomy_key),
+            taxonomy_key,
+            limit,
+            created,
+        );
+        let json_string = json::to_string_pretty(&index)?;
         let output_path: PathBuf = output_path.into();
         Ok(Doc::new(
             output_path.clone(),
@@ -129,27 +305,191 @@ pub trait TaggedDocs: Docs {
         template_path: Option<PathBuf>,
     ) -> impl Docs {
         let tax_index = self.index_by_tag(taxonomy_key);
-        tax_index.into_iter().map(move |(term, docs)| {
-            let mut parts = HashMap::new();
-            parts.insert("taxonomy", to_slug(taxonomy_key));
-            parts.insert("term", to_slug(&term));
-            let output_path: PathBuf = token_template::render(output_path_template, &parts).into();
-            let meta = json!({ "items": docs });
-            let now = chrono::Utc::now();
-            Doc::new(
-                output_path.clone(),
-                output_path.clone(),
-                None,
-                template_path.clone(),
-                now,
-                now,
-                term,
-                "".to_string(),
-                "content".to_string(),
-                meta,
-            )
+        generate_archives(tax_index, taxonomy_key, output_path_template, template_path)
+    }
+
+    /// Like `generate_tag_archives`, but yields these docs followed by the
+    /// generated archives, so one pass produces both. Docs are collected
+    /// first, since archives need every doc.
+    fn with_tag_archives(
+        self,
+        taxonomy_key: &str,
+        output_path_template: &str,
+        template_path: Option<PathBuf>,
+    ) -> impl Docs {
+        let docs: Vec<Doc> = self.collect();
+        let archives: Vec<Doc> = docs
+            .clone()
+            .into_iter()
+            .generate_tag_archives(taxonomy_key, output_path_template, template_path)
+            .collect();
+        docs.into_iter().chain(archives)
+    }
+
+    /// Like `generate_tag_index_doc`, but yields these docs followed by the
+    /// generated index doc, so one pass produces both
+    fn with_tag_index_doc(
+        self,
+        taxonomy_key: &str,
+        output_path: impl Into<PathBuf>,
+        limit: Option<usize>,
+    ) -> impl DocResults {
+        let docs: Vec<Doc> = self.collect();
+        let index =
+            docs.clone()
+                .into_iter()
+                .generate_tag_index_doc(taxonomy_key, output_path, limit);
+        docs.into_iter().map(Ok).chain(std::iter::once(index))
+    }
+
+    /// Merge term pages into archive docs.
+    /// Archives are matched to term pages by their title, normalized like
+    /// tags, which is the term for generated archives. See
+    /// `index_term_pages`.
+    fn merge_term_pages(self, term_pages: &HashMap<String, Doc>) -> impl Docs {
+        self.map(move |doc| match term_pages.get(&to_term_key(&doc.title)) {
+            Some(term_page) => doc.merge_term_page(term_page),
+            None => doc,
         })
     }
 }
 
 impl<I> TaggedDocs for I where I: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_meta_tags() {
+        let meta = json!({"tags": ["Rust", "static sites", "rust"]});
+        assert_eq!(read_meta_tags(&meta, "tags"), vec!["rust", "static_sites"]);
+        let meta = json!({"tags": "Rust, static sites,, rust"});
+        assert_eq!(read_meta_tags(&meta, "tags"), vec!["rust", "static_sites"]);
+        let meta = json!({"tags": 12});
+        assert!(read_meta_tags(&meta, "tags").is_empty());
+    }
+
+    #[test]
+    fn test_index_by_tag_comma_string() {
+        let docs = vec![
+            Doc::draft("a.md").set_meta(json!({"tags": "rust, ssg"})),
+            Doc::draft("b.md").set_meta(json!({"tags": ["ssg"]})),
+        ];
+        let index = docs.into_iter().index_by_tag("tags");
+        assert_eq!(index["rust"].len(), 1);
+        assert_eq!(index["ssg"].len(), 2);
+    }
+
+    #[test]
+    fn test_generate_tag_index_doc() {
+        use chrono::TimeZone;
+        let date = |day| Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap();
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_created(date(1))
+                .set_content("Long content")
+                .set_meta(json!({"tags": ["rust"]})),
+            Doc::draft("b.md")
+                .set_created(date(3))
+                .set_meta(json!({"tags": ["rust", "ssg"]})),
+            Doc::draft("c.md")
+                .set_created(date(2))
+                .set_meta(json!({"tags": ["rust"]})),
+        ];
+        let doc = docs
+            .into_iter()
+            .generate_tag_index_doc("tags", "tags.json", Some(2))
+            .unwrap();
+        assert!(!doc.content.contains("Long content"));
+        let index: TagIndex = json::from_str(&doc.content).unwrap();
+        assert_eq!(index.taxonomy, "tags");
+        let rust = &index.terms["rust"];
+        assert_eq!(rust.count, 3);
+        let ids: Vec<PathBuf> = rust.items.iter().map(|stub| stub.id_path.clone()).collect();
+        assert_eq!(ids, vec![PathBuf::from("b.md"), PathBuf::from("c.md")]);
+        assert_eq!(index.terms["ssg"].count, 1);
+        assert_eq!(index.into_doc_index()["rust"].len(), 2);
+    }
+
+    #[test]
+    fn test_merge_term_pages() {
+        let term_pages = index_term_pages(
+            vec![Doc::draft("tags/Rust.md")
+                .set_title("Rust")
+                .set_content("A systems language")
+                .set_template("term.html")
+                .set_meta(json!({"description": "Posts about Rust", "items": []}))]
+            .into_iter(),
+        );
+        let docs = vec![
+            Doc::draft("a.md").set_meta(json!({"tags": ["rust", "ssg"]})),
+            Doc::draft("b.md").set_meta(json!({"tags": ["ssg"]})),
+        ];
+        let archives: Vec<Doc> = docs
+            .into_iter()
+            .generate_tag_archives("tags", "{taxonomy}/{term}/index.html", None)
+            .merge_term_pages(&term_pages)
+            .collect();
+        assert_eq!(archives[0].title, "Rust");
+        assert_eq!(archives[0].content, "A systems language");
+        assert_eq!(archives[0].template_path, Some(PathBuf::from("term.html")));
+        assert_eq!(archives[0].meta["description"], "Posts about Rust");
+        assert_eq!(archives[0].meta["items"].as_array().unwrap().len(), 1);
+        assert_eq!(archives[1].title, "ssg");
+        assert_eq!(archives[1].content, "");
+    }
+
+    #[test]
+    fn test_merge_term_pages_multi_word() {
+        let docs = vec![Doc::draft("a.md").set_meta(json!({"tags": ["Static sites", "Web dev"]}))];
+        let term_pages = index_term_pages(
+            vec![
+                Doc::draft("tags/static-sites.md").set_content("About static sites"),
+                Doc::draft("tags/web dev.md").set_content("About web dev"),
+            ]
+            .into_iter(),
+        );
+        let archives: Vec<Doc> = docs
+            .into_iter()
+            .generate_tag_archives("tags", "{taxonomy}/{term}/index.html", None)
+            .merge_term_pages(&term_pages)
+            .collect();
+        assert_eq!(archives[0].title, "static_sites");
+        assert_eq!(archives[0].content, "About static sites");
+        assert_eq!(archives[1].title, "web_dev");
+        assert_eq!(archives[1].content, "About web dev");
+    }
+
+    #[test]
+    fn test_with_generated_docs() {
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_output_path("a/index.html")
+                .set_meta(json!({"tags": ["rust", "ssg"]})),
+            Doc::draft("b.md")
+                .set_output_path("b/index.html")
+                .set_meta(json!({"tags": ["ssg"]})),
+        ];
+        let docs: Vec<Doc> = docs
+            .into_iter()
+            .with_tag_archives("tags", "{taxonomy}/{term}/index.html", None)
+            .with_tag_index_doc("tags", "tags.json", None)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let paths: Vec<String> = docs
+            .iter()
+            .map(|doc| doc.output_path.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "a/index.html",
+                "b/index.html",
+                "tags/rust/index.html",
+                "tags/ssg/index.html",
+                "tags.json"
+            ]
+        );
+    }
+}
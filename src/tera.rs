@@ -1,50 +1,93 @@
+use crate::absolutize::to_url;
+use crate::config::Config;
 use crate::doc::Doc;
 use crate::docs::{DocResults, Docs, SortKey};
 use crate::error::Error;
+use crate::html::escape_xml;
 use crate::json::get_deep;
 use crate::markdown::render_markdown;
+use crate::stats;
+use crate::stub::Stub;
+use crate::tags::TagIndex;
 use crate::text;
+use crate::token_template;
+use crate::url::{permalink_url, TrailingSlash};
 use chrono::Utc;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
 pub use tera::{self, try_get_value, Context, Tera};
 
+static FAILED_TO_PARSE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"Failed to parse "([^"]+)""#).expect("Failed to parse regex should be valid")
+});
+
 impl Doc {
     /// Render the content as a Tera template
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id_path = %self.id_path.display())))]
     pub fn render_tera_in_content(
         self,
         renderer: &mut Tera,
         context: &tera::Context,
     ) -> Result<Self, Error> {
-        let content = renderer.render_str(&self.content, context)?;
+        let content = renderer
+            .render_str(&self.content, context)
+            .map_err(|err| Error::tera(err, None).with_doc(&self.id_path))?;
         Ok(self.set_content(content))
     }
 
     /// Render a str as a Tera template, assinging the result to content.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id_path = %self.id_path.display())))]
     pub fn render_tera_str(
         self,
         renderer: &mut Tera,
         template: &str,
         context: &tera::Context,
     ) -> Result<Self, Error> {
-        let content = renderer.render_str(template, context)?;
+        let content = renderer
+            .render_str(template, context)
+            .map_err(|err| Error::tera(err, None).with_doc(&self.id_path))?;
         Ok(self.set_content(content))
     }
 
-    /// Render the Tera template found at `template_path` and assign result to content
+    /// Render the Tera template found at `template_path` and assign result to content.
+    /// Copies the context to add `doc` to it. To render many docs, use
+    /// `TeraDocs::render_tera_template`, which reuses one copy.
     pub fn render_tera_template(
         self,
         renderer: &Tera,
         context: &tera::Context,
+    ) -> Result<Self, Error> {
+        if self.template_path.is_none() {
+            return Ok(self);
+        }
+        self.render_tera_template_in(renderer, &mut context.clone())
+    }
+
+    /// Render the Tera template found at `template_path` and assign result to content.
+    /// This doc is inserted into `context` as `doc`, replacing any `doc`
+    /// already there, so one context can be reused across docs without
+    /// copying shared data for each.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(id_path = %self.id_path.display())))]
+    pub fn render_tera_template_in(
+        self,
+        renderer: &Tera,
+        context: &mut tera::Context,
     ) -> Result<Self, Error> {
         let Some(template_path) = &self.template_path else {
             return Ok(self);
         };
         let template_name = template_path.to_string_lossy().into_owned();
-        let mut context_ext = context.clone();
-        context_ext.insert("doc", &self);
-        let content = renderer.render(&template_name, &context_ext)?;
+        context.insert("doc", &self);
+        let content = renderer
+            .render(&template_name, context)
+            .map_err(|err| Error::tera(err, Some(&template_name)).with_doc(&self.id_path))?;
+        stats::record_rendered();
         Ok(self.set_content(content))
     }
 }
@@ -102,7 +145,12 @@ fn filter_related(
     let Some(index_value) = args.get("index") else {
         return Ok(tera::Value::Array(Vec::new()));
     };
-    let index: HashMap<String, Vec<Doc>> = tera::from_value(index_value.to_owned())?;
+    // Read indexes written by `generate_tag_index_doc`, as well as plain
+    // objects mapping terms to arrays of docs
+    let index: HashMap<String, Vec<Doc>> = match index_value.get("terms") {
+        Some(_) => tera::from_value::<TagIndex>(index_value.to_owned())?.into_doc_index(),
+        None => tera::from_value(index_value.to_owned())?,
+    };
     let related: Vec<Doc> = doc
         .get_related_from_tag_index(taxonomy_key, index)
         .collect();
@@ -164,6 +212,75 @@ fn filter_to_slug(
     Ok(tera::Value::String(slug))
 }
 
+/// Tera filter to escape text for use in XML documents, such as feeds and
+/// sitemaps.
+/// Example:
+/// ```tera
+/// <title>{{ doc.title | escape_xml }}</title>
+/// ```
+fn filter_escape_xml(
+    value: &tera::Value,
+    _: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let str = value
+        .as_str()
+        .ok_or(tera::Error::msg("must be called on a string"))?;
+    Ok(tera::Value::String(escape_xml(str)))
+}
+
+/// Tera filter to turn a site-relative path into a URL under `base_url`.
+/// Example:
+/// ```tera
+/// {{ doc.output_path | to_url(base_url=site.site_url) }}
+/// ```
+fn filter_to_url(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let path = value
+        .as_str()
+        .ok_or(tera::Error::msg("must be called on a string"))?;
+    let base_url = match args.get("base_url") {
+        Some(base_url) => try_get_value!("to_url", "base_url", String, base_url),
+        None => "/".to_string(),
+    };
+    Ok(tera::Value::String(to_url(Path::new(path), &base_url)))
+}
+
+/// Tera filter to turn an output path into a public URL under `base_url`,
+/// stripping `index.html` according to the `trailing_slash` policy
+/// (`always`, `never`, or `index`). Defaults to `always`.
+/// Example:
+/// ```tera
+/// {{ doc.output_path | permalink_url(base_url=site.site_url, trailing_slash=site.trailing_slash) }}
+/// ```
+fn filter_permalink_url(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let path = value
+        .as_str()
+        .ok_or(tera::Error::msg("must be called on a string"))?;
+    let base_url = match args.get("base_url") {
+        Some(base_url) => try_get_value!("permalink_url", "base_url", String, base_url),
+        None => "/".to_string(),
+    };
+    let trailing_slash = match args.get("trailing_slash") {
+        Some(trailing_slash) => try_get_value!(
+            "permalink_url",
+            "trailing_slash",
+            TrailingSlash,
+            trailing_slash
+        ),
+        None => TrailingSlash::default(),
+    };
+    Ok(tera::Value::String(permalink_url(
+        Path::new(path),
+        &base_url,
+        trailing_slash,
+    )))
+}
+
 /// Deterministically choose an element in an array using the hash of a value
 /// to pick.
 fn filter_choose_by_hash(
@@ -234,6 +351,82 @@ pub fn filter_filter_by_id_path(
     Ok(tera::Value::Array(matching_docs))
 }
 
+/// Tera function to query stubs of the docs in the build.
+/// All arguments are optional:
+/// - `glob`: only include docs who's id_path matches this glob pattern
+/// - `sort`: sort key, such as `created`, `title` or `weight`.
+///   Defaults to `created`.
+/// - `asc`: sort ascending. Defaults to false, so the newest docs come first.
+/// - `limit`: the maximum number of stubs to return
+///
+/// Example:
+/// ```tera
+/// {% for page in pages(glob="posts/*", sort="created", limit=10) %}
+///   <a href="{{ page.output_path | to_url }}">{{ page.title }}</a>
+/// {% endfor %}
+/// ```
+pub struct PagesFunction {
+    pages: Vec<Stub>,
+}
+
+impl PagesFunction {
+    pub fn new(docs: &[Doc]) -> Self {
+        PagesFunction {
+            pages: docs.iter().map(Stub::from).collect(),
+        }
+    }
+}
+
+impl tera::Function for PagesFunction {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let glob = match args.get("glob") {
+            Some(glob) => Some(try_get_value!("pages", "glob", String, glob)),
+            None => None,
+        };
+        let key = match args.get("sort") {
+            Some(sort) => {
+                let sort = try_get_value!("pages", "sort", String, sort);
+                SortKey::try_from(sort.as_str()).map_err(|err| tera::Error::msg(err.to_string()))?
+            }
+            None => SortKey::Created,
+        };
+        let asc = match args.get("asc") {
+            Some(asc) => try_get_value!("pages", "asc", bool, asc),
+            None => false,
+        };
+        let limit = match args.get("limit") {
+            Some(limit) => try_get_value!("pages", "limit", usize, limit),
+            None => usize::MAX,
+        };
+        let matcher = match glob {
+            Some(glob) => Some(
+                glob::Pattern::new(&glob)
+                    .map_err(|err| tera::Error::msg(format!("Invalid glob pattern: {}", err)))?,
+            ),
+            None => None,
+        };
+        let docs = self
+            .pages
+            .iter()
+            .filter(|stub| match &matcher {
+                Some(matcher) => matcher.matches_path(&stub.id_path),
+                None => true,
+            })
+            .cloned()
+            .map(Doc::from);
+        let stubs: Vec<Stub> = docs
+            .sorted_by(key, asc)
+            .take(limit)
+            .map(Stub::from)
+            .collect();
+        Ok(tera::to_value(stubs)?)
+    }
+
+    fn is_safe(&self) -> bool {
+        false
+    }
+}
+
 /// Decorate Tera instance with Lettersmith-specific configuration
 pub fn decorate_renderer(renderer: Tera) -> Tera {
     let mut renderer = renderer;
@@ -243,6 +436,9 @@ pub fn decorate_renderer(renderer: Tera) -> Tera {
     renderer.register_filter("choose_by_hash", filter_choose_by_hash);
     renderer.register_filter("to_slug", filter_to_slug);
     renderer.register_filter("slugify", filter_to_slug);
+    renderer.register_filter("escape_xml", filter_escape_xml);
+    renderer.register_filter("to_url", filter_to_url);
+    renderer.register_filter("permalink_url", filter_permalink_url);
     renderer.register_filter("keys", filter_keys);
     renderer.register_filter("values", filter_values);
     renderer.register_filter("filter_by_id_path", filter_filter_by_id_path);
@@ -250,12 +446,251 @@ pub fn decorate_renderer(renderer: Tera) -> Tera {
     renderer
 }
 
+const BUILTIN_DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{ doc.title }}{% if site.site_title %} | {{ site.site_title }}{% endif %}</title>
+  <meta name="description" content="{{ doc.summary }}">
+</head>
+<body>
+  <main>
+    <h1>{{ doc.title }}</h1>
+    {{ doc.content | safe }}
+  </main>
+</body>
+</html>
+"#;
+
+const BUILTIN_ARCHIVE_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{ doc.title }}{% if site.site_title %} | {{ site.site_title }}{% endif %}</title>
+</head>
+<body>
+  <main>
+    <h1>{{ doc.title }}</h1>
+    {{ doc.content | safe }}
+    <ul>
+      {% for item in doc.meta.items | default(value=[]) %}
+      <li>
+        <a href="{{ item.output_path | permalink_url(base_url=site.site_url | default(value="/"), trailing_slash=site.trailing_slash | default(value="always")) }}">{{ item.title }}</a>
+        <time datetime="{{ item.created }}">{{ item.created | date(format="%B %d, %Y") }}</time>
+      </li>
+      {% endfor %}
+    </ul>
+  </main>
+</body>
+</html>
+"#;
+
+const BUILTIN_FEED_TEMPLATE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+  <title>{{ doc.title | escape_xml | safe }}</title>
+  <link>{{ site.site_url | default(value="/") | escape_xml | safe }}</link>
+  <description>{{ doc.summary | escape_xml | safe }}</description>
+  <generator>Lettersmith</generator>
+  {% for item in doc.meta.items | default(value=[]) %}
+  <item>
+    <title>{{ item.title | escape_xml | safe }}</title>
+    <link>{{ item.output_path | permalink_url(base_url=site.site_url | default(value="/"), trailing_slash=site.trailing_slash | default(value="always")) | escape_xml | safe }}</link>
+    <description>{{ item.summary | escape_xml | safe }}</description>
+    <pubDate>{{ item.created | date(format="%a, %d %b %Y %H:%M:%S %z") }}</pubDate>
+  </item>
+  {% endfor %}
+</channel>
+</rss>
+"#;
+
+/// Templates built into Lettersmith, used when no template of the same name
+/// is found in the project or theme:
+/// - `default.html`: a minimal page layout
+/// - `archive.html`: a tag or group archive, listing the docs in
+///   `meta.items`. Use with `smith groupby --template archive.html`.
+/// - `feed.xml`: an RSS feed of the docs in `meta.items`, for per-archive
+///   feeds. Use with `smith groupby --template feed.xml`.
+pub const BUILTIN_TEMPLATES: [(&str, &str); 3] = [
+    ("default.html", BUILTIN_DEFAULT_TEMPLATE),
+    ("archive.html", BUILTIN_ARCHIVE_TEMPLATE),
+    ("feed.xml", BUILTIN_FEED_TEMPLATE),
+];
+
+/// Convert a template directory into a glob matching every file under it.
+/// Globs are returned unchanged.
+pub(crate) fn to_template_glob(templates: &str) -> String {
+    if templates.contains('*') {
+        templates.to_string()
+    } else {
+        format!("{}/**/*", templates.trim_end_matches('/'))
+    }
+}
+
 /// Create a Tera renderer with Lettersmith-specific configuration.
-pub fn renderer(templates: &str) -> Result<Tera, Error> {
-    let tera = Tera::new(templates)?;
+///
+/// `templates` is a list of template globs or directories, in order of
+/// precedence, typically the project's templates followed by a theme's.
+/// When several contain a template of the same name, the first wins.
+/// Built-in templates (see `BUILTIN_TEMPLATES`) come last, as fallbacks.
+/// Templates can extend and include templates from any layer.
+#[cfg_attr(feature = "tracing", tracing::instrument)]
+pub fn renderer(templates: &[String]) -> Result<Tera, Error> {
+    let mut tera = Tera::default();
+    let mut builtins = Tera::default();
+    builtins.add_raw_templates(BUILTIN_TEMPLATES)?;
+    // Parse each layer without building inheritance chains, since templates
+    // may extend templates from a later layer
+    let mut layers = templates
+        .iter()
+        .map(|templates| {
+            let glob = to_template_glob(templates);
+            Tera::parse(&glob).map_err(|err| load_error(err, &glob))
+        })
+        .collect::<Result<Vec<Tera>, Error>>()?;
+    layers.push(builtins);
+    for layer in layers {
+        for (name, template) in layer.templates {
+            tera.templates.entry(name).or_insert(template);
+        }
+    }
+    let globs = templates.join(", ");
+    tera.build_inheritance_chains()
+        .map_err(|err| load_error(err, &globs))?;
+    tera.check_macro_files()
+        .map_err(|err| load_error(err, &globs))?;
     Ok(decorate_renderer(tera))
 }
 
+/// Wrap an error from loading the templates matching `glob`.
+/// Tera names the file that failed to parse in its message, so that file is
+/// used as the template name when there is one, and the glob otherwise.
+fn load_error(err: tera::Error, glob: &str) -> Error {
+    let path = FAILED_TO_PARSE
+        .captures(&err.to_string())
+        .map(|caps| caps[1].to_string());
+    Error::tera(err, Some(path.as_deref().unwrap_or(glob)))
+}
+
+/// A user-defined Tera filter, declared in the `template_filters` section
+/// of config
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FilterDef {
+    /// A token template. `{value}` is replaced with the filtered value, and
+    /// `{<arg>}` with each filter argument.
+    /// Example: `"shout": "{value}{suffix}"`, used as
+    /// `{{ doc.title | shout(suffix="!") }}`
+    Template(String),
+    /// A Rhai script, run with the filtered value in `value` and filter
+    /// arguments in `args`. The script evaluates to the filtered value.
+    /// Requires the `script` feature.
+    Script { script: PathBuf },
+}
+
+/// Render a filter value or argument as a token string
+fn to_token(value: &tera::Value) -> String {
+    match value {
+        tera::Value::String(string) => string.clone(),
+        tera::Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Tera filter rendered from a token template
+struct TemplateFilter(String);
+
+impl tera::Filter for TemplateFilter {
+    fn filter(
+        &self,
+        value: &tera::Value,
+        args: &HashMap<String, tera::Value>,
+    ) -> tera::Result<tera::Value> {
+        let mut parts: HashMap<&str, String> = args
+            .iter()
+            .map(|(key, value)| (key.as_str(), to_token(value)))
+            .collect();
+        parts.insert("value", to_token(value));
+        Ok(tera::Value::String(token_template::render(
+            self.0.clone(),
+            &parts,
+        )))
+    }
+}
+
+/// Register a user-defined filter
+pub fn register_filter_def(renderer: &mut Tera, name: &str, def: &FilterDef) -> Result<(), Error> {
+    match def {
+        FilterDef::Template(template) => {
+            renderer.register_filter(name, TemplateFilter(template.clone()));
+            Ok(())
+        }
+        #[cfg(feature = "script")]
+        FilterDef::Script { script } => {
+            let script = crate::script::Script::read(script)?;
+            renderer.register_filter(name, script);
+            Ok(())
+        }
+        #[cfg(not(feature = "script"))]
+        FilterDef::Script { .. } => Err(Error::value(format!(
+            "Filter \"{}\" is a script, which requires lettersmith to be built with the script feature",
+            name
+        ))),
+    }
+}
+
+/// Create a Tera renderer from config:
+/// - templates matching the `templates` glob, then `theme_templates`, then
+///   built-in templates. See `renderer`.
+/// - partials and macro libraries matching the `template_partials` globs.
+///   Templates of the same name in `templates` take precedence.
+/// - macro libraries in `template_macros` imported into every template
+///   under their namespace, so templates can call `{{ ui::button() }}`
+///   without an import
+/// - filters defined in `template_filters`
+pub fn renderer_for_config(config: &Config) -> Result<Tera, Error> {
+    let mut templates = vec![config.templates.clone()];
+    templates.extend(config.theme_templates.iter().cloned());
+    let mut renderer = renderer(&templates)?;
+    for glob in &config.template_partials {
+        let partials = Tera::new(glob).map_err(|err| load_error(err, glob))?;
+        renderer
+            .extend(&partials)
+            .map_err(|err| load_error(err, glob))?;
+    }
+    if !config.template_macros.is_empty() {
+        let macro_files: Vec<&String> = config.template_macros.values().collect();
+        for (name, template) in renderer.templates.iter_mut() {
+            if macro_files.contains(&name) {
+                continue;
+            }
+            for (namespace, file) in &config.template_macros {
+                let imported = template
+                    .imported_macro_files
+                    .iter()
+                    .any(|(_, existing)| existing == namespace);
+                if !imported {
+                    template
+                        .imported_macro_files
+                        .push((file.clone(), namespace.clone()));
+                }
+            }
+        }
+        renderer
+            .build_inheritance_chains()
+            .map_err(|err| load_error(err, &config.templates))?;
+        renderer
+            .check_macro_files()
+            .map_err(|err| load_error(err, &config.templates))?;
+    }
+    for (name, def) in &config.template_filters {
+        register_filter_def(&mut renderer, name, def)?;
+    }
+    Ok(renderer)
+}
+
 /// Decorate Tera context with default Lettersmith variables
 pub fn decorate_context(context: Context) -> Context {
     let mut context = context;
@@ -269,10 +704,309 @@ pub fn context() -> Context {
     decorate_context(Context::new())
 }
 
+/// Create the `site` context value: the config, plus `site.pages`, a list
+/// of stubs for every doc in the build.
+///
+/// Collect docs in a first pass, then pass them here, and render them in a
+/// second pass, so templates can list any page in the site:
+///
+/// ```tera
+/// {% for page in site.pages | filter_by_id_path(glob="posts/*") %}
+///   <a href="{{ page.output_path | to_url }}">{{ page.title }}</a>
+/// {% endfor %}
+/// ```
+pub fn site_context(config: &Config, docs: &[Doc]) -> Result<tera::Value, Error> {
+    let mut site = tera::to_value(config)?;
+    let pages: Vec<Stub> = docs.iter().map(Stub::from).collect();
+    if let Some(site) = site.as_object_mut() {
+        site.insert("pages".to_string(), tera::to_value(pages)?);
+    }
+    Ok(site)
+}
+
+/// Templates keyed by the glob pattern of the id paths they apply to, for
+/// docs the parent-directory heuristic of `auto_template` doesn't suit.
+///
+/// The most specific (longest) matching pattern wins. Docs that already
+/// have a template, or match no pattern, are left as-is.
+#[derive(Debug, Clone)]
+pub struct TemplateRules {
+    rules: Vec<(glob::Pattern, PathBuf)>,
+}
+
+impl TemplateRules {
+    /// Compile rules from a map of id path glob patterns to template paths
+    pub fn compile(rules: &BTreeMap<String, String>) -> Result<Self, Error> {
+        let mut compiled = Vec::new();
+        for (pattern, template) in rules {
+            compiled.push((glob::Pattern::new(pattern)?, PathBuf::from(template)));
+        }
+        // Most specific patterns first. Sorting is stable, so patterns of
+        // equal length stay in key order.
+        compiled.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.as_str().len()));
+        Ok(TemplateRules { rules: compiled })
+    }
+
+    /// Compile the `template_rules` in config
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        Self::compile(&config.template_rules)
+    }
+
+    /// Get the template for a doc, if a rule matches
+    pub fn template_for(&self, doc: &Doc) -> Option<&Path> {
+        let id_path = doc.id_path.to_string_lossy();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&id_path))
+            .map(|(_, template)| template.as_path())
+    }
+}
+
+impl Doc {
+    /// Set template from the most specific matching rule, unless the doc
+    /// already has a template. See `TemplateRules`.
+    pub fn apply_template_rules(self, rules: &TemplateRules) -> Self {
+        if self.template_path.is_some() {
+            return self;
+        }
+        match rules.template_for(&self) {
+            Some(template) => {
+                let template = template.to_path_buf();
+                self.set_template(template)
+            }
+            None => self,
+        }
+    }
+}
+
 pub trait TeraDocs: Docs {
+    /// Set templates from the `template_rules` in config, e.g.
+    /// `{"notes/**": "note.html"}`. Docs that already have a template, or
+    /// match no rule, are left as-is, so `auto_template` can fill in the
+    /// rest. See `TemplateRules`.
+    ///
+    /// Fails if a rule's glob pattern is invalid.
+    fn apply_template_rules(self, config: &Config) -> Result<impl Docs, Error> {
+        let rules = TemplateRules::from_config(config)?;
+        Ok(self.map(move |doc| doc.apply_template_rules(&rules)))
+    }
+
+    /// Render each doc's template, with the doc available as `doc`.
+    /// The shared context is copied once for the whole iterator, rather
+    /// than once per doc, and each doc replaces `doc` in the copy.
     fn render_tera_template(self, renderer: &Tera, context: &tera::Context) -> impl DocResults {
-        self.map(|doc| doc.render_tera_template(renderer, context))
+        let mut context = context.clone();
+        self.map(move |doc| doc.render_tera_template_in(renderer, &mut context))
     }
 }
 
 impl<T> TeraDocs for T where T: Docs {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_renderer_parse_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.html");
+        std::fs::write(&path, "<p>\n{{ x | }}</p>").unwrap();
+        let glob = dir.path().join("*.html").to_string_lossy().into_owned();
+        let err = renderer(&[glob]).unwrap_err();
+        assert_eq!(
+            err.msg,
+            format!(
+                "Could not render template \"{}\" at line 2, column 8: expected an identifier (must start with a-z)",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_pages_function() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let docs: Vec<Doc> = ["posts/a.md", "posts/b.md", "posts/c.md", "about.md"]
+            .iter()
+            .enumerate()
+            .map(|(i, id_path)| {
+                let mut doc = Doc::draft(id_path).set_title(*id_path);
+                doc.created = start + Duration::days(i as i64);
+                doc
+            })
+            .collect();
+        let mut renderer = decorate_renderer(Tera::default());
+        renderer.register_function("pages", PagesFunction::new(&docs));
+        let rendered = renderer
+            .render_str(
+                r#"{% for page in pages(glob="posts/*", limit=2) %}{{ page.title }} {% endfor %}"#,
+                &Context::new(),
+            )
+            .unwrap();
+        assert_eq!(rendered, "posts/c.md posts/b.md ");
+        let rendered = renderer
+            .render_str(
+                r#"{{ pages(sort="title", asc=true) | length }}"#,
+                &Context::new(),
+            )
+            .unwrap();
+        assert_eq!(rendered, "4");
+    }
+
+    #[test]
+    fn test_renderer_for_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let templates = dir.path().join("templates");
+        let shared = dir.path().join("shared");
+        std::fs::create_dir_all(&templates).unwrap();
+        std::fs::create_dir_all(&shared).unwrap();
+        std::fs::write(
+            templates.join("page.html"),
+            r#"{{ ui::badge(text=doc.title | shout(suffix="!")) }}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            shared.join("ui.html"),
+            r#"{% macro badge(text) %}<b>{{ text }}</b>{% endmacro badge %}"#,
+        )
+        .unwrap();
+        let config = Config {
+            templates: templates.join("*.html").to_string_lossy().into_owned(),
+            template_partials: vec![shared.join("*.html").to_string_lossy().into_owned()],
+            template_macros: std::collections::BTreeMap::from([(
+                "ui".to_string(),
+                "ui.html".to_string(),
+            )]),
+            template_filters: std::collections::BTreeMap::from([(
+                "shout".to_string(),
+                FilterDef::Template("{value}{suffix}".to_string()),
+            )]),
+            ..Config::default()
+        };
+        let renderer = renderer_for_config(&config).unwrap();
+        let mut context = context();
+        context.insert("doc", &Doc::draft("a.md").set_title("Hi"));
+        assert_eq!(
+            renderer.render("page.html", &context).unwrap(),
+            "<b>Hi!</b>"
+        );
+    }
+
+    #[test]
+    fn test_renderer_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("templates");
+        let theme = dir.path().join("theme");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&theme).unwrap();
+        std::fs::write(
+            project.join("page.html"),
+            r#"{% extends "base.html" %}{% block body %}project{% endblock body %}"#,
+        )
+        .unwrap();
+        std::fs::write(theme.join("page.html"), "theme").unwrap();
+        std::fs::write(
+            theme.join("base.html"),
+            r#"<main>{% block body %}{% endblock body %}</main>"#,
+        )
+        .unwrap();
+        let renderer = renderer(&[
+            project.join("*.html").to_string_lossy().into_owned(),
+            theme.to_string_lossy().into_owned(),
+        ])
+        .unwrap();
+        let mut context = context();
+        context.insert("doc", &Doc::draft("a.md").set_title("Hi"));
+        context.insert("site", &crate::json::json!({}));
+        assert_eq!(
+            renderer.render("page.html", &context).unwrap(),
+            "<main>project</main>"
+        );
+        assert!(renderer
+            .render("default.html", &context)
+            .unwrap()
+            .contains("<h1>Hi</h1>"));
+    }
+
+    #[test]
+    fn test_render_tera_template_reuses_context() {
+        let mut renderer = Tera::default();
+        renderer
+            .add_raw_template("page.html", "{{ doc.title }} {{ data.x }}")
+            .unwrap();
+        let mut context = context();
+        context.insert("data", &crate::json::json!({"x": 1}));
+        let docs: Vec<Doc> = vec![
+            Doc::draft("a.md").set_title("A").set_template("page.html"),
+            Doc::draft("b.md").set_title("B"),
+            Doc::draft("c.md").set_title("C").set_template("page.html"),
+        ]
+        .into_iter()
+        .render_tera_template(&renderer, &context)
+        .collect::<Result<_, _>>()
+        .unwrap();
+        let contents: Vec<&str> = docs.iter().map(|doc| doc.content.as_str()).collect();
+        assert_eq!(contents, vec!["A 1", "", "C 1"]);
+        // The shared context is left untouched
+        assert!(!context.contains_key("doc"));
+    }
+
+    #[test]
+    fn test_apply_template_rules() {
+        let mut config = Config::default();
+        config.template_rules = BTreeMap::from([
+            ("notes/**".to_string(), "note.html".to_string()),
+            ("notes/drafts/*".to_string(), "draft.html".to_string()),
+        ]);
+        let docs: Vec<Doc> = vec![
+            Doc::draft("notes/a.md"),
+            Doc::draft("notes/drafts/b.md"),
+            Doc::draft("notes/c.md").set_template("custom.html"),
+            Doc::draft("posts/d.md"),
+        ]
+        .into_iter()
+        .apply_template_rules(&config)
+        .unwrap()
+        .auto_template()
+        .collect();
+        let templates: Vec<&Path> = docs
+            .iter()
+            .map(|doc| doc.template_path.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            templates,
+            vec![
+                Path::new("note.html"),
+                Path::new("draft.html"),
+                Path::new("custom.html"),
+                Path::new("posts.html"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_errors_name_template_and_location() {
+        let mut renderer = Tera::default();
+        renderer
+            .add_raw_template("page.html", "{{ doc.nope }}")
+            .unwrap();
+        let err = Doc::draft("a.md")
+            .set_template("page.html")
+            .render_tera_template(&renderer, &context())
+            .unwrap_err();
+        assert_eq!(err.doc, Some(PathBuf::from("a.md")));
+        assert_eq!(
+            err.msg,
+            "Could not render template \"page.html\": Variable `doc.nope` not found in context while rendering 'page.html'"
+        );
+
+        let err = Doc::draft("b.md")
+            .set_content("a\n\n  {{ x | }}")
+            .render_tera_in_content(&mut renderer, &context())
+            .unwrap_err();
+        assert_eq!(
+            err.msg,
+            "Could not render template string at line 3, column 10: expected an identifier (must start with a-z)"
+        );
+    }
+}
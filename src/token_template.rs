@@ -1,13 +1,77 @@
+use crate::text::to_slug;
+use regex::Regex;
 use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static TOKEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{|\}\}|\{([^{}]*)\}").expect("Could not compile token Regex"));
+
+/// Apply a single token filter to a value.
+/// Returns `None` if the filter is unknown.
+fn apply_filter(value: Option<String>, name: &str, arg: Option<&str>) -> Option<Option<String>> {
+    match name {
+        "default" => match value {
+            Some(value) if !value.is_empty() => Some(Some(value)),
+            _ => Some(Some(arg.unwrap_or("").to_string())),
+        },
+        "slug" => Some(value.map(|value| to_slug(&value))),
+        "upper" => Some(value.map(|value| value.to_uppercase())),
+        "lower" => Some(value.map(|value| value.to_lowercase())),
+        _ => None,
+    }
+}
+
+/// Render the inside of a single `{token}`.
+/// Returns `None` if the token can't be rendered, in which case it should be
+/// left untouched.
+fn render_token(token: &str, parts: &HashMap<&str, String>) -> Option<String> {
+    let mut segments = token.split('|');
+    let key = segments.next()?.trim();
+    let mut value = parts.get(key).cloned();
+    for filter in segments {
+        let (name, arg) = match filter.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg)),
+            None => (filter.trim(), None),
+        };
+        value = apply_filter(value, name, arg)?;
+    }
+    value
+}
 
 /// Render a simple string template, where variables are enclosed in curly braces `{}`.
 /// Substitutions not present in hashmap will be left untouched.
+///
+/// Variables may be followed by filters separated with `|`:
+/// - `{key|default:value}`: use `value` if key is missing or empty
+/// - `{key|slug}`: sluggify the value
+/// - `{key|upper}`: uppercase the value
+/// - `{key|lower}`: lowercase the value
+///
+/// Filters are applied left-to-right, e.g. `{title|default:Untitled|slug}`.
+/// Doubled braces `{{` and `}}` are escapes, and render as literal `{` and `}`.
 pub fn render(template: impl Into<String>, parts: &HashMap<&str, String>) -> String {
-    let mut result: String = template.into();
-    for (key, value) in parts {
-        result = result.replace(&format!("{{{}}}", key), value);
+    let template: String = template.into();
+    TOKEN
+        .replace_all(&template, |caps: &regex::Captures| match &caps[0] {
+            "{{" => "{".to_string(),
+            "}}" => "}".to_string(),
+            whole => render_token(&caps[1], parts).unwrap_or_else(|| whole.to_string()),
+        })
+        .into_owned()
+}
+
+/// Substitute site-wide tokens in text.
+/// Each key in `tokens` is matched in two forms: `{{key}}` and `:key:`.
+/// Unlike `render`, this leaves all other braces alone, so it is safe to run
+/// over raw content before Markdown or template rendering.
+pub fn substitute_tokens(text: &str, tokens: &HashMap<String, String>) -> String {
+    let mut text = text.to_string();
+    for (key, value) in tokens {
+        text = text
+            .replace(&format!("{{{{{}}}}}", key), value)
+            .replace(&format!(":{}:", key), value);
     }
-    result
+    text
 }
 
 #[cfg(test)]
@@ -46,6 +110,50 @@ mod tests {
         assert_eq!(result, "");
     }
 
+    #[test]
+    fn test_render_with_default() {
+        let mut parts = HashMap::new();
+        parts.insert("empty", "".to_string());
+
+        let template = "{missing|default:none}/{empty|default:blank}";
+        let result = render(template, &parts);
+
+        assert_eq!(result, "none/blank");
+    }
+
+    #[test]
+    fn test_render_with_filters() {
+        let mut parts = HashMap::new();
+        parts.insert("title", "Hello World".to_string());
+
+        let template = "{title|slug}/{title|upper}/{title|lower}/{missing|default:A B|slug}";
+        let result = render(template, &parts);
+
+        assert_eq!(result, "hello-world/HELLO WORLD/hello world/a-b");
+    }
+
+    #[test]
+    fn test_render_with_unknown_filter() {
+        let mut parts = HashMap::new();
+        parts.insert("name", "Bob".to_string());
+
+        let template = "{name|reverse}";
+        let result = render(template, &parts);
+
+        assert_eq!(result, "{name|reverse}");
+    }
+
+    #[test]
+    fn test_render_with_escaped_braces() {
+        let mut parts = HashMap::new();
+        parts.insert("name", "Alice".to_string());
+
+        let template = "{{name}} is {name}";
+        let result = render(template, &parts);
+
+        assert_eq!(result, "{name} is Alice");
+    }
+
     #[test]
     fn test_render_with_empty_hashmap() {
         let parts = HashMap::new();
@@ -54,4 +162,19 @@ mod tests {
 
         assert_eq!(result, "This is a {test} template.");
     }
+
+    #[test]
+    fn test_substitute_tokens() {
+        let tokens = HashMap::from([
+            ("site_url".to_string(), "https://example.com".to_string()),
+            ("site_title".to_string(), "Example".to_string()),
+        ]);
+        assert_eq!(
+            substitute_tokens(
+                "[:site_title:]({{site_url}}/about) {{ doc.title }} {other}",
+                &tokens
+            ),
+            "[Example](https://example.com/about) {{ doc.title }} {other}"
+        );
+    }
 }
@@ -0,0 +1,108 @@
+// Turn output paths into public URLs
+use crate::absolutize::to_url;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// How to write URLs for docs that are output to `index.html` files
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TrailingSlash {
+    /// Strip `index.html` and keep the trailing slash: `/posts/a/`
+    #[default]
+    Always,
+    /// Strip `index.html` and the trailing slash: `/posts/a`
+    Never,
+    /// Keep `index.html`: `/posts/a/index.html`
+    Index,
+}
+
+/// Convert an output path into a public path, following the trailing slash
+/// policy. The site root is always `/`.
+///
+/// output_path:
+///     posts/a/index.html
+///
+/// permalink_path:
+///     /posts/a/ (always)
+///     /posts/a (never)
+///     /posts/a/index.html (index)
+///
+/// Paths to other files are left as they are, aside from the leading slash.
+pub fn to_permalink_path(output_path: &Path, trailing_slash: TrailingSlash) -> String {
+    let path = output_path.to_string_lossy();
+    let path = path.trim_start_matches('/');
+    if trailing_slash == TrailingSlash::Index {
+        return format!("/{}", path);
+    }
+    let dir = if path == "index.html" {
+        ""
+    } else if let Some(dir) = path.strip_suffix("/index.html") {
+        dir
+    } else {
+        return format!("/{}", path);
+    };
+    match (dir, trailing_slash) {
+        ("", _) => "/".to_string(),
+        (dir, TrailingSlash::Never) => format!("/{}", dir),
+        (dir, _) => format!("/{}/", dir),
+    }
+}
+
+/// Convert an output path into a public URL under `base_url`, following
+/// the trailing slash policy.
+pub fn permalink_url(output_path: &Path, base_url: &str, trailing_slash: TrailingSlash) -> String {
+    to_url(
+        Path::new(&to_permalink_path(output_path, trailing_slash)),
+        base_url,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_permalink_path() {
+        let path = Path::new("posts/a/index.html");
+        assert_eq!(to_permalink_path(path, TrailingSlash::Always), "/posts/a/");
+        assert_eq!(to_permalink_path(path, TrailingSlash::Never), "/posts/a");
+        assert_eq!(
+            to_permalink_path(path, TrailingSlash::Index),
+            "/posts/a/index.html"
+        );
+        assert_eq!(
+            to_permalink_path(Path::new("index.html"), TrailingSlash::Never),
+            "/"
+        );
+        assert_eq!(
+            to_permalink_path(Path::new("feed.xml"), TrailingSlash::Always),
+            "/feed.xml"
+        );
+        assert_eq!(
+            to_permalink_path(Path::new("posts/not-index.html"), TrailingSlash::Always),
+            "/posts/not-index.html"
+        );
+    }
+
+    #[test]
+    fn test_permalink_url() {
+        assert_eq!(
+            permalink_url(
+                Path::new("posts/a/index.html"),
+                "https://example.com/",
+                TrailingSlash::Always
+            ),
+            "https://example.com/posts/a/"
+        );
+        assert_eq!(
+            permalink_url(
+                Path::new("index.html"),
+                "https://example.com",
+                TrailingSlash::Never
+            ),
+            "https://example.com/"
+        );
+    }
+}
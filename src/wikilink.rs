@@ -1,11 +1,15 @@
+use crate::config::Config;
 use crate::doc::Doc;
 use crate::docs::Docs;
 use crate::html::strip_html;
 use crate::markdown::strip_markdown;
 use crate::text::{first_sentence, to_slug};
 use crate::token_template;
+use crate::url::{permalink_url, to_permalink_path, TrailingSlash};
 use regex::{self, Regex};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::LazyLock;
 use tap::Pipe;
 
@@ -60,6 +64,54 @@ pub fn strip_wikilinks(text: &str) -> String {
         .into_owned()
 }
 
+/// Default template for wikilinks that resolve to a doc
+pub const WIKILINK_TEMPLATE: &str = r#"<a class="wikilink" href="{permalink}">{text}</a>"#;
+
+/// Default template for wikilinks that don't resolve to a doc
+pub const NOLINK_TEMPLATE: &str = r#"<span class="nolink">{text}</span>"#;
+
+/// Templates for rendering wikilinks, and the site settings used to render
+/// the `permalink` and `canonical_url` variables.
+/// See `render_wikilinks_with_templates`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikilinkTemplates {
+    /// Template for wikilinks that resolve to a doc
+    pub link: String,
+    /// Template for wikilinks that don't resolve to a doc
+    pub nolink: String,
+    pub site_url: String,
+    pub trailing_slash: TrailingSlash,
+}
+
+impl Default for WikilinkTemplates {
+    fn default() -> Self {
+        WikilinkTemplates {
+            link: WIKILINK_TEMPLATE.to_string(),
+            nolink: NOLINK_TEMPLATE.to_string(),
+            site_url: "/".to_string(),
+            trailing_slash: TrailingSlash::default(),
+        }
+    }
+}
+
+impl WikilinkTemplates {
+    /// Read wikilink templates from config, falling back to the defaults
+    pub fn from_config(config: &Config) -> Self {
+        WikilinkTemplates {
+            link: config
+                .wikilink_template
+                .clone()
+                .unwrap_or_else(|| WIKILINK_TEMPLATE.to_string()),
+            nolink: config
+                .nolink_template
+                .clone()
+                .unwrap_or_else(|| NOLINK_TEMPLATE.to_string()),
+            site_url: config.site_url.clone(),
+            trailing_slash: config.trailing_slash,
+        }
+    }
+}
+
 /// Render wikilinks in text using a template and an index of slugs to stubs.
 /// Wikilink will be sluggified, then the slug used to look up a corresponding
 /// stub who's values are used to render the wikilink.
@@ -70,12 +122,13 @@ pub fn strip_wikilinks(text: &str) -> String {
 /// - `text`: the text of the wikilink (display text if using pipe)
 /// - `slug`: the sluggified text of the wikilink
 /// - `output_path`: the output_path of the stub
+/// - `permalink`: the public path of the stub, following `trailing_slash`
+/// - `canonical_url`: the permalink of the stub, qualified with `site_url`
 /// - `title`: the title of the stub
 /// - `summary`: the summary of the stub
-pub fn render_wikilinks_with_template(
+pub fn render_wikilinks_with_templates(
     text: &str,
-    wikilink_template: &str,
-    nolink_template: &str,
+    templates: &WikilinkTemplates,
     slug_to_doc_index: &HashMap<String, Doc>,
 ) -> String {
     WIKILINK
@@ -88,23 +141,103 @@ pub fn render_wikilinks_with_template(
                         "output_path",
                         doc.output_path.to_string_lossy().into_owned(),
                     );
+                    context.insert(
+                        "permalink",
+                        to_permalink_path(&doc.output_path, templates.trailing_slash),
+                    );
+                    context.insert(
+                        "canonical_url",
+                        permalink_url(
+                            &doc.output_path,
+                            &templates.site_url,
+                            templates.trailing_slash,
+                        ),
+                    );
                     context.insert("title", doc.title.clone());
                     context.insert("summary", doc.summary.clone());
                     context.insert("text", wikilink.text);
                     context.insert("slug", wikilink.slug);
-                    token_template::render(wikilink_template, &context)
+                    token_template::render(&templates.link, &context)
                 }
                 None => {
                     let mut context: HashMap<&str, String> = HashMap::new();
                     context.insert("text", wikilink.text);
                     context.insert("slug", wikilink.slug);
-                    token_template::render(nolink_template, &context)
+                    token_template::render(&templates.nolink, &context)
                 }
             }
         })
         .into_owned()
 }
 
+/// Render wikilinks in text using a link template and a nolink template.
+/// See `render_wikilinks_with_templates` for the template variables.
+pub fn render_wikilinks_with_template(
+    text: &str,
+    wikilink_template: &str,
+    nolink_template: &str,
+    slug_to_doc_index: &HashMap<String, Doc>,
+) -> String {
+    let templates = WikilinkTemplates {
+        link: wikilink_template.to_string(),
+        nolink: nolink_template.to_string(),
+        ..Default::default()
+    };
+    render_wikilinks_with_templates(text, &templates, slug_to_doc_index)
+}
+
+/// How to pick a doc when several docs share a title slug.
+/// - `First` picks the first doc in the stream
+/// - `Last` picks the last doc in the stream
+/// - `Oldest` picks the doc with the earliest created date
+/// - `Newest` picks the doc with the latest created date
+#[derive(
+    clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SlugResolution {
+    First,
+    #[default]
+    Last,
+    Oldest,
+    Newest,
+}
+
+impl SlugResolution {
+    /// Pick one doc from the candidates sharing a title slug
+    pub fn resolve(&self, candidates: Vec<Doc>) -> Option<Doc> {
+        match self {
+            SlugResolution::First => candidates.into_iter().next(),
+            SlugResolution::Last => candidates.into_iter().last(),
+            // min_by_key/max_by_key return the first/last of equal elements,
+            // so ties fall back to stream order.
+            SlugResolution::Oldest => candidates.into_iter().min_by_key(|doc| doc.created),
+            SlugResolution::Newest => candidates.into_iter().max_by_key(|doc| doc.created),
+        }
+    }
+}
+
+/// A title slug shared by more than one doc
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateSlug {
+    pub slug: String,
+    pub id_paths: Vec<PathBuf>,
+}
+
+/// Find title slugs shared by more than one candidate, sorted by slug.
+pub fn find_duplicate_slugs(candidates: &HashMap<String, Vec<Doc>>) -> Vec<DuplicateSlug> {
+    let mut duplicates: Vec<DuplicateSlug> = candidates
+        .iter()
+        .filter(|(_, docs)| docs.len() > 1)
+        .map(|(slug, docs)| DuplicateSlug {
+            slug: slug.clone(),
+            id_paths: docs.iter().map(|doc| doc.id_path.clone()).collect(),
+        })
+        .collect();
+    duplicates.sort_by(|a, b| a.slug.cmp(&b.slug));
+    duplicates
+}
+
 pub fn get_summary_wiki_html(text: &str) -> String {
     first_sentence(text)
         .pipe(|s| strip_wikilinks(&s))
@@ -151,24 +284,91 @@ impl Doc {
         self
     }
 
+    /// Render wikilinks using templates and site settings.
+    /// See `render_wikilinks_with_templates`.
+    pub fn render_wikilinks_with_templates(
+        mut self,
+        templates: &WikilinkTemplates,
+        slug_to_doc_index: &HashMap<String, Doc>,
+    ) -> Self {
+        self.content = render_wikilinks_with_templates(&self.content, templates, slug_to_doc_index);
+        self
+    }
+
     /// Render wikilinks using a default template
     pub fn render_wikilinks<'a>(self, slug_to_doc_index: &'a HashMap<String, Doc>) -> Self {
-        self.render_wikilinks_with_template(
-            r#"<a class="wikilink" href="{output_path}">{text}</a>"#,
-            r#"<span class="nolink">{text}</span>"#,
-            slug_to_doc_index,
-        )
+        self.render_wikilinks_with_templates(&WikilinkTemplates::default(), slug_to_doc_index)
     }
 }
 
+/// Set permalinks, then render wikilinks between docs.
+/// See `WikilinkDocs::render_wikilinks_permalinked`.
+fn render_wikilinks_permalinked(
+    docs: impl Docs,
+    permalink_template: &str,
+    templates: &WikilinkTemplates,
+    resolution: SlugResolution,
+) -> Vec<Doc> {
+    let docs: Vec<Doc> = docs
+        .map(|doc| doc.set_permalink(permalink_template))
+        .collect();
+    let index = docs
+        .clone()
+        .into_iter()
+        .index_by_title_slug_with(resolution);
+    docs.into_iter()
+        .render_wikilinks_with_templates(templates, &index)
+        .collect()
+}
+
 pub trait WikilinkDocs: Docs {
     /// Create a hashmap of docs keyed by sluggified-title.
     /// This hashmap can be passed to `render_wikilinks` to render wikilinks
     /// in the content.
+    ///
+    /// When several docs share a title slug, the last one wins, and a
+    /// `tracing` warning listing the candidates is logged.
     fn index_by_title_slug(self) -> HashMap<String, Doc> {
-        self.map(|doc| (doc.get_title_slug(), doc))
+        self.index_by_title_slug_with(SlugResolution::default())
+    }
+
+    /// Create a hashmap of every candidate doc keyed by sluggified-title,
+    /// in stream order.
+    fn index_title_slug_candidates(self) -> HashMap<String, Vec<Doc>> {
+        let mut candidates: HashMap<String, Vec<Doc>> = HashMap::new();
+        for doc in self {
+            candidates
+                .entry(doc.get_title_slug())
+                .or_default()
+                .push(doc);
+        }
+        candidates
+    }
+
+    /// Create a hashmap of docs keyed by sluggified-title, using `resolution`
+    /// to pick between docs that share a title slug.
+    /// A `tracing` warning listing the candidates is logged for each shared
+    /// slug.
+    fn index_by_title_slug_with(self, resolution: SlugResolution) -> HashMap<String, Doc> {
+        let candidates = self.index_title_slug_candidates();
+        for duplicate in find_duplicate_slugs(&candidates) {
+            let id_paths: Vec<String> = duplicate
+                .id_paths
+                .iter()
+                .map(|id_path| id_path.to_string_lossy().into_owned())
+                .collect();
+            tracing::warn!(
+                "Wikilink slug \"{}\" matches {} docs ({}). Resolving with {:?}.",
+                duplicate.slug,
+                id_paths.len(),
+                id_paths.join(", "),
+                resolution
+            );
+        }
+        candidates
             .into_iter()
-            .pipe(|iter| HashMap::from_iter(iter))
+            .filter_map(|(slug, docs)| resolution.resolve(docs).map(|doc| (slug, doc)))
+            .collect()
     }
 
     /// Render wikilinks using a custom template
@@ -187,6 +387,16 @@ pub trait WikilinkDocs: Docs {
         })
     }
 
+    /// Render wikilinks using templates and site settings.
+    /// See `Doc::render_wikilinks_with_templates`.
+    fn render_wikilinks_with_templates(
+        self,
+        templates: &WikilinkTemplates,
+        slug_to_doc_index: &HashMap<String, Doc>,
+    ) -> impl Docs {
+        self.map(|doc| doc.render_wikilinks_with_templates(templates, slug_to_doc_index))
+    }
+
     /// Render wikilinks using default template
     fn render_wikilinks(self, slug_to_doc_index: &HashMap<String, Doc>) -> impl Docs {
         self.map(|doc| doc.render_wikilinks(slug_to_doc_index))
@@ -196,11 +406,72 @@ pub trait WikilinkDocs: Docs {
     /// E.g. a wikilink will match if there is a doc in this iterator that has a title who's slug
     /// matches the sluggified wikilink.
     fn render_wikilinks_between(self) -> impl Docs {
+        self.render_wikilinks_between_with(SlugResolution::default())
+    }
+
+    /// Render wikilinks between the docs in this iterator, using `resolution`
+    /// to pick between docs that share a title slug.
+    fn render_wikilinks_between_with(self, resolution: SlugResolution) -> impl Docs {
         let docs: Vec<Doc> = self.collect();
-        let index = docs.clone().into_iter().index_by_title_slug();
+        let index = docs
+            .clone()
+            .into_iter()
+            .index_by_title_slug_with(resolution);
         let docs: Vec<Doc> = docs.into_iter().render_wikilinks(&index).collect();
         docs.into_iter()
     }
+
+    /// Set permalinks, then render wikilinks between the docs in this
+    /// iterator using the default template.
+    ///
+    /// Wikilinks link to the output path a doc has when they are rendered,
+    /// so rendering them before permalinks are set links to the wrong
+    /// paths. This sets every permalink first, so links always point at
+    /// final permalinks, whatever order the steps are written in.
+    fn render_wikilinks_permalinked(self, permalink_template: impl Into<String>) -> impl Docs {
+        let docs = render_wikilinks_permalinked(
+            self,
+            &permalink_template.into(),
+            &WikilinkTemplates::default(),
+            SlugResolution::default(),
+        );
+        docs.into_iter()
+    }
+
+    /// Set permalinks, then render wikilinks between the docs in this
+    /// iterator using templates and site settings, and `resolution` to pick
+    /// between docs that share a title slug.
+    /// See `render_wikilinks_permalinked`.
+    fn render_wikilinks_permalinked_with(
+        self,
+        permalink_template: impl Into<String>,
+        templates: &WikilinkTemplates,
+        resolution: SlugResolution,
+    ) -> impl Docs {
+        let docs =
+            render_wikilinks_permalinked(self, &permalink_template.into(), templates, resolution);
+        docs.into_iter()
+    }
+
+    /// Render wikilinks between the docs in this iterator using templates
+    /// and site settings, and `resolution` to pick between docs that share
+    /// a title slug.
+    fn render_wikilinks_between_with_templates(
+        self,
+        templates: &WikilinkTemplates,
+        resolution: SlugResolution,
+    ) -> impl Docs {
+        let docs: Vec<Doc> = self.collect();
+        let index = docs
+            .clone()
+            .into_iter()
+            .index_by_title_slug_with(resolution);
+        let docs: Vec<Doc> = docs
+            .into_iter()
+            .render_wikilinks_with_templates(templates, &index)
+            .collect();
+        docs.into_iter()
+    }
 }
 
 impl<I> WikilinkDocs for I where I: Docs {}
@@ -208,6 +479,7 @@ impl<I> WikilinkDocs for I where I: Docs {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{TimeZone, Utc};
 
     #[test]
     fn test_parse_wikilink() {
@@ -259,6 +531,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_render_wikilinks_with_templates() {
+        let mut index: HashMap<String, Doc> = HashMap::new();
+        index.insert(
+            "a".into(),
+            Doc::draft("a.md").set_output_path("posts/a/index.html"),
+        );
+        let templates = WikilinkTemplates {
+            link: r#"<a href="{canonical_url}" data-path="{permalink}">{text}</a>"#.to_string(),
+            nolink: "{text}?".to_string(),
+            site_url: "https://example.com/".to_string(),
+            trailing_slash: TrailingSlash::Never,
+        };
+        let rendered = render_wikilinks_with_templates("[[A]] [[B]]", &templates, &index);
+        assert_eq!(
+            rendered,
+            r#"<a href="https://example.com/posts/a" data-path="/posts/a">A</a> B?"#
+        );
+    }
+
+    #[test]
+    fn test_render_wikilinks_permalinked() {
+        let docs: Vec<Doc> = vec![
+            Doc::draft("notes/a.md")
+                .set_title("A")
+                .set_content("See [[B]]."),
+            Doc::draft("notes/b.md").set_title("B"),
+        ]
+        .into_iter()
+        .render_wikilinks_permalinked("{parents}/{slug}/index.html")
+        .collect();
+        assert_eq!(docs[1].output_path, PathBuf::from("notes/b/index.html"));
+        assert_eq!(
+            docs[0].content,
+            r#"See <a class="wikilink" href="/notes/b/">B</a>."#
+        );
+    }
+
     #[test]
     fn test_render_wikilinks_nolink() {
         let text = "This is a [[wikilink]] and a [[link|Custom Text]].";
@@ -280,4 +590,38 @@ mod tests {
         let wikilinks: Vec<Wikilink> = find_wikilinks(text).collect();
         assert_eq!(wikilinks.len(), 0);
     }
+
+    #[test]
+    fn test_index_by_title_slug_duplicates() {
+        let docs = vec![
+            Doc::draft("a.md")
+                .set_title("Hello")
+                .set_created(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+            Doc::draft("b.md")
+                .set_title("hello")
+                .set_created(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            Doc::draft("c.md").set_title("Other"),
+        ];
+
+        let candidates = docs.clone().into_iter().index_title_slug_candidates();
+        let duplicates = find_duplicate_slugs(&candidates);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].slug, "hello");
+        assert_eq!(
+            duplicates[0].id_paths,
+            vec![PathBuf::from("a.md"), PathBuf::from("b.md")]
+        );
+
+        let index = docs.clone().into_iter().index_by_title_slug();
+        assert_eq!(index["hello"].id_path, PathBuf::from("b.md"));
+        let index = docs
+            .clone()
+            .into_iter()
+            .index_by_title_slug_with(SlugResolution::First);
+        assert_eq!(index["hello"].id_path, PathBuf::from("a.md"));
+        let index = docs
+            .into_iter()
+            .index_by_title_slug_with(SlugResolution::Oldest);
+        assert_eq!(index["hello"].id_path, PathBuf::from("b.md"));
+    }
 }